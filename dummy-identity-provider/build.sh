@@ -0,0 +1,14 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+# Make sure we always run from the identity provider root
+IDP_DIR="$( cd "$( dirname "${BASH_SOURCE[0]}" )" && pwd )"
+cd "$IDP_DIR"
+
+# Build the canister
+cargo build --release --target wasm32-unknown-unknown --manifest-path ./Cargo.toml -j1
+ic-wasm "../target/wasm32-unknown-unknown/release/dummy_identity_provider.wasm" -o "./dummy_identity_provider.wasm" shrink
+ic-wasm dummy_identity_provider.wasm -o dummy_identity_provider.wasm metadata candid:service -f dummy_identity_provider.did -v public
+# indicate support for certificate version 1 and 2 in the canister metadata
+ic-wasm dummy_identity_provider.wasm -o dummy_identity_provider.wasm metadata supported_certificate_versions -d "1,2" -v public
+gzip --no-name --force "dummy_identity_provider.wasm"
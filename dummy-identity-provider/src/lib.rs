@@ -0,0 +1,260 @@
+use base64::Engine;
+use candid::{CandidType, Deserialize, Principal, candid_method};
+use ic_canister_sig_creation::CanisterSigPublicKey;
+use ic_canister_sig_creation::signature_map::{CanisterSigInputs, LABEL_SIG, SignatureMap};
+use ic_cdk::api::{canister_self, certified_data_set, msg_caller, time};
+use ic_cdk_macros::{query, update};
+use ic_certification::{Hash, labeled_hash};
+use ic_verifiable_credentials::issuer_api::{ArgumentValue, CredentialSpec};
+use ic_verifiable_credentials::{
+    CredentialParams, II_ISSUER_URL, VC_SIGNING_INPUT_DOMAIN, build_credential_jwt,
+    build_ii_verifiable_presentation_jwt, did_for_principal, principal_for_did, vc_jwt_to_jws,
+    vc_signing_input,
+};
+use lazy_static::lazy_static;
+use serde_bytes::ByteBuf;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+const CREDENTIAL_URL_PREFIX: &str = "data:text/plain;charset=UTF-8,";
+const ID_ALIAS_CREDENTIAL_TYPE: &str = "InternetIdentityIdAlias";
+const MINUTE_NS: u64 = 60 * 1_000_000_000;
+// The expiration of issued id_alias credentials.
+const ID_ALIAS_EXPIRATION_PERIOD_NS: u64 = 15 * MINUTE_NS;
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct PrepareIdAliasRequest {
+    pub relying_party: String,
+    pub issuer: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct PreparedIdAlias {
+    pub rp_id_alias_jwt: String,
+    pub issuer_id_alias_jwt: String,
+    pub canister_sig_pk_der: ByteBuf,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum PrepareIdAliasError {
+    Internal(String),
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct GetIdAliasRequest {
+    pub rp_id_alias_jwt: String,
+    pub issuer_id_alias_jwt: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct SignedIdAlias {
+    pub id_alias: Principal,
+    pub id_dapp: Principal,
+    pub credential_jws: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct IdAliasCredentials {
+    pub rp_id_alias_credential: SignedIdAlias,
+    pub issuer_id_alias_credential: SignedIdAlias,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum GetIdAliasError {
+    Unauthorized(Principal),
+    NoSuchCredentials(String),
+    Internal(String),
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct GetPresentationRequest {
+    pub rp_id_alias_jws: String,
+    pub requested_vc_jws: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct PresentationData {
+    pub vp_jwt: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum GetPresentationError {
+    Internal(String),
+}
+
+thread_local! {
+    /// Non-stable structures
+    // Canister signatures
+    static SIGNATURES : RefCell<SignatureMap> = RefCell::new(SignatureMap::default());
+}
+
+lazy_static! {
+    // Seed and public key used for signing the id_alias credentials.
+    static ref CANISTER_SIG_SEED: Vec<u8> = hash_bytes("DummyIdentityProvider").to_vec();
+    static ref CANISTER_SIG_PK: CanisterSigPublicKey = CanisterSigPublicKey::new(canister_self(), CANISTER_SIG_SEED.clone());
+}
+
+fn hash_bytes(value: impl AsRef<[u8]>) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(value.as_ref());
+    hasher.finalize().into()
+}
+
+fn update_root_hash() {
+    SIGNATURES.with_borrow(|sigs| {
+        certified_data_set(labeled_hash(LABEL_SIG, &sigs.root_hash()));
+    })
+}
+
+/// Returns the id_alias of `id_dapp` for the given relying party, i.e. the self-authenticating
+/// principal of a canister signature public key of this canister, with a seed derived from
+/// `id_dapp` and the (derivation) origin of the relying party.
+fn id_alias_for(id_dapp: Principal, relying_party: &str) -> Principal {
+    let seed = hash_bytes([id_dapp.as_slice(), b"#", relying_party.as_bytes()].concat());
+    let pk = CanisterSigPublicKey::new(canister_self(), seed.to_vec());
+    Principal::self_authenticating(pk.to_der())
+}
+
+fn exp_timestamp_s() -> u32 {
+    ((time() + ID_ALIAS_EXPIRATION_PERIOD_NS) / 1_000_000_000) as u32
+}
+
+// Prepares a unique id for the given id_alias credential.
+// The returned URL has the format: "data:text/plain;charset=UTF-8,timestamp_ns:...,alias_hash:..."
+fn credential_id_for_alias(id_alias: Principal) -> String {
+    let timestamp = format!("timestamp_ns:{}", time());
+    let alias_hash = format!(
+        "alias_hash:{}",
+        hex_string(&hash_bytes(id_alias.as_slice()))
+    );
+    format!("{}{},{}", CREDENTIAL_URL_PREFIX, timestamp, alias_hash)
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn id_alias_credential(id_dapp: Principal, id_alias: Principal, derivation_origin: &str) -> String {
+    let params = CredentialParams {
+        spec: CredentialSpec {
+            credential_type: ID_ALIAS_CREDENTIAL_TYPE.to_string(),
+            arguments: Some(HashMap::from([
+                (
+                    "hasIdAlias".to_string(),
+                    ArgumentValue::String(id_alias.to_text()),
+                ),
+                (
+                    "derivationOrigin".to_string(),
+                    ArgumentValue::String(derivation_origin.to_string()),
+                ),
+            ])),
+        },
+        subject_id: did_for_principal(id_dapp),
+        credential_id_url: credential_id_for_alias(id_alias),
+        issuer_url: II_ISSUER_URL.to_string(),
+        expiration_timestamp_s: exp_timestamp_s(),
+    };
+    build_credential_jwt(params)
+}
+
+fn sig_inputs(signing_input: &[u8]) -> CanisterSigInputs<'_> {
+    CanisterSigInputs {
+        domain: VC_SIGNING_INPUT_DOMAIN,
+        message: signing_input,
+        seed: &CANISTER_SIG_SEED,
+    }
+}
+
+/// Decodes an id_alias credential JWT and returns the values of `sub` and `hasIdAlias`.
+/// This function doesn't perform any validation or signature verification.
+fn get_id_dapp_and_alias_from_jwt(jwt: &str) -> Result<(Principal, Principal), &'static str> {
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+    let payload = jwt.split('.').nth(1).ok_or("Failed to parse JWT")?;
+    let claims: Value = serde_json::from_slice(
+        &BASE64
+            .decode(payload)
+            .map_err(|_| "Failed to decode base64")?,
+    )
+    .map_err(|_| "Failed to parse payload JSON")?;
+    let sub = claims
+        .get("sub")
+        .and_then(Value::as_str)
+        .ok_or("Failed to extract subject")?;
+    let id_dapp = principal_for_did(sub).map_err(|_| "Failed to parse subject")?;
+    let alias = claims
+        .pointer("/vc/credentialSubject/InternetIdentityIdAlias/hasIdAlias")
+        .ok_or("Failed to extract alias")?
+        .as_str()
+        .ok_or("Invalid value for 'hasIdAlias'")?;
+    let id_alias = Principal::from_text(alias).map_err(|_| "Failed to parse principal")?;
+    Ok((id_dapp, id_alias))
+}
+
+#[update]
+#[candid_method]
+async fn prepare_id_alias(
+    req: PrepareIdAliasRequest,
+) -> Result<PreparedIdAlias, PrepareIdAliasError> {
+    let id_dapp = msg_caller();
+    let id_alias = id_alias_for(id_dapp, &req.relying_party);
+    let rp_id_alias_jwt = id_alias_credential(id_dapp, id_alias, &req.relying_party);
+    let issuer_id_alias_jwt = id_alias_credential(id_dapp, id_alias, &req.issuer);
+    for credential_jwt in [&rp_id_alias_jwt, &issuer_id_alias_jwt] {
+        let signing_input = vc_signing_input(credential_jwt, &CANISTER_SIG_PK)
+            .map_err(PrepareIdAliasError::Internal)?;
+        SIGNATURES.with_borrow_mut(|sigs| sigs.add_signature(&sig_inputs(&signing_input)));
+    }
+    update_root_hash();
+    Ok(PreparedIdAlias {
+        rp_id_alias_jwt,
+        issuer_id_alias_jwt,
+        canister_sig_pk_der: ByteBuf::from(CANISTER_SIG_PK.to_der()),
+    })
+}
+
+#[query]
+#[candid_method(query)]
+fn get_id_alias(req: GetIdAliasRequest) -> Result<IdAliasCredentials, GetIdAliasError> {
+    Ok(IdAliasCredentials {
+        rp_id_alias_credential: signed_id_alias(&req.rp_id_alias_jwt)?,
+        issuer_id_alias_credential: signed_id_alias(&req.issuer_id_alias_jwt)?,
+    })
+}
+
+fn signed_id_alias(credential_jwt: &str) -> Result<SignedIdAlias, GetIdAliasError> {
+    let (id_dapp, id_alias) = get_id_dapp_and_alias_from_jwt(credential_jwt)
+        .map_err(|e| GetIdAliasError::Internal(e.to_string()))?;
+    if id_dapp != msg_caller() {
+        return Err(GetIdAliasError::Unauthorized(msg_caller()));
+    }
+    let signing_input =
+        vc_signing_input(credential_jwt, &CANISTER_SIG_PK).map_err(GetIdAliasError::Internal)?;
+    let sig = SIGNATURES
+        .with_borrow(|sigs| sigs.get_signature_as_cbor(&sig_inputs(&signing_input), None))
+        .map_err(|e| {
+            GetIdAliasError::NoSuchCredentials(format!("signature not prepared or expired: {}", e))
+        })?;
+    let credential_jws =
+        vc_jwt_to_jws(credential_jwt, &CANISTER_SIG_PK, &sig).map_err(GetIdAliasError::Internal)?;
+    Ok(SignedIdAlias {
+        id_alias,
+        id_dapp,
+        credential_jws,
+    })
+}
+
+/// Assembles the presentation for the relying party, as done by the frontend of II.
+/// The holder of the presentation is the caller.
+#[query]
+#[candid_method(query)]
+fn get_presentation(req: GetPresentationRequest) -> Result<PresentationData, GetPresentationError> {
+    let vp_jwt = build_ii_verifiable_presentation_jwt(
+        msg_caller(),
+        req.rp_id_alias_jws,
+        req.requested_vc_jws,
+    )
+    .map_err(GetPresentationError::Internal)?;
+    Ok(PresentationData { vp_jwt })
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use candid::{CandidType, Deserialize, Principal, encode_args};
+use ic_canister_sig_creation::CanisterSigPublicKey;
+use ic_verifiable_credentials::get_verified_id_alias_from_jws;
+use ic_verifiable_credentials::issuer_api::{ArgumentValue, CredentialSpec};
+use vc_test_harness::idp_api::{
+    GetIdAliasError, GetIdAliasRequest, IdAliasCredentials, PrepareIdAliasRequest, PreparedIdAlias,
+    get_id_alias, prepare_id_alias,
+};
+use vc_test_harness::{VcFlowRequest, VcTestEnv};
+
+const DUMMY_IDP_WASM: &[u8] = include_bytes!("../dummy_identity_provider.wasm.gz");
+const DUMMY_ISSUER_WASM: &[u8] = include_bytes!("../../dummy-issuer/dummy_issuer.wasm.gz");
+const DUMMY_ISSUER_URL: &str = "https://dummy-issuer.vc";
+const RP_ORIGIN: &str = "https://relying-party.vc";
+const ISSUER_ORIGIN: &str = "https://issuer.vc";
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+struct IssuerConfig {
+    ic_root_key_der: Vec<u8>,
+    idp_canister_ids: Vec<Principal>,
+    derivation_origin: String,
+    frontend_hostname: String,
+}
+
+fn prepare(env: &VcTestEnv, sender: Principal) -> PreparedIdAlias {
+    prepare_id_alias(
+        &env.pic,
+        env.idp_id(),
+        PrepareIdAliasRequest {
+            relying_party: RP_ORIGIN.to_string(),
+            issuer: ISSUER_ORIGIN.to_string(),
+        },
+        Some(sender),
+    )
+    .expect("failed preparing id_alias")
+}
+
+fn get(
+    env: &VcTestEnv,
+    sender: Principal,
+    prepared: &PreparedIdAlias,
+) -> Result<IdAliasCredentials, GetIdAliasError> {
+    get_id_alias(
+        &env.pic,
+        env.idp_id(),
+        GetIdAliasRequest {
+            rp_id_alias_jwt: prepared.rp_id_alias_jwt.clone(),
+            issuer_id_alias_jwt: prepared.issuer_id_alias_jwt.clone(),
+        },
+        Some(sender),
+    )
+}
+
+fn env_with_idp() -> VcTestEnv {
+    let mut env = VcTestEnv::new();
+    env.install_identity_provider(DUMMY_IDP_WASM);
+    env
+}
+
+fn test_principal(n: u8) -> Principal {
+    Principal::self_authenticating([n])
+}
+
+#[test]
+fn should_issue_verifiable_id_alias_credentials() {
+    let env = env_with_idp();
+    let idp = env.idp_id();
+    let user = test_principal(1);
+
+    let prepared = prepare(&env, user);
+    let credentials = get(&env, user, &prepared).expect("failed getting id_alias");
+
+    let canister_sig_pk = CanisterSigPublicKey::try_from(prepared.canister_sig_pk_der.as_slice())
+        .expect("invalid canister sig public key");
+    assert_eq!(canister_sig_pk.canister_id, idp);
+    let rp_credential = credentials.rp_id_alias_credential;
+    let issuer_credential = credentials.issuer_id_alias_credential;
+    assert_eq!(rp_credential.id_dapp, user);
+    assert_eq!(issuer_credential.id_dapp, user);
+    assert_eq!(rp_credential.id_alias, issuer_credential.id_alias);
+    for (credential, derivation_origin) in [
+        (&rp_credential, RP_ORIGIN),
+        (&issuer_credential, ISSUER_ORIGIN),
+    ] {
+        let alias_tuple = get_verified_id_alias_from_jws(
+            &credential.credential_jws,
+            &user,
+            derivation_origin,
+            &idp,
+            &env.ic_root_key(),
+            env.time_ns(),
+        )
+        .expect("invalid id_alias credential");
+        assert_eq!(alias_tuple.id_alias, credential.id_alias);
+        assert_eq!(alias_tuple.derivation_origin, derivation_origin);
+    }
+}
+
+#[test]
+fn should_not_return_id_alias_of_other_caller() {
+    let env = env_with_idp();
+    let prepared = prepare(&env, test_principal(1));
+
+    let result = get(&env, test_principal(2), &prepared);
+
+    assert_eq!(
+        result,
+        Err(GetIdAliasError::Unauthorized(test_principal(2)))
+    );
+}
+
+#[test]
+fn should_not_return_unprepared_id_alias() {
+    let env = env_with_idp();
+    let user = test_principal(1);
+    let prepared = prepare(&env, user);
+    // Re-install the canister to drop the prepared signatures.
+    env.pic
+        .reinstall_canister(env.idp_id(), DUMMY_IDP_WASM.to_vec(), vec![], None)
+        .expect("failed reinstalling canister");
+
+    let result = get(&env, user, &prepared);
+
+    assert!(
+        matches!(result, Err(GetIdAliasError::NoSuchCredentials(e)) if e.contains("signature not prepared"))
+    );
+}
+
+#[test]
+fn should_not_return_malformed_id_alias() {
+    let env = env_with_idp();
+    let user = test_principal(1);
+    let mut prepared = prepare(&env, user);
+    prepared.issuer_id_alias_jwt = "not a jwt".to_string();
+
+    let result = get(&env, user, &prepared);
+
+    assert!(
+        matches!(result, Err(GetIdAliasError::Internal(e)) if e.contains("Failed to parse JWT"))
+    );
+}
+
+#[test]
+fn should_run_vc_flow_locally() {
+    let mut env = env_with_idp();
+    let issuer_config = IssuerConfig {
+        ic_root_key_der: env.root_key_der(),
+        idp_canister_ids: vec![env.idp_id()],
+        derivation_origin: ISSUER_ORIGIN.to_string(),
+        frontend_hostname: ISSUER_ORIGIN.to_string(),
+    };
+    env.install_issuer(
+        DUMMY_ISSUER_WASM,
+        encode_args((Some(issuer_config),)).unwrap(),
+    );
+    let user = test_principal(1);
+    let request = VcFlowRequest {
+        relying_party: RP_ORIGIN.to_string(),
+        issuer_frontend_hostname: ISSUER_ORIGIN.to_string(),
+        credential_spec: CredentialSpec {
+            credential_type: "VerifiedAge".to_string(),
+            arguments: Some(HashMap::from([(
+                "ageAtLeast".to_string(),
+                ArgumentValue::Int(18),
+            )])),
+        },
+    };
+
+    let result = env.run_vc_flow(user, &request).expect("VC flow failed");
+
+    env.validate_presentation(
+        &result.vp_jwt,
+        user,
+        RP_ORIGIN,
+        DUMMY_ISSUER_URL,
+        &request.credential_spec,
+    )
+    .expect("invalid presentation");
+}
@@ -0,0 +1,13 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+# Prints the init arguments of the dummy issuer for the local replica, which accept the id_alias
+# credentials of the local Internet Identity, certified with the root key of the replica.
+# Both Internet Identity and the dummy issuer must have been created on the local replica.
+II_CANISTER_ID=$(dfx canister id internet_identity --network local)
+ROOT_KEY=$(dfx ping local | python3 -c 'import json, sys; print("".join("\\%02x" % b for b in json.load(sys.stdin)["root_key"]))')
+ISSUER_CANISTER_ID=$(dfx canister id dummy_issuer --network local)
+REPLICA_SERVER_PORT=$(dfx info webserver-port)
+ISSUER_ORIGIN="http://${ISSUER_CANISTER_ID}.localhost:${REPLICA_SERVER_PORT}"
+
+echo "(opt record { ic_root_key_der = blob \"${ROOT_KEY}\"; idp_canister_ids = vec { principal \"${II_CANISTER_ID}\" }; derivation_origin = \"${ISSUER_ORIGIN}\"; frontend_hostname = \"${ISSUER_ORIGIN}\" })"
@@ -1,27 +1,39 @@
-use base64::Engine;
-use candid::{Principal, candid_method};
-use ic_canister_sig_creation::CanisterSigPublicKey;
-use ic_canister_sig_creation::signature_map::{CanisterSigInputs, LABEL_SIG, SignatureMap};
-use ic_cdk::api::{canister_self, certified_data_set, time};
-use ic_cdk_macros::{query, update};
-use ic_certification::{Hash, labeled_hash};
+use candid::{CandidType, Deserialize, Principal, candid_method};
+use ic_canister_sig_creation::signature_map::{CanisterSigInputs, SignatureMap};
+use ic_cdk::api::{
+    canister_self, certified_data_set, data_certificate, is_controller, msg_caller, time,
+};
+use ic_cdk::storage::{stable_restore, stable_save};
+use ic_cdk::trap;
+use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
+use ic_certification::Hash;
+use ic_http_certification::{HttpRequest, HttpResponse};
+use ic_verifiable_credentials::environment::{IcRootKey, VcEnvironment};
 use ic_verifiable_credentials::issuer_api::{
     ArgumentValue, CredentialSpec, DerivationOriginData, DerivationOriginError,
     DerivationOriginRequest, GetCredentialRequest, Icrc21ConsentInfo, Icrc21Error,
     Icrc21VcConsentMessageRequest, IssueCredentialError, IssuedCredentialData,
-    PrepareCredentialRequest, PreparedCredentialData,
+    PrepareCredentialRequest, PreparedCredentialData, SignedIdAlias,
+};
+use ic_verifiable_credentials::issuer_keys::{DEFAULT_KEY_GRACE_PERIOD, IssuerKey, IssuerKeyRing};
+use ic_verifiable_credentials::rate_limit::{RateLimitConfig, RateLimitCounters, RateLimiter};
+use ic_verifiable_credentials::status_list::{
+    CertifiedStatusList, CredentialStatus, MIN_STATUS_LIST_SIZE, StatusList, certified_data,
+    status_list_tree,
 };
 use ic_verifiable_credentials::{
-    CredentialParams, VC_SIGNING_INPUT_DOMAIN, build_credential_jwt, did_for_principal,
-    vc_jwt_to_jws, vc_signing_input,
+    CredentialParams, II_ISSUER_URL, VC_SIGNING_INPUT_DOMAIN, build_credential_jwt_with_status,
+    canister_sig_pk_from_vc_signing_input, did_for_principal, vc_signing_input_to_jws,
+    verify_id_alias_credential_jws,
 };
-use lazy_static::lazy_static;
 use serde_bytes::ByteBuf;
-use serde_json::Value;
 use sha2::{Digest, Sha256};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+
+mod openid4vci;
 
 const ISSUER_URL: &str = "https://dummy-issuer.vc";
+const STATUS_LIST_URL: &str = "https://dummy-issuer.vc/status-list";
 const CREDENTIAL_URL_PREFIX: &str = "data:text/plain;charset=UTF-8,";
 const MINUTE_NS: u64 = 60 * 1_000_000_000;
 // The expiration of issued verifiable credentials.
@@ -31,12 +43,119 @@ thread_local! {
     /// Non-stable structures
     // Canister signatures
     static SIGNATURES : RefCell<SignatureMap> = RefCell::new(SignatureMap::default());
+    /// Structures kept in stable memory across upgrades, cf. `StableState`
+    // Configuration of the issuer, together with its validated root key.
+    static CONFIG : RefCell<Option<(IssuerConfig, IcRootKey)>> = const { RefCell::new(None) };
+    // Keys (i.e. seeds) used for signing the credentials, and the number of the next key.
+    static ISSUER_KEYS : RefCell<IssuerKeyRing> = RefCell::new(initial_issuer_keys());
+    static NEXT_KEY_NUMBER : Cell<u64> = const { Cell::new(2) };
+    // Revocation flags of the issued credentials, and the index of the next credential.
+    static STATUS_LIST : RefCell<StatusList> = RefCell::new(StatusList::new(MIN_STATUS_LIST_SIZE));
+    static NEXT_STATUS_INDEX : Cell<usize> = const { Cell::new(0) };
+    // Requests to prepare credentials per caller and id_alias, and per holder via OpenID4VCI.
+    static RATE_LIMITER : RefCell<RateLimiter> = RefCell::new(RateLimiter::default());
+}
+
+/// Configuration of the issuer.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct IssuerConfig {
+    /// Root of trust for checking canister signatures.
+    pub ic_root_key_der: Vec<u8>,
+    /// List of canister ids that are allowed to provide id alias credentials.
+    pub idp_canister_ids: Vec<Principal>,
+    /// The derivation origin to be used by the issuer.
+    pub derivation_origin: String,
+    /// Frontend hostname be used by the issuer.
+    pub frontend_hostname: String,
+}
+
+impl Default for IssuerConfig {
+    fn default() -> Self {
+        let origin = format!("https://{}.icp0.io", canister_self());
+        Self {
+            ic_root_key_der: IcRootKey::mainnet().to_der(),
+            idp_canister_ids: vec![VcEnvironment::mainnet().ii_canister_id],
+            derivation_origin: origin.clone(),
+            frontend_hostname: origin,
+        }
+    }
+}
+
+// The state of the issuer which is saved in stable memory across upgrades.
+#[derive(CandidType, Deserialize)]
+struct StableState {
+    config: IssuerConfig,
+    rate_limit_counters: RateLimitCounters,
+    status_list: StatusList,
+    next_status_index: u64,
+    issuer_keys: IssuerKeyRing,
+    next_key_number: u64,
+}
+
+#[init]
+fn init(config: Option<IssuerConfig>) {
+    apply_config(config.unwrap_or_default());
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    let state = StableState {
+        config: CONFIG
+            .with_borrow(|config| config.as_ref().map(|(config, _)| config.clone()))
+            .unwrap_or_default(),
+        rate_limit_counters: RATE_LIMITER.with_borrow(|limiter| limiter.counters().clone()),
+        status_list: STATUS_LIST.with_borrow(|list| list.clone()),
+        next_status_index: NEXT_STATUS_INDEX.get() as u64,
+        issuer_keys: ISSUER_KEYS.with_borrow(|keys| keys.clone()),
+        next_key_number: NEXT_KEY_NUMBER.get(),
+    };
+    stable_save((state,)).expect("failed saving stable state");
+}
+
+#[post_upgrade]
+fn post_upgrade(config: Option<IssuerConfig>) {
+    let mut saved_config = None;
+    let counters = match stable_restore::<(StableState,)>() {
+        Ok((state,)) => {
+            saved_config = Some(state.config);
+            STATUS_LIST.set(state.status_list);
+            NEXT_STATUS_INDEX.set(state.next_status_index as usize);
+            ISSUER_KEYS.set(state.issuer_keys);
+            NEXT_KEY_NUMBER.set(state.next_key_number);
+            state.rate_limit_counters
+        }
+        // Canisters upgraded from a version which saved only the rate limit counters (or
+        // nothing at all) start with an empty status list and the initial key.
+        Err(_) => stable_restore::<(RateLimitCounters,)>()
+            .map(|(counters,)| counters)
+            .unwrap_or_default(),
+    };
+    RATE_LIMITER.set(RateLimiter::with_counters(
+        RateLimitConfig::default(),
+        counters,
+    ));
+    // The configuration is kept unless a new one is given.
+    apply_config(config.or(saved_config).unwrap_or_default());
+    // The certified data is not preserved across upgrades.
+    update_root_hash();
 }
 
-lazy_static! {
-    // Seed and public key used for signing the credentials.
-    static ref CANISTER_SIG_SEED: Vec<u8> = hash_bytes("DummyIssuer").to_vec();
-    static ref CANISTER_SIG_PK: CanisterSigPublicKey = CanisterSigPublicKey::new(canister_self(), CANISTER_SIG_SEED.clone());
+fn apply_config(config: IssuerConfig) {
+    let root_key = IcRootKey::from_der(&config.ic_root_key_der)
+        .unwrap_or_else(|e| trap(format!("invalid config: {}", e)));
+    CONFIG.set(Some((config, root_key)));
+}
+
+fn key_seed(key_id: &str) -> Vec<u8> {
+    hash_bytes(format!("DummyIssuer#{}", key_id)).to_vec()
+}
+
+fn initial_issuer_keys() -> IssuerKeyRing {
+    let mut issuer_keys = IssuerKeyRing::new();
+    issuer_keys
+        .rotate("key-1", key_seed("key-1"), 0)
+        .expect("failed creating initial key");
+    issuer_keys
 }
 
 fn hash_bytes(value: impl AsRef<[u8]>) -> Hash {
@@ -45,12 +164,36 @@ fn hash_bytes(value: impl AsRef<[u8]>) -> Hash {
     hasher.finalize().into()
 }
 
+// Certifies both the canister signatures and the status list.
+// The status list is certified once the first credential has been prepared.
 fn update_root_hash() {
+    let encoded_list = STATUS_LIST.with_borrow(|list| list.encode());
     SIGNATURES.with_borrow(|sigs| {
-        certified_data_set(&labeled_hash(LABEL_SIG, &sigs.root_hash()));
+        certified_data_set(certified_data(&encoded_list, &sigs.root_hash()));
     })
 }
 
+// Returns the root hash of the certified status list, which is part of the witness
+// of the canister signatures.
+fn status_list_root_hash() -> Hash {
+    STATUS_LIST.with_borrow(|list| status_list_tree(&list.encode()).digest())
+}
+
+// Returns the index in the status list for the next credential, growing the list if all
+// entries have been assigned.
+fn next_status_index() -> Result<usize, IssueCredentialError> {
+    let index = NEXT_STATUS_INDEX.get();
+    STATUS_LIST.with_borrow_mut(|list| {
+        if index >= list.len() {
+            list.grow(MIN_STATUS_LIST_SIZE)
+                .map_err(|e| internal_error(&format!("failed growing status list: {:?}", e)))?;
+        }
+        Ok::<(), IssueCredentialError>(())
+    })?;
+    NEXT_STATUS_INDEX.set(index + 1);
+    Ok(index)
+}
+
 pub fn format_credential_spec(spec: &CredentialSpec) -> String {
     let mut description = format!("# Credential Type\n{}\n", spec.credential_type);
 
@@ -95,54 +238,73 @@ fn internal_error(msg: &str) -> IssueCredentialError {
     IssueCredentialError::Internal(String::from(msg))
 }
 
-/// Decodes a Verifiable Credential JWT and returns the value within `credentialSubject`.
-/// This function doesn't perform any validation or signature verification.
-fn get_alias_from_jwt(jwt_alias: &str) -> Result<Principal, &'static str> {
-    use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
-    let payload = jwt_alias
-        .split('.')
-        .skip(1)
-        .next()
-        .ok_or("Failed to parse JWT")?;
-    let claims: Value = serde_json::from_slice(
-        &BASE64
-            .decode(payload)
-            .map_err(|_| "Failed to decode base64")?,
-    )
-    .map_err(|_| "Failed to parse payload JSON")?;
-    let alias = claims
-        .pointer("/vc/credentialSubject/InternetIdentityIdAlias/hasIdAlias")
-        .ok_or("Failed to extract alias")?
-        .as_str()
-        .ok_or("Invalid value for 'hasIdAlias'")?;
-    Principal::from_text(alias).map_err(|_| "Failed to parse principal")
+/// Verifies the id_alias credential of the caller, which must be signed by one of the
+/// configured identity providers, and returns the id_alias.
+fn verified_id_alias(signed_id_alias: &SignedIdAlias) -> Result<Principal, IssueCredentialError> {
+    let alias_tuple = CONFIG.with_borrow(|config| {
+        let (config, root_key) = config.as_ref().expect("missing config");
+        let mut result = Err(IssueCredentialError::InvalidIdAlias(
+            "no identity providers configured".to_string(),
+        ));
+        for idp_canister_id in &config.idp_canister_ids {
+            result = verify_id_alias_credential_jws(
+                &signed_id_alias.credential_jws,
+                idp_canister_id,
+                // II issues id_alias credentials as `II_ISSUER_URL` on every network.
+                II_ISSUER_URL,
+                root_key,
+                time() as u128,
+            )
+            .map_err(|e| IssueCredentialError::InvalidIdAlias(format!("{:?}", e)));
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    })?;
+    if alias_tuple.id_dapp != msg_caller() {
+        return Err(IssueCredentialError::UnauthorizedSubject(format!(
+            "id_alias credential is not for caller {}",
+            msg_caller()
+        )));
+    }
+    Ok(alias_tuple.id_alias)
 }
 
 fn exp_timestamp_s() -> u32 {
     ((time() + VC_EXPIRATION_PERIOD_NS) / 1_000_000_000) as u32
 }
 
-// Prepares a unique id for the given subject_principal.
+// Prepares a unique id for the given subject (a principal, or the DID of an OpenID4VCI holder).
 // The returned URL has the format: "data:text/plain;charset=UTF-8,issuer:...,timestamp_ns:...,subject:..."
-fn credential_id_for_principal(subject_principal: Principal) -> String {
+fn credential_id_for_subject(subject: &str) -> String {
     let issuer = format!("issuer:{}", ISSUER_URL);
     let timestamp = format!("timestamp_ns:{}", time());
-    let subject = format!("subject:{}", subject_principal.to_text());
+    let subject = format!("subject:{}", subject);
     format!(
         "{}{},{},{}",
         CREDENTIAL_URL_PREFIX, issuer, timestamp, subject
     )
 }
 
-fn verified_credential(subject_principal: Principal, credential_spec: &CredentialSpec) -> String {
+fn verified_credential(
+    subject_id: String,
+    credential_id_url: String,
+    credential_spec: &CredentialSpec,
+    status_index: usize,
+) -> String {
     let params = CredentialParams {
         spec: credential_spec.clone(),
-        subject_id: did_for_principal(subject_principal),
-        credential_id_url: credential_id_for_principal(subject_principal),
+        subject_id,
+        credential_id_url,
         issuer_url: ISSUER_URL.to_string(),
         expiration_timestamp_s: exp_timestamp_s(),
     };
-    build_credential_jwt(params)
+    let status = CredentialStatus {
+        status_list_url: STATUS_LIST_URL.to_string(),
+        index: status_index,
+    };
+    build_credential_jwt_with_status(params, &status)
 }
 
 #[update]
@@ -150,63 +312,143 @@ fn verified_credential(subject_principal: Principal, credential_spec: &Credentia
 async fn prepare_credential(
     req: PrepareCredentialRequest,
 ) -> Result<PreparedCredentialData, IssueCredentialError> {
-    let Ok(id_alias) = get_alias_from_jwt(&req.signed_id_alias.credential_jws) else {
-        return Err(internal_error("Error getting id_alias"));
-    };
-    let credential_jwt = verified_credential(id_alias, &req.credential_spec);
-    let signing_input =
-        vc_signing_input(&credential_jwt, &CANISTER_SIG_PK).expect("failed getting signing_input");
+    // The id_alias is verified first, so that callers cannot exhaust the rate limits of
+    // other id_aliases, nor obtain indices in the status list.
+    let id_alias = verified_id_alias(&req.signed_id_alias)?;
+    // Every prepared credential adds a signature to the certified signature map.
+    RATE_LIMITER.with_borrow_mut(|limiter| {
+        limiter.check_and_record(msg_caller(), id_alias, time() as u128)
+    })?;
+    let credential_jwt = verified_credential(
+        did_for_principal(id_alias),
+        credential_id_for_subject(&id_alias.to_text()),
+        &req.credential_spec,
+        next_status_index()?,
+    );
+    let signing_input = prepare_signature(&credential_jwt)?;
+    // The signing input contains the header with the signing key, which may be rotated
+    // before the credential is obtained via `get_credential`.
+    Ok(PreparedCredentialData {
+        prepared_context: Some(ByteBuf::from(signing_input)),
+    })
+}
+
+// Adds the canister signature of the given credential with the current signing key, and returns
+// the signing input, from which the signed credential is obtained via `signed_credential`.
+fn prepare_signature(credential_jwt: &str) -> Result<Vec<u8>, IssueCredentialError> {
+    let signing_key = ISSUER_KEYS.with_borrow(|keys| keys.signing_key().cloned());
+    let signing_key = signing_key.map_err(|e| internal_error(&format!("{:?}", e)))?;
+    let signing_input = signing_key
+        .vc_signing_input(canister_self(), credential_jwt)
+        .expect("failed getting signing_input");
     let sig_inputs = CanisterSigInputs {
         domain: VC_SIGNING_INPUT_DOMAIN,
         message: &signing_input,
-        seed: &CANISTER_SIG_SEED,
+        seed: &signing_key.seed,
     };
     SIGNATURES.with_borrow_mut(|sigs| sigs.add_signature(&sig_inputs));
     update_root_hash();
-    Ok(PreparedCredentialData {
-        prepared_context: Some(ByteBuf::from(credential_jwt.as_bytes())),
-    })
+    Ok(signing_input)
 }
 
-#[query]
-#[candid_method(query)]
-fn get_credential(req: GetCredentialRequest) -> Result<IssuedCredentialData, IssueCredentialError> {
-    let prepared_context = match req.prepared_context {
-        Some(context) => context,
-        None => {
-            return Result::<IssuedCredentialData, IssueCredentialError>::Err(internal_error(
-                "missing prepared_context",
-            ));
-        }
-    };
-    let credential_jwt = match String::from_utf8(prepared_context.into_vec()) {
-        Ok(s) => s,
-        Err(_) => {
-            return Result::<IssuedCredentialData, IssueCredentialError>::Err(internal_error(
-                "invalid prepared_context",
-            ));
-        }
-    };
-    let signing_input =
-        vc_signing_input(&credential_jwt, &CANISTER_SIG_PK).expect("failed getting signing_input");
+// Returns the signed credential for a signing input returned by `prepare_signature`.
+// Can only be called in query calls, as it requires the data certificate.
+fn signed_credential(signing_input: &[u8]) -> Result<String, IssueCredentialError> {
+    let canister_sig_pk = canister_sig_pk_from_vc_signing_input(signing_input)
+        .map_err(|_| internal_error("invalid prepared_context"))?;
     let sig_inputs = CanisterSigInputs {
         domain: VC_SIGNING_INPUT_DOMAIN,
-        message: &signing_input,
-        seed: &CANISTER_SIG_SEED,
-    };
-    let sig_result = SIGNATURES.with_borrow(|sigs| sigs.get_signature_as_cbor(&sig_inputs, None));
-    let sig = match sig_result {
-        Ok(sig) => sig,
-        Err(e) => {
-            return Result::<IssuedCredentialData, IssueCredentialError>::Err(
-                IssueCredentialError::SignatureNotFound(format!(
-                    "signature not prepared or expired: {}",
-                    e
-                )),
-            );
-        }
+        message: signing_input,
+        seed: &canister_sig_pk.seed,
     };
-    let vc_jws =
-        vc_jwt_to_jws(&credential_jwt, &CANISTER_SIG_PK, &sig).expect("failed constructing JWS");
-    Result::<IssuedCredentialData, IssueCredentialError>::Ok(IssuedCredentialData { vc_jws })
+    let sig = SIGNATURES
+        .with_borrow(|sigs| sigs.get_signature_as_cbor(&sig_inputs, Some(status_list_root_hash())))
+        .map_err(|e| {
+            IssueCredentialError::SignatureNotFound(format!(
+                "signature not prepared or expired: {}",
+                e
+            ))
+        })?;
+    Ok(vc_signing_input_to_jws(signing_input, &sig).expect("failed constructing JWS"))
+}
+
+#[query]
+#[candid_method(query)]
+fn get_credential(req: GetCredentialRequest) -> Result<IssuedCredentialData, IssueCredentialError> {
+    let signing_input = req
+        .prepared_context
+        .ok_or(internal_error("missing prepared_context"))?;
+    let vc_jws = signed_credential(&signing_input)?;
+    Ok(IssuedCredentialData { vc_jws })
+}
+
+/// Returns the status list of the issued credentials together with a data certificate,
+/// so that verifiers can check whether a credential has been revoked.
+#[query]
+#[candid_method(query)]
+fn get_status_list() -> Result<CertifiedStatusList, String> {
+    let certificate = data_certificate().ok_or("no data certificate available")?;
+    let encoded_list = STATUS_LIST.with_borrow(|list| list.encode());
+    let sigs_root_hash = SIGNATURES.with_borrow(|sigs| sigs.root_hash());
+    Ok(CertifiedStatusList::new(
+        encoded_list,
+        certificate,
+        &sigs_root_hash,
+    ))
+}
+
+/// Revokes the credential with the given index in the status list.
+/// Can only be called by the controllers of the issuer.
+#[update]
+#[candid_method]
+fn revoke_credential(status_index: u64) -> Result<(), String> {
+    if !is_controller(&msg_caller()) {
+        return Err(format!("{} is not a controller", msg_caller()));
+    }
+    STATUS_LIST
+        .with_borrow_mut(|list| list.set_revoked(status_index as usize, true))
+        .map_err(|e| format!("failed revoking credential: {:?}", e))?;
+    update_root_hash();
+    Ok(())
+}
+
+/// Returns the signing keys of the issuer, so that verifiers can check the `kid` of
+/// credentials, cf. `verify_credential_jws_with_issuer_keys`.
+#[query]
+#[candid_method(query)]
+fn get_issuer_keys() -> Vec<IssuerKey> {
+    ISSUER_KEYS.with_borrow(|keys| keys.keys().to_vec())
+}
+
+/// Replaces the signing key with a new key, and removes the keys whose grace period
+/// has ended.  Can only be called by the controllers of the issuer.
+#[update]
+#[candid_method]
+fn rotate_signing_key() -> Result<IssuerKey, String> {
+    if !is_controller(&msg_caller()) {
+        return Err(format!("{} is not a controller", msg_caller()));
+    }
+    let key_id = format!("key-{}", NEXT_KEY_NUMBER.get());
+    NEXT_KEY_NUMBER.set(NEXT_KEY_NUMBER.get() + 1);
+    ISSUER_KEYS.with_borrow_mut(|keys| {
+        keys.rotate(&key_id, key_seed(&key_id), time())
+            .map_err(|e| format!("failed rotating key: {:?}", e))?;
+        keys.prune(time(), DEFAULT_KEY_GRACE_PERIOD);
+        keys.signing_key()
+            .cloned()
+            .map_err(|e| format!("failed rotating key: {:?}", e))
+    })
+}
+
+/// Serves the OpenID4VCI endpoints of the issuer, cf. `openid4vci`.
+#[query]
+#[candid_method(query)]
+fn http_request(req: HttpRequest) -> HttpResponse {
+    openid4vci::http_request(req)
+}
+
+#[update]
+#[candid_method]
+async fn http_request_update(req: HttpRequest) -> HttpResponse {
+    openid4vci::http_request_update(req).await
 }
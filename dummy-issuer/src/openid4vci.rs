@@ -0,0 +1,313 @@
+//! OpenID4VCI endpoints of the dummy issuer (cf. `ic_verifiable_credentials::openid4vci`),
+//! served via `http_request` and `http_request_update`.
+//!
+//! Like the VC flow via II, the dummy issuer offers its credentials to anyone, i.e. every
+//! call of the credential offer endpoint returns an offer with a new pre-authorized code.
+use crate::{
+    RATE_LIMITER, credential_id_for_subject, next_status_index, prepare_signature,
+    signed_credential, verified_credential,
+};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use ic_cdk::api::time;
+use ic_http_certification::{HeaderField, HttpRequest, HttpResponse};
+use ic_verifiable_credentials::challenge::ChallengeGenerator;
+use ic_verifiable_credentials::did_resolution::DidKeyResolver;
+use ic_verifiable_credentials::issuer_api::{ArgumentValue, CredentialSpec, IssueCredentialError};
+use ic_verifiable_credentials::openid4vci::{
+    AUTHORIZATION_SERVER_METADATA_PATH, CREDENTIAL_ENDPOINT_PATH, CREDENTIAL_ISSUER_METADATA_PATH,
+    CredentialRequest, CredentialResponse, DEFAULT_ACCESS_TOKEN_TTL,
+    DEFERRED_CREDENTIAL_ENDPOINT_PATH, DeferredCredentialRequest, IssuanceSessions, IssuerManifest,
+    Openid4vciError, TOKEN_ENDPOINT_PATH, TokenRequest, bearer_token,
+};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+/// Path of the endpoint returning a new credential offer.
+pub const CREDENTIAL_OFFER_PATH: &str = "/credential_offer";
+// Minimal time (in seconds) the wallet should wait before fetching a deferred credential.
+const DEFERRED_CREDENTIAL_INTERVAL_S: u64 = 1;
+
+thread_local! {
+    // Pre-authorized codes and access tokens.
+    static SESSIONS : RefCell<IssuanceSessions> = RefCell::new(IssuanceSessions::default());
+    // Source of the pre-authorized codes, access tokens and transaction ids, seeded on first use.
+    static GENERATOR : RefCell<Option<ChallengeGenerator>> = const { RefCell::new(None) };
+    // Credentials prepared at the credential endpoint, by transaction id.
+    static TRANSACTIONS : RefCell<HashMap<String, Transaction>> = RefCell::new(HashMap::new());
+}
+
+struct Transaction {
+    // The access token used to request the credential, which is required to fetch it.
+    access_token: String,
+    signing_input: Vec<u8>,
+    created_at_ns: u64,
+}
+
+#[derive(Deserialize)]
+struct CredentialOfferRequest {
+    credential_configuration_id: String,
+}
+
+fn manifest() -> IssuerManifest {
+    let spec = |credential_type: &str, arguments: Option<(&str, &str)>| {
+        let arguments = arguments.map(|(name, value)| {
+            HashMap::from([(name.to_string(), ArgumentValue::String(value.to_string()))])
+        });
+        (
+            credential_type.to_string(),
+            CredentialSpec {
+                credential_type: credential_type.to_string(),
+                arguments,
+            },
+        )
+    };
+    IssuerManifest {
+        display_name: "Dummy Issuer".to_string(),
+        credential_configurations: BTreeMap::from([
+            spec("VerifiedAdult", None),
+            spec(
+                "VerifiedEmployee",
+                Some(("employerName", "DFINITY Foundation")),
+            ),
+            spec(
+                "UniversityCredential",
+                Some(("institutionName", "DFINITY College of Engineering")),
+            ),
+        ]),
+        binding_methods: vec!["did:key".to_string()],
+    }
+}
+
+pub fn http_request(req: HttpRequest) -> HttpResponse {
+    let path = path(&req.url);
+    let result = match (req.method.as_str(), path) {
+        ("GET", CREDENTIAL_ISSUER_METADATA_PATH) => credential_issuer(&req.headers)
+            .map(|issuer| json_response(200, &manifest().credential_issuer_metadata(&issuer))),
+        ("GET", AUTHORIZATION_SERVER_METADATA_PATH) => credential_issuer(&req.headers)
+            .map(|issuer| json_response(200, &manifest().authorization_server_metadata(&issuer))),
+        ("POST", DEFERRED_CREDENTIAL_ENDPOINT_PATH) => deferred_credential(&req.headers, &req.body),
+        ("POST", CREDENTIAL_OFFER_PATH | TOKEN_ENDPOINT_PATH | CREDENTIAL_ENDPOINT_PATH) => {
+            return HttpResponse {
+                status_code: 200,
+                headers: vec![],
+                body: vec![],
+                upgrade: Some(true),
+            };
+        }
+        _ => Ok(not_found()),
+    };
+    let (status_code, headers, body) = result.unwrap_or_else(|e| error_response(&e));
+    HttpResponse {
+        status_code,
+        headers,
+        body,
+        upgrade: None,
+    }
+}
+
+pub async fn http_request_update(req: HttpRequest) -> HttpResponse {
+    let path = path(&req.url);
+    let result = match (req.method.as_str(), path) {
+        ("POST", CREDENTIAL_OFFER_PATH) => credential_offer(&req.headers, &req.body).await,
+        ("POST", TOKEN_ENDPOINT_PATH) => token(&req.body).await,
+        ("POST", CREDENTIAL_ENDPOINT_PATH) => credential(&req.headers, &req.body).await,
+        _ => Ok(not_found()),
+    };
+    let (status_code, headers, body) = result.unwrap_or_else(|e| error_response(&e));
+    HttpResponse {
+        status_code,
+        headers,
+        body,
+        upgrade: None,
+    }
+}
+
+type Response = (u16, Vec<HeaderField>, Vec<u8>);
+
+async fn credential_offer(
+    headers: &[HeaderField],
+    body: &[u8],
+) -> Result<Response, Openid4vciError> {
+    let issuer = credential_issuer(headers)?;
+    let request: CredentialOfferRequest = parse_json(body)?;
+    let offer = with_generator(|generator| {
+        SESSIONS.with_borrow_mut(|sessions| {
+            sessions.create_offer(
+                &manifest(),
+                &issuer,
+                &request.credential_configuration_id,
+                generator,
+                time() as u128,
+            )
+        })
+    })
+    .await??;
+    Ok(json_response(200, &offer))
+}
+
+async fn token(body: &[u8]) -> Result<Response, Openid4vciError> {
+    let request = TokenRequest::from_form_urlencoded(body)?;
+    let token = with_generator(|generator| {
+        SESSIONS.with_borrow_mut(|sessions| {
+            sessions.exchange_pre_authorized_code(&request, generator, time() as u128)
+        })
+    })
+    .await??;
+    Ok(json_response(200, &token))
+}
+
+// Prepares the signature of the credential, which is returned at the deferred credential endpoint.
+async fn credential(headers: &[HeaderField], body: &[u8]) -> Result<Response, Openid4vciError> {
+    let issuer = credential_issuer(headers)?;
+    let access_token = bearer_token(header(headers, "authorization").unwrap_or_default())?;
+    let request: CredentialRequest = parse_json(body)?;
+    let transaction_id = with_generator(|generator| BASE64.encode(generator.next_bytes())).await?;
+    let authorized = SESSIONS.with_borrow_mut(|sessions| {
+        sessions.verify_credential_request(
+            access_token,
+            &request,
+            &issuer,
+            &DidKeyResolver,
+            time() as u128,
+        )
+    })?;
+    // Every prepared credential adds a signature to the certified signature map.  The calls
+    // of the endpoint are anonymous, so they are limited per holder.  Rate-limited requests
+    // do not consume the access token, so they can be retried.
+    RATE_LIMITER.with_borrow_mut(|limiter| {
+        limiter.check_and_record_holder(&authorized.holder_did, time() as u128)
+    })?;
+    SESSIONS
+        .with_borrow_mut(|sessions| sessions.consume_access_token(access_token, time() as u128))?;
+    let manifest = manifest();
+    let spec = manifest.credential_spec(&authorized.credential_configuration_id)?;
+    let status_index = next_status_index().map_err(issuance_error)?;
+    let credential_jwt = verified_credential(
+        authorized.holder_did.clone(),
+        credential_id_for_subject(&authorized.holder_did),
+        spec,
+        status_index,
+    );
+    let signing_input = prepare_signature(&credential_jwt).map_err(issuance_error)?;
+    TRANSACTIONS.with_borrow_mut(|transactions| {
+        // Signatures expire, so older transactions can no longer be completed.
+        transactions.retain(|_, transaction| {
+            transaction.created_at_ns + DEFAULT_ACCESS_TOKEN_TTL.as_nanos() as u64 > time()
+        });
+        transactions.insert(
+            transaction_id.clone(),
+            Transaction {
+                access_token: access_token.to_string(),
+                signing_input,
+                created_at_ns: time(),
+            },
+        );
+    });
+    Ok(json_response(
+        202,
+        &CredentialResponse::deferred(transaction_id, DEFERRED_CREDENTIAL_INTERVAL_S),
+    ))
+}
+
+// Returns the credential of a transaction of the credential endpoint, with the canister signature,
+// which is only available in query calls.
+fn deferred_credential(headers: &[HeaderField], body: &[u8]) -> Result<Response, Openid4vciError> {
+    let access_token = bearer_token(header(headers, "authorization").unwrap_or_default())?;
+    let request: DeferredCredentialRequest = parse_json(body)?;
+    let signing_input = TRANSACTIONS.with_borrow(|transactions| {
+        match transactions.get(&request.transaction_id) {
+            Some(transaction) if transaction.access_token == access_token => {
+                Ok(transaction.signing_input.clone())
+            }
+            _ => Err(Openid4vciError::InvalidTransactionId(format!(
+                "unknown transaction {}",
+                request.transaction_id
+            ))),
+        }
+    })?;
+    let credential_jws = signed_credential(&signing_input).map_err(|e| match e {
+        IssueCredentialError::SignatureNotFound(message) => {
+            Openid4vciError::InvalidTransactionId(message)
+        }
+        e => issuance_error(e),
+    })?;
+    Ok(json_response(
+        200,
+        &CredentialResponse::issued(credential_jws),
+    ))
+}
+
+// Runs `f` with the generator of random values, which is seeded via `raw_rand` on first use.
+async fn with_generator<T>(
+    f: impl FnOnce(&mut ChallengeGenerator) -> T,
+) -> Result<T, Openid4vciError> {
+    if GENERATOR.with_borrow(|generator| generator.is_none()) {
+        let seeded = ChallengeGenerator::from_raw_rand().await.map_err(|e| {
+            Openid4vciError::ServerError(format!("failed seeding generator: {}", e))
+        })?;
+        GENERATOR.with_borrow_mut(|generator| {
+            generator.get_or_insert(seeded);
+        });
+    }
+    Ok(GENERATOR.with_borrow_mut(|generator| f(generator.as_mut().expect("generator is seeded"))))
+}
+
+// The credential issuer identifier is the origin under which the canister is reached, which is
+// served via HTTP only for local deployments.
+fn credential_issuer(headers: &[HeaderField]) -> Result<String, Openid4vciError> {
+    let host = header(headers, "host").ok_or(Openid4vciError::InvalidRequest(
+        "missing host header".to_string(),
+    ))?;
+    let hostname = host.split(':').next().unwrap_or_default();
+    let scheme =
+        if hostname == "localhost" || hostname.ends_with(".localhost") || hostname == "127.0.0.1" {
+            "http"
+        } else {
+            "https"
+        };
+    Ok(format!("{}://{}", scheme, host))
+}
+
+fn header<'a>(headers: &'a [HeaderField], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+fn path(url: &str) -> &str {
+    url.split(['?', '#']).next().unwrap_or_default()
+}
+
+fn parse_json<T: for<'de> Deserialize<'de>>(body: &[u8]) -> Result<T, Openid4vciError> {
+    serde_json::from_slice(body)
+        .map_err(|e| Openid4vciError::InvalidRequest(format!("invalid JSON body: {}", e)))
+}
+
+fn issuance_error(e: IssueCredentialError) -> Openid4vciError {
+    Openid4vciError::ServerError(format!("failed issuing credential: {:?}", e))
+}
+
+fn json_response(status_code: u16, body: &impl Serialize) -> Response {
+    let headers = vec![
+        ("content-type".to_string(), "application/json".to_string()),
+        ("cache-control".to_string(), "no-store".to_string()),
+    ];
+    let body = serde_json::to_vec(body).expect("failed serializing response");
+    (status_code, headers, body)
+}
+
+fn error_response(error: &Openid4vciError) -> Response {
+    let (status_code, mut headers, body) =
+        json_response(error.status_code(), &error.to_error_response());
+    if let Openid4vciError::RateLimited { retry_after_s } = error {
+        headers.push(("retry-after".to_string(), retry_after_s.to_string()));
+    }
+    (status_code, headers, body)
+}
+
+fn not_found() -> Response {
+    (404, vec![], b"Not found".to_vec())
+}
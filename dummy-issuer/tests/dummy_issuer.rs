@@ -1,157 +1,113 @@
 use base64::Engine;
 use std::collections::HashMap;
 
-use candid::{CandidType, Deserialize, Principal, decode_one, encode_one};
+use candid::{CandidType, Deserialize, Principal, encode_args};
 use ic_verifiable_credentials::issuer_api::{
-    ArgumentValue, CredentialSpec, DerivationOriginData, DerivationOriginError,
-    DerivationOriginRequest, GetCredentialRequest, Icrc21ConsentInfo, Icrc21ConsentPreferences,
-    Icrc21Error, Icrc21VcConsentMessageRequest, PrepareCredentialRequest, SignedIdAlias,
+    ArgumentValue, CredentialSpec, DerivationOriginData, DerivationOriginRequest,
+    GetCredentialRequest, Icrc21ConsentInfo, Icrc21ConsentPreferences,
+    Icrc21VcConsentMessageRequest, IssueCredentialError, PrepareCredentialRequest,
+    PreparedCredentialData, SignedIdAlias,
+};
+use ic_verifiable_credentials::issuer_keys::{DEFAULT_KEY_GRACE_PERIOD, IssuerKey, IssuerKeyRing};
+use ic_verifiable_credentials::openid4vci::{
+    CredentialIssuerMetadata, CredentialOffer, CredentialResponse, ErrorResponse, TokenResponse,
+};
+use ic_verifiable_credentials::rate_limit::{
+    DEFAULT_MAX_REQUESTS_PER_HOLDER, DEFAULT_MAX_REQUESTS_PER_ID_ALIAS, DEFAULT_RATE_LIMIT_WINDOW,
+};
+use ic_verifiable_credentials::status_list::{
+    CertifiedStatusList, DEFAULT_MAX_STATUS_LIST_AGE, StatusListError,
+};
+use ic_verifiable_credentials::test_utils::TestThresholdSigner;
+use ic_verifiable_credentials::threshold_signing::ThresholdAlgorithm;
+use ic_verifiable_credentials::{
+    PresentationVerificationError, VerificationOptions,
+    validate_ii_presentation_and_claims_with_options, verify_credential_jws_with_canister_id,
+};
+use serde_json::{Value, json};
+use std::time::Duration;
+use vc_test_harness::assertions::{
+    assert_invalid_id_alias, assert_rate_limited, assert_unauthorized_subject,
+};
+use vc_test_harness::http_gateway::{HttpRequest, HttpResponse, http_request};
+use vc_test_harness::idp_api::{self, GetIdAliasRequest, PrepareIdAliasRequest};
+use vc_test_harness::{
+    CanisterCall, VcFlowRequest, VcFlowResult, VcTestEnv, call_canister, issuer_api as api,
 };
-use pocket_ic::{PocketIc, WasmResult};
-use serde::de::DeserializeOwned;
-use serde_json::Value;
 
 const DUMMY_ISSUER_WASM: &[u8] = include_bytes!("../dummy_issuer.wasm.gz");
+const DUMMY_IDP_WASM: &[u8] =
+    include_bytes!("../../dummy-identity-provider/dummy_identity_provider.wasm.gz");
+const ISSUER_URL: &str = "https://dummy-issuer.vc";
+const STATUS_LIST_URL: &str = "https://dummy-issuer.vc/status-list";
 const ID_ALIAS_JWT: &str = "eyJqd2siOnsia3R5Ijoib2N0IiwiYWxnIjoiSWNDcyIsImsiOiJNRHd3REFZS0t3WUJCQUdEdUVNQkFnTXNBQXFBQUFBQUFCQUFGZ0VCVko4aGgwR2xBTmFMdUtRVGNZWTlwa01WVFhPLTMzaEctY0tyaHVkaTZ3cyJ9LCJraWQiOiJkaWQ6aWNwOmNwbWNyLXllYWFhLWFhYWFhLXFhYWxhLWNhaSIsImFsZyI6IkljQ3MifQ.eyJleHAiOjE3MTc1MDAwOTcsImlzcyI6Imh0dHBzOi8vaWRlbnRpdHkuaWMwLmFwcC8iLCJuYmYiOjE3MTc0OTkxOTcsImp0aSI6ImRhdGE6dGV4dC9wbGFpbjtjaGFyc2V0PVVURi04LHRpbWVzdGFtcF9uczoxNzE3NDk5MTk3NjkxMjUzMDAwLGFsaWFzX2hhc2g6ZWJjOThmYTk2NDFlZGIwYTY3ZGEwYjBkZjExZDIyZjVjNDRjYTNlNWI2OWM5MTA0NTA4M2FkNzY5NmNmMjQ4NSIsInN1YiI6ImRpZDppY3A6MmRyN2ItZHkyN28tYXQzbDQtdGlra2otamdmNWYtYjRxb2gtbzNpcWQtcWdmN2ktYnhpeWUtenpmaWUtbGFlIiwidmMiOnsiQGNvbnRleHQiOiJodHRwczovL3d3dy53My5vcmcvMjAxOC9jcmVkZW50aWFscy92MSIsInR5cGUiOlsiVmVyaWZpYWJsZUNyZWRlbnRpYWwiLCJJbnRlcm5ldElkZW50aXR5SWRBbGlhcyJdLCJjcmVkZW50aWFsU3ViamVjdCI6eyJJbnRlcm5ldElkZW50aXR5SWRBbGlhcyI6eyJoYXNJZEFsaWFzIjoieHA3bWYtaWR6eTYtaHMzM2cteGc2Z3ota25henktdG8yM3EtdHVnZTYtN2JoNmEtanp0bXctYjNwd3QtZWFlIn19fX0.2dn3omtjZXJ0aWZpY2F0ZVkB19nZ96JkdHJlZYMBgwGDAYMCSGNhbmlzdGVygwGCBFggWoRx2PbCEeN0ixn7e-UirzJAHQY9r9kyhb3SnPxBP4uDAYIEWCBMONzHAnK0jVuK997XJV_6hFZbaBWN0KTUmlYR3WWXuoMBggRYIDi4Wsz22ukd8m0kIdYCk9K2rg70THv5w85DEDpYdZIDgwJKgAAAAAAQABYBAYMBgwGDAk5jZXJ0aWZpZWRfZGF0YYIDWCA_rx5TB6eC52CeEdXy4s34iY3s2EASfBqBFcPS9fH3uYIEWCBatD7fWrBBUJYAaHUYRNPKsGB2BCCknoh1Rkwqf-_CaoIEWCAcUK9eLRSw46lWjStyyRFOKRFUS7OBv0QxoMpeALgGdoIEWCAK7Ec4DvTqpmpE9JHYsT8FHSrfNiKVnu3yVlMU-6KxIYIEWCCQkT1Z3skTRzJUOWzrPTf_sBu5aZ6qr88jo8smnm6f_YMBggRYIEMI93i492dWsJprkB2UAvBYtBIysPetVVgxHc4T-hWDgwJEdGltZYIDScDeppy9jfLqF2lzaWduYXR1cmVYMKd5pfn-heKQin4SIIfx8m0q7zYdhEHYVIxuYOBaAF3-ufINwggmfZ1Zksa22lTCRWR0cmVlgwGCBFgglmDq7rrtAl5ZQOMxkfGbeb5IVvzUoR--PM8Xn7FF7SCDAkNzaWeDAYIEWCCDC09GDBV0Srb1Wq3RvbhIEva9o85g64EBa50fPSKTN4MCWCDY4NRNLSFMY8yUHhJMPqTKnNY9KWJdPlHJyeuTexz8HYMBgwJYIBoUAasHcdl_6m08nzzRfhIlxxAp3PNHf9xhI3E9wIkmggNAggRYIOkldmMiQ8kGhCHGzH6xlfCbGo7cpFVzoEJVpg204zCc";
 
-#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
-pub enum VariantResponse<T, E> {
-    Ok(T),
-    Err(E),
-}
+const RP_ORIGIN: &str = "https://relying-party.vc";
 
-fn install_issuer_canister(pic: &PocketIc) -> Principal {
-    // Create an empty canister as the anonymous principal and add cycles.
-    let canister_id = pic.create_canister();
-    pic.add_cycles(canister_id, 2_000_000_000_000);
-    pic.install_canister(canister_id, DUMMY_ISSUER_WASM.to_vec(), vec![], None);
-    canister_id
+#[derive(Clone, Debug, CandidType, Deserialize)]
+struct IssuerConfig {
+    ic_root_key_der: Vec<u8>,
+    idp_canister_ids: Vec<Principal>,
+    derivation_origin: String,
+    frontend_hostname: String,
 }
 
-enum CanisterCall {
-    Query,
-    Update,
-}
-
-mod api {
-    use ic_cdk::management_canister::CanisterId;
-    use ic_verifiable_credentials::issuer_api::{
-        GetCredentialRequest, IssueCredentialError, IssuedCredentialData, PrepareCredentialRequest,
-        PreparedCredentialData,
+/// Installs the dummy identity provider and the issuer, which accepts the id_alias credentials
+/// of the dummy identity provider.
+fn install_issuer_canister(env: &mut VcTestEnv) -> Principal {
+    let idp = env.install_identity_provider(DUMMY_IDP_WASM);
+    let config = IssuerConfig {
+        ic_root_key_der: env.root_key_der(),
+        idp_canister_ids: vec![idp],
+        derivation_origin: ISSUER_URL.to_string(),
+        frontend_hostname: ISSUER_URL.to_string(),
     };
+    env.install_issuer(DUMMY_ISSUER_WASM, encode_args((Some(config),)).unwrap())
+}
 
-    use super::*;
-
-    fn call_canister<'a, Req, Succ, Err>(
-        pic: &PocketIc,
-        method: &str,
-        call_type: CanisterCall,
-        canister_id: CanisterId,
-        request: Req,
-        sender: Option<Principal>,
-    ) -> Result<Succ, Err>
-    where
-        Req: CandidType,
-        Succ: CandidType + DeserializeOwned,
-        Err: CandidType + DeserializeOwned,
-    {
-        let consent_message_reply = match call_type {
-            CanisterCall::Query => pic
-                .query_call(
-                    canister_id,
-                    sender.unwrap_or(Principal::anonymous()),
-                    method,
-                    encode_one(request).unwrap(),
-                )
-                .expect("Error calling canister"),
-            CanisterCall::Update => pic
-                .update_call(
-                    canister_id,
-                    sender.unwrap_or(Principal::anonymous()),
-                    method,
-                    encode_one(request).unwrap(),
-                )
-                .expect("Error calling canister"),
-        };
-        let WasmResult::Reply(reply) = consent_message_reply else {
-            unreachable!()
-        };
-        let response: VariantResponse<Succ, Err> = decode_one(&reply).unwrap();
-        match response {
-            VariantResponse::Ok(success) => Ok(success),
-            VariantResponse::Err(err) => Err(err),
-        }
-    }
-
-    pub fn consent_message(
-        pic: &PocketIc,
-        canister_id: CanisterId,
-        request: Icrc21VcConsentMessageRequest,
-        sender: Option<Principal>,
-    ) -> Result<Icrc21ConsentInfo, Icrc21Error> {
-        call_canister::<Icrc21VcConsentMessageRequest, Icrc21ConsentInfo, Icrc21Error>(
-            pic,
-            "vc_consent_message",
-            CanisterCall::Update,
-            canister_id,
-            request,
-            sender,
-        )
-    }
-
-    pub fn derivation_origin(
-        pic: &PocketIc,
-        canister_id: CanisterId,
-        request: DerivationOriginRequest,
-        sender: Option<Principal>,
-    ) -> Result<DerivationOriginData, DerivationOriginError> {
-        call_canister::<DerivationOriginRequest, DerivationOriginData, DerivationOriginError>(
-            pic,
-            "derivation_origin",
-            CanisterCall::Update,
-            canister_id,
-            request,
-            sender,
-        )
-    }
-
-    pub fn prepare_credential(
-        pic: &PocketIc,
-        canister_id: CanisterId,
-        request: PrepareCredentialRequest,
-        sender: Option<Principal>,
-    ) -> Result<PreparedCredentialData, IssueCredentialError> {
-        call_canister::<PrepareCredentialRequest, PreparedCredentialData, IssueCredentialError>(
-            pic,
-            "prepare_credential",
-            CanisterCall::Update,
-            canister_id,
-            request,
-            sender,
+/// Upgrades the issuer without init arguments, i.e. keeping its configuration.
+fn upgrade_issuer_canister(env: &VcTestEnv) {
+    env.pic
+        .upgrade_canister(
+            env.issuer_id(),
+            DUMMY_ISSUER_WASM.to_vec(),
+            encode_args((None::<IssuerConfig>,)).unwrap(),
+            None,
         )
-    }
+        .expect("failed upgrading issuer");
+}
 
-    pub fn get_credential(
-        pic: &PocketIc,
-        canister_id: CanisterId,
-        request: GetCredentialRequest,
-        sender: Option<Principal>,
-    ) -> Result<IssuedCredentialData, IssueCredentialError> {
-        call_canister::<GetCredentialRequest, IssuedCredentialData, IssueCredentialError>(
-            pic,
-            "get_credential",
-            CanisterCall::Query,
-            canister_id,
-            request,
-            sender,
-        )
+/// Obtains the id_alias credential of `user` for the issuer from the dummy identity provider.
+fn signed_id_alias(env: &VcTestEnv, user: Principal) -> SignedIdAlias {
+    let prepared = idp_api::prepare_id_alias(
+        &env.pic,
+        env.idp_id(),
+        PrepareIdAliasRequest {
+            relying_party: RP_ORIGIN.to_string(),
+            issuer: ISSUER_URL.to_string(),
+        },
+        Some(user),
+    )
+    .expect("failed preparing id_alias");
+    let credentials = idp_api::get_id_alias(
+        &env.pic,
+        env.idp_id(),
+        GetIdAliasRequest {
+            rp_id_alias_jwt: prepared.rp_id_alias_jwt,
+            issuer_id_alias_jwt: prepared.issuer_id_alias_jwt,
+        },
+        Some(user),
+    )
+    .expect("failed getting id_alias");
+    SignedIdAlias {
+        credential_jws: credentials.issuer_id_alias_credential.credential_jws,
     }
 }
 
 #[test]
 fn test_consent_message() {
-    let pic = PocketIc::new();
-    let canister_id = install_issuer_canister(&pic);
+    let mut env = VcTestEnv::new();
+    let canister_id = install_issuer_canister(&mut env);
 
     let request = Icrc21VcConsentMessageRequest {
         credential_spec: CredentialSpec {
@@ -163,7 +119,7 @@ fn test_consent_message() {
         },
     };
 
-    let response = api::consent_message(&pic, canister_id, request, None);
+    let response = api::vc_consent_message(&env.pic, canister_id, request, None);
     match response {
         Ok(Icrc21ConsentInfo {
             consent_message,
@@ -180,15 +136,15 @@ fn test_consent_message() {
 
 #[test]
 fn test_derivation_origin() {
-    let pic = PocketIc::new();
-    let canister_id = install_issuer_canister(&pic);
+    let mut env = VcTestEnv::new();
+    let canister_id = install_issuer_canister(&mut env);
 
     let request_fe_hostname = "http://demo-issuer.vc".to_string();
     let request = DerivationOriginRequest {
         frontend_hostname: request_fe_hostname.clone(),
     };
 
-    let response = api::derivation_origin(&pic, canister_id, request, None);
+    let response = api::derivation_origin(&env.pic, canister_id, request, None);
     match response {
         Ok(DerivationOriginData { origin }) => {
             assert_eq!(origin, request_fe_hostname);
@@ -215,10 +171,149 @@ fn get_credential_subject_from_jwt(jwt_vc: String) -> String {
         .to_string()
 }
 
+/// Decodes a Verifiable Credential JWT and returns its index in the status list of the issuer.
+/// This function doesn't perform any validation or signature verification.
+fn get_status_index_from_jwt(jwt_vc: &str) -> u64 {
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+    let payload = jwt_vc.split('.').nth(1).expect("Failed to parse JWT");
+    let claims: Value =
+        serde_json::from_slice(&BASE64.decode(payload).expect("Failed to decode base64"))
+            .expect("Failed to parse JSON");
+    claims
+        .pointer("/vc/credentialStatus/statusListIndex")
+        .and_then(Value::as_str)
+        .expect("Failed to extract statusListIndex")
+        .parse()
+        .expect("Failed to parse statusListIndex")
+}
+
+/// Decodes the header of a JWS and returns the value of `kid`.
+fn get_kid_from_jws(jws: &str) -> String {
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+    let header = jws.split('.').next().expect("Failed to parse JWS");
+    let header: Value =
+        serde_json::from_slice(&BASE64.decode(header).expect("Failed to decode base64"))
+            .expect("Failed to parse JSON");
+    header["kid"]
+        .as_str()
+        .expect("Failed to extract kid")
+        .to_string()
+}
+
+fn get_issuer_keys(env: &VcTestEnv) -> Vec<IssuerKey> {
+    let reply = env
+        .pic
+        .query_call(
+            env.issuer_id(),
+            Principal::anonymous(),
+            "get_issuer_keys",
+            candid::encode_args(()).unwrap(),
+        )
+        .expect("Error calling canister");
+    let pocket_ic::WasmResult::Reply(reply) = reply else {
+        unreachable!()
+    };
+    candid::decode_one(&reply).unwrap()
+}
+
+fn rotate_signing_key(env: &VcTestEnv, sender: Option<Principal>) -> Result<IssuerKey, String> {
+    call_canister(
+        &env.pic,
+        "rotate_signing_key",
+        CanisterCall::Update,
+        env.issuer_id(),
+        (),
+        sender,
+    )
+}
+
+fn validate_presentation_with_issuer_keys(
+    env: &VcTestEnv,
+    user: Principal,
+    request: &VcFlowRequest,
+    result: &VcFlowResult,
+    grace_period: Duration,
+) -> Result<(), PresentationVerificationError> {
+    let issuer_keys = IssuerKeyRing::from_keys(get_issuer_keys(env)).expect("invalid issuer keys");
+    validate_ii_presentation_and_claims_with_options(
+        &result.vp_jwt,
+        user,
+        &request.relying_party,
+        &env.vc_flow_signers(ISSUER_URL),
+        &request.credential_spec,
+        &VerificationOptions::default().with_issuer_keys(&issuer_keys, grace_period),
+        &env.ic_root_key(),
+        env.time_ns(),
+    )
+}
+
+fn get_status_list(env: &VcTestEnv) -> CertifiedStatusList {
+    call_canister::<_, _, String>(
+        &env.pic,
+        "get_status_list",
+        CanisterCall::Query,
+        env.issuer_id(),
+        (),
+        None,
+    )
+    .expect("failed getting status list")
+}
+
+fn revoke_credential(
+    env: &VcTestEnv,
+    status_index: u64,
+    sender: Option<Principal>,
+) -> Result<(), String> {
+    call_canister(
+        &env.pic,
+        "revoke_credential",
+        CanisterCall::Update,
+        env.issuer_id(),
+        status_index,
+        sender,
+    )
+}
+
+fn run_verified_adult_flow(env: &VcTestEnv, user: Principal) -> (VcFlowRequest, VcFlowResult) {
+    let request = VcFlowRequest {
+        relying_party: "https://relying-party.vc".to_string(),
+        issuer_frontend_hostname: "https://demo-issuer.vc".to_string(),
+        credential_spec: CredentialSpec {
+            credential_type: "VerifiedAdult".to_string(),
+            arguments: None,
+        },
+    };
+    let result = env.run_vc_flow(user, &request).expect("VC flow failed");
+    (request, result)
+}
+
+fn validate_presentation_with_status(
+    env: &VcTestEnv,
+    user: Principal,
+    request: &VcFlowRequest,
+    result: &VcFlowResult,
+) -> Result<(), PresentationVerificationError> {
+    let status_list = get_status_list(env);
+    validate_ii_presentation_and_claims_with_options(
+        &result.vp_jwt,
+        user,
+        &request.relying_party,
+        &env.vc_flow_signers(ISSUER_URL),
+        &request.credential_spec,
+        &VerificationOptions::default().with_status_list(
+            STATUS_LIST_URL,
+            &status_list,
+            DEFAULT_MAX_STATUS_LIST_AGE,
+        ),
+        &env.ic_root_key(),
+        env.time_ns(),
+    )
+}
+
 #[test]
 fn should_issue_any_credential() {
-    let pic = PocketIc::new();
-    let issuer_canister_id = install_issuer_canister(&pic);
+    let mut env = VcTestEnv::new();
+    let issuer_canister_id = install_issuer_canister(&mut env);
     let credential_type = "VerifiedAge".to_string();
     let mut args = HashMap::new();
     args.insert("ageAtLeast".to_string(), ArgumentValue::Int(18));
@@ -226,29 +321,630 @@ fn should_issue_any_credential() {
         credential_type: credential_type.clone(),
         arguments: Some(args),
     };
-    let signed_id_alias = SignedIdAlias {
-        credential_jws: ID_ALIAS_JWT.to_string(),
-    };
+    let user = Principal::self_authenticating([1]);
+    let signed_id_alias = signed_id_alias(&env, user);
     let prepare_credential_request = PrepareCredentialRequest {
-        signed_id_alias: SignedIdAlias {
-            credential_jws: ID_ALIAS_JWT.to_string(),
-        },
+        signed_id_alias: signed_id_alias.clone(),
         credential_spec: credential_spec.clone(),
     };
 
-    let prepared_context_response =
-        api::prepare_credential(&pic, issuer_canister_id, prepare_credential_request, None)
-            .unwrap();
+    let prepared_context_response = api::prepare_credential(
+        &env.pic,
+        issuer_canister_id,
+        prepare_credential_request,
+        Some(user),
+    )
+    .unwrap();
 
     let get_credential_request = GetCredentialRequest {
         signed_id_alias,
         credential_spec: credential_spec.clone(),
         prepared_context: prepared_context_response.prepared_context,
     };
-    let get_credential_response =
-        api::get_credential(&pic, issuer_canister_id, get_credential_request, None).unwrap();
+    let get_credential_response = api::get_credential(
+        &env.pic,
+        issuer_canister_id,
+        get_credential_request,
+        Some(user),
+    )
+    .unwrap();
 
     let vc_jwt = get_credential_subject_from_jwt(get_credential_response.vc_jws);
 
     assert_eq!(vc_jwt, "{\"VerifiedAge\":{\"ageAtLeast\":18}}");
 }
+
+#[test]
+fn should_issue_credential_verifiable_by_relying_party() {
+    let mut env = VcTestEnv::new();
+    install_issuer_canister(&mut env);
+    let user = Principal::self_authenticating([1]);
+
+    let (request, result) = run_verified_adult_flow(&env, user);
+
+    assert_eq!(result.issuer_derivation_origin, "https://demo-issuer.vc");
+    env.validate_presentation(
+        &result.vp_jwt,
+        user,
+        &request.relying_party,
+        ISSUER_URL,
+        &request.credential_spec,
+    )
+    .expect("invalid presentation");
+}
+
+#[test]
+fn should_assign_distinct_status_indices() {
+    let mut env = VcTestEnv::new();
+    install_issuer_canister(&mut env);
+
+    let (_, first) = run_verified_adult_flow(&env, Principal::self_authenticating([1]));
+    let (_, second) = run_verified_adult_flow(&env, Principal::self_authenticating([2]));
+
+    assert_eq!(get_status_index_from_jwt(&first.vc_jws), 0);
+    assert_eq!(get_status_index_from_jwt(&second.vc_jws), 1);
+}
+
+#[test]
+fn should_reject_revoked_credential() {
+    let mut env = VcTestEnv::new();
+    install_issuer_canister(&mut env);
+    let user = Principal::self_authenticating([1]);
+    let (request, result) = run_verified_adult_flow(&env, user);
+    let (other_request, other_result) =
+        run_verified_adult_flow(&env, Principal::self_authenticating([2]));
+    validate_presentation_with_status(&env, user, &request, &result)
+        .expect("invalid presentation before revocation");
+
+    let status_index = get_status_index_from_jwt(&result.vc_jws);
+    revoke_credential(&env, status_index, None).expect("failed revoking credential");
+
+    let validation = validate_presentation_with_status(&env, user, &request, &result);
+    assert!(matches!(
+        validation,
+        Err(PresentationVerificationError::InvalidCredentialStatus(
+            StatusListError::Revoked { index }
+        )) if index as u64 == status_index
+    ));
+    // Other credentials of the issuer are not affected by the revocation.
+    validate_presentation_with_status(
+        &env,
+        Principal::self_authenticating([2]),
+        &other_request,
+        &other_result,
+    )
+    .expect("invalid presentation of other credential");
+}
+
+#[test]
+fn should_reject_revoked_credential_after_upgrade() {
+    let mut env = VcTestEnv::new();
+    install_issuer_canister(&mut env);
+    let user = Principal::self_authenticating([1]);
+    let (request, result) = run_verified_adult_flow(&env, user);
+    let status_index = get_status_index_from_jwt(&result.vc_jws);
+    revoke_credential(&env, status_index, None).expect("failed revoking credential");
+
+    upgrade_issuer_canister(&env);
+
+    let validation = validate_presentation_with_status(&env, user, &request, &result);
+    assert!(matches!(
+        validation,
+        Err(PresentationVerificationError::InvalidCredentialStatus(
+            StatusListError::Revoked { index }
+        )) if index as u64 == status_index
+    ));
+    // Credentials issued after the upgrade get new status indices.
+    let (_, new_result) = run_verified_adult_flow(&env, Principal::self_authenticating([2]));
+    assert_eq!(
+        get_status_index_from_jwt(&new_result.vc_jws),
+        status_index + 1
+    );
+}
+
+#[test]
+fn should_only_allow_controllers_to_revoke_credentials() {
+    let mut env = VcTestEnv::new();
+    install_issuer_canister(&mut env);
+    let user = Principal::self_authenticating([1]);
+
+    let result = revoke_credential(&env, 0, Some(user));
+
+    assert_eq!(result, Err(format!("{} is not a controller", user)));
+}
+
+#[test]
+fn should_reject_revocation_out_of_range() {
+    let mut env = VcTestEnv::new();
+    install_issuer_canister(&mut env);
+
+    let result = revoke_credential(&env, u64::MAX / 2, None);
+
+    assert!(matches!(result, Err(e) if e.contains("IndexOutOfRange")));
+}
+
+#[test]
+fn should_sign_credentials_with_rotated_key() {
+    let mut env = VcTestEnv::new();
+    let issuer = install_issuer_canister(&mut env);
+    let old_user = Principal::self_authenticating([1]);
+    let new_user = Principal::self_authenticating([2]);
+    let (old_request, old_result) = run_verified_adult_flow(&env, old_user);
+
+    let new_key = rotate_signing_key(&env, None).expect("failed rotating key");
+    let (new_request, new_result) = run_verified_adult_flow(&env, new_user);
+
+    let did = format!("did:icp:{}", issuer);
+    assert_eq!(new_key.key_id, "key-2");
+    assert_eq!(
+        get_kid_from_jws(&old_result.vc_jws),
+        format!("{}#key-1", did)
+    );
+    assert_eq!(
+        get_kid_from_jws(&new_result.vc_jws),
+        format!("{}#key-2", did)
+    );
+    let keys = get_issuer_keys(&env);
+    assert_eq!(keys.len(), 2);
+    assert!(keys[0].retired_at_ns.is_some());
+    assert_eq!(keys[1], new_key);
+    // Credentials signed with the retired key are accepted during the grace period.
+    for (user, request, result) in [
+        (old_user, &old_request, &old_result),
+        (new_user, &new_request, &new_result),
+    ] {
+        validate_presentation_with_issuer_keys(
+            &env,
+            user,
+            request,
+            result,
+            DEFAULT_KEY_GRACE_PERIOD,
+        )
+        .expect("invalid presentation");
+    }
+}
+
+#[test]
+fn should_reject_credential_of_retired_key_after_grace_period() {
+    let mut env = VcTestEnv::new();
+    install_issuer_canister(&mut env);
+    let user = Principal::self_authenticating([1]);
+    let (request, result) = run_verified_adult_flow(&env, user);
+
+    rotate_signing_key(&env, None).expect("failed rotating key");
+    env.pic.advance_time(Duration::from_secs(2));
+
+    let validation =
+        validate_presentation_with_issuer_keys(&env, user, &request, &result, Duration::ZERO);
+    assert!(matches!(
+        validation,
+        Err(PresentationVerificationError::InvalidRequestedCredential(e))
+            if format!("{:?}", e).contains("InactiveKey")
+    ));
+}
+
+#[test]
+fn should_keep_rotated_keys_across_upgrades() {
+    let mut env = VcTestEnv::new();
+    install_issuer_canister(&mut env);
+    let user = Principal::self_authenticating([1]);
+    let (request, result) = run_verified_adult_flow(&env, user);
+    rotate_signing_key(&env, None).expect("failed rotating key");
+    let keys = get_issuer_keys(&env);
+
+    upgrade_issuer_canister(&env);
+
+    assert_eq!(get_issuer_keys(&env), keys);
+    // The credential signed with the retired key is still accepted during the grace period.
+    validate_presentation_with_issuer_keys(&env, user, &request, &result, DEFAULT_KEY_GRACE_PERIOD)
+        .expect("invalid presentation");
+    // The numbering of the keys continues after the upgrade.
+    let new_key = rotate_signing_key(&env, None).expect("failed rotating key");
+    assert_eq!(new_key.key_id, "key-3");
+    let (_, new_result) = run_verified_adult_flow(&env, Principal::self_authenticating([2]));
+    assert!(get_kid_from_jws(&new_result.vc_jws).ends_with("#key-3"));
+}
+
+#[test]
+fn should_issue_prepared_credential_after_key_rotation() {
+    let mut env = VcTestEnv::new();
+    let issuer_canister_id = install_issuer_canister(&mut env);
+    let user = Principal::self_authenticating([1]);
+    let signed_id_alias = signed_id_alias(&env, user);
+    let credential_spec = CredentialSpec {
+        credential_type: "VerifiedAdult".to_string(),
+        arguments: None,
+    };
+    let prepared = api::prepare_credential(
+        &env.pic,
+        issuer_canister_id,
+        PrepareCredentialRequest {
+            signed_id_alias: signed_id_alias.clone(),
+            credential_spec: credential_spec.clone(),
+        },
+        Some(user),
+    )
+    .unwrap();
+
+    rotate_signing_key(&env, None).expect("failed rotating key");
+    let issued = api::get_credential(
+        &env.pic,
+        issuer_canister_id,
+        GetCredentialRequest {
+            signed_id_alias,
+            credential_spec,
+            prepared_context: prepared.prepared_context,
+        },
+        Some(user),
+    )
+    .expect("failed getting credential");
+
+    assert!(get_kid_from_jws(&issued.vc_jws).ends_with("#key-1"));
+}
+
+#[test]
+fn should_only_allow_controllers_to_rotate_keys() {
+    let mut env = VcTestEnv::new();
+    install_issuer_canister(&mut env);
+    let user = Principal::self_authenticating([1]);
+
+    let result = rotate_signing_key(&env, Some(user));
+
+    assert_eq!(result, Err(format!("{} is not a controller", user)));
+    assert_eq!(get_issuer_keys(&env).len(), 1);
+}
+
+fn prepare_verified_adult_credential(
+    env: &VcTestEnv,
+    signed_id_alias: &SignedIdAlias,
+    sender: Principal,
+) -> Result<PreparedCredentialData, IssueCredentialError> {
+    api::prepare_credential(
+        &env.pic,
+        env.issuer_id(),
+        PrepareCredentialRequest {
+            signed_id_alias: signed_id_alias.clone(),
+            credential_spec: CredentialSpec {
+                credential_type: "VerifiedAdult".to_string(),
+                arguments: None,
+            },
+        },
+        Some(sender),
+    )
+}
+
+#[test]
+fn should_reject_invalid_id_alias() {
+    let mut env = VcTestEnv::new();
+    install_issuer_canister(&mut env);
+    let user = Principal::self_authenticating([1]);
+    // The id_alias credential is signed by the mainnet II, which is not configured.
+    let mainnet_id_alias = SignedIdAlias {
+        credential_jws: ID_ALIAS_JWT.to_string(),
+    };
+
+    let result = prepare_verified_adult_credential(&env, &mainnet_id_alias, user);
+
+    assert_invalid_id_alias(result, "InvalidJws");
+}
+
+#[test]
+fn should_reject_id_alias_of_other_user() {
+    let mut env = VcTestEnv::new();
+    install_issuer_canister(&mut env);
+    let user = Principal::self_authenticating([1]);
+    let other_user = Principal::self_authenticating([2]);
+    let signed_id_alias = signed_id_alias(&env, user);
+
+    let result = prepare_verified_adult_credential(&env, &signed_id_alias, other_user);
+
+    assert_unauthorized_subject(result, "not for caller");
+}
+
+#[test]
+fn should_not_rate_limit_or_assign_status_index_for_invalid_id_alias() {
+    let mut env = VcTestEnv::new();
+    install_issuer_canister(&mut env);
+    let user = Principal::self_authenticating([1]);
+    let mainnet_id_alias = SignedIdAlias {
+        credential_jws: ID_ALIAS_JWT.to_string(),
+    };
+    for _ in 0..DEFAULT_MAX_REQUESTS_PER_ID_ALIAS + 1 {
+        assert_invalid_id_alias(
+            prepare_verified_adult_credential(&env, &mainnet_id_alias, user),
+            "InvalidJws",
+        );
+    }
+
+    let (_, result) = run_verified_adult_flow(&env, user);
+
+    assert_eq!(get_status_index_from_jwt(&result.vc_jws), 0);
+}
+
+#[test]
+fn should_rate_limit_prepare_credential_across_upgrades() {
+    let mut env = VcTestEnv::new();
+    install_issuer_canister(&mut env);
+    let user = Principal::self_authenticating([1]);
+    let signed_id_alias = signed_id_alias(&env, user);
+    for _ in 0..DEFAULT_MAX_REQUESTS_PER_ID_ALIAS {
+        prepare_verified_adult_credential(&env, &signed_id_alias, user)
+            .expect("failed preparing credential");
+    }
+    let retry_after_s = assert_rate_limited(prepare_verified_adult_credential(
+        &env,
+        &signed_id_alias,
+        user,
+    ));
+    assert!(retry_after_s > 0 && retry_after_s <= DEFAULT_RATE_LIMIT_WINDOW.as_secs());
+
+    upgrade_issuer_canister(&env);
+    assert_rate_limited(prepare_verified_adult_credential(
+        &env,
+        &signed_id_alias,
+        user,
+    ));
+
+    env.pic.advance_time(DEFAULT_RATE_LIMIT_WINDOW);
+    prepare_verified_adult_credential(&env, &signed_id_alias, user)
+        .expect("failed preparing credential");
+}
+
+/// Sends the request to the issuer via its `raw` domain, which is the credential issuer identifier.
+fn openid4vci_request(env: &VcTestEnv, request: HttpRequest) -> HttpResponse {
+    let host = format!("{}.raw.icp0.io", env.issuer_id());
+    http_request(
+        &env.pic,
+        env.issuer_id(),
+        request.with_header("host", &host),
+    )
+}
+
+fn credential_issuer(env: &VcTestEnv) -> String {
+    format!("https://{}.raw.icp0.io", env.issuer_id())
+}
+
+fn openid4vci_token(env: &VcTestEnv, credential_configuration_id: &str) -> TokenResponse {
+    let offer: CredentialOffer = openid4vci_request(
+        env,
+        HttpRequest::post(
+            "/credential_offer",
+            json!({ "credential_configuration_id": credential_configuration_id }).to_string(),
+        ),
+    )
+    .json();
+    assert_eq!(offer.credential_issuer, credential_issuer(env));
+    let body = format!(
+        "grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Apre-authorized_code&pre-authorized_code={}",
+        offer.grants.pre_authorized_code.pre_authorized_code
+    );
+    let response = openid4vci_request(
+        env,
+        HttpRequest::post("/token", body)
+            .with_header("content-type", "application/x-www-form-urlencoded"),
+    );
+    assert_eq!(response.status_code, 200);
+    response.json()
+}
+
+fn openid4vci_credential(
+    env: &VcTestEnv,
+    access_token: &str,
+    credential_configuration_id: &str,
+    proof_jwt: &str,
+) -> HttpResponse {
+    let body = json!({
+        "credential_configuration_id": credential_configuration_id,
+        "proof": { "proof_type": "jwt", "jwt": proof_jwt },
+    });
+    openid4vci_request(
+        env,
+        HttpRequest::post("/credential", body.to_string())
+            .with_header("authorization", &format!("Bearer {}", access_token)),
+    )
+}
+
+fn openid4vci_deferred_credential(
+    env: &VcTestEnv,
+    access_token: &str,
+    transaction_id: &str,
+) -> HttpResponse {
+    let body = json!({ "transaction_id": transaction_id });
+    openid4vci_request(
+        env,
+        HttpRequest::post("/deferred_credential", body.to_string())
+            .with_header("authorization", &format!("Bearer {}", access_token)),
+    )
+}
+
+fn holder_proof(env: &VcTestEnv, holder: &TestThresholdSigner, c_nonce: &str) -> String {
+    holder.openid4vci_proof_jwt(
+        &credential_issuer(env),
+        c_nonce,
+        (env.time_ns() / 1_000_000_000) as u64,
+    )
+}
+
+#[test]
+fn should_serve_openid4vci_metadata() {
+    let mut env = VcTestEnv::new();
+    install_issuer_canister(&mut env);
+
+    let response = openid4vci_request(
+        &env,
+        HttpRequest::get("/.well-known/openid-credential-issuer"),
+    );
+
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.header("content-type"), Some("application/json"));
+    let metadata: CredentialIssuerMetadata = response.json();
+    assert_eq!(metadata.credential_issuer, credential_issuer(&env));
+    assert_eq!(
+        metadata.credential_endpoint,
+        format!("{}/credential", credential_issuer(&env))
+    );
+    let configuration = &metadata.credential_configurations_supported["VerifiedEmployee"];
+    assert_eq!(configuration.format, "jwt_vc_json");
+    assert_eq!(
+        configuration.credential_definition.types,
+        vec!["VerifiableCredential", "VerifiedEmployee"]
+    );
+    let as_metadata: Value = openid4vci_request(
+        &env,
+        HttpRequest::get("/.well-known/oauth-authorization-server"),
+    )
+    .json();
+    assert_eq!(
+        as_metadata["token_endpoint"],
+        format!("{}/token", credential_issuer(&env))
+    );
+}
+
+#[test]
+fn should_issue_credential_via_openid4vci() {
+    let mut env = VcTestEnv::new();
+    let issuer = install_issuer_canister(&mut env);
+    let holder = TestThresholdSigner::new(ThresholdAlgorithm::EdDsa, [7; 32]);
+    let holder_did = holder.did_key_url().split('#').next().unwrap().to_string();
+
+    let token = openid4vci_token(&env, "VerifiedEmployee");
+    let response = openid4vci_credential(
+        &env,
+        &token.access_token,
+        "VerifiedEmployee",
+        &holder_proof(&env, &holder, &token.c_nonce),
+    );
+    assert_eq!(response.status_code, 202);
+    let deferred: CredentialResponse = response.json();
+    let transaction_id = deferred.transaction_id.expect("missing transaction_id");
+
+    let response = openid4vci_deferred_credential(&env, &token.access_token, &transaction_id);
+    assert_eq!(response.status_code, 200);
+    let issued: CredentialResponse = response.json();
+    let credential_jws = &issued.credentials.expect("missing credentials")[0].credential;
+    let claims = verify_credential_jws_with_canister_id(
+        credential_jws,
+        &issuer,
+        &env.ic_root_key(),
+        env.time_ns(),
+    )
+    .expect("invalid credential");
+    assert_eq!(claims.sub(), Some(holder_did.as_str()));
+    let subject: Value =
+        serde_json::from_str(&get_credential_subject_from_jwt(credential_jws.clone())).unwrap();
+    assert_eq!(
+        subject["VerifiedEmployee"]["employerName"],
+        "DFINITY Foundation"
+    );
+    assert_eq!(get_status_index_from_jwt(credential_jws), 0);
+}
+
+#[test]
+fn should_reject_openid4vci_credential_request_with_invalid_proof() {
+    let mut env = VcTestEnv::new();
+    install_issuer_canister(&mut env);
+    let holder = TestThresholdSigner::new(ThresholdAlgorithm::Es256k, [7; 32]);
+    let token = openid4vci_token(&env, "VerifiedAdult");
+
+    let response = openid4vci_credential(
+        &env,
+        &token.access_token,
+        "VerifiedAdult",
+        &holder_proof(&env, &holder, "other nonce"),
+    );
+    assert_eq!(response.status_code, 400);
+    assert_eq!(response.json::<ErrorResponse>().error, "invalid_nonce");
+
+    let response = openid4vci_credential(
+        &env,
+        &token.access_token,
+        "VerifiedEmployee",
+        &holder_proof(&env, &holder, &token.c_nonce),
+    );
+    assert_eq!(
+        response.json::<ErrorResponse>().error,
+        "unknown_credential_configuration"
+    );
+
+    let response = openid4vci_credential(
+        &env,
+        "unknown token",
+        "VerifiedAdult",
+        &holder_proof(&env, &holder, &token.c_nonce),
+    );
+    assert_eq!(response.status_code, 401);
+    assert_eq!(response.json::<ErrorResponse>().error, "invalid_token");
+}
+
+#[test]
+fn should_use_openid4vci_tokens_only_once() {
+    let mut env = VcTestEnv::new();
+    install_issuer_canister(&mut env);
+    let holder = TestThresholdSigner::new(ThresholdAlgorithm::EdDsa, [7; 32]);
+    let token = openid4vci_token(&env, "VerifiedAdult");
+    let proof = holder_proof(&env, &holder, &token.c_nonce);
+
+    let response = openid4vci_credential(&env, &token.access_token, "VerifiedAdult", &proof);
+    assert_eq!(response.status_code, 202);
+    let transaction_id = response
+        .json::<CredentialResponse>()
+        .transaction_id
+        .expect("missing transaction_id");
+
+    let response = openid4vci_credential(&env, &token.access_token, "VerifiedAdult", &proof);
+    assert_eq!(response.status_code, 401);
+    // The deferred credential can only be fetched with the access token of the transaction.
+    let other_token = openid4vci_token(&env, "VerifiedAdult");
+    let response = openid4vci_deferred_credential(&env, &other_token.access_token, &transaction_id);
+    assert_eq!(
+        response.json::<ErrorResponse>().error,
+        "invalid_transaction_id"
+    );
+}
+
+#[test]
+fn should_rate_limit_openid4vci_credential_requests_per_holder() {
+    let mut env = VcTestEnv::new();
+    install_issuer_canister(&mut env);
+    let holder = TestThresholdSigner::new(ThresholdAlgorithm::EdDsa, [7; 32]);
+    let request_credential = |env: &VcTestEnv, holder: &TestThresholdSigner| {
+        let token = openid4vci_token(env, "VerifiedAdult");
+        openid4vci_credential(
+            env,
+            &token.access_token,
+            "VerifiedAdult",
+            &holder_proof(env, holder, &token.c_nonce),
+        )
+    };
+    for _ in 0..DEFAULT_MAX_REQUESTS_PER_HOLDER {
+        assert_eq!(request_credential(&env, &holder).status_code, 202);
+    }
+
+    let token = openid4vci_token(&env, "VerifiedAdult");
+    let response = openid4vci_credential(
+        &env,
+        &token.access_token,
+        "VerifiedAdult",
+        &holder_proof(&env, &holder, &token.c_nonce),
+    );
+    assert_eq!(response.status_code, 429);
+    let retry_after_s: u64 = response
+        .header("retry-after")
+        .expect("missing retry-after header")
+        .parse()
+        .expect("invalid retry-after header");
+    assert!(retry_after_s > 0 && retry_after_s <= DEFAULT_RATE_LIMIT_WINDOW.as_secs());
+    assert_eq!(response.json::<ErrorResponse>().error, "rate_limited");
+    let other_holder = TestThresholdSigner::new(ThresholdAlgorithm::EdDsa, [8; 32]);
+    assert_eq!(request_credential(&env, &other_holder).status_code, 202);
+
+    // The access token of the rate-limited request has not been consumed.
+    env.pic.advance_time(DEFAULT_RATE_LIMIT_WINDOW);
+    let response = openid4vci_credential(
+        &env,
+        &token.access_token,
+        "VerifiedAdult",
+        &holder_proof(&env, &holder, &token.c_nonce),
+    );
+    assert_eq!(response.status_code, 202);
+}
@@ -4,6 +4,7 @@ use base64::Engine;
 use base64::engine::general_purpose::STANDARD as BASE64;
 use ic_cdk::{
     api::{certified_data_set, data_certificate},
+    storage::{stable_restore, stable_save},
     *,
 };
 use ic_http_certification::{
@@ -11,22 +12,44 @@ use ic_http_certification::{
     HttpCertification, HttpCertificationPath, HttpCertificationTree, HttpCertificationTreeEntry,
     HttpRequest, HttpResponse,
 };
+use ic_verifiable_credentials::alternative_origins::ALTERNATIVE_ORIGINS_PATH;
+use ic_verifiable_credentials::diagnostics::{IcPrintlnDiagnostics, set_diagnostics};
 use include_dir::{Dir, include_dir};
 use lazy_static::lazy_static;
 use serde::Serialize;
 use std::{borrow::Cow, cell::RefCell, collections::HashMap};
+use verification::RelyingPartyConfig;
+
+mod verification;
 
 // Public methods
 
 #[init]
-fn init() {
-    prepare_cel_exprs();
-    certify_all_assets();
+fn init(config: Option<RelyingPartyConfig>) {
+    apply_config(config.unwrap_or_default());
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    stable_save((verification::get_config(),)).expect("failed saving config");
 }
 
 #[post_upgrade]
-fn post_upgrade() {
-    init();
+fn post_upgrade(config: Option<RelyingPartyConfig>) {
+    // Canisters upgraded from a version without a saved config use the default config.
+    let saved_config = stable_restore::<(Option<RelyingPartyConfig>,)>()
+        .ok()
+        .and_then(|(config,)| config);
+    apply_config(config.or(saved_config).unwrap_or_default());
+}
+
+fn apply_config(config: RelyingPartyConfig) {
+    if let Err(e) = verification::set_config(config) {
+        trap(format!("invalid config: {}", e));
+    }
+    set_diagnostics(IcPrintlnDiagnostics);
+    prepare_cel_exprs();
+    certify_all_assets();
 }
 
 #[query]
@@ -101,6 +124,7 @@ fn certify_all_assets() {
     certify_asset_glob("assets/**/*.js", "text/javascript");
     certify_asset_glob("assets/**/*.ico", "image/x-icon");
     certify_asset_glob("assets/**/*.svg", "image/svg+xml");
+    certify_alternative_origins();
 }
 
 fn certify_index_asset() {
@@ -126,6 +150,28 @@ fn certify_index_asset() {
     );
 }
 
+// The alternative origins document is fetched by II from the frontend, i.e. cross-origin.
+fn certify_alternative_origins() {
+    let Some(document) = verification::alternative_origins_json() else {
+        return;
+    };
+    let additional_headers = vec![
+        ("content-type".to_string(), "application/json".to_string()),
+        ("access-control-allow-origin".to_string(), "*".to_string()),
+        (
+            "cache-control".to_string(),
+            "public, no-cache, no-store".to_string(),
+        ),
+    ];
+
+    certify_asset_response(
+        Cow::Owned(document.into_bytes()),
+        additional_headers,
+        &HttpCertificationPath::exact(ALTERNATIVE_ORIGINS_PATH),
+        ALTERNATIVE_ORIGINS_PATH.to_string(),
+    );
+}
+
 fn certify_asset_glob(glob: &str, content_type: &str) {
     // iterate over every asset matching the glob
     for identity_file in ASSETS_DIR
@@ -170,7 +216,7 @@ fn certify_asset(
     additional_headers: Vec<HeaderField>,
 ) {
     certify_asset_response(
-        body,
+        Cow::Borrowed(body),
         additional_headers.clone(),
         asset_tree_path,
         asset_req_path.to_string(),
@@ -211,7 +257,8 @@ fn certify_asset_with_encoding(
             let (cel_expr_def, cel_expr_str) = cel_exprs.get(*ASSET_CEL_EXPR_PATH).unwrap();
 
             // create the response
-            let response = create_asset_response(headers, body, cel_expr_str.to_string());
+            let response =
+                create_asset_response(headers, Cow::Borrowed(body), cel_expr_str.to_string());
 
             // certify the response
             let certification =
@@ -245,7 +292,7 @@ fn certify_asset_with_encoding(
 
 const IC_CERTIFICATE_EXPRESSION_HEADER: &str = "IC-CertificateExpression";
 fn certify_asset_response(
-    body: &'static [u8],
+    body: Cow<'static, [u8]>,
     additional_headers: Vec<HeaderField>,
     asset_tree_path: &HttpCertificationPath,
     asset_req_path: String,
@@ -387,9 +434,9 @@ fn add_certificate_header(
 
 fn create_asset_response(
     additional_headers: Vec<HeaderField>,
-    body: &[u8],
+    body: Cow<'_, [u8]>,
     cel_expr: String,
-) -> HttpAssetResponse {
+) -> HttpAssetResponse<'_> {
     // set up the default headers and include additional headers provided by the caller
     let mut headers = vec![
         ("content-length".to_string(), body.len().to_string()),
@@ -400,7 +447,7 @@ fn create_asset_response(
     HttpAssetResponse {
         status_code: 200,
         headers,
-        body: Cow::Borrowed(body),
+        body,
     }
 }
 
@@ -0,0 +1,222 @@
+// Verification of the presentations obtained by the frontend from Internet Identity.
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::{canister_self, msg_caller, time};
+use ic_cdk::{query, update};
+use ic_verifiable_credentials::alternative_origins::AlternativeOrigins;
+use ic_verifiable_credentials::environment::{IcRootKey, VcEnvironment};
+use ic_verifiable_credentials::issuer_api::CredentialSpec;
+use ic_verifiable_credentials::verification_error::VerificationError;
+use ic_verifiable_credentials::{
+    II_ISSUER_URL, VerificationOptions, validate_ii_presentation_and_claims_with_options,
+};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+
+// The maximal number of verification results stored per caller.
+const MAX_RESULTS_PER_CALLER: usize = 10;
+// The maximal number of callers whose verification results are stored.
+const MAX_CALLERS: usize = 1_000;
+
+/// Configuration of the relying party.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct RelyingPartyConfig {
+    /// Root of trust for checking canister signatures.
+    pub ic_root_key_der: Vec<u8>,
+    /// The canister id of Internet Identity, which signs the id_alias credentials.
+    pub ii_canister_id: Principal,
+    /// The derivation origin used by the frontend when requesting credentials.
+    pub derivation_origin: String,
+    /// The issuers whose credentials are accepted.
+    pub trusted_issuers: Vec<TrustedIssuer>,
+    /// Further origins of the frontend, which use `derivation_origin` as derivation origin.
+    /// They are served as `/.well-known/ii-alternative-origins`, and presentations for them
+    /// are accepted.
+    pub alternative_origins: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct TrustedIssuer {
+    pub origin: String,
+    pub canister_id: Principal,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct VerifyPresentationRequest {
+    pub vp_jwt: String,
+    pub credential_spec: CredentialSpec,
+    /// The origin of the issuer of the requested credential, which must be a trusted issuer.
+    pub issuer_origin: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum VerifyPresentationError {
+    /// Presentations can only be verified for authenticated callers.
+    AnonymousCaller,
+    /// The issuer is not in the list of trusted issuers.
+    UntrustedIssuer(String),
+    /// The presentation is invalid, or does not match the credential spec.
+    InvalidPresentation(VerificationError),
+}
+
+/// The outcome of a call to `verify_presentation`.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct VerificationResult {
+    pub credential_spec: CredentialSpec,
+    pub issuer_origin: String,
+    pub timestamp_ns: u64,
+    pub result: Result<(), VerifyPresentationError>,
+}
+
+impl Default for RelyingPartyConfig {
+    fn default() -> Self {
+        Self {
+            ic_root_key_der: IcRootKey::mainnet().to_der(),
+            ii_canister_id: VcEnvironment::mainnet().ii_canister_id,
+            derivation_origin: format!("https://{}.icp0.io", canister_self()),
+            trusted_issuers: vec![],
+            alternative_origins: None,
+        }
+    }
+}
+
+// A configuration whose root key and alternative origins have been validated.
+struct ValidatedConfig {
+    config: RelyingPartyConfig,
+    environment: VcEnvironment,
+    alternative_origins: AlternativeOrigins,
+}
+
+impl ValidatedConfig {
+    fn new(config: RelyingPartyConfig) -> Result<Self, String> {
+        let root_key = IcRootKey::from_der(&config.ic_root_key_der)
+            .map_err(|e| format!("invalid root key: {}", e))?;
+        let mainnet = VcEnvironment::mainnet();
+        // The environment of II and the issuers, with the configured II and root key.
+        let environment =
+            if root_key == mainnet.root_key && config.ii_canister_id == mainnet.ii_canister_id {
+                mainnet
+            } else {
+                // II issues id_alias credentials as `II_ISSUER_URL` on every network.
+                VcEnvironment::local(config.ii_canister_id, II_ISSUER_URL, root_key)
+            };
+        let alternative_origins =
+            AlternativeOrigins::new(config.alternative_origins.clone().unwrap_or_default())
+                .map_err(|e| format!("invalid alternative origins: {:?}", e))?;
+        Ok(Self {
+            config,
+            environment,
+            alternative_origins,
+        })
+    }
+}
+
+thread_local! {
+    /// Non-stable structures
+    // Configuration of the relying party, which is kept in stable memory across upgrades.
+    static CONFIG: RefCell<Option<ValidatedConfig>> = const { RefCell::new(None) };
+    // The latest verification results of each caller.
+    static RESULTS: RefCell<BTreeMap<Principal, VecDeque<VerificationResult>>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Validates and sets the configuration of the relying party.
+pub fn set_config(config: RelyingPartyConfig) -> Result<(), String> {
+    let config = ValidatedConfig::new(config)?;
+    CONFIG.set(Some(config));
+    Ok(())
+}
+
+/// Returns the current configuration, if set.
+pub fn get_config() -> Option<RelyingPartyConfig> {
+    CONFIG.with_borrow(|config| config.as_ref().map(|config| config.config.clone()))
+}
+
+/// Returns the alternative origins document, if alternative origins are configured.
+pub fn alternative_origins_json() -> Option<String> {
+    with_config(|config| {
+        config
+            .config
+            .alternative_origins
+            .is_some()
+            .then(|| config.alternative_origins.to_json())
+    })
+}
+
+fn with_config<R>(f: impl FnOnce(&ValidatedConfig) -> R) -> R {
+    CONFIG.with_borrow(|config| f(config.as_ref().expect("missing config")))
+}
+
+/// Verifies that the given presentation contains a credential matching `credential_spec`
+/// issued by the given issuer to the caller, and stores the result for the caller.
+#[update]
+fn verify_presentation(req: VerifyPresentationRequest) -> Result<(), VerifyPresentationError> {
+    let caller = msg_caller();
+    if caller == Principal::anonymous() {
+        return Err(VerifyPresentationError::AnonymousCaller);
+    }
+    let result = verify(&req, caller);
+    RESULTS.with_borrow_mut(|results| {
+        if !results.contains_key(&caller) && results.len() >= MAX_CALLERS {
+            evict_least_recent_caller(results);
+        }
+        let caller_results = results.entry(caller).or_default();
+        caller_results.push_back(VerificationResult {
+            credential_spec: req.credential_spec,
+            issuer_origin: req.issuer_origin,
+            timestamp_ns: time(),
+            result: result.clone(),
+        });
+        if caller_results.len() > MAX_RESULTS_PER_CALLER {
+            caller_results.pop_front();
+        }
+    });
+    result
+}
+
+// Removes the results of the caller whose latest verification is the oldest.
+fn evict_least_recent_caller(results: &mut BTreeMap<Principal, VecDeque<VerificationResult>>) {
+    let least_recent = results
+        .iter()
+        .min_by_key(|(_, caller_results)| caller_results.back().map(|result| result.timestamp_ns))
+        .map(|(caller, _)| *caller);
+    if let Some(caller) = least_recent {
+        results.remove(&caller);
+    }
+}
+
+/// Returns the latest verification results of the caller, oldest first.
+#[query]
+fn get_verification_results() -> Vec<VerificationResult> {
+    RESULTS.with_borrow(|results| {
+        results
+            .get(&msg_caller())
+            .map(|caller_results| caller_results.iter().cloned().collect())
+            .unwrap_or_default()
+    })
+}
+
+fn verify(
+    req: &VerifyPresentationRequest,
+    caller: Principal,
+) -> Result<(), VerifyPresentationError> {
+    with_config(|config| {
+        let issuer = config
+            .config
+            .trusted_issuers
+            .iter()
+            .find(|issuer| issuer.origin == req.issuer_origin)
+            .ok_or_else(|| VerifyPresentationError::UntrustedIssuer(req.issuer_origin.clone()))?;
+        let env = &config.environment;
+        let vc_flow_signers = env.vc_flow_signers(issuer.canister_id, &issuer.origin);
+        validate_ii_presentation_and_claims_with_options(
+            &req.vp_jwt,
+            caller,
+            &config.config.derivation_origin,
+            &vc_flow_signers,
+            &req.credential_spec,
+            &VerificationOptions::default().with_alternative_origins(&config.alternative_origins),
+            env,
+            time() as u128,
+        )
+        .map_err(|e| VerifyPresentationError::InvalidPresentation(e.into()))
+    })
+}
@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+
+use candid::{CandidType, Deserialize, Principal, encode_args};
+use ic_verifiable_credentials::alternative_origins::{
+    ALTERNATIVE_ORIGINS_PATH, AlternativeOrigins,
+};
+use ic_verifiable_credentials::issuer_api::{ArgumentValue, CredentialSpec};
+use ic_verifiable_credentials::verification_error::{
+    PresentedCredential, VerificationError, VerificationErrorCode,
+};
+use vc_test_harness::http_gateway::{HttpRequest, http_request};
+use vc_test_harness::{CanisterCall, VariantResponse, VcFlowRequest, VcTestEnv, call_canister};
+
+const DUMMY_RP_WASM: &[u8] = include_bytes!("../dummy_relying_party.wasm.gz");
+const DUMMY_IDP_WASM: &[u8] =
+    include_bytes!("../../dummy-identity-provider/dummy_identity_provider.wasm.gz");
+const DUMMY_ISSUER_WASM: &[u8] = include_bytes!("../../dummy-issuer/dummy_issuer.wasm.gz");
+const DUMMY_ISSUER_URL: &str = "https://dummy-issuer.vc";
+const RP_ORIGIN: &str = "https://relying-party.vc";
+const RP_ALTERNATIVE_ORIGIN: &str = "https://app.relying-party.vc";
+const ISSUER_ORIGIN: &str = "https://issuer.vc";
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+struct IssuerConfig {
+    ic_root_key_der: Vec<u8>,
+    idp_canister_ids: Vec<Principal>,
+    derivation_origin: String,
+    frontend_hostname: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+struct RelyingPartyConfig {
+    ic_root_key_der: Vec<u8>,
+    ii_canister_id: Principal,
+    derivation_origin: String,
+    trusted_issuers: Vec<TrustedIssuer>,
+    alternative_origins: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+struct TrustedIssuer {
+    origin: String,
+    canister_id: Principal,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+struct VerifyPresentationRequest {
+    vp_jwt: String,
+    credential_spec: CredentialSpec,
+    issuer_origin: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+enum VerifyPresentationError {
+    AnonymousCaller,
+    UntrustedIssuer(String),
+    InvalidPresentation(VerificationError),
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+struct VerificationResult {
+    credential_spec: CredentialSpec,
+    issuer_origin: String,
+    timestamp_ns: u64,
+    result: VariantResponse<(), VerifyPresentationError>,
+}
+
+struct TestEnv {
+    env: VcTestEnv,
+    rp: Principal,
+}
+
+impl TestEnv {
+    /// Installs the relying party, which trusts the dummy issuer under `DUMMY_ISSUER_URL`
+    /// and the dummy identity provider as II.
+    fn new() -> Self {
+        Self::with_alternative_origins(None)
+    }
+
+    fn with_alternative_origins(alternative_origins: Option<Vec<String>>) -> Self {
+        let mut env = VcTestEnv::new();
+        let idp = env.install_identity_provider(DUMMY_IDP_WASM);
+        let issuer_config = IssuerConfig {
+            ic_root_key_der: env.root_key_der(),
+            idp_canister_ids: vec![idp],
+            derivation_origin: ISSUER_ORIGIN.to_string(),
+            frontend_hostname: ISSUER_ORIGIN.to_string(),
+        };
+        let issuer = env.install_issuer(
+            DUMMY_ISSUER_WASM,
+            encode_args((Some(issuer_config),)).unwrap(),
+        );
+        let config = RelyingPartyConfig {
+            ic_root_key_der: env.root_key_der(),
+            ii_canister_id: idp,
+            derivation_origin: RP_ORIGIN.to_string(),
+            trusted_issuers: vec![TrustedIssuer {
+                origin: DUMMY_ISSUER_URL.to_string(),
+                canister_id: issuer,
+            }],
+            alternative_origins,
+        };
+        let rp = env.install_canister(DUMMY_RP_WASM, encode_args((Some(config),)).unwrap());
+        Self { env, rp }
+    }
+
+    /// Runs the VC flow for `user` with the dummy identity provider and the dummy issuer,
+    /// and returns the presentation for the relying party.
+    fn presentation(&self, user: Principal, credential_spec: &CredentialSpec) -> String {
+        self.presentation_for_origin(user, RP_ORIGIN, credential_spec)
+    }
+
+    /// Like `presentation`, for the frontend of the relying party at `origin`.
+    fn presentation_for_origin(
+        &self,
+        user: Principal,
+        origin: &str,
+        credential_spec: &CredentialSpec,
+    ) -> String {
+        self.env
+            .run_vc_flow(
+                user,
+                &VcFlowRequest {
+                    relying_party: origin.to_string(),
+                    issuer_frontend_hostname: ISSUER_ORIGIN.to_string(),
+                    credential_spec: credential_spec.clone(),
+                },
+            )
+            .expect("VC flow failed")
+            .vp_jwt
+    }
+
+    fn verify_presentation(
+        &self,
+        sender: Principal,
+        request: VerifyPresentationRequest,
+    ) -> Result<(), VerifyPresentationError> {
+        call_canister(
+            &self.env.pic,
+            "verify_presentation",
+            CanisterCall::Update,
+            self.rp,
+            request,
+            Some(sender),
+        )
+    }
+
+    fn get_verification_results(&self, sender: Principal) -> Vec<VerificationResult> {
+        let reply = self
+            .env
+            .pic
+            .query_call(
+                self.rp,
+                sender,
+                "get_verification_results",
+                encode_args(()).unwrap(),
+            )
+            .expect("Error calling canister");
+        let pocket_ic::WasmResult::Reply(reply) = reply else {
+            unreachable!()
+        };
+        candid::decode_one(&reply).unwrap()
+    }
+}
+
+fn test_principal(n: u8) -> Principal {
+    Principal::self_authenticating([n])
+}
+
+fn verified_age_spec() -> CredentialSpec {
+    CredentialSpec {
+        credential_type: "VerifiedAge".to_string(),
+        arguments: Some(HashMap::from([(
+            "ageAtLeast".to_string(),
+            ArgumentValue::Int(18),
+        )])),
+    }
+}
+
+#[test]
+fn should_verify_presentation() {
+    let env = TestEnv::new();
+    let user = test_principal(1);
+    let vp_jwt = env.presentation(user, &verified_age_spec());
+
+    let result = env.verify_presentation(
+        user,
+        VerifyPresentationRequest {
+            vp_jwt,
+            credential_spec: verified_age_spec(),
+            issuer_origin: DUMMY_ISSUER_URL.to_string(),
+        },
+    );
+
+    assert_eq!(result, Ok(()));
+    let results = env.get_verification_results(user);
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].issuer_origin, DUMMY_ISSUER_URL);
+    assert_eq!(results[0].credential_spec, verified_age_spec());
+    assert_eq!(results[0].result, VariantResponse::Ok(()));
+    assert!(results[0].timestamp_ns > 0);
+}
+
+#[test]
+fn should_reject_presentation_of_other_caller() {
+    let env = TestEnv::new();
+    let vp_jwt = env.presentation(test_principal(1), &verified_age_spec());
+
+    let result = env.verify_presentation(
+        test_principal(2),
+        VerifyPresentationRequest {
+            vp_jwt,
+            credential_spec: verified_age_spec(),
+            issuer_origin: DUMMY_ISSUER_URL.to_string(),
+        },
+    );
+
+    assert!(matches!(
+        result,
+        Err(VerifyPresentationError::InvalidPresentation(_))
+    ));
+    assert!(env.get_verification_results(test_principal(1)).is_empty());
+    assert_eq!(env.get_verification_results(test_principal(2)).len(), 1);
+}
+
+#[test]
+fn should_reject_presentation_not_matching_spec() {
+    let env = TestEnv::new();
+    let user = test_principal(1);
+    let vp_jwt = env.presentation(user, &verified_age_spec());
+    let other_spec = CredentialSpec {
+        credential_type: "VerifiedAdult".to_string(),
+        arguments: None,
+    };
+
+    let result = env.verify_presentation(
+        user,
+        VerifyPresentationRequest {
+            vp_jwt,
+            credential_spec: other_spec,
+            issuer_origin: DUMMY_ISSUER_URL.to_string(),
+        },
+    );
+
+    let Err(VerifyPresentationError::InvalidPresentation(e)) = result else {
+        panic!("unexpected result {:?}", result);
+    };
+    assert_eq!(e.code, VerificationErrorCode::UnexpectedClaim);
+    assert_eq!(e.credential, Some(PresentedCredential::Requested));
+    assert_eq!(e.claim.as_deref(), Some("vc.type"));
+    assert_eq!(e.expected.as_deref(), Some("VerifiedAdult"));
+}
+
+#[test]
+fn should_reject_untrusted_issuer() {
+    let env = TestEnv::new();
+    let user = test_principal(1);
+    let vp_jwt = env.presentation(user, &verified_age_spec());
+
+    let result = env.verify_presentation(
+        user,
+        VerifyPresentationRequest {
+            vp_jwt,
+            credential_spec: verified_age_spec(),
+            issuer_origin: "https://untrusted-issuer.vc".to_string(),
+        },
+    );
+
+    assert_eq!(
+        result,
+        Err(VerifyPresentationError::UntrustedIssuer(
+            "https://untrusted-issuer.vc".to_string()
+        ))
+    );
+}
+
+#[test]
+fn should_keep_latest_results_per_caller() {
+    let env = TestEnv::new();
+    let user = test_principal(1);
+
+    for i in 0..12 {
+        let _ = env.verify_presentation(
+            user,
+            VerifyPresentationRequest {
+                vp_jwt: format!("invalid presentation #{}", i),
+                credential_spec: verified_age_spec(),
+                issuer_origin: DUMMY_ISSUER_URL.to_string(),
+            },
+        );
+    }
+
+    let results = env.get_verification_results(user);
+    assert_eq!(results.len(), 10);
+    assert!(
+        results
+            .iter()
+            .all(|result| matches!(result.result, VariantResponse::Err(_)))
+    );
+}
+
+#[test]
+fn should_reject_anonymous_caller() {
+    let env = TestEnv::new();
+
+    let result = env.verify_presentation(
+        Principal::anonymous(),
+        VerifyPresentationRequest {
+            vp_jwt: "invalid presentation".to_string(),
+            credential_spec: verified_age_spec(),
+            issuer_origin: DUMMY_ISSUER_URL.to_string(),
+        },
+    );
+
+    assert_eq!(result, Err(VerifyPresentationError::AnonymousCaller));
+    assert!(
+        env.get_verification_results(Principal::anonymous())
+            .is_empty()
+    );
+}
+
+#[test]
+fn should_keep_config_on_upgrade_without_argument() {
+    let env = TestEnv::new();
+    let user = test_principal(1);
+    let vp_jwt = env.presentation(user, &verified_age_spec());
+
+    env.env
+        .pic
+        .upgrade_canister(
+            env.rp,
+            DUMMY_RP_WASM.to_vec(),
+            encode_args((None::<RelyingPartyConfig>,)).unwrap(),
+            None,
+        )
+        .expect("failed to upgrade relying party");
+
+    let result = env.verify_presentation(
+        user,
+        VerifyPresentationRequest {
+            vp_jwt,
+            credential_spec: verified_age_spec(),
+            issuer_origin: DUMMY_ISSUER_URL.to_string(),
+        },
+    );
+    assert_eq!(result, Ok(()));
+}
+
+#[test]
+fn should_serve_alternative_origins() {
+    let env = TestEnv::with_alternative_origins(Some(vec![RP_ALTERNATIVE_ORIGIN.to_string()]));
+
+    let response = http_request(
+        &env.env.pic,
+        env.rp,
+        HttpRequest::get(ALTERNATIVE_ORIGINS_PATH),
+    );
+
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.header("content-type"), Some("application/json"));
+    assert_eq!(response.header("access-control-allow-origin"), Some("*"));
+    assert!(response.header("IC-Certificate").is_some());
+    assert_eq!(
+        AlternativeOrigins::from_json(std::str::from_utf8(&response.body).unwrap()),
+        AlternativeOrigins::new(vec![RP_ALTERNATIVE_ORIGIN.to_string()])
+    );
+}
+
+#[test]
+fn should_verify_presentation_for_alternative_origin() {
+    let env = TestEnv::with_alternative_origins(Some(vec![RP_ALTERNATIVE_ORIGIN.to_string()]));
+    let user = test_principal(1);
+    let vp_jwt = env.presentation_for_origin(user, RP_ALTERNATIVE_ORIGIN, &verified_age_spec());
+
+    let result = env.verify_presentation(
+        user,
+        VerifyPresentationRequest {
+            vp_jwt,
+            credential_spec: verified_age_spec(),
+            issuer_origin: DUMMY_ISSUER_URL.to_string(),
+        },
+    );
+
+    assert_eq!(result, Ok(()));
+}
+
+#[test]
+fn should_reject_presentation_for_unlisted_origin() {
+    let env = TestEnv::new();
+    let user = test_principal(1);
+    let vp_jwt = env.presentation_for_origin(user, RP_ALTERNATIVE_ORIGIN, &verified_age_spec());
+
+    let result = env.verify_presentation(
+        user,
+        VerifyPresentationRequest {
+            vp_jwt,
+            credential_spec: verified_age_spec(),
+            issuer_origin: DUMMY_ISSUER_URL.to_string(),
+        },
+    );
+
+    let Err(VerifyPresentationError::InvalidPresentation(e)) = result else {
+        panic!("unexpected result {:?}", result);
+    };
+    assert_eq!(e.code, VerificationErrorCode::UnexpectedClaim);
+    assert_eq!(e.credential, Some(PresentedCredential::IdAlias));
+    assert_eq!(e.expected.as_deref(), Some(RP_ORIGIN));
+    assert_eq!(e.actual.as_deref(), Some(RP_ALTERNATIVE_ORIGIN));
+}
@@ -0,0 +1,17 @@
+//! Extracts the canister signature public key from arbitrary input as signing input.
+#![no_main]
+
+use ic_canister_sig_creation::{CanisterSigPublicKey, extract_raw_canister_sig_pk_from_der};
+use ic_verifiable_credentials::canister_sig_pk_from_vc_signing_input;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(pk) = canister_sig_pk_from_vc_signing_input(data) else {
+        return;
+    };
+    // An extracted key is well-formed, i.e. survives a roundtrip through its DER encoding.
+    let pk_der = pk.to_der();
+    let pk_raw = extract_raw_canister_sig_pk_from_der(&pk_der).expect("invalid DER of key");
+    let roundtripped = CanisterSigPublicKey::try_from_raw(&pk_raw).expect("invalid raw key");
+    assert_eq!(roundtripped.to_der(), pk_der);
+});
@@ -0,0 +1,17 @@
+//! Extracts the DER-encoded canister signature public key from an arbitrary JWS header.
+#![no_main]
+
+use ic_verifiable_credentials::get_canister_sig_pk_der;
+use identity_jose::jws::JwsHeader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(header) = serde_json::from_slice::<JwsHeader>(data) else {
+        return;
+    };
+    let Ok(pk_der) = get_canister_sig_pk_der(&header) else {
+        return;
+    };
+    // The key is base64url-decoded from the header, so it cannot be larger than the input.
+    assert!(pk_der.len() <= data.len());
+});
@@ -0,0 +1,33 @@
+//! Validates arbitrary `vc` claims against an arbitrary credential spec.
+#![no_main]
+
+use ic_verifiable_credentials::issuer_api::CredentialSpec;
+use ic_verifiable_credentials::validate_claims_match_spec;
+use libfuzzer_sys::fuzz_target;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// The input of the target, as JSON.
+#[derive(Deserialize)]
+struct Input {
+    vc: Map<String, Value>,
+    spec: CredentialSpec,
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(Input { vc, spec }) = serde_json::from_slice::<Input>(data) else {
+        return;
+    };
+    if validate_claims_match_spec(&vc, &spec).is_ok() {
+        // Accepted claims are of the requested type.
+        let types = vc
+            .get("type")
+            .and_then(Value::as_array)
+            .expect("missing type");
+        assert!(
+            types
+                .iter()
+                .any(|t| t.as_str() == Some(spec.credential_type.as_str()))
+        );
+    }
+});
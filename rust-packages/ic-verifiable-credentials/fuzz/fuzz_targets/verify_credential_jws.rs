@@ -0,0 +1,42 @@
+//! Verifies arbitrary input as a canister-signed JWS credential.
+#![no_main]
+
+use candid::Principal;
+use ic_canister_sig_creation::CanisterSigPublicKey;
+use ic_verifiable_credentials::environment::IcRootKey;
+use ic_verifiable_credentials::{get_canister_sig_pk_der, verify_credential_jws_with_canister_id};
+use identity_jose::jws::Decoder;
+use libfuzzer_sys::fuzz_target;
+
+// The II canister and the time of the id_alias credentials of the seed corpus, so that
+// mutations of the seeds get past the checks of the signer and the expiration.
+const II_CANISTER_ID: &str = "fgte5-ciaaa-aaaad-aaatq-cai";
+const CURRENT_TIME_NS: u128 = 1_729_758_357_000_000_000;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(credential_jws) = std::str::from_utf8(data) else {
+        return;
+    };
+    let ii_canister_id = Principal::from_text(II_CANISTER_ID).expect("invalid canister id");
+    let Ok(claims) = verify_credential_jws_with_canister_id(
+        credential_jws,
+        &ii_canister_id,
+        &IcRootKey::mainnet(),
+        CURRENT_TIME_NS,
+    ) else {
+        return;
+    };
+    // Accepted credentials are signed by the given canister, and have not expired.
+    let jws = Decoder::new()
+        .decode_compact_serialization(data, None)
+        .expect("accepted credential is not a JWS");
+    let header = jws.protected_header().expect("missing JWS header");
+    let pk_der = get_canister_sig_pk_der(header).expect("missing canister sig key");
+    let pk = CanisterSigPublicKey::try_from(pk_der.as_slice()).expect("invalid canister sig key");
+    assert_eq!(pk.canister_id, ii_canister_id);
+    let exp = claims.exp().expect("accepted credential without exp");
+    assert!(
+        i128::from(exp) * 1_000_000_000 > CURRENT_TIME_NS as i128,
+        "accepted expired credential"
+    );
+});
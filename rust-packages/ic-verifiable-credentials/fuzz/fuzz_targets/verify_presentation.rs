@@ -0,0 +1,96 @@
+//! Parses and verifies arbitrary input as a presentation returned by II.
+#![no_main]
+
+use candid::Principal;
+use ic_canister_sig_creation::CanisterSigPublicKey;
+use ic_verifiable_credentials::environment::IcRootKey;
+use ic_verifiable_credentials::{
+    AliasTuple, DEFAULT_MAX_PRESENTATION_SIZE, II_ISSUER_URL, VcFlowSigners,
+    get_canister_sig_pk_der, principal_for_did, verify_presentation,
+};
+use identity_jose::jws::Decoder;
+use identity_jose::jwu::decode_b64;
+use libfuzzer_sys::fuzz_target;
+use serde_json::Value;
+use std::cell::RefCell;
+
+// The signers and the time of the presentation of the seed corpus.
+const II_CANISTER_ID: &str = "fgte5-ciaaa-aaaad-aaatq-cai";
+const ISSUER_CANISTER_ID: &str = "qdiif-2iaaa-aaaap-ahjaq-cai";
+const ISSUER_URL: &str = "https://dummy-issuer.vc/";
+const CURRENT_TIME_NS: u128 = 1_729_758_357_000_000_000;
+
+// Returns the canister whose canister signature key is in the header of the given JWS.
+fn signing_canister(jws: &str) -> Principal {
+    let jws = Decoder::new()
+        .decode_compact_serialization(jws.as_bytes(), None)
+        .expect("accepted credential is not a JWS");
+    let header = jws.protected_header().expect("missing JWS header");
+    let pk_der = get_canister_sig_pk_der(header).expect("missing canister sig key");
+    CanisterSigPublicKey::try_from(pk_der.as_slice())
+        .expect("invalid canister sig key")
+        .canister_id
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(vp_jwt) = std::str::from_utf8(data) else {
+        return;
+    };
+    let vc_flow_signers = VcFlowSigners {
+        ii_canister_id: Principal::from_text(II_CANISTER_ID).expect("invalid canister id"),
+        ii_origin: II_ISSUER_URL.to_string(),
+        issuer_canister_id: Principal::from_text(ISSUER_CANISTER_ID).expect("invalid canister id"),
+        issuer_origin: ISSUER_URL.to_string(),
+    };
+    let policy_input: RefCell<Option<AliasTuple>> = RefCell::new(None);
+    let result = verify_presentation(
+        vp_jwt,
+        &vc_flow_signers,
+        &IcRootKey::mainnet(),
+        CURRENT_TIME_NS,
+        |alias_tuple| {
+            *policy_input.borrow_mut() = Some(alias_tuple.clone());
+            Ok(())
+        },
+    );
+    let Ok((alias_tuple, claims)) = result else {
+        return;
+    };
+    assert!(
+        vp_jwt.len() <= DEFAULT_MAX_PRESENTATION_SIZE,
+        "accepted presentation exceeding the maximum size"
+    );
+    // The returned alias tuple is the one accepted by the policy.
+    assert_eq!(policy_input.into_inner().as_ref(), Some(&alias_tuple));
+
+    // The holder is the subject of the id_alias credential, which is signed by II, and the
+    // requested credential is signed by the issuer.
+    let payload = vp_jwt.split('.').nth(1).expect("missing payload");
+    let payload: Value = serde_json::from_slice(&decode_b64(payload).expect("invalid base64"))
+        .expect("invalid payload");
+    let holder = payload["iss"].as_str().expect("missing holder");
+    assert_eq!(principal_for_did(holder), Ok(alias_tuple.id_dapp));
+    let credentials = payload["vp"]["verifiableCredential"]
+        .as_array()
+        .expect("missing credentials");
+    let [id_alias_vc, requested_vc] = &credentials[..] else {
+        panic!("accepted presentation without exactly two credentials");
+    };
+    assert_eq!(
+        signing_canister(id_alias_vc.as_str().expect("malformed credential")),
+        vc_flow_signers.ii_canister_id
+    );
+    assert_eq!(
+        signing_canister(requested_vc.as_str().expect("malformed credential")),
+        vc_flow_signers.issuer_canister_id
+    );
+
+    // The requested credential is issued to the id_alias, and has not expired.
+    let subject = claims.sub().expect("accepted credential without sub");
+    assert_eq!(principal_for_did(subject), Ok(alias_tuple.id_alias));
+    let exp = claims.exp().expect("accepted credential without exp");
+    assert!(
+        i128::from(exp) * 1_000_000_000 > CURRENT_TIME_NS as i128,
+        "accepted expired credential"
+    );
+});
@@ -0,0 +1,354 @@
+//! Alternative origins of relying parties and issuers.
+//!
+//! A dapp reachable under several origins can ask II to use the same derivation origin (and
+//! hence the same principals) for all of them, by serving an alternative origins document at
+//! `<derivation origin>/.well-known/ii-alternative-origins`, e.g.
+//! ```json
+//! { "alternativeOrigins": ["https://app.example.com", "https://example.com"] }
+//! ```
+//! II accepts a derivation origin only for frontends whose origin is listed in the document
+//! (cf. [Alternative Frontend Origins](https://internetcomputer.org/docs/current/references/ii-spec#alternative-frontend-origins)).
+//!
+//! Issuers use the document to answer `derivation_origin` requests only for their listed
+//! frontends (cf. `AlternativeOrigins::derivation_origin()`), and relying parties to accept
+//! presentations whose id_alias credential was derived for any of their origins
+//! (cf. `VerificationOptions::with_alternative_origins()`).
+use crate::issuer_api::{DerivationOriginData, DerivationOriginError, DerivationOriginRequest};
+use crate::{
+    AliasTuple, CredentialVerificationError, check_alias_tuple, matches_expected_origin,
+    normalize_origin,
+};
+use candid::Principal;
+use serde::{Deserialize, Serialize};
+
+/// The path under which the alternative origins document is served.
+pub const ALTERNATIVE_ORIGINS_PATH: &str = "/.well-known/ii-alternative-origins";
+/// The maximal number of origins II accepts in an alternative origins document.
+pub const MAX_ALTERNATIVE_ORIGINS: usize = 10;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum AlternativeOriginsError {
+    /// The document is not valid JSON, or does not contain `alternativeOrigins`.
+    InvalidDocument(String),
+    /// The document lists more than `MAX_ALTERNATIVE_ORIGINS` origins.
+    TooManyOrigins(usize),
+    /// The given entry is not an origin as accepted by II.
+    InvalidOrigin(String),
+}
+
+/// A validated alternative origins document.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlternativeOrigins {
+    alternative_origins: Vec<String>,
+}
+
+// The document as served, before validation.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AlternativeOriginsDocument {
+    alternative_origins: Vec<String>,
+}
+
+impl AlternativeOrigins {
+    /// Validates the given origins, each of which must be an `https` origin (or an `http`
+    /// origin on `localhost`) without path, query, fragment, default port or upper-case letters,
+    /// i.e. exactly as the browser reports the origin of a frontend to II.
+    pub fn new(origins: Vec<String>) -> Result<Self, AlternativeOriginsError> {
+        if origins.len() > MAX_ALTERNATIVE_ORIGINS {
+            return Err(AlternativeOriginsError::TooManyOrigins(origins.len()));
+        }
+        if let Some(origin) = origins.iter().find(|origin| !is_valid_origin(origin)) {
+            return Err(AlternativeOriginsError::InvalidOrigin(origin.clone()));
+        }
+        Ok(Self {
+            alternative_origins: origins,
+        })
+    }
+
+    /// Parses and validates an alternative origins document, cf. `AlternativeOrigins::new()`.
+    pub fn from_json(json: &str) -> Result<Self, AlternativeOriginsError> {
+        let document: AlternativeOriginsDocument = serde_json::from_str(json)
+            .map_err(|e| AlternativeOriginsError::InvalidDocument(e.to_string()))?;
+        Self::new(document.alternative_origins)
+    }
+
+    /// Returns the document to be served at `ALTERNATIVE_ORIGINS_PATH`.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("failed serializing alternative origins")
+    }
+
+    pub fn origins(&self) -> &[String] {
+        &self.alternative_origins
+    }
+
+    /// Returns whether the given origin (in any form, cf. `normalize_origin()`) is listed.
+    pub fn contains(&self, origin: &str) -> bool {
+        normalize_origin(origin).is_some_and(|origin| self.alternative_origins.contains(&origin))
+    }
+
+    /// Answers a `derivation_origin` request of II for an issuer with the given derivation
+    /// origin, which serves this document: returns `derivation_origin` if the frontend of the
+    /// request is the derivation origin itself or listed, and `UnsupportedOrigin` otherwise.
+    pub fn derivation_origin(
+        &self,
+        derivation_origin: &str,
+        req: &DerivationOriginRequest,
+    ) -> Result<DerivationOriginData, DerivationOriginError> {
+        let Some(frontend) = normalize_origin(&req.frontend_hostname) else {
+            return Err(DerivationOriginError::UnsupportedOrigin(
+                req.frontend_hostname.clone(),
+            ));
+        };
+        if Some(&frontend) != normalize_origin(derivation_origin).as_ref()
+            && !self.contains(&frontend)
+        {
+            return Err(DerivationOriginError::UnsupportedOrigin(
+                req.frontend_hostname.clone(),
+            ));
+        }
+        Ok(DerivationOriginData {
+            origin: derivation_origin.to_string(),
+        })
+    }
+}
+
+// II rejects documents with entries which are not the origin of a frontend, so such entries
+// are rejected already when the document is created.
+fn is_valid_origin(origin: &str) -> bool {
+    let Some((scheme, host)) = origin.split_once("://") else {
+        return false;
+    };
+    let hostname = host.rsplit_once(':').map_or(host, |(hostname, _)| hostname);
+    let is_localhost = hostname == "localhost" || hostname.ends_with(".localhost");
+    let valid_scheme = scheme == "https" || (scheme == "http" && is_localhost);
+    valid_scheme
+        && !hostname.is_empty()
+        && !host.contains('@')
+        && normalize_origin(origin).as_deref() == Some(origin)
+}
+
+/// Checks that the alias tuple was derived for the expected subject, and for the expected
+/// derivation origin or any of the alternative origins (cf. `check_alias_tuple()`).
+pub fn check_alias_tuple_with_alternative_origins(
+    alias_tuple: &AliasTuple,
+    expected_vc_subject: &Principal,
+    expected_derivation_origin: &str,
+    alternative_origins: &AlternativeOrigins,
+    ii_canister_id: &Principal,
+) -> Result<(), CredentialVerificationError> {
+    check_alias_tuple(
+        alias_tuple,
+        expected_vc_subject,
+        expected_derivation_origin,
+        ii_canister_id,
+    )
+    .or_else(|e| {
+        let is_alternative = alias_tuple.id_dapp == *expected_vc_subject
+            && alternative_origins
+                .origins()
+                .iter()
+                .any(|origin| matches_expected_origin(ii_canister_id, origin, alias_tuple));
+        if is_alternative { Ok(()) } else { Err(e) }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+
+    const RP_ORIGIN: &str = "https://rp.test";
+    const ALTERNATIVE_ORIGIN: &str = "https://app.rp.test";
+
+    fn id_dapp() -> Principal {
+        Principal::from_slice(&[42; 29])
+    }
+
+    fn ii_canister_id() -> Principal {
+        Principal::from_text("rwlgt-iiaaa-aaaaa-aaaaa-cai").unwrap()
+    }
+
+    fn alternative_origins() -> AlternativeOrigins {
+        AlternativeOrigins::new(vec![
+            ALTERNATIVE_ORIGIN.to_string(),
+            "http://bd3sg-teaaa-aaaaa-qaaba-cai.localhost:4943".to_string(),
+        ])
+        .unwrap()
+    }
+
+    fn alias_tuple(derivation_origin: &str) -> AliasTuple {
+        AliasTuple {
+            id_alias: Principal::from_slice(&[7; 29]),
+            id_dapp: id_dapp(),
+            derivation_origin: derivation_origin.to_string(),
+        }
+    }
+
+    #[test]
+    fn should_parse_and_serialize_document() {
+        let json = r#"{"alternativeOrigins":["https://app.rp.test","http://bd3sg-teaaa-aaaaa-qaaba-cai.localhost:4943"]}"#;
+
+        let origins = AlternativeOrigins::from_json(json).unwrap();
+
+        assert_eq!(origins, alternative_origins());
+        assert_eq!(origins.to_json(), json);
+        assert_eq!(
+            AlternativeOrigins::from_json(r#"{"alternativeOrigins":[]}"#),
+            Ok(AlternativeOrigins::default())
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_documents() {
+        assert_matches!(
+            AlternativeOrigins::from_json(r#"{"origins":[]}"#),
+            Err(AlternativeOriginsError::InvalidDocument(_))
+        );
+        assert_matches!(
+            AlternativeOrigins::from_json(r#"{"alternativeOrigins":"https://rp.test"}"#),
+            Err(AlternativeOriginsError::InvalidDocument(_))
+        );
+        let too_many = (0..=MAX_ALTERNATIVE_ORIGINS)
+            .map(|i| format!("https://{}.rp.test", i))
+            .collect();
+        assert_eq!(
+            AlternativeOrigins::new(too_many),
+            Err(AlternativeOriginsError::TooManyOrigins(
+                MAX_ALTERNATIVE_ORIGINS + 1
+            ))
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_origins() {
+        for origin in [
+            "rp.test",
+            "http://rp.test",
+            "https://rp.test/",
+            "https://rp.test/app",
+            "https://rp.test?app",
+            "https://RP.test",
+            "https://rp.test:443",
+            "https://user@rp.test",
+            "https://",
+            "ftp://rp.test",
+        ] {
+            assert_eq!(
+                AlternativeOrigins::new(vec![origin.to_string()]),
+                Err(AlternativeOriginsError::InvalidOrigin(origin.to_string())),
+                "{}",
+                origin
+            );
+        }
+    }
+
+    #[test]
+    fn should_contain_origins_in_any_form() {
+        let origins = alternative_origins();
+
+        assert!(origins.contains(ALTERNATIVE_ORIGIN));
+        assert!(origins.contains("https://APP.rp.test:443/"));
+        assert!(!origins.contains("https://app.rp.test/index.html"));
+        assert!(origins.contains("http://127.0.0.1:4943/?canisterId=bd3sg-teaaa-aaaaa-qaaba-cai"));
+        assert!(!origins.contains(RP_ORIGIN));
+        assert!(!origins.contains("http://app.rp.test"));
+    }
+
+    #[test]
+    fn should_answer_derivation_origin_only_for_listed_frontends() {
+        let origins = alternative_origins();
+        let request = |frontend_hostname: &str| DerivationOriginRequest {
+            frontend_hostname: frontend_hostname.to_string(),
+        };
+        let expected = Ok(DerivationOriginData {
+            origin: RP_ORIGIN.to_string(),
+        });
+
+        assert_eq!(
+            origins.derivation_origin(RP_ORIGIN, &request(ALTERNATIVE_ORIGIN)),
+            expected
+        );
+        assert_eq!(
+            origins.derivation_origin(RP_ORIGIN, &request(RP_ORIGIN)),
+            expected
+        );
+        for frontend_hostname in ["https://evil.test", &format!("{}/app", ALTERNATIVE_ORIGIN)] {
+            assert_eq!(
+                origins.derivation_origin(RP_ORIGIN, &request(frontend_hostname)),
+                Err(DerivationOriginError::UnsupportedOrigin(
+                    frontend_hostname.to_string()
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn should_check_alias_tuple_with_alternative_origins() {
+        let check = |alias_tuple: &AliasTuple, expected_vc_subject: &Principal| {
+            check_alias_tuple_with_alternative_origins(
+                alias_tuple,
+                expected_vc_subject,
+                RP_ORIGIN,
+                &alternative_origins(),
+                &ii_canister_id(),
+            )
+        };
+
+        assert_matches!(check(&alias_tuple(RP_ORIGIN), &id_dapp()), Ok(()));
+        assert_matches!(check(&alias_tuple(ALTERNATIVE_ORIGIN), &id_dapp()), Ok(()));
+        assert_matches!(
+            check(&alias_tuple("https://evil.test"), &id_dapp()),
+            Err(CredentialVerificationError::UnexpectedClaim(_))
+        );
+        assert_matches!(
+            check(
+                &alias_tuple(ALTERNATIVE_ORIGIN),
+                &Principal::from_slice(&[43; 29])
+            ),
+            Err(CredentialVerificationError::UnexpectedClaim(_))
+        );
+    }
+
+    #[cfg(feature = "test-utils")]
+    #[test]
+    fn should_validate_presentation_for_alternative_origin() {
+        use crate::issuer_api::{ArgumentValue, CredentialSpec};
+        use crate::test_utils::TestVcFlow;
+        use crate::{
+            PresentationVerificationError, VerificationOptions,
+            validate_ii_presentation_and_claims_with_options,
+        };
+        use std::collections::HashMap;
+
+        const NOW_NS: u128 = 1_700_000_000_000_000_000;
+        let flow = TestVcFlow::default();
+        let spec = CredentialSpec {
+            credential_type: "VerifiedAdult".to_string(),
+            arguments: Some(HashMap::from([(
+                "minAge".to_string(),
+                ArgumentValue::Int(18),
+            )])),
+        };
+        let validate = |derivation_origin: &str| {
+            let vp_jwt = flow.presentation_jwt(id_dapp(), derivation_origin, &spec, NOW_NS);
+            validate_ii_presentation_and_claims_with_options(
+                &vp_jwt,
+                id_dapp(),
+                RP_ORIGIN,
+                &flow.vc_flow_signers(),
+                &spec,
+                &VerificationOptions::default().with_alternative_origins(&alternative_origins()),
+                &flow.ic_root_key(),
+                NOW_NS,
+            )
+        };
+
+        assert_matches!(validate(RP_ORIGIN), Ok(()));
+        assert_matches!(validate(ALTERNATIVE_ORIGIN), Ok(()));
+        assert_matches!(
+            validate("https://evil.test"),
+            Err(PresentationVerificationError::InvalidIdAliasCredential(_))
+        );
+    }
+}
@@ -0,0 +1,59 @@
+// Verification of IC certificates, as returned by `ic_cdk::api::data_certificate()`,
+// which certify the certified data of a canister.
+use candid::Principal;
+use ic_canister_sig_creation::IC_ROOT_PK_DER_PREFIX;
+use ic_certificate_verification::{CertificateVerificationError, VerifyCertificate};
+use ic_certification::{Certificate, Hash, LookupResult};
+use std::time::Duration;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum CertificateError {
+    /// The certificate is malformed, or not valid for the canister.
+    Invalid(String),
+    /// The certificate was created more than the maximal age before the current time.
+    Stale { certified_at_ns: u128 },
+}
+
+/// Verifies the given CBOR-encoded certificate against the root public key `root_pk_raw`,
+/// including a possible subnet delegation, and returns the certified data of `canister_id`.
+/// The time of the certificate must be at most `max_age` away from `current_time_ns`.
+pub(crate) fn verify_certified_data(
+    certificate_cbor: &[u8],
+    canister_id: &Principal,
+    root_pk_raw: &[u8],
+    current_time_ns: u128,
+    max_age: Duration,
+) -> Result<Hash, CertificateError> {
+    let certificate: Certificate = serde_cbor::from_slice(certificate_cbor)
+        .map_err(|e| invalid(format!("malformed certificate: {}", e)))?;
+    let root_pk_der = [IC_ROOT_PK_DER_PREFIX.as_slice(), root_pk_raw].concat();
+    certificate
+        .verify(
+            canister_id.as_slice(),
+            &root_pk_der,
+            &current_time_ns,
+            &max_age.as_nanos(),
+        )
+        .map_err(|e| match e {
+            CertificateVerificationError::TimeTooFarInThePast {
+                certificate_time, ..
+            } => CertificateError::Stale {
+                certified_at_ns: certificate_time,
+            },
+            e => invalid(format!("invalid certificate: {}", e)),
+        })?;
+
+    let path: [&[u8]; 3] = [b"canister", canister_id.as_slice(), b"certified_data"];
+    match certificate.tree.lookup_path(path) {
+        LookupResult::Found(certified_data) => certified_data
+            .try_into()
+            .map_err(|_| invalid("malformed certified data".to_string())),
+        _ => Err(invalid(
+            "certificate does not contain certified data of the canister".to_string(),
+        )),
+    }
+}
+
+fn invalid(message: String) -> CertificateError {
+    CertificateError::Invalid(message)
+}
@@ -0,0 +1,382 @@
+//! Challenges issued by relying parties, which holders include as `nonce` in holder-bound
+//! presentations (cf. `holder_binding`).  A challenge binds a presentation to a particular
+//! request of the relying party, so that a presentation cannot be replayed.
+//!
+//! Challenges are derived by a `ChallengeGenerator` from a random seed (in canisters
+//! obtained via `raw_rand` of the management canister), are stored per caller in a
+//! `ChallengeStore`, and can be consumed exactly once before they expire.
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use candid::{CandidType, Deserialize, Principal};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+/// Number of random bytes in the nonce of a challenge.
+pub const CHALLENGE_NONCE_BYTES: usize = 32;
+/// Default lifetime of a challenge.
+pub const DEFAULT_CHALLENGE_TTL: Duration = Duration::from_secs(5 * 60);
+/// Default maximal number of outstanding challenges per caller.
+pub const DEFAULT_MAX_CHALLENGES_PER_CALLER: usize = 10;
+/// Default maximal number of outstanding challenges of all callers.
+pub const DEFAULT_MAX_CHALLENGES: usize = 10_000;
+const CHALLENGE_GENERATOR_DOMAIN: &[u8] = b"ic-vc-challenge";
+
+/// A challenge issued by a relying party.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Challenge {
+    /// base64url-encoded random nonce.
+    pub nonce: String,
+    /// Time (in nanoseconds since the UNIX epoch) after which the challenge is no longer valid.
+    pub expires_at_ns: u128,
+}
+
+impl Challenge {
+    /// Creates a challenge from the given random bytes, valid for `ttl` from `current_time_ns`.
+    pub fn new(
+        random_bytes: [u8; CHALLENGE_NONCE_BYTES],
+        current_time_ns: u128,
+        ttl: Duration,
+    ) -> Self {
+        Self {
+            nonce: BASE64.encode(random_bytes),
+            expires_at_ns: current_time_ns.saturating_add(ttl.as_nanos()),
+        }
+    }
+
+    pub fn is_expired(&self, current_time_ns: u128) -> bool {
+        self.expires_at_ns <= current_time_ns
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ChallengeError {
+    /// The challenge was never issued to the caller, or has already been consumed.
+    UnknownChallenge,
+    /// The challenge was issued, but is no longer valid.
+    ExpiredChallenge,
+}
+
+/// Generates the random bytes of challenges from a secret seed, by hashing the seed
+/// together with a counter.  The seed must be unpredictable, cf. `from_raw_rand()`.
+#[derive(Clone)]
+pub struct ChallengeGenerator {
+    seed: [u8; 32],
+    counter: u64,
+}
+
+impl ChallengeGenerator {
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Self { seed, counter: 0 }
+    }
+
+    /// Creates a generator seeded with randomness obtained from the management canister.
+    /// Can only be used within a canister.
+    pub async fn from_raw_rand() -> Result<Self, String> {
+        let random_bytes = ic_cdk::management_canister::raw_rand()
+            .await
+            .map_err(|e| format!("failed obtaining randomness: {:?}", e))?;
+        let seed: [u8; 32] = random_bytes
+            .try_into()
+            .map_err(|_| "unexpected length of randomness".to_string())?;
+        Ok(Self::from_seed(seed))
+    }
+
+    pub fn next_bytes(&mut self) -> [u8; CHALLENGE_NONCE_BYTES] {
+        let mut hasher = Sha256::new();
+        hasher.update(CHALLENGE_GENERATOR_DOMAIN);
+        hasher.update(self.seed);
+        hasher.update(self.counter.to_be_bytes());
+        self.counter += 1;
+        hasher.finalize().into()
+    }
+}
+
+/// Storage for the challenges issued by a relying party, keyed by the caller to whom
+/// they were issued.  Every challenge can be consumed at most once, and only by its caller.
+/// Can be persisted across upgrades, e.g. via stable memory.
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct ChallengeStore {
+    // Maps (caller, nonce) to the expiration time of the challenge.
+    challenges: BTreeMap<(Principal, String), u128>,
+    // The keys of `challenges` ordered by expiration time, to prune and evict challenges
+    // without scanning all of them.
+    expirations: BTreeSet<(u128, Principal, String)>,
+    ttl_ns: u64,
+    max_challenges_per_caller: usize,
+    max_challenges: usize,
+}
+
+impl Default for ChallengeStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_CHALLENGE_TTL, DEFAULT_MAX_CHALLENGES_PER_CALLER)
+    }
+}
+
+impl ChallengeStore {
+    /// Creates a store for challenges that are valid for `ttl`, and of which at most
+    /// `max_challenges_per_caller` can be outstanding for the same caller, and at most
+    /// `DEFAULT_MAX_CHALLENGES` in total.
+    pub fn new(ttl: Duration, max_challenges_per_caller: usize) -> Self {
+        Self {
+            challenges: BTreeMap::new(),
+            expirations: BTreeSet::new(),
+            ttl_ns: ttl.as_nanos().try_into().unwrap_or(u64::MAX),
+            max_challenges_per_caller: max_challenges_per_caller.max(1),
+            max_challenges: DEFAULT_MAX_CHALLENGES,
+        }
+    }
+
+    /// Returns this store with at most `max_challenges` outstanding challenges in total.
+    pub fn with_max_challenges(self, max_challenges: usize) -> Self {
+        Self {
+            max_challenges: max_challenges.max(1),
+            ..self
+        }
+    }
+
+    /// Issues a new challenge to `caller` and stores it.  If the caller already has the maximal
+    /// number of outstanding challenges, the one that expires first is dropped.  If the store
+    /// is full, the challenges of any caller that expire first are dropped.
+    pub fn issue(
+        &mut self,
+        caller: Principal,
+        generator: &mut ChallengeGenerator,
+        current_time_ns: u128,
+    ) -> Challenge {
+        self.prune_expired(current_time_ns);
+        let mut outstanding: Vec<(String, u128)> = self
+            .caller_challenges(caller)
+            .map(|(nonce, expires_at_ns)| (nonce.clone(), *expires_at_ns))
+            .collect();
+        if outstanding.len() >= self.max_challenges_per_caller {
+            outstanding.sort_by_key(|(_, expires_at_ns)| *expires_at_ns);
+            let excess = outstanding.len() + 1 - self.max_challenges_per_caller;
+            for (nonce, _) in outstanding.into_iter().take(excess) {
+                self.remove(caller, &nonce);
+            }
+        }
+        while self.challenges.len() >= self.max_challenges {
+            let Some((_, oldest_caller, oldest_nonce)) = self.expirations.pop_first() else {
+                break;
+            };
+            self.challenges.remove(&(oldest_caller, oldest_nonce));
+        }
+        let challenge = Challenge::new(
+            generator.next_bytes(),
+            current_time_ns,
+            Duration::from_nanos(self.ttl_ns),
+        );
+        self.insert(caller, challenge.clone());
+        challenge
+    }
+
+    pub fn insert(&mut self, caller: Principal, challenge: Challenge) {
+        self.remove(caller, &challenge.nonce);
+        self.expirations
+            .insert((challenge.expires_at_ns, caller, challenge.nonce.clone()));
+        self.challenges
+            .insert((caller, challenge.nonce), challenge.expires_at_ns);
+    }
+
+    /// Consumes the challenge with the given nonce issued to `caller`, i.e. removes it from
+    /// the store, and checks that it has not expired yet.
+    pub fn consume(
+        &mut self,
+        caller: Principal,
+        nonce: &str,
+        current_time_ns: u128,
+    ) -> Result<(), ChallengeError> {
+        let expires_at_ns = self
+            .remove(caller, nonce)
+            .ok_or(ChallengeError::UnknownChallenge)?;
+        if expires_at_ns <= current_time_ns {
+            return Err(ChallengeError::ExpiredChallenge);
+        }
+        Ok(())
+    }
+
+    /// Removes all the challenges that have expired at `current_time_ns`.  Only visits the
+    /// expired challenges.
+    pub fn prune_expired(&mut self, current_time_ns: u128) {
+        while let Some((expires_at_ns, _, _)) = self.expirations.first() {
+            if *expires_at_ns > current_time_ns {
+                break;
+            }
+            if let Some((_, caller, nonce)) = self.expirations.pop_first() {
+                self.challenges.remove(&(caller, nonce));
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.challenges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.challenges.is_empty()
+    }
+
+    // Removes the given challenge, and returns its expiration time if it was stored.
+    fn remove(&mut self, caller: Principal, nonce: &str) -> Option<u128> {
+        let expires_at_ns = self.challenges.remove(&(caller, nonce.to_string()))?;
+        self.expirations
+            .remove(&(expires_at_ns, caller, nonce.to_string()));
+        Some(expires_at_ns)
+    }
+
+    fn caller_challenges(&self, caller: Principal) -> impl Iterator<Item = (&String, &u128)> {
+        self.challenges
+            .range((caller, String::new())..)
+            .take_while(move |((challenge_caller, _), _)| *challenge_caller == caller)
+            .map(|((_, nonce), expires_at_ns)| (nonce, expires_at_ns))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+    const NOW_NS: u128 = 1_700_000_000_000_000_000;
+
+    fn caller(id: u8) -> Principal {
+        Principal::from_slice(&[id; 10])
+    }
+
+    #[test]
+    fn should_create_challenge_with_expiry() {
+        let challenge = Challenge::new([7; CHALLENGE_NONCE_BYTES], NOW_NS, MINUTE);
+        assert_eq!(challenge.nonce.len(), 43);
+        assert_eq!(challenge.expires_at_ns, NOW_NS + MINUTE.as_nanos());
+        assert!(!challenge.is_expired(NOW_NS));
+        assert!(challenge.is_expired(NOW_NS + MINUTE.as_nanos()));
+    }
+
+    #[test]
+    fn should_generate_distinct_challenges() {
+        let mut generator = ChallengeGenerator::from_seed([42; 32]);
+        let first = generator.next_bytes();
+        let second = generator.next_bytes();
+        assert_ne!(first, second);
+        assert_eq!(
+            ChallengeGenerator::from_seed([42; 32]).next_bytes(),
+            first,
+            "generator should be deterministic for a given seed"
+        );
+        assert_ne!(ChallengeGenerator::from_seed([43; 32]).next_bytes(), first);
+    }
+
+    #[test]
+    fn should_consume_challenge_only_once() {
+        let mut store = ChallengeStore::default();
+        let mut generator = ChallengeGenerator::from_seed([1; 32]);
+        let challenge = store.issue(caller(1), &mut generator, NOW_NS);
+        assert_eq!(
+            challenge.expires_at_ns,
+            NOW_NS + DEFAULT_CHALLENGE_TTL.as_nanos()
+        );
+        assert_eq!(
+            store.consume(caller(1), &challenge.nonce, NOW_NS + 1),
+            Ok(())
+        );
+        assert_eq!(
+            store.consume(caller(1), &challenge.nonce, NOW_NS + 1),
+            Err(ChallengeError::UnknownChallenge)
+        );
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn should_fail_consuming_challenge_of_other_caller() {
+        let mut store = ChallengeStore::default();
+        let mut generator = ChallengeGenerator::from_seed([2; 32]);
+        let challenge = store.issue(caller(1), &mut generator, NOW_NS);
+        assert_eq!(
+            store.consume(caller(2), &challenge.nonce, NOW_NS),
+            Err(ChallengeError::UnknownChallenge)
+        );
+        assert_eq!(store.consume(caller(1), &challenge.nonce, NOW_NS), Ok(()));
+    }
+
+    #[test]
+    fn should_fail_consuming_unknown_or_expired_challenge() {
+        let mut store = ChallengeStore::new(MINUTE, 3);
+        let mut generator = ChallengeGenerator::from_seed([3; 32]);
+        let challenge = store.issue(caller(1), &mut generator, NOW_NS);
+        assert_eq!(
+            store.consume(caller(1), "unknown nonce", NOW_NS),
+            Err(ChallengeError::UnknownChallenge)
+        );
+        assert_eq!(
+            store.consume(caller(1), &challenge.nonce, challenge.expires_at_ns),
+            Err(ChallengeError::ExpiredChallenge)
+        );
+    }
+
+    #[test]
+    fn should_prune_expired_challenges() {
+        let mut store = ChallengeStore::new(MINUTE, 3);
+        let mut generator = ChallengeGenerator::from_seed([4; 32]);
+        store.issue(caller(1), &mut generator, NOW_NS);
+        store.issue(caller(2), &mut generator, NOW_NS + MINUTE.as_nanos() / 2);
+        store.prune_expired(NOW_NS + MINUTE.as_nanos());
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn should_limit_outstanding_challenges_per_caller() {
+        let mut store = ChallengeStore::new(MINUTE, 2);
+        let mut generator = ChallengeGenerator::from_seed([5; 32]);
+        let first = store.issue(caller(1), &mut generator, NOW_NS);
+        let second = store.issue(caller(1), &mut generator, NOW_NS + 1);
+        let third = store.issue(caller(1), &mut generator, NOW_NS + 2);
+        let other = store.issue(caller(2), &mut generator, NOW_NS + 3);
+        assert_eq!(store.len(), 3);
+        assert_eq!(
+            store.consume(caller(1), &first.nonce, NOW_NS + 4),
+            Err(ChallengeError::UnknownChallenge)
+        );
+        assert_eq!(store.consume(caller(1), &second.nonce, NOW_NS + 4), Ok(()));
+        assert_eq!(store.consume(caller(1), &third.nonce, NOW_NS + 4), Ok(()));
+        assert_eq!(store.consume(caller(2), &other.nonce, NOW_NS + 4), Ok(()));
+    }
+
+    #[test]
+    fn should_limit_outstanding_challenges_of_all_callers() {
+        let mut store = ChallengeStore::new(MINUTE, 2).with_max_challenges(3);
+        let mut generator = ChallengeGenerator::from_seed([6; 32]);
+        let challenges: Vec<Challenge> = (0..5)
+            .map(|i| store.issue(caller(i), &mut generator, NOW_NS + i as u128))
+            .collect();
+        assert_eq!(store.len(), 3);
+        for (i, challenge) in challenges.iter().enumerate().take(2) {
+            assert_eq!(
+                store.consume(caller(i as u8), &challenge.nonce, NOW_NS + 5),
+                Err(ChallengeError::UnknownChallenge)
+            );
+        }
+        for (i, challenge) in challenges.iter().enumerate().skip(2) {
+            assert_eq!(
+                store.consume(caller(i as u8), &challenge.nonce, NOW_NS + 5),
+                Ok(())
+            );
+        }
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn should_persist_challenge_store() {
+        let mut store = ChallengeStore::new(MINUTE, 2);
+        let mut generator = ChallengeGenerator::from_seed([7; 32]);
+        let challenge = store.issue(caller(1), &mut generator, NOW_NS);
+        let bytes = candid::encode_one(&store).expect("failed encoding store");
+        let mut restored: ChallengeStore =
+            candid::decode_one(&bytes).expect("failed decoding store");
+        assert_eq!(restored, store);
+        assert_eq!(
+            restored.consume(caller(1), &challenge.nonce, NOW_NS + 1),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! Source of the current time.
+//!
+//! `identity_core` obtains the current time via a function registered with
+//! `register_custom_now_utc!`, which is registered by this crate depending on the enabled features:
+//!  * `ic-time` (default): the time of the IC, via `ic_cdk::api::time()`, for use in canisters.
+//!  * `std-time`: the system time, via `std::time::SystemTime`, for use off-chain (e.g. in a CLI
+//!    or a backend service).
+//!
+//! If both features are enabled (e.g. due to feature unification in a workspace that contains
+//! both canisters and native binaries), `ic-time` is used when compiling to `wasm32`, and
+//! `std-time` otherwise.
+//!
+//! Additionally, a `Clock` can be set at runtime via `set_clock()`, which then takes precedence
+//! over the clock selected by the features.  If neither feature is enabled, a clock must be set
+//! before any functionality relying on the current time is used.
+use identity_core::common::Timestamp;
+use std::sync::RwLock;
+
+/// A source of the current time.
+pub trait Clock: Send + Sync {
+    /// Returns the current time in nanoseconds since the UNIX epoch.
+    fn now_ns(&self) -> u128;
+}
+
+/// The time of the IC, as returned by `ic_cdk::api::time()`.  Can only be used within a canister.
+#[cfg(feature = "ic-time")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IcClock;
+
+#[cfg(feature = "ic-time")]
+impl Clock for IcClock {
+    fn now_ns(&self) -> u128 {
+        ic_cdk::api::time() as u128
+    }
+}
+
+/// The system time.  Cannot be used within a canister.
+#[cfg(feature = "std-time")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std-time")]
+impl Clock for SystemClock {
+    fn now_ns(&self) -> u128 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("system time before UNIX epoch")
+            .as_nanos()
+    }
+}
+
+/// A clock that always returns the same time, e.g. for tests or for verifying
+/// credentials as of a given point in time.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FixedClock(pub u128);
+
+impl Clock for FixedClock {
+    fn now_ns(&self) -> u128 {
+        self.0
+    }
+}
+
+static CLOCK: RwLock<Option<Box<dyn Clock>>> = RwLock::new(None);
+
+/// Sets the clock used by this crate (and by `identity_core`), replacing the clock
+/// selected by the features.
+pub fn set_clock(clock: impl Clock + 'static) {
+    *CLOCK.write().expect("clock lock poisoned") = Some(Box::new(clock));
+}
+
+/// Removes the clock set via `set_clock()`, so that the clock selected by the features is used.
+pub fn reset_clock() {
+    *CLOCK.write().expect("clock lock poisoned") = None;
+}
+
+/// Returns the current time in nanoseconds since the UNIX epoch, as returned by the clock
+/// set via `set_clock()`, or else by the clock selected by the features.
+pub fn now_ns() -> u128 {
+    if let Some(clock) = CLOCK.read().expect("clock lock poisoned").as_ref() {
+        return clock.now_ns();
+    }
+    default_now_ns()
+}
+
+#[cfg(all(
+    feature = "ic-time",
+    any(target_arch = "wasm32", not(feature = "std-time"))
+))]
+fn default_now_ns() -> u128 {
+    IcClock.now_ns()
+}
+
+#[cfg(all(
+    feature = "std-time",
+    any(not(target_arch = "wasm32"), not(feature = "ic-time"))
+))]
+fn default_now_ns() -> u128 {
+    SystemClock.now_ns()
+}
+
+#[cfg(not(any(feature = "ic-time", feature = "std-time")))]
+fn default_now_ns() -> u128 {
+    panic!("no clock available: enable feature `ic-time` or `std-time`, or call `set_clock()`")
+}
+
+pub(crate) fn timestamp_from_ns(time_ns: u128) -> Timestamp {
+    Timestamp::from_unix((time_ns / 1_000_000_000) as i64).expect("invalid timestamp")
+}
+
+#[cfg(not(test))]
+mod registration {
+    use super::*;
+    use identity_core::register_custom_now_utc;
+
+    fn now_utc() -> Timestamp {
+        timestamp_from_ns(now_ns())
+    }
+    register_custom_now_utc!(now_utc);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_use_clock_set_at_runtime() {
+        set_clock(FixedClock(1_620_328_630_000_000_000));
+        assert_eq!(now_ns(), 1_620_328_630_000_000_000);
+        assert_eq!(
+            timestamp_from_ns(now_ns()),
+            Timestamp::from_unix(1_620_328_630).unwrap()
+        );
+        set_clock(FixedClock(42));
+        assert_eq!(now_ns(), 42);
+        reset_clock();
+    }
+
+    // With both features enabled (e.g. via `--all-features`), native builds use the system time.
+    #[cfg(all(feature = "ic-time", feature = "std-time", not(target_arch = "wasm32")))]
+    #[test]
+    fn should_use_system_clock_if_both_features_are_enabled_off_wasm() {
+        let before_ns = SystemClock.now_ns();
+        let now = default_now_ns();
+        assert!(before_ns <= now && now <= SystemClock.now_ns());
+    }
+}
@@ -0,0 +1,356 @@
+//! Structured diagnostics of the verification steps.
+//!
+//! The verification functions of this crate report every step of the verification of a
+//! presentation as a `VerificationEvent` to the `Diagnostics` set via `set_diagnostics()`:
+//! which credential was checked, which check was performed, how long it took, and whether it
+//! passed.  The following implementations are provided:
+//!  * `IcPrintlnDiagnostics` (feature `ic-time`): writes the events to the canister log, via
+//!    `ic_cdk::println!`.
+//!  * `TracingDiagnostics` (feature `tracing`): emits the events as `tracing` events, to be
+//!    consumed by the subscribers of a native service.
+//!
+//! If no `Diagnostics` has been set, the events are emitted via `TracingDiagnostics` if the
+//! feature `tracing` is enabled, and discarded otherwise.
+use crate::verification_error::PresentedCredential;
+use std::fmt;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// A verification step.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VerificationCheck {
+    /// Decoding the presentation and extracting the contained credentials.
+    Presentation,
+    /// The signature, expiration and subject of a credential.
+    Credential,
+    /// The claims of the requested credential, against the issuer and the credential spec.
+    Claims,
+    /// The policy of the verifier on the alias tuple, e.g. the expected subject and
+    /// derivation origin.
+    Policy,
+    /// The signing key of the requested credential, against the keys of the issuer.
+    IssuerKey,
+    /// The revocation status of the requested credential.
+    CredentialStatus,
+}
+
+/// A verification step that has been performed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VerificationEvent {
+    /// The credential the check refers to, if any.
+    pub credential: Option<PresentedCredential>,
+    pub check: VerificationCheck,
+    /// The time the check took.  Always zero within canisters, where the time does not advance
+    /// during a message execution (cf. `instructions`).
+    pub duration: Duration,
+    /// The number of instructions executed by the check, within canisters with feature `ic-time`.
+    pub instructions: Option<u64>,
+    /// `Err` with a description of the error if the check failed.
+    pub outcome: Result<(), String>,
+}
+
+impl fmt::Display for VerificationEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "verification check {:?}", self.check)?;
+        if let Some(credential) = self.credential {
+            write!(f, " of {:?} credential", credential)?;
+        }
+        match &self.outcome {
+            Ok(()) => write!(f, " passed")?,
+            Err(error) => write!(f, " failed: {}", error)?,
+        }
+        write!(f, " ({:?}", self.duration)?;
+        if let Some(instructions) = self.instructions {
+            write!(f, ", {} instructions", instructions)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// A sink of verification events.
+pub trait Diagnostics: Send + Sync {
+    fn on_event(&self, event: &VerificationEvent);
+}
+
+/// Writes verification events to the canister log, via `ic_cdk::println!`.  Can only be used
+/// within a canister.
+#[cfg(feature = "ic-time")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IcPrintlnDiagnostics;
+
+#[cfg(feature = "ic-time")]
+impl Diagnostics for IcPrintlnDiagnostics {
+    fn on_event(&self, event: &VerificationEvent) {
+        ic_cdk::println!("{}", event);
+    }
+}
+
+/// Emits verification events as `tracing` events with target `ic_verifiable_credentials`,
+/// at level `DEBUG` for passed checks and `WARN` for failed checks.
+#[cfg(feature = "tracing")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TracingDiagnostics;
+
+#[cfg(feature = "tracing")]
+impl Diagnostics for TracingDiagnostics {
+    fn on_event(&self, event: &VerificationEvent) {
+        let duration_us = event.duration.as_micros() as u64;
+        match &event.outcome {
+            Ok(()) => tracing::debug!(
+                target: "ic_verifiable_credentials",
+                credential = ?event.credential,
+                check = ?event.check,
+                duration_us,
+                instructions = ?event.instructions,
+                "verification check passed"
+            ),
+            Err(error) => tracing::warn!(
+                target: "ic_verifiable_credentials",
+                credential = ?event.credential,
+                check = ?event.check,
+                duration_us,
+                instructions = ?event.instructions,
+                error = %error,
+                "verification check failed"
+            ),
+        }
+    }
+}
+
+static DIAGNOSTICS: RwLock<Option<Box<dyn Diagnostics>>> = RwLock::new(None);
+
+/// Sets the sink of the verification events emitted by this crate.
+pub fn set_diagnostics(diagnostics: impl Diagnostics + 'static) {
+    *DIAGNOSTICS.write().expect("diagnostics lock poisoned") = Some(Box::new(diagnostics));
+}
+
+/// Removes the sink set via `set_diagnostics()`.
+pub fn reset_diagnostics() {
+    *DIAGNOSTICS.write().expect("diagnostics lock poisoned") = None;
+}
+
+fn is_enabled() -> bool {
+    cfg!(feature = "tracing") || DIAGNOSTICS.read().is_ok_and(|d| d.is_some())
+}
+
+fn emit(event: &VerificationEvent) {
+    let Ok(diagnostics) = DIAGNOSTICS.read() else {
+        return;
+    };
+    match diagnostics.as_ref() {
+        Some(diagnostics) => diagnostics.on_event(event),
+        None => default_on_event(event),
+    }
+}
+
+#[cfg(feature = "tracing")]
+fn default_on_event(event: &VerificationEvent) {
+    TracingDiagnostics.on_event(event);
+}
+
+#[cfg(not(feature = "tracing"))]
+fn default_on_event(_event: &VerificationEvent) {}
+
+/// Measures the wall-clock time, which is not available on `wasm32`.
+struct Stopwatch {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+    instructions: Option<u64>,
+}
+
+impl Stopwatch {
+    fn start() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: std::time::Instant::now(),
+            instructions: instruction_counter(),
+        }
+    }
+
+    fn elapsed(&self) -> (Duration, Option<u64>) {
+        #[cfg(not(target_arch = "wasm32"))]
+        let duration = self.start.elapsed();
+        #[cfg(target_arch = "wasm32")]
+        let duration = Duration::ZERO;
+        let instructions = self
+            .instructions
+            .zip(instruction_counter())
+            .map(|(start, end)| end.saturating_sub(start));
+        (duration, instructions)
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "ic-time"))]
+fn instruction_counter() -> Option<u64> {
+    Some(ic_cdk::api::instruction_counter())
+}
+
+#[cfg(not(all(target_arch = "wasm32", feature = "ic-time")))]
+fn instruction_counter() -> Option<u64> {
+    None
+}
+
+/// Performs the verification step `f`, and emits its outcome as a `VerificationEvent`.
+pub(crate) fn observe<T, E: fmt::Debug>(
+    credential: Option<PresentedCredential>,
+    check: VerificationCheck,
+    f: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    if !is_enabled() {
+        return f();
+    }
+    let stopwatch = Stopwatch::start();
+    let result = f();
+    let (duration, instructions) = stopwatch.elapsed();
+    emit(&VerificationEvent {
+        credential,
+        check,
+        duration,
+        instructions,
+        outcome: result.as_ref().map(|_| ()).map_err(|e| format!("{:?}", e)),
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+    use std::sync::{Arc, Mutex};
+    use std::thread::ThreadId;
+
+    /// Records the events emitted on the current thread, as tests run concurrently.
+    #[derive(Clone)]
+    struct RecordingDiagnostics {
+        thread: ThreadId,
+        events: Arc<Mutex<Vec<VerificationEvent>>>,
+    }
+
+    impl RecordingDiagnostics {
+        fn new() -> Self {
+            Self {
+                thread: std::thread::current().id(),
+                events: Arc::new(Mutex::new(vec![])),
+            }
+        }
+
+        fn events(&self) -> Vec<VerificationEvent> {
+            self.events.lock().unwrap().clone()
+        }
+    }
+
+    impl Diagnostics for RecordingDiagnostics {
+        fn on_event(&self, event: &VerificationEvent) {
+            if std::thread::current().id() == self.thread {
+                self.events.lock().unwrap().push(event.clone());
+            }
+        }
+    }
+
+    #[test]
+    fn should_report_failed_check() {
+        let recorder = RecordingDiagnostics::new();
+        set_diagnostics(recorder.clone());
+
+        let result = crate::verify_presentation(
+            "not a presentation",
+            &crate::environment::VcEnvironment::mainnet()
+                .vc_flow_signers(candid::Principal::anonymous(), "https://issuer.test"),
+            &crate::environment::IcRootKey::mainnet(),
+            0,
+            |_| Ok(()),
+        );
+        reset_diagnostics();
+
+        assert!(result.is_err());
+        let events = recorder.events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].credential, None);
+        assert_eq!(events[0].check, VerificationCheck::Presentation);
+        assert!(events[0].outcome.is_err());
+    }
+
+    #[cfg(feature = "test-utils")]
+    #[test]
+    fn should_report_all_checks_of_presentation() {
+        use crate::issuer_api::{ArgumentValue, CredentialSpec};
+        use crate::test_utils::TestVcFlow;
+        use crate::validate_ii_presentation_and_claims;
+        use candid::Principal;
+        use std::collections::HashMap;
+
+        const NOW_NS: u128 = 1_700_000_000_000_000_000;
+        const RP_ORIGIN: &str = "https://relying-party.test";
+        let flow = TestVcFlow::default();
+        let id_dapp = Principal::from_slice(&[7; 29]);
+        let spec = CredentialSpec {
+            credential_type: "VerifiedAdult".to_string(),
+            arguments: Some(HashMap::from([(
+                "minAge".to_string(),
+                ArgumentValue::Int(18),
+            )])),
+        };
+        let vp_jwt = flow.presentation_jwt(id_dapp, RP_ORIGIN, &spec, NOW_NS);
+        let recorder = RecordingDiagnostics::new();
+        set_diagnostics(recorder.clone());
+
+        let result = validate_ii_presentation_and_claims(
+            &vp_jwt,
+            id_dapp,
+            RP_ORIGIN.to_string(),
+            &flow.vc_flow_signers(),
+            &spec,
+            &flow.ic_root_key(),
+            NOW_NS,
+        );
+        reset_diagnostics();
+
+        assert_matches!(result, Ok(()));
+        let checks: Vec<_> = recorder
+            .events()
+            .into_iter()
+            .map(|event| {
+                assert_eq!(event.outcome, Ok(()));
+                (event.credential, event.check)
+            })
+            .collect();
+        assert_eq!(
+            checks,
+            vec![
+                (None, VerificationCheck::Presentation),
+                (
+                    Some(PresentedCredential::IdAlias),
+                    VerificationCheck::Credential
+                ),
+                (
+                    Some(PresentedCredential::Requested),
+                    VerificationCheck::Credential
+                ),
+                (
+                    Some(PresentedCredential::IdAlias),
+                    VerificationCheck::Policy
+                ),
+                (
+                    Some(PresentedCredential::Requested),
+                    VerificationCheck::Claims
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_describe_event() {
+        let event = VerificationEvent {
+            credential: Some(PresentedCredential::Requested),
+            check: VerificationCheck::Claims,
+            duration: Duration::from_millis(3),
+            instructions: Some(42),
+            outcome: Err("unexpected claim iss".to_string()),
+        };
+
+        assert_eq!(
+            event.to_string(),
+            "verification check Claims of Requested credential failed: unexpected claim iss (3ms, 42 instructions)"
+        );
+    }
+}
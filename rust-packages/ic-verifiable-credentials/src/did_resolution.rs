@@ -0,0 +1,684 @@
+//! Resolution of the keys of issuers outside of the IC, identified by DIDs.
+//!
+//! Credentials signed with standard JWS algorithms (`ES256`, `ES256K`, `EdDSA`) reference the
+//! signing key via the `kid` in the JWS header, which is a DID URL, i.e. the DID of the issuer
+//! with a fragment identifying one of its verification methods.  A `DidResolver` returns the
+//! public key of such a verification method as JWK.  Built-in resolvers are
+//!  * `DidKeyResolver` for `did:key`, where the key is encoded in the DID itself, and
+//!  * `DidWebResolver` for `did:web`, which resolves keys from DID documents fed by the caller
+//!    (e.g. fetched via HTTPS outcalls, or configured at deployment).
+//!
+//! Resolvers for multiple DID methods are combined via `DidResolvers`, cf.
+//! `verify_credential_jws()` for the verification of credentials.
+use crate::threshold_signing::{ThresholdAlgorithm, ThresholdPublicKey};
+use identity_jose::jwk::{Jwk, JwkParams, JwkParamsEc, JwkType};
+use identity_jose::jws::JwsAlgorithm;
+use identity_jose::jwu::{decode_b64, encode_b64};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+pub const DID_KEY_PREFIX: &str = "did:key:";
+pub const DID_WEB_PREFIX: &str = "did:web:";
+const P256_CURVE: &str = "P-256";
+// Multicodec prefixes (as unsigned varints) of the public keys in `did:key`-DIDs.
+const ED25519_PUB_MULTICODEC: [u8; 2] = [0xed, 0x01];
+const SECP256K1_PUB_MULTICODEC: [u8; 2] = [0xe7, 0x01];
+const P256_PUB_MULTICODEC: [u8; 2] = [0x80, 0x24];
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DidResolutionError {
+    /// The DID URL is not of the form `did:<method>:<method-specific id>#<fragment>`.
+    InvalidDidUrl(String),
+    /// No resolver is registered for the DID method.
+    UnsupportedMethod(String),
+    /// The DID or its verification method is unknown to the resolver.
+    NotFound(String),
+    InvalidDocument(String),
+    InvalidKey(String),
+}
+
+/// A resolver of the public keys of verification methods of DIDs.
+pub trait DidResolver {
+    /// Returns the public key of the verification method identified by `did_url`
+    /// (e.g. the `kid` of a JWS header), which the DID subject uses to sign credentials.
+    fn resolve_verification_method(&self, did_url: &str) -> Result<Jwk, DidResolutionError>;
+}
+
+/// Combines resolvers for multiple DID methods, and resolves each DID URL with the resolver
+/// of its method.  By default, resolves `did:key`-DIDs via `DidKeyResolver`.
+pub struct DidResolvers {
+    resolvers: BTreeMap<String, Box<dyn DidResolver>>,
+}
+
+impl Default for DidResolvers {
+    fn default() -> Self {
+        Self::new().with_resolver("key", DidKeyResolver)
+    }
+}
+
+impl DidResolvers {
+    /// Creates a combination of resolvers without any resolver.
+    pub fn new() -> Self {
+        Self {
+            resolvers: BTreeMap::new(),
+        }
+    }
+
+    /// Uses `resolver` for the DIDs of the given method (e.g. `web` for `did:web`-DIDs),
+    /// replacing any resolver previously used for that method.
+    pub fn with_resolver(mut self, method: &str, resolver: impl DidResolver + 'static) -> Self {
+        self.resolvers
+            .insert(method.to_string(), Box::new(resolver));
+        self
+    }
+}
+
+impl DidResolver for DidResolvers {
+    fn resolve_verification_method(&self, did_url: &str) -> Result<Jwk, DidResolutionError> {
+        let (did, _) = split_did_url(did_url)?;
+        let method = did_method(did)?;
+        self.resolvers
+            .get(method)
+            .ok_or(DidResolutionError::UnsupportedMethod(method.to_string()))?
+            .resolve_verification_method(did_url)
+    }
+}
+
+/// A resolver of `did:key`-DIDs with Ed25519, secp256k1 or P-256 keys, cf.
+/// https://w3c-ccg.github.io/did-method-key/.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DidKeyResolver;
+
+impl DidResolver for DidKeyResolver {
+    fn resolve_verification_method(&self, did_url: &str) -> Result<Jwk, DidResolutionError> {
+        let (did, fragment) = split_did_url(did_url)?;
+        let multibase_key = did
+            .strip_prefix(DID_KEY_PREFIX)
+            .ok_or(DidResolutionError::UnsupportedMethod(did.to_string()))?;
+        // The only verification method of a `did:key`-DID is identified by the encoded key.
+        if fragment != multibase_key {
+            return Err(DidResolutionError::NotFound(did_url.to_string()));
+        }
+        multibase_key_to_jwk(multibase_key)
+    }
+}
+
+/// Returns the DID URL of the only verification method of the `did:key`-DID of the given key,
+/// i.e. `did:key:<multibase key>#<multibase key>`, e.g. to be used as `kid` of the holder.
+pub fn did_key_url(public_key: &ThresholdPublicKey) -> String {
+    let codec = match public_key.algorithm {
+        ThresholdAlgorithm::Es256k => SECP256K1_PUB_MULTICODEC,
+        ThresholdAlgorithm::EdDsa => ED25519_PUB_MULTICODEC,
+    };
+    let multibase_key = format!(
+        "z{}",
+        bs58::encode([&codec[..], &public_key.public_key[..]].concat()).into_string()
+    );
+    format!("{}{}#{}", DID_KEY_PREFIX, multibase_key, multibase_key)
+}
+
+/// A resolver of `did:web`-DIDs, which resolves keys from the DID documents fed via
+/// `add_document()`, cf. https://w3c-ccg.github.io/did-method-web/.
+/// Only verification methods referenced as `assertionMethod` are resolved, and their keys
+/// must be given either as `publicKeyJwk` or as `publicKeyMultibase` (as in `did:key`).
+#[derive(Clone, Debug, Default)]
+pub struct DidWebResolver {
+    documents: HashMap<String, Value>,
+}
+
+impl DidWebResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the given DID document (in JSON), replacing any document with the same `id`.
+    pub fn add_document(&mut self, document_json: &str) -> Result<(), DidResolutionError> {
+        let document: Value = serde_json::from_str(document_json)
+            .map_err(|e| invalid_document(&format!("invalid JSON: {}", e)))?;
+        let did = document
+            .get("id")
+            .and_then(Value::as_str)
+            .ok_or(invalid_document("missing id"))?;
+        if !did.starts_with(DID_WEB_PREFIX) {
+            return Err(invalid_document(&format!("{} is not a did:web-DID", did)));
+        }
+        self.documents.insert(did.to_string(), document);
+        Ok(())
+    }
+}
+
+impl DidResolver for DidWebResolver {
+    fn resolve_verification_method(&self, did_url: &str) -> Result<Jwk, DidResolutionError> {
+        let (did, fragment) = split_did_url(did_url)?;
+        let document = self
+            .documents
+            .get(did)
+            .ok_or(DidResolutionError::NotFound(did.to_string()))?;
+        // Verification method ids are either absolute DID URLs, or relative to the DID.
+        let is_method_id = |id: &str| id == did_url || id.strip_prefix('#') == Some(fragment);
+        let methods_of = |property: &str| {
+            document
+                .get(property)
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default()
+        };
+        let assertion_methods = methods_of("assertionMethod");
+        let is_referenced = assertion_methods.iter().any(|method| match method {
+            Value::String(id) => is_method_id(id),
+            method => method
+                .get("id")
+                .and_then(Value::as_str)
+                .is_some_and(is_method_id),
+        });
+        if !is_referenced {
+            return Err(DidResolutionError::NotFound(did_url.to_string()));
+        }
+        let method = methods_of("verificationMethod")
+            .into_iter()
+            .chain(assertion_methods)
+            .find(|method| {
+                method
+                    .get("id")
+                    .and_then(Value::as_str)
+                    .is_some_and(is_method_id)
+            })
+            .ok_or(DidResolutionError::NotFound(did_url.to_string()))?;
+        if let Some(jwk) = method.get("publicKeyJwk") {
+            let jwk: Jwk = serde_json::from_value(jwk.clone())
+                .map_err(|e| invalid_document(&format!("invalid publicKeyJwk: {}", e)))?;
+            if jwk.is_private() {
+                return Err(invalid_document("publicKeyJwk contains a private key"));
+            }
+            Ok(jwk)
+        } else if let Some(multibase_key) = method.get("publicKeyMultibase") {
+            multibase_key_to_jwk(
+                multibase_key
+                    .as_str()
+                    .ok_or(invalid_document("invalid publicKeyMultibase"))?,
+            )
+        } else {
+            Err(invalid_document(
+                "missing public key of verification method",
+            ))
+        }
+    }
+}
+
+/// Verifies the JWS signature `signature` on `message` with the given algorithm and key.
+/// Supports `ES256` (with P-256 keys), `ES256K` (with secp256k1 keys) and `EdDSA`
+/// (with Ed25519 keys), where ECDSA-signatures are expected as concatenation of `r` and `s`.
+pub fn verify_jws_signature(
+    alg: &JwsAlgorithm,
+    jwk: &Jwk,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<(), String> {
+    match alg {
+        JwsAlgorithm::ES256 => {
+            use p256::ecdsa::signature::Verifier;
+            let verifying_key = p256::ecdsa::VerifyingKey::from(p256_public_key_from_jwk(jwk)?);
+            let signature = p256::ecdsa::Signature::from_slice(signature)
+                .map_err(|_| "malformed ES256 signature".to_string())?;
+            verifying_key
+                .verify(message, &signature)
+                .map_err(|_| "invalid ES256 signature".to_string())
+        }
+        JwsAlgorithm::ES256K | JwsAlgorithm::EdDSA => {
+            let public_key = ThresholdPublicKey::from_jwk(jwk)?;
+            if &public_key.algorithm.jws_algorithm() != alg {
+                return Err(format!("key is not a key for {}", alg.name()));
+            }
+            public_key.verify(message, signature)
+        }
+        alg => Err(format!("unsupported algorithm {}", alg.name())),
+    }
+}
+
+/// Splits the given DID URL into the DID and the fragment.
+fn split_did_url(did_url: &str) -> Result<(&str, &str), DidResolutionError> {
+    let (did, fragment) = did_url
+        .split_once('#')
+        .ok_or(DidResolutionError::InvalidDidUrl(did_url.to_string()))?;
+    did_method(did)?;
+    if fragment.is_empty() {
+        return Err(DidResolutionError::InvalidDidUrl(did_url.to_string()));
+    }
+    Ok((did, fragment))
+}
+
+fn did_method(did: &str) -> Result<&str, DidResolutionError> {
+    let mut parts = did.splitn(3, ':');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("did"), Some(method), Some(id)) if !method.is_empty() && !id.is_empty() => Ok(method),
+        _ => Err(DidResolutionError::InvalidDidUrl(did.to_string())),
+    }
+}
+
+/// Decodes a multibase (base58btc) and multicodec encoded public key, as in `did:key`.
+fn multibase_key_to_jwk(multibase_key: &str) -> Result<Jwk, DidResolutionError> {
+    let encoded = multibase_key
+        .strip_prefix('z')
+        .ok_or(invalid_key("expected base58btc multibase encoding"))?;
+    let bytes = bs58::decode(encoded)
+        .into_vec()
+        .map_err(|_| invalid_key("invalid base58btc encoding"))?;
+    let (codec, public_key) = bytes.split_at_checked(2).ok_or(invalid_key("too short"))?;
+    let threshold_key = |algorithm| {
+        ThresholdPublicKey::new(algorithm, public_key.to_vec())
+            .and_then(|public_key| public_key.to_jwk())
+            .map_err(|e| invalid_key(&e))
+    };
+    match <[u8; 2]>::try_from(codec).expect("codec has 2 bytes") {
+        ED25519_PUB_MULTICODEC => threshold_key(ThresholdAlgorithm::EdDsa),
+        SECP256K1_PUB_MULTICODEC => threshold_key(ThresholdAlgorithm::Es256k),
+        P256_PUB_MULTICODEC => p256_jwk(public_key).map_err(|e| invalid_key(&e)),
+        _ => Err(invalid_key("unsupported multicodec")),
+    }
+}
+
+fn p256_jwk(public_key: &[u8]) -> Result<Jwk, String> {
+    let public_key = p256::PublicKey::from_sec1_bytes(public_key)
+        .map_err(|_| "invalid P-256 public key".to_string())?;
+    let point = public_key.to_encoded_point(false);
+    let (Some(x), Some(y)) = (point.x(), point.y()) else {
+        return Err("internal: failed decompressing public key".to_string());
+    };
+    let mut params = JwkParamsEc::new();
+    params.crv = P256_CURVE.to_string();
+    params.x = encode_b64(x);
+    params.y = encode_b64(y);
+    let mut jwk = Jwk::new(JwkType::Ec);
+    jwk.set_alg(JwsAlgorithm::ES256.name());
+    jwk.set_params(JwkParams::Ec(params))
+        .map_err(|e| format!("internal: failed creating JWK: {:?}", e))?;
+    Ok(jwk)
+}
+
+fn p256_public_key_from_jwk(jwk: &Jwk) -> Result<p256::PublicKey, String> {
+    let params = jwk
+        .try_ec_params()
+        .map_err(|_| "expected JWK of type EC".to_string())?;
+    if params.crv != P256_CURVE {
+        return Err(format!("unsupported curve {}", params.crv));
+    }
+    let decode = |value: &str| {
+        decode_b64(value.as_bytes()).map_err(|_| "invalid base64url encoding".to_string())
+    };
+    let mut uncompressed = vec![0x04];
+    uncompressed.extend(decode(&params.x)?);
+    uncompressed.extend(decode(&params.y)?);
+    p256::PublicKey::from_sec1_bytes(&uncompressed)
+        .map_err(|_| "invalid P-256 public key".to_string())
+}
+
+fn invalid_document(message: &str) -> DidResolutionError {
+    DidResolutionError::InvalidDocument(message.to_string())
+}
+
+fn invalid_key(message: &str) -> DidResolutionError {
+    DidResolutionError::InvalidKey(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::IcRootKey;
+    use crate::verify_credential_jws;
+    use assert_matches::assert_matches;
+    use identity_jose::jws::{CompactJwsEncoder, JwsHeader};
+
+    // The Ed25519 key of https://datatracker.ietf.org/doc/html/rfc8037#appendix-A.
+    const ED25519_DID_KEY: &str = "did:key:z6MktwupdmLXVVqTzCw4i46r4uGyosGXRnR3XjN4Zq7oMMsw";
+    const ED25519_JWK_X: &str = "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo";
+    const DID_WEB_ISSUER: &str = "did:web:issuer.test";
+    const CREDENTIAL_JWT: &str = r#"{"iss":"https://issuer.test","exp":1700000000}"#;
+    const BEFORE_EXPIRATION_NS: u128 = 1_600_000_000_000_000_000;
+
+    enum TestKey {
+        Es256(p256::ecdsa::SigningKey),
+        Es256k(k256::ecdsa::SigningKey),
+        EdDsa(ed25519_dalek::SigningKey),
+    }
+
+    impl TestKey {
+        fn all(seed: u8) -> Vec<Self> {
+            vec![
+                TestKey::Es256(p256::ecdsa::SigningKey::from_slice(&[seed; 32]).unwrap()),
+                TestKey::Es256k(k256::ecdsa::SigningKey::from_slice(&[seed; 32]).unwrap()),
+                TestKey::EdDsa(ed25519_dalek::SigningKey::from_bytes(&[seed; 32])),
+            ]
+        }
+
+        fn alg(&self) -> JwsAlgorithm {
+            match self {
+                TestKey::Es256(_) => JwsAlgorithm::ES256,
+                TestKey::Es256k(_) => JwsAlgorithm::ES256K,
+                TestKey::EdDsa(_) => JwsAlgorithm::EdDSA,
+            }
+        }
+
+        fn multibase_key(&self) -> String {
+            let (codec, public_key) = match self {
+                TestKey::Es256(key) => (
+                    P256_PUB_MULTICODEC,
+                    key.verifying_key()
+                        .to_encoded_point(true)
+                        .as_bytes()
+                        .to_vec(),
+                ),
+                TestKey::Es256k(key) => (
+                    SECP256K1_PUB_MULTICODEC,
+                    key.verifying_key()
+                        .to_encoded_point(true)
+                        .as_bytes()
+                        .to_vec(),
+                ),
+                TestKey::EdDsa(key) => (
+                    ED25519_PUB_MULTICODEC,
+                    key.verifying_key().to_bytes().to_vec(),
+                ),
+            };
+            format!(
+                "z{}",
+                bs58::encode([&codec[..], &public_key[..]].concat()).into_string()
+            )
+        }
+
+        fn did_key_url(&self) -> String {
+            format!(
+                "{}{}#{}",
+                DID_KEY_PREFIX,
+                self.multibase_key(),
+                self.multibase_key()
+            )
+        }
+
+        fn sign(&self, message: &[u8]) -> Vec<u8> {
+            match self {
+                TestKey::Es256(key) => {
+                    use p256::ecdsa::signature::Signer;
+                    let sig: p256::ecdsa::Signature = key.sign(message);
+                    sig.to_bytes().to_vec()
+                }
+                TestKey::Es256k(key) => {
+                    use k256::ecdsa::signature::Signer;
+                    let sig: k256::ecdsa::Signature = key.sign(message);
+                    sig.to_bytes().to_vec()
+                }
+                TestKey::EdDsa(key) => {
+                    use ed25519_dalek::Signer;
+                    key.sign(message).to_bytes().to_vec()
+                }
+            }
+        }
+
+        fn credential_jws(&self, alg: JwsAlgorithm, kid: &str) -> String {
+            let mut header = JwsHeader::new();
+            header.set_alg(alg);
+            header.set_kid(kid);
+            let encoder = CompactJwsEncoder::new(CREDENTIAL_JWT.as_bytes(), &header).unwrap();
+            let sig = self.sign(encoder.signing_input());
+            encoder.into_jws(&sig)
+        }
+    }
+
+    fn did_of(did_url: &str) -> &str {
+        did_url.split_once('#').unwrap().0
+    }
+
+    fn did_web_resolver(key: &TestKey) -> DidWebResolver {
+        let mut resolver = DidWebResolver::new();
+        resolver
+            .add_document(
+                &serde_json::json!({
+                    "id": DID_WEB_ISSUER,
+                    "verificationMethod": [{
+                        "id": "#key-1",
+                        "type": "Multikey",
+                        "controller": DID_WEB_ISSUER,
+                        "publicKeyMultibase": key.multibase_key(),
+                    }, {
+                        "id": format!("{}#key-2", DID_WEB_ISSUER),
+                        "type": "JsonWebKey",
+                        "controller": DID_WEB_ISSUER,
+                        "publicKeyJwk": { "kty": "OKP", "crv": "Ed25519", "x": ED25519_JWK_X },
+                    }],
+                    "assertionMethod": ["#key-1"],
+                })
+                .to_string(),
+            )
+            .expect("invalid DID document");
+        resolver
+    }
+
+    #[test]
+    fn should_resolve_did_key() {
+        let did_url = format!(
+            "{}#{}",
+            ED25519_DID_KEY,
+            &ED25519_DID_KEY[DID_KEY_PREFIX.len()..]
+        );
+
+        let jwk = DidKeyResolver
+            .resolve_verification_method(&did_url)
+            .expect("failed resolving did:key");
+
+        assert_eq!(jwk.kty(), JwkType::Okp);
+        assert_eq!(jwk.try_okp_params().unwrap().x, ED25519_JWK_X);
+    }
+
+    #[test]
+    fn should_compute_did_key_url() {
+        let public_key = ThresholdPublicKey::new(
+            ThresholdAlgorithm::EdDsa,
+            decode_b64(ED25519_JWK_X).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            did_key_url(&public_key),
+            format!(
+                "{}#{}",
+                ED25519_DID_KEY,
+                &ED25519_DID_KEY[DID_KEY_PREFIX.len()..]
+            )
+        );
+
+        let TestKey::Es256k(secp256k1_key) = &TestKey::all(1)[1] else {
+            panic!("expected secp256k1 key");
+        };
+        let public_key = ThresholdPublicKey::new(
+            ThresholdAlgorithm::Es256k,
+            secp256k1_key.verifying_key().to_sec1_bytes().to_vec(),
+        )
+        .unwrap();
+        assert_eq!(did_key_url(&public_key), TestKey::all(1)[1].did_key_url());
+    }
+
+    #[test]
+    fn should_reject_invalid_did_urls() {
+        for did_url in [
+            ED25519_DID_KEY,
+            "did:key#z6Mk",
+            "key:z6Mk#z6Mk",
+            "did:key:z6Mk#",
+        ] {
+            assert_matches!(
+                DidKeyResolver.resolve_verification_method(did_url),
+                Err(DidResolutionError::InvalidDidUrl(_))
+            );
+        }
+        assert_matches!(
+            DidKeyResolver.resolve_verification_method(&format!("{}#key-1", ED25519_DID_KEY)),
+            Err(DidResolutionError::NotFound(_))
+        );
+        assert_matches!(
+            DidResolvers::default().resolve_verification_method("did:web:issuer.test#key-1"),
+            Err(DidResolutionError::UnsupportedMethod(method)) if method == "web"
+        );
+    }
+
+    #[test]
+    fn should_resolve_did_web_assertion_methods() {
+        let key = TestKey::Es256k(k256::ecdsa::SigningKey::from_slice(&[1; 32]).unwrap());
+        let resolver = did_web_resolver(&key);
+
+        let jwk = resolver
+            .resolve_verification_method(&format!("{}#key-1", DID_WEB_ISSUER))
+            .expect("failed resolving did:web");
+        assert_eq!(jwk.try_ec_params().unwrap().crv, "secp256k1");
+
+        // key-2 is not an assertion method.
+        assert_matches!(
+            resolver.resolve_verification_method(&format!("{}#key-2", DID_WEB_ISSUER)),
+            Err(DidResolutionError::NotFound(_))
+        );
+        assert_matches!(
+            resolver.resolve_verification_method("did:web:other.test#key-1"),
+            Err(DidResolutionError::NotFound(_))
+        );
+        assert_matches!(
+            DidWebResolver::new().add_document(r#"{"id":"did:key:z6Mk"}"#),
+            Err(DidResolutionError::InvalidDocument(_))
+        );
+    }
+
+    #[test]
+    fn should_verify_credentials_of_did_key_issuers() {
+        for key in TestKey::all(1) {
+            let kid = key.did_key_url();
+            let credential_jws = key.credential_jws(key.alg(), &kid);
+
+            let claims = verify_credential_jws(
+                &credential_jws,
+                did_of(&kid),
+                &DidResolvers::default(),
+                &IcRootKey::mainnet(),
+                BEFORE_EXPIRATION_NS,
+            )
+            .expect("failed verifying credential");
+
+            assert_eq!(claims.iss(), Some("https://issuer.test"));
+        }
+    }
+
+    #[test]
+    fn should_verify_credentials_of_did_web_issuers() {
+        for key in TestKey::all(1) {
+            let resolvers = DidResolvers::default().with_resolver("web", did_web_resolver(&key));
+            let credential_jws =
+                key.credential_jws(key.alg(), &format!("{}#key-1", DID_WEB_ISSUER));
+
+            let result = verify_credential_jws(
+                &credential_jws,
+                DID_WEB_ISSUER,
+                &resolvers,
+                &IcRootKey::mainnet(),
+                BEFORE_EXPIRATION_NS,
+            );
+
+            assert_matches!(result, Ok(_));
+        }
+    }
+
+    #[test]
+    fn should_fail_verifying_credential_with_kid_of_other_issuer() {
+        for key in TestKey::all(1) {
+            let credential_jws = key.credential_jws(key.alg(), &key.did_key_url());
+
+            let result = verify_credential_jws(
+                &credential_jws,
+                DID_WEB_ISSUER,
+                &DidResolvers::default(),
+                &IcRootKey::mainnet(),
+                BEFORE_EXPIRATION_NS,
+            );
+
+            assert_matches!(result, Err(e) if e.to_string().contains("is not a verification method"));
+        }
+    }
+
+    #[test]
+    fn should_fail_verifying_credential_of_other_key() {
+        for (key, other_key) in TestKey::all(1).into_iter().zip(TestKey::all(2)) {
+            let kid = other_key.did_key_url();
+            let credential_jws = key.credential_jws(key.alg(), &kid);
+
+            let result = verify_credential_jws(
+                &credential_jws,
+                did_of(&kid),
+                &DidResolvers::default(),
+                &IcRootKey::mainnet(),
+                BEFORE_EXPIRATION_NS,
+            );
+
+            assert_matches!(result, Err(e) if e.to_string().contains("signature verification error"));
+        }
+    }
+
+    #[test]
+    fn should_fail_verifying_credential_with_alg_not_matching_key() {
+        let key = TestKey::EdDsa(ed25519_dalek::SigningKey::from_bytes(&[1; 32]));
+        let kid = key.did_key_url();
+
+        for alg in [JwsAlgorithm::ES256, JwsAlgorithm::ES256K] {
+            let result = verify_credential_jws(
+                &key.credential_jws(alg, &kid),
+                did_of(&kid),
+                &DidResolvers::default(),
+                &IcRootKey::mainnet(),
+                BEFORE_EXPIRATION_NS,
+            );
+
+            assert_matches!(result, Err(e) if e.to_string().contains("signature verification error"));
+        }
+    }
+
+    #[test]
+    fn should_fail_verifying_expired_credential() {
+        for key in TestKey::all(1) {
+            let kid = key.did_key_url();
+            let credential_jws = key.credential_jws(key.alg(), &kid);
+
+            let result = verify_credential_jws(
+                &credential_jws,
+                did_of(&kid),
+                &DidResolvers::default(),
+                &IcRootKey::mainnet(),
+                1_700_000_000_000_000_000,
+            );
+
+            assert_matches!(result, Err(e) if e.to_string().contains("credential expired"));
+        }
+    }
+
+    #[cfg(feature = "test-utils")]
+    #[test]
+    fn should_verify_canister_signed_credential() {
+        use crate::did_for_principal;
+        use crate::test_utils::{TestCanisterSigner, TestIcRootKey};
+        use candid::Principal;
+
+        let root_key = TestIcRootKey::generate();
+        let issuer = Principal::from_slice(&[1; 10]);
+        let credential_jws = TestCanisterSigner::new(issuer, b"seed").sign_credential_jwt(
+            &root_key,
+            CREDENTIAL_JWT,
+            BEFORE_EXPIRATION_NS,
+        );
+        let verify = |issuer_did: &str| {
+            verify_credential_jws(
+                &credential_jws,
+                issuer_did,
+                &DidResolvers::default(),
+                &root_key.ic_root_key(),
+                BEFORE_EXPIRATION_NS,
+            )
+        };
+
+        assert_matches!(verify(&did_for_principal(issuer)), Ok(_));
+        assert_matches!(verify(&did_for_principal(Principal::anonymous())), Err(_));
+        assert_matches!(verify(ED25519_DID_KEY), Err(e) if e.to_string().contains("invalid issuer"));
+    }
+}
@@ -0,0 +1,254 @@
+//! Presets of the parameters that depend on the network on which II and the issuers run.
+//!
+//! A `VcEnvironment` bundles the canister id and origin of II with the IC root public key of
+//! the network, against which all canister signatures are verified.  `VcEnvironment::mainnet()`
+//! describes the Internet Computer mainnet, and `VcEnvironment::local()` a local replica (or any
+//! other network) with its own II deployment and root key.
+//!
+//! All verification functions accept a `VcEnvironment` (or just its `IcRootKey`) wherever
+//! they need the root public key, cf. `RootKeyProvider`.
+use crate::{II_ISSUER_URL, II_MAINNET, VcFlowSigners, allowed_origins};
+use candid::Principal;
+use ic_canister_sig_creation::{
+    IC_ROOT_PK_DER, IC_ROOT_PK_DER_PREFIX, extract_raw_root_pk_from_der,
+};
+use ic_verify_bls_signature::PublicKey;
+
+/// An IC root public key, i.e. a BLS12-381 G2 public key, against which canister signatures
+/// and certificates are verified.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IcRootKey {
+    raw: Vec<u8>,
+}
+
+impl IcRootKey {
+    /// The root public key of the Internet Computer mainnet.
+    pub fn mainnet() -> Self {
+        Self::from_der(IC_ROOT_PK_DER.as_slice()).expect("invalid mainnet root key")
+    }
+
+    /// Parses a DER-encoded root public key, e.g. as returned by `dfx ping` or
+    /// `Agent::fetch_root_key`.
+    pub fn from_der(der: &[u8]) -> Result<Self, String> {
+        let raw =
+            extract_raw_root_pk_from_der(der).map_err(|e| format!("invalid root key: {}", e))?;
+        Self::from_raw(&raw)
+    }
+
+    /// Parses a raw (i.e. compressed, 96 bytes) root public key.
+    pub fn from_raw(raw: &[u8]) -> Result<Self, String> {
+        PublicKey::deserialize(raw)
+            .map_err(|e| format!("invalid root key: not a BLS12-381 G2 key: {:?}", e))?;
+        Ok(Self { raw: raw.to_vec() })
+    }
+
+    pub fn as_raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    pub fn to_der(&self) -> Vec<u8> {
+        [IC_ROOT_PK_DER_PREFIX.as_slice(), &self.raw].concat()
+    }
+}
+
+/// Provides the IC root public key, against which canister signatures are verified.
+/// Sealed: only implemented by `IcRootKey` and `VcEnvironment`, whose root keys are validated.
+pub trait RootKeyProvider: sealed::Sealed {
+    fn root_pk_raw(&self) -> &[u8];
+}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for super::IcRootKey {}
+    impl Sealed for super::VcEnvironment {}
+    impl<T: Sealed + ?Sized> Sealed for &T {}
+}
+
+impl RootKeyProvider for IcRootKey {
+    fn root_pk_raw(&self) -> &[u8] {
+        self.as_raw()
+    }
+}
+
+impl<T: RootKeyProvider + ?Sized> RootKeyProvider for &T {
+    fn root_pk_raw(&self) -> &[u8] {
+        (**self).root_pk_raw()
+    }
+}
+
+/// The network on which II and the issuers run.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VcEnvironment {
+    /// The canister that signs the id_alias credentials.
+    pub ii_canister_id: Principal,
+    /// The issuer (`iss`) of the id_alias credentials.
+    pub ii_origin: String,
+    /// The IC root public key of the network.
+    pub root_key: IcRootKey,
+}
+
+impl VcEnvironment {
+    /// The Internet Computer mainnet, with II at `https://identity.ic0.app`.
+    pub fn mainnet() -> Self {
+        Self {
+            ii_canister_id: Principal::from_text(II_MAINNET).expect("invalid II canister id"),
+            ii_origin: II_ISSUER_URL.to_string(),
+            root_key: IcRootKey::mainnet(),
+        }
+    }
+
+    /// A local replica, or any other network, with II deployed as `ii_canister_id`, issuing
+    /// id_alias credentials as `ii_origin`, and the given root key.
+    pub fn local(ii_canister_id: Principal, ii_origin: &str, root_key: IcRootKey) -> Self {
+        Self {
+            ii_canister_id,
+            ii_origin: ii_origin.to_string(),
+            root_key,
+        }
+    }
+
+    /// Returns the signers of a VC flow with II of this environment and the given issuer.
+    pub fn vc_flow_signers(
+        &self,
+        issuer_canister_id: Principal,
+        issuer_origin: &str,
+    ) -> VcFlowSigners {
+        VcFlowSigners {
+            ii_canister_id: self.ii_canister_id,
+            ii_origin: self.ii_origin.clone(),
+            issuer_canister_id,
+            issuer_origin: issuer_origin.to_string(),
+        }
+    }
+
+    /// Returns the derivation origins which II of this environment may put into id_alias
+    /// credentials for the given expected derivation origin of a relying party.
+    /// II on mainnet maps canister domains on `icp0.io` to the legacy domain `ic0.app`.
+    pub fn allowed_derivation_origins(&self, expected_derivation_origin: &str) -> Vec<String> {
+        allowed_origins(expected_derivation_origin, &self.ii_canister_id)
+    }
+}
+
+impl RootKeyProvider for VcEnvironment {
+    fn root_pk_raw(&self) -> &[u8] {
+        self.root_key.as_raw()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use identity_jose::jwu::decode_b64;
+
+    const LOCAL_II_CANISTER_ID: &str = "be2us-64aaa-aaaaa-qaabq-cai";
+    // The DER-encoded root key of a local replica.
+    const LOCAL_ROOT_KEY_B64URL: &str = "MIGCMB0GDSsGAQQBgtx8BQMBAgEGDCsGAQQBgtx8BQMCAQNhAK32VjilMFayIiyRuyRXsCdLypUZilrL2t_n_XIXjwab3qjZnpR52Ah6Job8gb88SxH-J1Vw1IHxaY951Giv4OV6zB4pj4tpeY2nqJG77Blwk-xfR1kJkj1Iv-1oQ9vtHw";
+
+    fn local_ii_canister_id() -> Principal {
+        Principal::from_text(LOCAL_II_CANISTER_ID).unwrap()
+    }
+
+    fn local_root_key_der() -> Vec<u8> {
+        decode_b64(LOCAL_ROOT_KEY_B64URL).unwrap()
+    }
+
+    fn local_ii_origin() -> String {
+        format!("http://{}.localhost:4943", LOCAL_II_CANISTER_ID)
+    }
+
+    fn local_root_key() -> IcRootKey {
+        IcRootKey::from_der(&local_root_key_der()).unwrap()
+    }
+
+    #[test]
+    fn should_parse_root_key_from_der_and_raw() {
+        let root_key = local_root_key();
+
+        assert_eq!(root_key.to_der(), local_root_key_der());
+        assert_eq!(root_key.as_raw().len(), 96);
+        assert_eq!(IcRootKey::from_raw(root_key.as_raw()), Ok(root_key));
+        assert_eq!(IcRootKey::mainnet().to_der(), IC_ROOT_PK_DER.to_vec());
+    }
+
+    #[test]
+    fn should_reject_invalid_root_key() {
+        let mut not_a_point = local_root_key().as_raw().to_vec();
+        not_a_point[1] ^= 1;
+        let cases = [
+            IcRootKey::from_raw(&not_a_point),
+            IcRootKey::from_raw(&[7; 95]),
+            IcRootKey::from_der(&[7; 133]),
+            IcRootKey::from_der(IcRootKey::mainnet().as_raw()),
+        ];
+        for result in cases {
+            assert!(
+                result.as_ref().unwrap_err().starts_with("invalid root key"),
+                "{:?}",
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn should_describe_mainnet() {
+        let env = VcEnvironment::mainnet();
+
+        assert_eq!(env.ii_canister_id.to_text(), II_MAINNET);
+        assert_eq!(env.ii_origin, II_ISSUER_URL);
+        assert_eq!(env.root_key, IcRootKey::mainnet());
+        assert_eq!(env.root_pk_raw(), IcRootKey::mainnet().as_raw());
+    }
+
+    #[test]
+    fn should_describe_local_replica() {
+        let env =
+            VcEnvironment::local(local_ii_canister_id(), &local_ii_origin(), local_root_key());
+
+        assert_eq!(env.ii_canister_id, local_ii_canister_id());
+        assert_eq!(env.ii_origin, local_ii_origin());
+        assert_eq!(
+            env.vc_flow_signers(local_ii_canister_id(), "https://issuer.example.com")
+                .ii_origin,
+            local_ii_origin()
+        );
+        assert_eq!(env.root_pk_raw(), local_root_key().as_raw());
+    }
+
+    #[test]
+    fn should_return_vc_flow_signers_template() {
+        let issuer_canister_id = Principal::from_text("bkyz2-fmaaa-aaaaa-qaaaq-cai").unwrap();
+        let signers = VcEnvironment::mainnet()
+            .vc_flow_signers(issuer_canister_id, "https://issuer.example.com");
+
+        assert_eq!(
+            signers,
+            VcFlowSigners {
+                ii_canister_id: Principal::from_text(II_MAINNET).unwrap(),
+                ii_origin: II_ISSUER_URL.to_string(),
+                issuer_canister_id,
+                issuer_origin: "https://issuer.example.com".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn should_return_origin_rules_of_environment() {
+        let local =
+            VcEnvironment::local(local_ii_canister_id(), &local_ii_origin(), local_root_key());
+        let origin = "https://example.icp0.io";
+
+        assert_eq!(
+            VcEnvironment::mainnet().allowed_derivation_origins(origin),
+            vec!["https://example.ic0.app"]
+        );
+        assert_eq!(
+            local.allowed_derivation_origins(origin),
+            vec!["https://example.ic0.app", origin]
+        );
+        assert_eq!(
+            local.allowed_derivation_origins("https://custom-domain.com"),
+            vec!["https://custom-domain.com"]
+        );
+    }
+}
@@ -0,0 +1,1009 @@
+//! Holder-bound verifiable presentations.
+//!
+//! The presentations returned by II (cf. `build_ii_verifiable_presentation_jwt()`) are not
+//! signed and contain neither an audience nor a nonce, so a presentation intercepted while
+//! still valid could be handed to any relying party.  A holder-bound presentation in addition
+//!  * contains the origin of the relying party as `aud`-claim,
+//!  * contains a challenge issued by the relying party as `nonce`-claim (cf. `challenge`),
+//!  * contains an expiration (`exp`), and
+//!  * is signed with the session key of the frontend of the relying party, to which the holder
+//!    (i.e. the `id_dapp` of the alias tuple) delegated via II.
+//!
+//! The delegation chain of the session key, as obtained by the frontend from II, is included
+//! in the JWS header as `ic_delegation`, in the JSON format of `DelegationChain.toJSON()` of
+//! agent-js.  The holder is the self-authenticating principal of the public key of the chain.
+use crate::challenge::ChallengeStore;
+use crate::environment::RootKeyProvider;
+use crate::{
+    AliasTuple, DEFAULT_MAX_PRESENTATION_SIZE, PresentationFormat, PresentationVerificationError,
+    VcFlowSigners, build_presentation, check_presentation_size, did_for_principal,
+    domain_separated, normalize_origin, principal_for_did, validate_expiration,
+    verify_presentation_with_format,
+};
+use candid::Principal;
+use ic_canister_sig_creation::{
+    CanisterSigPublicKey, DELEGATION_SIG_DOMAIN, delegation_signature_msg,
+};
+use ic_signature_verification::verify_canister_sig;
+use identity_core::common::{Object, Timestamp, Url};
+use identity_credential::presentation::JwtPresentationOptions;
+use identity_jose::jws::{CompactJwsEncoder, Decoder, JwsAlgorithm, JwsHeader};
+use identity_jose::jwt::JwtClaims;
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+
+pub const VP_SIGNING_INPUT_DOMAIN: &[u8; 28] = b"iccs_verifiable_presentation";
+/// The JWS header parameter containing the delegation chain of the session key.
+pub const DELEGATION_CHAIN_HEADER: &str = "ic_delegation";
+/// The maximal number of delegations in a delegation chain, as on the IC.
+pub const MAX_DELEGATIONS: usize = 20;
+const NONCE_CLAIM: &str = "nonce";
+
+// DER-prefixes of the public keys supported as session keys, followed by the raw 32 bytes for
+// Ed25519 and by the uncompressed SEC1-encoded point for ECDSA.
+pub(crate) const ED25519_DER_PREFIX: &[u8; 12] =
+    b"\x30\x2a\x30\x05\x06\x03\x2b\x65\x70\x03\x21\x00";
+pub(crate) const P256_DER_PREFIX: &[u8; 26] = b"\x30\x59\x30\x13\x06\x07\x2a\x86\x48\xce\x3d\x02\x01\x06\x08\x2a\x86\x48\xce\x3d\x03\x01\x07\x03\x42\x00";
+pub(crate) const SECP256K1_DER_PREFIX: &[u8; 23] =
+    b"\x30\x56\x30\x10\x06\x07\x2a\x86\x48\xce\x3d\x02\x01\x06\x05\x2b\x81\x04\x00\x0a\x03\x42\x00";
+
+/// Parameters of the holder binding of a presentation.
+pub struct HolderBindingOptions {
+    /// The origin of the relying party the presentation is intended for.
+    pub audience: String,
+    /// The challenge issued by the relying party.
+    pub nonce: String,
+    pub issuance_timestamp_s: u32,
+    pub expiration_timestamp_s: u32,
+}
+
+impl HolderBindingOptions {
+    fn to_jwt_presentation_options(&self) -> Result<JwtPresentationOptions, String> {
+        let timestamp = |timestamp_s: u32| {
+            Timestamp::from_unix(timestamp_s as i64)
+                .map_err(|e| format!("invalid timestamp {}: {}", timestamp_s, e))
+        };
+        let audience = Url::parse(&self.audience)
+            .map_err(|e| format!("invalid audience {}: {}", self.audience, e))?;
+        let mut custom_claims = Object::new();
+        custom_claims.insert(NONCE_CLAIM.to_string(), Value::String(self.nonce.clone()));
+        Ok(JwtPresentationOptions {
+            expiration_date: Some(timestamp(self.expiration_timestamp_s)?),
+            issuance_date: Some(timestamp(self.issuance_timestamp_s)?),
+            audience: Some(audience),
+            custom_claims: Some(custom_claims),
+        })
+    }
+}
+
+/// Expected values of the holder binding of a presentation.
+pub struct HolderBinding<'a> {
+    /// The origin of the relying party, i.e. the expected `aud`-claim.
+    pub audience: &'a str,
+    /// The challenge issued by the relying party, i.e. the expected `nonce`-claim.
+    pub nonce: &'a str,
+}
+
+/// A delegation of a key to `pubkey` (DER-encoded) until `expiration` (in nanoseconds since the
+/// UNIX epoch), as returned by `get_delegation` of II.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Delegation {
+    pub pubkey: Vec<u8>,
+    pub expiration: u64,
+    /// The canisters to which the delegation is restricted.  Delegations with targets are
+    /// rejected for holder bindings.
+    pub targets: Option<Vec<Principal>>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SignedDelegation {
+    pub delegation: Delegation,
+    pub signature: Vec<u8>,
+}
+
+/// A chain of delegations from `public_key` (DER-encoded) to a session key, where the first
+/// delegation is signed by `public_key`, and every other by the key of the previous delegation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DelegationChain {
+    pub public_key: Vec<u8>,
+    pub delegations: Vec<SignedDelegation>,
+}
+
+impl DelegationChain {
+    /// The principal delegating to the session key, i.e. the holder of presentations.
+    pub fn holder(&self) -> Principal {
+        Principal::self_authenticating(&self.public_key)
+    }
+
+    /// The DER-encoded public key to which the chain delegates.
+    pub fn session_key(&self) -> &[u8] {
+        self.delegations
+            .last()
+            .map_or(self.public_key.as_slice(), |signed| {
+                signed.delegation.pubkey.as_slice()
+            })
+    }
+
+    /// Parses a delegation chain in the JSON format of `DelegationChain.toJSON()` of agent-js,
+    /// i.e. with hex-encoded keys, signatures, expirations and targets.
+    pub fn from_json(json: &Value) -> Result<Self, String> {
+        let field = |value: &Value, name: &str| {
+            value
+                .get(name)
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .ok_or(format!("missing {} of delegation chain", name))
+        };
+        let bytes = |value: &Value, name: &str| {
+            decode_hex(&field(value, name)?).map_err(|e| format!("invalid {}: {}", name, e))
+        };
+        let delegations = json
+            .get("delegations")
+            .and_then(Value::as_array)
+            .ok_or("missing delegations of delegation chain")?
+            .iter()
+            .map(|signed| {
+                let delegation = signed
+                    .get("delegation")
+                    .ok_or("missing delegation of delegation chain")?;
+                let expiration = u64::from_str_radix(&field(delegation, "expiration")?, 16)
+                    .map_err(|e| format!("invalid expiration: {}", e))?;
+                let targets = match delegation.get("targets") {
+                    None => None,
+                    Some(targets) => Some(
+                        targets
+                            .as_array()
+                            .ok_or("invalid targets of delegation")?
+                            .iter()
+                            .map(|target| {
+                                let target = target.as_str().ok_or("invalid target")?;
+                                Principal::try_from_slice(&decode_hex(target)?)
+                                    .map_err(|e| format!("invalid target: {}", e))
+                            })
+                            .collect::<Result<Vec<_>, String>>()?,
+                    ),
+                };
+                Ok(SignedDelegation {
+                    delegation: Delegation {
+                        pubkey: bytes(delegation, "pubkey")?,
+                        expiration,
+                        targets,
+                    },
+                    signature: bytes(signed, "signature")?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Self {
+            public_key: bytes(json, "publicKey")?,
+            delegations,
+        })
+    }
+
+    /// Returns the delegation chain in the JSON format parsed by `from_json()`.
+    pub fn to_json(&self) -> Value {
+        let delegations: Vec<Value> = self
+            .delegations
+            .iter()
+            .map(|signed| {
+                let mut delegation = json!({
+                    "expiration": format!("{:x}", signed.delegation.expiration),
+                    "pubkey": encode_hex(&signed.delegation.pubkey),
+                });
+                if let Some(targets) = &signed.delegation.targets {
+                    delegation["targets"] = targets
+                        .iter()
+                        .map(|target| Value::String(encode_hex(target.as_slice())))
+                        .collect();
+                }
+                json!({
+                    "delegation": delegation,
+                    "signature": encode_hex(&signed.signature),
+                })
+            })
+            .collect();
+        json!({
+            "delegations": delegations,
+            "publicKey": encode_hex(&self.public_key),
+        })
+    }
+}
+
+/// Builds an (unsigned) holder-bound presentation of the given VCs, to be signed via
+/// `vp_signing_input()` and `vp_jwt_to_jws()` with the session key of `holder`.
+/// Apart from the holder binding, the presentation is identical to the one returned by
+/// `build_ii_verifiable_presentation_jwt()`.
+pub fn build_ii_holder_bound_presentation_jwt(
+    holder: Principal,
+    id_alias_vc_jws: String,
+    requested_vc_jws: String,
+    options: &HolderBindingOptions,
+) -> Result<String, String> {
+    let presentation = build_presentation(holder, vec![id_alias_vc_jws, requested_vc_jws])?;
+    presentation
+        .serialize_jwt(&options.to_jwt_presentation_options()?)
+        .map_err(|e| format!("failed serializing presentation: {}", e))
+}
+
+/// Returns the bytes to be signed with the session key of the given delegation chain for the
+/// given holder-bound presentation, i.e. the JWS signing input prefixed with
+///      `length(VP_SIGNING_INPUT_DOMAIN) · VP_SIGNING_INPUT_DOMAIN`.
+/// ECDSA-signatures are expected as concatenation of `r` and `s` of the signature on the
+/// SHA-256 hash of the bytes, as created by the `sign`-method of agent-js identities.
+pub fn vp_signing_input(
+    vp_jwt: &str,
+    delegation_chain: &DelegationChain,
+) -> Result<Vec<u8>, String> {
+    let encoder = vp_jws_encoder(vp_jwt, delegation_chain)?;
+    Ok(domain_separated(
+        VP_SIGNING_INPUT_DOMAIN,
+        encoder.signing_input(),
+    ))
+}
+
+/// Constructs and returns a signed holder-bound presentation from the given components.
+/// The given signature should be created over the bytes returned by `vp_signing_input()`.
+/// Note: the validity of the signature is not checked.
+pub fn vp_jwt_to_jws(
+    vp_jwt: &str,
+    delegation_chain: &DelegationChain,
+    sig: &[u8],
+) -> Result<String, String> {
+    let encoder = vp_jws_encoder(vp_jwt, delegation_chain)?;
+    Ok(encoder.into_jws(sig))
+}
+
+fn vp_jws_encoder<'a>(
+    vp_jwt: &'a str,
+    delegation_chain: &DelegationChain,
+) -> Result<CompactJwsEncoder<'a>, String> {
+    let session_key = SessionKey::from_der(delegation_chain.session_key())?;
+    let mut header = JwsHeader::new();
+    header.set_alg(session_key.jws_algorithm());
+    header.set_custom(BTreeMap::from([(
+        DELEGATION_CHAIN_HEADER.to_string(),
+        delegation_chain.to_json(),
+    )]));
+    CompactJwsEncoder::new(vp_jwt.as_bytes(), &header)
+        .map_err(|e| format!("internal: failed creating JWS encoder: {:?}", e))
+}
+
+/// Verifies the holder binding of the given presentation, and returns the holder.
+/// Specifically, it checks that
+///  - the presentation is signed with the session key of the delegation chain in its header,
+///  - the delegations of the chain are valid signatures, have not expired, and are not
+///    restricted to canisters,
+///  - the self-authenticating principal of the public key of the chain is the holder (`iss`)
+///    of the presentation,
+///  - the `aud`-claim contains `expected.audience`,
+///  - the `nonce`-claim equals `expected.nonce`,
+///  - the presentation has not expired, and is not used before its issuance.
+///
+/// DOES NOT verify the credentials contained in the presentation, cf. `verify_holder_bound_presentation()`.
+pub fn verify_holder_binding(
+    vp_jwt: &str,
+    expected: &HolderBinding,
+    root_key: &impl RootKeyProvider,
+    current_time_ns: u128,
+) -> Result<Principal, PresentationVerificationError> {
+    check_presentation_size(vp_jwt, DEFAULT_MAX_PRESENTATION_SIZE)
+        .map_err(PresentationVerificationError::InvalidPresentationJwt)?;
+    let jws = Decoder::new()
+        .decode_compact_serialization(vp_jwt.as_bytes(), None)
+        .map_err(|e| invalid_holder_binding(format!("presentation JWS parsing error: {}", e)))?;
+    let header = jws
+        .protected_header()
+        .ok_or(invalid_holder_binding("missing JWS header".to_string()))?;
+    let delegation_chain = header
+        .custom()
+        .and_then(|custom| custom.get(DELEGATION_CHAIN_HEADER))
+        .ok_or(invalid_holder_binding(
+            "presentation is not signed by the holder: missing delegation chain".to_string(),
+        ))
+        .and_then(|json| DelegationChain::from_json(json).map_err(invalid_holder_binding))?;
+    let session_key = verify_delegation_chain(&delegation_chain, root_key, current_time_ns)
+        .map_err(|e| invalid_holder_binding(format!("invalid delegation chain: {}", e)))?;
+    if header.alg() != Some(session_key.jws_algorithm()) {
+        return Err(invalid_holder_binding(format!(
+            "unexpected alg of presentation: expected {}, got {:?}",
+            session_key.jws_algorithm().name(),
+            header.alg()
+        )));
+    }
+    let message = domain_separated(VP_SIGNING_INPUT_DOMAIN, jws.signing_input());
+    session_key
+        .verify(&message, jws.decoded_signature())
+        .map_err(|e| invalid_holder_binding(format!("signature verification error: {}", e)))?;
+    let holder = delegation_chain.holder();
+
+    let claims: Map<String, Value> = serde_json::from_slice(jws.claims())
+        .map_err(|e| invalid_holder_binding(format!("failed parsing JSON JWT claims: {}", e)))?;
+    let issuer = claims
+        .get("iss")
+        .and_then(Value::as_str)
+        .ok_or(invalid_holder_binding("missing iss claim".to_string()))?;
+    if principal_for_did(issuer).ok() != Some(holder) {
+        return Err(invalid_holder_binding(format!(
+            "presentation holder {} does not match the signer {}",
+            issuer,
+            did_for_principal(holder)
+        )));
+    }
+    if !claims
+        .get("aud")
+        .is_some_and(|aud| audience_matches(aud, expected.audience))
+    {
+        return Err(invalid_holder_binding(format!(
+            "presentation is not intended for audience {}",
+            expected.audience
+        )));
+    }
+    if claims.get(NONCE_CLAIM).and_then(Value::as_str) != Some(expected.nonce) {
+        return Err(invalid_holder_binding(
+            "presentation does not contain the expected nonce".to_string(),
+        ));
+    }
+    validate_expiration(claims.get("exp").and_then(Value::as_i64), current_time_ns)
+        .map_err(|e| invalid_holder_binding(format!("presentation expired: {}", e)))?;
+    let not_before_s = claims
+        .get("nbf")
+        .or(claims.get("iat"))
+        .and_then(Value::as_i64);
+    if not_before_s.is_some_and(|nbf_s| nbf_s as i128 * 1_000_000_000 > current_time_ns as i128) {
+        return Err(invalid_holder_binding(
+            "presentation is not valid yet".to_string(),
+        ));
+    }
+    Ok(holder)
+}
+
+// Verifies the signatures and expirations of the delegations of the given chain, and returns
+// the session key.
+fn verify_delegation_chain(
+    delegation_chain: &DelegationChain,
+    root_key: &impl RootKeyProvider,
+    current_time_ns: u128,
+) -> Result<SessionKey, String> {
+    if delegation_chain.delegations.is_empty() {
+        return Err("no delegations".to_string());
+    }
+    if delegation_chain.delegations.len() > MAX_DELEGATIONS {
+        return Err(format!(
+            "too many delegations: {}, maximum {}",
+            delegation_chain.delegations.len(),
+            MAX_DELEGATIONS
+        ));
+    }
+    let mut signer = delegation_chain.public_key.as_slice();
+    for signed in &delegation_chain.delegations {
+        let delegation = &signed.delegation;
+        if delegation.targets.is_some() {
+            return Err("delegation is restricted to canisters".to_string());
+        }
+        if delegation.expiration as u128 <= current_time_ns {
+            return Err("delegation expired".to_string());
+        }
+        let message = domain_separated(
+            DELEGATION_SIG_DOMAIN,
+            &delegation_signature_msg(&delegation.pubkey, delegation.expiration, None),
+        );
+        if CanisterSigPublicKey::try_from(signer).is_ok() {
+            verify_canister_sig(&message, &signed.signature, signer, root_key.root_pk_raw())
+                .map_err(|e| format!("invalid canister signature of delegation: {}", e))?;
+        } else {
+            SessionKey::from_der(signer)?
+                .verify(&message, &signed.signature)
+                .map_err(|e| format!("invalid signature of delegation: {}", e))?;
+        }
+        signer = &delegation.pubkey;
+    }
+    SessionKey::from_der(signer)
+}
+
+// The public keys supported as session keys, as created by agent-js.
+#[derive(Debug)]
+enum SessionKey {
+    Ed25519(ed25519_dalek::VerifyingKey),
+    P256(p256::ecdsa::VerifyingKey),
+    Secp256k1(k256::ecdsa::VerifyingKey),
+}
+
+impl SessionKey {
+    fn from_der(public_key_der: &[u8]) -> Result<Self, String> {
+        if let Some(raw) = public_key_der.strip_prefix(ED25519_DER_PREFIX.as_slice()) {
+            let raw: [u8; 32] = raw
+                .try_into()
+                .map_err(|_| "invalid Ed25519 public key".to_string())?;
+            ed25519_dalek::VerifyingKey::from_bytes(&raw)
+                .map(SessionKey::Ed25519)
+                .map_err(|_| "invalid Ed25519 public key".to_string())
+        } else if let Some(point) = public_key_der.strip_prefix(P256_DER_PREFIX.as_slice()) {
+            p256::ecdsa::VerifyingKey::from_sec1_bytes(point)
+                .map(SessionKey::P256)
+                .map_err(|_| "invalid P-256 public key".to_string())
+        } else if let Some(point) = public_key_der.strip_prefix(SECP256K1_DER_PREFIX.as_slice()) {
+            k256::ecdsa::VerifyingKey::from_sec1_bytes(point)
+                .map(SessionKey::Secp256k1)
+                .map_err(|_| "invalid secp256k1 public key".to_string())
+        } else {
+            Err("unsupported session key".to_string())
+        }
+    }
+
+    fn jws_algorithm(&self) -> JwsAlgorithm {
+        match self {
+            SessionKey::Ed25519(_) => JwsAlgorithm::EdDSA,
+            SessionKey::P256(_) => JwsAlgorithm::ES256,
+            SessionKey::Secp256k1(_) => JwsAlgorithm::ES256K,
+        }
+    }
+
+    fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), String> {
+        match self {
+            SessionKey::Ed25519(verifying_key) => {
+                let signature = ed25519_dalek::Signature::from_slice(signature)
+                    .map_err(|_| "malformed Ed25519 signature".to_string())?;
+                verifying_key
+                    .verify_strict(message, &signature)
+                    .map_err(|_| "invalid Ed25519 signature".to_string())
+            }
+            SessionKey::P256(verifying_key) => {
+                use p256::ecdsa::signature::Verifier;
+                let signature = p256::ecdsa::Signature::from_slice(signature)
+                    .map_err(|_| "malformed P-256 signature".to_string())?;
+                // Signatures with a high `s` are valid ECDSA signatures, e.g. from WebCrypto.
+                let signature = signature.normalize_s().unwrap_or(signature);
+                verifying_key
+                    .verify(message, &signature)
+                    .map_err(|_| "invalid P-256 signature".to_string())
+            }
+            SessionKey::Secp256k1(verifying_key) => {
+                use k256::ecdsa::signature::Verifier;
+                let signature = k256::ecdsa::Signature::from_slice(signature)
+                    .map_err(|_| "malformed secp256k1 signature".to_string())?;
+                let signature = signature.normalize_s().unwrap_or(signature);
+                verifying_key
+                    .verify(message, &signature)
+                    .map_err(|_| "invalid secp256k1 signature".to_string())
+            }
+        }
+    }
+}
+
+/// Verifies the given holder-bound presentation: checks the holder binding via
+/// `verify_holder_binding()`, and the contained credentials like `verify_presentation()`.
+/// Additionally checks that the holder of the presentation is the subject of the
+/// id_alias credential, before passing the alias tuple to the given `policy`.
+pub fn verify_holder_bound_presentation<P>(
+    vp_jwt: &str,
+    expected: &HolderBinding,
+    vc_flow_signers: &VcFlowSigners,
+    root_key: &impl RootKeyProvider,
+    current_time_ns: u128,
+    policy: P,
+) -> Result<(AliasTuple, JwtClaims<Value>), PresentationVerificationError>
+where
+    P: FnOnce(&AliasTuple) -> Result<(), PresentationVerificationError>,
+{
+    let holder = verify_holder_binding(vp_jwt, expected, root_key, current_time_ns)?;
+    verify_presentation_with_format(
+        vp_jwt,
+        PresentationFormat::HolderBound,
+        DEFAULT_MAX_PRESENTATION_SIZE,
+        vc_flow_signers,
+        root_key,
+        current_time_ns,
+        |alias_tuple| {
+            if alias_tuple.id_dapp != holder {
+                return Err(invalid_holder_binding(format!(
+                    "presentation holder {} is not the subject of the id_alias credential",
+                    did_for_principal(holder)
+                )));
+            }
+            policy(alias_tuple)
+        },
+    )
+    .map(|verified| (verified.alias_tuple, verified.claims))
+}
+
+/// Verifies the given holder-bound presentation submitted by `caller`, whose `nonce`-claim must
+/// be a challenge issued to `caller` via `challenges`.  The challenge is consumed before any
+/// other check, so that every challenge can be used at most once, even if the verification fails.
+/// Apart from that, the verification is done by `verify_holder_bound_presentation()`.
+#[allow(clippy::too_many_arguments)]
+pub fn verify_holder_bound_presentation_with_challenge<P>(
+    vp_jwt: &str,
+    audience: &str,
+    caller: Principal,
+    challenges: &mut ChallengeStore,
+    vc_flow_signers: &VcFlowSigners,
+    root_key: &impl RootKeyProvider,
+    current_time_ns: u128,
+    policy: P,
+) -> Result<(AliasTuple, JwtClaims<Value>), PresentationVerificationError>
+where
+    P: FnOnce(&AliasTuple) -> Result<(), PresentationVerificationError>,
+{
+    let nonce = unverified_nonce(vp_jwt)?;
+    challenges
+        .consume(caller, &nonce, current_time_ns)
+        .map_err(PresentationVerificationError::InvalidChallenge)?;
+    verify_holder_bound_presentation(
+        vp_jwt,
+        &HolderBinding {
+            audience,
+            nonce: &nonce,
+        },
+        vc_flow_signers,
+        root_key,
+        current_time_ns,
+        policy,
+    )
+}
+
+// Extracts the `nonce`-claim of the given presentation, without verifying the presentation.
+fn unverified_nonce(vp_jwt: &str) -> Result<String, PresentationVerificationError> {
+    check_presentation_size(vp_jwt, DEFAULT_MAX_PRESENTATION_SIZE)
+        .map_err(PresentationVerificationError::InvalidPresentationJwt)?;
+    let jws = Decoder::new()
+        .decode_compact_serialization(vp_jwt.as_bytes(), None)
+        .map_err(|e| invalid_holder_binding(format!("presentation JWS parsing error: {}", e)))?;
+    let claims: Map<String, Value> = serde_json::from_slice(jws.claims())
+        .map_err(|e| invalid_holder_binding(format!("failed parsing JSON JWT claims: {}", e)))?;
+    claims
+        .get(NONCE_CLAIM)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or(invalid_holder_binding("missing nonce claim".to_string()))
+}
+
+// The `aud`-claim is either a single string or an array of strings, which are compared to the
+// expected audience as origins, cf. `normalize_origin()`.
+fn audience_matches(aud: &Value, expected_audience: &str) -> bool {
+    let Some(expected_audience) = normalize_origin(expected_audience) else {
+        return false;
+    };
+    let matches = |aud: &Value| {
+        aud.as_str()
+            .and_then(normalize_origin)
+            .is_some_and(|aud| aud == expected_audience)
+    };
+    match aud {
+        Value::Array(auds) => auds.iter().any(matches),
+        aud => matches(aud),
+    }
+}
+
+fn invalid_holder_binding(message: String) -> PresentationVerificationError {
+    PresentationVerificationError::InvalidHolderBinding(message)
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>, String> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return Err(format!("invalid hex string {}", hex));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| format!("invalid hex string {}", hex))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_ii_verifiable_presentation_jwt;
+    use crate::challenge::{ChallengeError, ChallengeGenerator};
+    use crate::environment::IcRootKey;
+    use assert_matches::assert_matches;
+
+    const HOLDER: &str = "7eboi-tyuys-aqm4c-w2l7i-vgucm-xvawx-lemzx-6kq2g-f53u7-yvfh2-nae";
+    const RP_ORIGIN: &str = "https://l7rua-raaaa-aaaap-ahh6a-cai.icp0.io";
+    const NOW_NS: u128 = 1_620_329_000 * 1_000_000_000;
+
+    fn test_options() -> HolderBindingOptions {
+        HolderBindingOptions {
+            audience: RP_ORIGIN.to_string(),
+            nonce: "some nonce".to_string(),
+            issuance_timestamp_s: 1_620_328_630,
+            expiration_timestamp_s: 1_620_329_530,
+        }
+    }
+
+    fn unsigned_delegation_chain() -> DelegationChain {
+        let session_key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        DelegationChain {
+            public_key: vec![1, 2, 3],
+            delegations: vec![SignedDelegation {
+                delegation: Delegation {
+                    pubkey: [
+                        ED25519_DER_PREFIX.as_slice(),
+                        session_key.verifying_key().as_bytes(),
+                    ]
+                    .concat(),
+                    expiration: (NOW_NS + 1) as u64,
+                    targets: Some(vec![Principal::anonymous()]),
+                },
+                signature: vec![4, 5, 6],
+            }],
+        }
+    }
+
+    #[test]
+    fn should_build_holder_bound_presentation() {
+        let holder = Principal::from_text(HOLDER).expect("wrong principal");
+        let vp_jwt = build_ii_holder_bound_presentation_jwt(
+            holder,
+            "id_alias_vc_jws".to_string(),
+            "requested_vc_jws".to_string(),
+            &test_options(),
+        )
+        .expect("vp creation failed");
+        let claims: Map<String, Value> =
+            serde_json::from_str(&vp_jwt).expect("failed parsing VP claims");
+        assert_eq!(claims["iss"], json!(did_for_principal(holder)));
+        assert_eq!(claims["nonce"], json!("some nonce"));
+        assert_eq!(claims["exp"], json!(1_620_329_530));
+        assert!(audience_matches(&claims["aud"], RP_ORIGIN));
+        assert_eq!(
+            claims["vp"]["verifiableCredential"],
+            json!(["id_alias_vc_jws", "requested_vc_jws"])
+        );
+    }
+
+    #[test]
+    fn should_encode_delegation_chain_as_json() {
+        let delegation_chain = unsigned_delegation_chain();
+        let json = delegation_chain.to_json();
+        assert_eq!(json["publicKey"], json!("010203"));
+        assert_eq!(json["delegations"][0]["signature"], json!("040506"));
+        assert_eq!(
+            json["delegations"][0]["delegation"]["expiration"],
+            json!(format!("{:x}", NOW_NS + 1))
+        );
+        assert_eq!(
+            json["delegations"][0]["delegation"]["targets"],
+            json!(["04"])
+        );
+        assert_eq!(
+            DelegationChain::from_json(&json).expect("failed parsing delegation chain"),
+            delegation_chain
+        );
+        assert_matches!(
+            DelegationChain::from_json(&json!({"publicKey": "0g", "delegations": []})),
+            Err(e) if e.contains("invalid publicKey")
+        );
+    }
+
+    #[test]
+    fn should_verify_signature_of_p256_session_key() {
+        use p256::ecdsa::signature::Signer;
+        let signing_key =
+            p256::ecdsa::SigningKey::from_slice(&[9; 32]).expect("invalid P-256 secret key");
+        let public_key_der = [
+            P256_DER_PREFIX.as_slice(),
+            signing_key
+                .verifying_key()
+                .to_encoded_point(false)
+                .as_bytes(),
+        ]
+        .concat();
+        let signature: p256::ecdsa::Signature = signing_key.sign(b"message");
+        let session_key = SessionKey::from_der(&public_key_der).expect("invalid session key");
+
+        assert_eq!(session_key.jws_algorithm(), JwsAlgorithm::ES256);
+        assert_eq!(
+            session_key.verify(b"message", &signature.to_bytes()),
+            Ok(())
+        );
+        assert_matches!(
+            session_key.verify(b"other message", &signature.to_bytes()),
+            Err(e) if e.contains("invalid P-256 signature")
+        );
+        assert_matches!(
+            SessionKey::from_der(&public_key_der[1..]),
+            Err(e) if e.contains("unsupported session key")
+        );
+    }
+
+    #[test]
+    fn should_fail_verify_holder_binding_of_unsigned_presentation() {
+        let holder = Principal::from_text(HOLDER).expect("wrong principal");
+        let vp_jwt = build_ii_verifiable_presentation_jwt(
+            holder,
+            "id_alias_vc_jws".to_string(),
+            "requested_vc_jws".to_string(),
+        )
+        .expect("vp creation failed");
+        let result = verify_holder_binding(
+            &vp_jwt,
+            &HolderBinding {
+                audience: RP_ORIGIN,
+                nonce: "some nonce",
+            },
+            &IcRootKey::mainnet(),
+            NOW_NS,
+        );
+        assert_matches!(result, Err(PresentationVerificationError::InvalidHolderBinding(e)) if e.contains("not signed by the holder"));
+    }
+
+    #[test]
+    fn should_reject_presentation_with_unknown_or_reused_challenge() {
+        let holder = Principal::from_text(HOLDER).expect("wrong principal");
+        let mut challenges = ChallengeStore::default();
+        let challenge =
+            challenges.issue(holder, &mut ChallengeGenerator::from_seed([1; 32]), NOW_NS);
+        let vp_jws_with_nonce = |nonce: &str| {
+            let options = HolderBindingOptions {
+                nonce: nonce.to_string(),
+                ..test_options()
+            };
+            let vp_jwt = build_ii_holder_bound_presentation_jwt(
+                holder,
+                "id_alias_vc_jws".to_string(),
+                "requested_vc_jws".to_string(),
+                &options,
+            )
+            .expect("vp creation failed");
+            vp_jwt_to_jws(&vp_jwt, &unsigned_delegation_chain(), &[4, 5, 6])
+                .expect("vp signing failed")
+        };
+        let verify = |vp_jws: &str, challenges: &mut ChallengeStore| {
+            verify_holder_bound_presentation_with_challenge(
+                vp_jws,
+                RP_ORIGIN,
+                holder,
+                challenges,
+                &VcFlowSigners {
+                    ii_canister_id: Principal::anonymous(),
+                    ii_origin: "https://identity.ic0.app/".to_string(),
+                    issuer_canister_id: Principal::anonymous(),
+                    issuer_origin: "https://issuer.example".to_string(),
+                },
+                &IcRootKey::mainnet(),
+                NOW_NS,
+                |_| Ok(()),
+            )
+        };
+
+        assert_matches!(
+            verify(&vp_jws_with_nonce("unknown nonce"), &mut challenges),
+            Err(PresentationVerificationError::InvalidChallenge(
+                ChallengeError::UnknownChallenge
+            ))
+        );
+        // The challenge is consumed by the first attempt, even though the signature is invalid.
+        let vp_jws = vp_jws_with_nonce(&challenge.nonce);
+        assert_matches!(
+            verify(&vp_jws, &mut challenges),
+            Err(PresentationVerificationError::InvalidHolderBinding(_))
+        );
+        assert_matches!(
+            verify(&vp_jws, &mut challenges),
+            Err(PresentationVerificationError::InvalidChallenge(
+                ChallengeError::UnknownChallenge
+            ))
+        );
+    }
+
+    #[test]
+    fn should_match_audience() {
+        assert!(audience_matches(&json!(RP_ORIGIN), RP_ORIGIN));
+        assert!(audience_matches(
+            &json!(format!("{}/", RP_ORIGIN)),
+            RP_ORIGIN
+        ));
+        assert!(audience_matches(
+            &json!("HTTPS://L7RUA-RAAAA-AAAAP-AHH6A-CAI.ICP0.IO:443"),
+            RP_ORIGIN
+        ));
+        assert!(audience_matches(
+            &json!("http://127.0.0.1:4943/?canisterId=l7rua-raaaa-aaaap-ahh6a-cai"),
+            "http://l7rua-raaaa-aaaap-ahh6a-cai.localhost:4943"
+        ));
+        assert!(audience_matches(
+            &json!(["https://other.origin", RP_ORIGIN]),
+            RP_ORIGIN
+        ));
+        assert!(!audience_matches(&json!("https://other.origin"), RP_ORIGIN));
+        assert!(!audience_matches(
+            &json!(format!("{}/path", RP_ORIGIN)),
+            RP_ORIGIN
+        ));
+        assert!(!audience_matches(&json!(RP_ORIGIN), "not an origin"));
+        assert!(!audience_matches(&json!(42), RP_ORIGIN));
+        assert!(!audience_matches(&json!([]), RP_ORIGIN));
+    }
+
+    #[cfg(feature = "test-utils")]
+    mod signed {
+        use super::*;
+        use crate::issuer_api::CredentialSpec;
+        use crate::test_utils::{TestIcRootKey, TestThresholdSigner, TestVcFlow};
+        use crate::threshold_signing::ThresholdAlgorithm;
+
+        const MINTED_AT_NS: u128 = NOW_NS - 60 * 1_000_000_000;
+        const DELEGATION_EXPIRATION_NS: u64 = (NOW_NS + 30 * 60 * 1_000_000_000) as u64;
+
+        fn session_key(algorithm: ThresholdAlgorithm) -> TestThresholdSigner {
+            TestThresholdSigner::new(algorithm, [9; 32])
+        }
+
+        fn verified_adult_spec() -> CredentialSpec {
+            CredentialSpec {
+                credential_type: "VerifiedAdult".to_string(),
+                arguments: None,
+            }
+        }
+
+        fn delegation_chain(
+            flow: &TestVcFlow,
+            session_key: &TestThresholdSigner,
+        ) -> DelegationChain {
+            flow.delegation_chain(
+                b"user",
+                &session_key.public_key_der(),
+                DELEGATION_EXPIRATION_NS,
+                MINTED_AT_NS,
+            )
+        }
+
+        fn signed_vp_jws(
+            flow: &TestVcFlow,
+            delegation_chain: &DelegationChain,
+            session_key: &TestThresholdSigner,
+        ) -> String {
+            flow.holder_bound_presentation_jws(
+                delegation_chain,
+                session_key,
+                RP_ORIGIN,
+                &verified_adult_spec(),
+                &test_options(),
+                MINTED_AT_NS,
+            )
+        }
+
+        fn verify(
+            vp_jws: &str,
+            audience: &str,
+            nonce: &str,
+            root_key: &IcRootKey,
+        ) -> Result<Principal, PresentationVerificationError> {
+            verify_holder_binding(vp_jws, &HolderBinding { audience, nonce }, root_key, NOW_NS)
+        }
+
+        #[test]
+        fn should_verify_holder_binding_with_session_keys() {
+            for algorithm in [ThresholdAlgorithm::EdDsa, ThresholdAlgorithm::Es256k] {
+                let flow = TestVcFlow::new(TestIcRootKey::generate());
+                let session_key = session_key(algorithm);
+                let delegation_chain = delegation_chain(&flow, &session_key);
+                let vp_jws = signed_vp_jws(&flow, &delegation_chain, &session_key);
+
+                let result = verify(&vp_jws, RP_ORIGIN, "some nonce", &flow.ic_root_key());
+
+                assert_eq!(
+                    result.expect("verification failed"),
+                    delegation_chain.holder()
+                );
+            }
+        }
+
+        #[test]
+        fn should_verify_holder_bound_presentation() {
+            let flow = TestVcFlow::new(TestIcRootKey::generate());
+            let session_key = session_key(ThresholdAlgorithm::EdDsa);
+            let delegation_chain = delegation_chain(&flow, &session_key);
+            let vp_jws = signed_vp_jws(&flow, &delegation_chain, &session_key);
+
+            let (alias_tuple, _) = verify_holder_bound_presentation(
+                &vp_jws,
+                &HolderBinding {
+                    audience: RP_ORIGIN,
+                    nonce: "some nonce",
+                },
+                &flow.vc_flow_signers(),
+                &flow.environment(),
+                NOW_NS,
+                |_| Ok(()),
+            )
+            .expect("verification failed");
+
+            assert_eq!(alias_tuple.id_dapp, delegation_chain.holder());
+        }
+
+        #[test]
+        fn should_fail_verify_holder_binding_with_wrong_audience() {
+            let flow = TestVcFlow::new(TestIcRootKey::generate());
+            let session_key = session_key(ThresholdAlgorithm::EdDsa);
+            let delegation_chain = delegation_chain(&flow, &session_key);
+            let vp_jws = signed_vp_jws(&flow, &delegation_chain, &session_key);
+
+            let result = verify(
+                &vp_jws,
+                "https://other.origin",
+                "some nonce",
+                &flow.ic_root_key(),
+            );
+
+            assert_matches!(result, Err(PresentationVerificationError::InvalidHolderBinding(e)) if e.contains("not intended for audience"));
+        }
+
+        #[test]
+        fn should_fail_verify_holder_binding_with_wrong_nonce() {
+            let flow = TestVcFlow::new(TestIcRootKey::generate());
+            let session_key = session_key(ThresholdAlgorithm::EdDsa);
+            let delegation_chain = delegation_chain(&flow, &session_key);
+            let vp_jws = signed_vp_jws(&flow, &delegation_chain, &session_key);
+
+            let result = verify(&vp_jws, RP_ORIGIN, "other nonce", &flow.ic_root_key());
+
+            assert_matches!(result, Err(PresentationVerificationError::InvalidHolderBinding(e)) if e.contains("expected nonce"));
+        }
+
+        #[test]
+        fn should_fail_verify_holder_binding_with_delegation_under_other_root_key() {
+            let flow = TestVcFlow::new(TestIcRootKey::generate());
+            let session_key = session_key(ThresholdAlgorithm::EdDsa);
+            let delegation_chain = delegation_chain(&flow, &session_key);
+            let vp_jws = signed_vp_jws(&flow, &delegation_chain, &session_key);
+
+            let result = verify(
+                &vp_jws,
+                RP_ORIGIN,
+                "some nonce",
+                &TestIcRootKey::generate().ic_root_key(),
+            );
+
+            assert_matches!(result, Err(PresentationVerificationError::InvalidHolderBinding(e)) if e.contains("invalid canister signature of delegation"));
+        }
+
+        #[test]
+        fn should_fail_verify_holder_binding_with_expired_delegation() {
+            let flow = TestVcFlow::new(TestIcRootKey::generate());
+            let session_key = session_key(ThresholdAlgorithm::EdDsa);
+            let delegation_chain = flow.delegation_chain(
+                b"user",
+                &session_key.public_key_der(),
+                NOW_NS as u64,
+                MINTED_AT_NS,
+            );
+            let vp_jws = signed_vp_jws(&flow, &delegation_chain, &session_key);
+
+            let result = verify(&vp_jws, RP_ORIGIN, "some nonce", &flow.ic_root_key());
+
+            assert_matches!(result, Err(PresentationVerificationError::InvalidHolderBinding(e)) if e.contains("delegation expired"));
+        }
+
+        #[test]
+        fn should_fail_verify_holder_binding_with_delegation_to_other_session_key() {
+            let flow = TestVcFlow::new(TestIcRootKey::generate());
+            let session_key = session_key(ThresholdAlgorithm::EdDsa);
+            let other_session_key = TestThresholdSigner::new(ThresholdAlgorithm::EdDsa, [10; 32]);
+            let delegation_chain = delegation_chain(&flow, &session_key);
+            let vp_jws = signed_vp_jws(&flow, &delegation_chain, &other_session_key);
+
+            let result = verify(&vp_jws, RP_ORIGIN, "some nonce", &flow.ic_root_key());
+
+            assert_matches!(result, Err(PresentationVerificationError::InvalidHolderBinding(e)) if e.contains("signature verification error"));
+        }
+
+        #[test]
+        fn should_fail_verify_holder_binding_with_tampered_delegation() {
+            let flow = TestVcFlow::new(TestIcRootKey::generate());
+            let session_key = session_key(ThresholdAlgorithm::EdDsa);
+            let mut delegation_chain = delegation_chain(&flow, &session_key);
+            delegation_chain.delegations[0].delegation.expiration += 1;
+            let vp_jws = signed_vp_jws(&flow, &delegation_chain, &session_key);
+
+            let result = verify(&vp_jws, RP_ORIGIN, "some nonce", &flow.ic_root_key());
+
+            assert_matches!(result, Err(PresentationVerificationError::InvalidHolderBinding(e)) if e.contains("invalid canister signature of delegation"));
+        }
+    }
+}
@@ -0,0 +1,499 @@
+//! Rotation of the signing keys of issuers.
+//!
+//! The signing keys of an issuer are canister signature public keys of the issuer canister,
+//! which differ in their seeds.  An `IssuerKeyRing` manages the seeds together with key ids,
+//! which are included as fragment of the `kid` in the JWS header of the issued credentials,
+//! e.g. `did:icp:<issuer canister id>#key-2`.  When a key is rotated, the previous key is
+//! retired, and verifiers keep accepting credentials signed with it for a grace period,
+//! cf. `verify_credential_jws_with_issuer_keys()`.
+use crate::environment::RootKeyProvider;
+use crate::{
+    did_for_principal, get_canister_sig_pk_der, invalid_signature_err, jws_encoder_with_kid,
+    key_decoding_err, verify_credential_jws_with_canister_id,
+};
+use candid::{CandidType, Deserialize, Principal};
+use ic_canister_sig_creation::CanisterSigPublicKey;
+use identity_jose::jws::{Decoder, SignatureVerificationError};
+use identity_jose::jwt::JwtClaims;
+use serde_bytes::ByteBuf;
+use serde_json::Value;
+use std::time::Duration;
+
+/// Default period during which credentials signed with a retired key are still accepted.
+pub const DEFAULT_KEY_GRACE_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+/// Maximal length of a key id.
+pub const MAX_KEY_ID_LENGTH: usize = 64;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum IssuerKeyError {
+    /// The key id is empty, too long, or contains characters other than `[A-Za-z0-9._-]`.
+    InvalidKeyId(String),
+    /// A key with the same id already exists.
+    DuplicateKeyId(String),
+    /// The key ring contains no key with the given id.
+    UnknownKeyId(String),
+    /// All keys of the key ring are retired.
+    NoActiveKey,
+    /// The key was not active at the given time, i.e. it was activated later, or it was
+    /// retired before the start of the grace period.
+    InactiveKey(String),
+    /// The `kid` in the JWS header is missing, malformed, or not a key of the issuer.
+    InvalidKid(String),
+}
+
+/// A signing key of an issuer.  The public key of the signing key is the canister signature
+/// public key of the issuer canister with the given `seed`.
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct IssuerKey {
+    pub key_id: String,
+    pub seed: ByteBuf,
+    /// Time (in nanoseconds since the UNIX epoch) when the key became the signing key.
+    pub activated_at_ns: u64,
+    /// Time (in nanoseconds since the UNIX epoch) when the key was retired, if it was.
+    pub retired_at_ns: Option<u64>,
+}
+
+impl IssuerKey {
+    pub fn public_key(&self, issuer_canister_id: Principal) -> CanisterSigPublicKey {
+        CanisterSigPublicKey::new(issuer_canister_id, self.seed.to_vec())
+    }
+
+    /// Returns the `kid` of credentials signed with this key, i.e. the DID of the issuer
+    /// with the key id as fragment.
+    pub fn kid(&self, issuer_canister_id: Principal) -> String {
+        format!("{}#{}", did_for_principal(issuer_canister_id), self.key_id)
+    }
+
+    /// Returns whether credentials signed with this key are accepted at `current_time_ns`,
+    /// i.e. whether the key is active, or was retired at most `grace_period` ago.
+    pub fn is_accepted_at(&self, current_time_ns: u128, grace_period: Duration) -> bool {
+        if current_time_ns < self.activated_at_ns as u128 {
+            return false;
+        }
+        match self.retired_at_ns {
+            None => true,
+            Some(retired_at_ns) => {
+                current_time_ns <= (retired_at_ns as u128).saturating_add(grace_period.as_nanos())
+            }
+        }
+    }
+
+    /// Returns the effective bytes that will be signed when signing the given JWT-credential
+    /// with this key, like `vc_signing_input()`, but with the key id in the `kid`.
+    pub fn vc_signing_input(
+        &self,
+        issuer_canister_id: Principal,
+        credential_jwt: &str,
+    ) -> Result<Vec<u8>, String> {
+        let encoder = jws_encoder_with_kid(
+            credential_jwt,
+            &self.public_key(issuer_canister_id),
+            self.kid(issuer_canister_id),
+        )?;
+        Ok(encoder.signing_input().to_vec())
+    }
+
+    /// Constructs a JWS from the given JWT-credential and the canister signature `sig`
+    /// created over the bytes returned by `vc_signing_input()` of this key.
+    /// Note: the validity of the signature is not checked.
+    pub fn vc_jwt_to_jws(
+        &self,
+        issuer_canister_id: Principal,
+        credential_jwt: &str,
+        sig: &[u8],
+    ) -> Result<String, String> {
+        let encoder = jws_encoder_with_kid(
+            credential_jwt,
+            &self.public_key(issuer_canister_id),
+            self.kid(issuer_canister_id),
+        )?;
+        Ok(encoder.into_jws(sig))
+    }
+}
+
+/// The signing keys of an issuer, ordered by activation.  The most recently added key
+/// which is not retired is the signing key.
+#[derive(Clone, Debug, Default, CandidType, Deserialize, Eq, PartialEq)]
+pub struct IssuerKeyRing {
+    keys: Vec<IssuerKey>,
+}
+
+impl IssuerKeyRing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a key ring from the given keys, e.g. as obtained from the issuer.
+    pub fn from_keys(keys: Vec<IssuerKey>) -> Result<Self, IssuerKeyError> {
+        let mut key_ring = Self::new();
+        for key in keys {
+            check_key_id(&key.key_id)?;
+            if key_ring.key(&key.key_id).is_some() {
+                return Err(IssuerKeyError::DuplicateKeyId(key.key_id));
+            }
+            key_ring.keys.push(key);
+        }
+        Ok(key_ring)
+    }
+
+    pub fn keys(&self) -> &[IssuerKey] {
+        &self.keys
+    }
+
+    pub fn key(&self, key_id: &str) -> Option<&IssuerKey> {
+        self.keys.iter().find(|key| key.key_id == key_id)
+    }
+
+    /// Returns the key to be used for signing new credentials.
+    pub fn signing_key(&self) -> Result<&IssuerKey, IssuerKeyError> {
+        self.keys
+            .iter()
+            .rev()
+            .find(|key| key.retired_at_ns.is_none())
+            .ok_or(IssuerKeyError::NoActiveKey)
+    }
+
+    /// Adds a new key with the given id and seed, which becomes the signing key.
+    /// The previous signing key (if any) is retired at `current_time_ns`.
+    pub fn rotate(
+        &mut self,
+        key_id: &str,
+        seed: Vec<u8>,
+        current_time_ns: u64,
+    ) -> Result<(), IssuerKeyError> {
+        check_key_id(key_id)?;
+        if self.key(key_id).is_some() {
+            return Err(IssuerKeyError::DuplicateKeyId(key_id.to_string()));
+        }
+        if let Ok(current_key_id) = self.signing_key().map(|key| key.key_id.clone()) {
+            self.retire(&current_key_id, current_time_ns)?;
+        }
+        self.keys.push(IssuerKey {
+            key_id: key_id.to_string(),
+            seed: ByteBuf::from(seed),
+            activated_at_ns: current_time_ns,
+            retired_at_ns: None,
+        });
+        Ok(())
+    }
+
+    /// Retires the given key at `current_time_ns`, e.g. because it was compromised.
+    /// Retiring a key which is already retired has no effect.
+    pub fn retire(&mut self, key_id: &str, current_time_ns: u64) -> Result<(), IssuerKeyError> {
+        let key = self
+            .keys
+            .iter_mut()
+            .find(|key| key.key_id == key_id)
+            .ok_or(IssuerKeyError::UnknownKeyId(key_id.to_string()))?;
+        key.retired_at_ns.get_or_insert(current_time_ns);
+        Ok(())
+    }
+
+    /// Removes the keys whose grace period has ended, as credentials signed with them
+    /// are no longer accepted.
+    pub fn prune(&mut self, current_time_ns: u64, grace_period: Duration) {
+        self.keys.retain(|key| {
+            key.retired_at_ns.is_none() || key.is_accepted_at(current_time_ns as u128, grace_period)
+        });
+    }
+
+    /// Returns the key identified by the `kid` in the header of the given JWS-credential,
+    /// if credentials signed with it are accepted at `current_time_ns`, and if its public
+    /// key matches the public key in the header.
+    /// DOES NOT verify the signature of the credential, cf. `verify_credential_jws_with_issuer_keys()`.
+    pub fn accepted_key_for_jws(
+        &self,
+        credential_jws: &str,
+        issuer_canister_id: Principal,
+        current_time_ns: u128,
+        grace_period: Duration,
+    ) -> Result<&IssuerKey, IssuerKeyError> {
+        let decoder = Decoder::new();
+        let jws = decoder
+            .decode_compact_serialization(credential_jws.as_bytes(), None)
+            .map_err(|e| invalid_kid(&format!("credential JWS parsing error: {}", e)))?;
+        let header = jws
+            .protected_header()
+            .ok_or(invalid_kid("missing JWS header"))?;
+        let kid = header.kid().ok_or(invalid_kid("missing kid"))?;
+        let key_id = kid
+            .strip_prefix(&did_for_principal(issuer_canister_id))
+            .and_then(|fragment| fragment.strip_prefix('#'))
+            .ok_or(invalid_kid("kid is not a key of the issuer"))?;
+        let key = self
+            .key(key_id)
+            .ok_or(IssuerKeyError::UnknownKeyId(key_id.to_string()))?;
+        if !key.is_accepted_at(current_time_ns, grace_period) {
+            return Err(IssuerKeyError::InactiveKey(key_id.to_string()));
+        }
+        let header_pk_der = get_canister_sig_pk_der(header)
+            .map_err(|e| invalid_kid(&format!("invalid public key in JWS header: {}", e)))?;
+        if header_pk_der != key.public_key(issuer_canister_id).to_der() {
+            return Err(invalid_kid("public key does not match the key of the kid"));
+        }
+        Ok(key)
+    }
+}
+
+/// Verifies the given JWS-credential like `verify_credential_jws_with_canister_id()`, and
+/// additionally checks that it was signed with a key of `issuer_keys` which is accepted at
+/// `current_time_ns` (cf. `IssuerKeyRing::accepted_key_for_jws()`).
+pub fn verify_credential_jws_with_issuer_keys(
+    credential_jws: &str,
+    issuer_canister_id: &Principal,
+    issuer_keys: &IssuerKeyRing,
+    grace_period: Duration,
+    root_key: &impl RootKeyProvider,
+    current_time_ns: u128,
+) -> Result<JwtClaims<Value>, SignatureVerificationError> {
+    issuer_keys
+        .accepted_key_for_jws(
+            credential_jws,
+            *issuer_canister_id,
+            current_time_ns,
+            grace_period,
+        )
+        .map_err(issuer_key_err)?;
+    verify_credential_jws_with_canister_id(
+        credential_jws,
+        issuer_canister_id,
+        root_key,
+        current_time_ns,
+    )
+}
+
+pub(crate) fn issuer_key_err(e: IssuerKeyError) -> SignatureVerificationError {
+    match e {
+        IssuerKeyError::InvalidKid(message) => key_decoding_err(&message),
+        e => invalid_signature_err(&format!("signing key not accepted: {:?}", e)),
+    }
+}
+
+fn check_key_id(key_id: &str) -> Result<(), IssuerKeyError> {
+    let is_valid = !key_id.is_empty()
+        && key_id.len() <= MAX_KEY_ID_LENGTH
+        && key_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if is_valid {
+        Ok(())
+    } else {
+        Err(IssuerKeyError::InvalidKeyId(key_id.to_string()))
+    }
+}
+
+fn invalid_kid(message: &str) -> IssuerKeyError {
+    IssuerKeyError::InvalidKid(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+
+    const HOUR_NS: u64 = 60 * 60 * 1_000_000_000;
+    const NOW_NS: u64 = 1_700_000_000_000_000_000;
+
+    fn issuer() -> Principal {
+        Principal::from_slice(&[1; 10])
+    }
+
+    fn rotated_key_ring() -> IssuerKeyRing {
+        let mut key_ring = IssuerKeyRing::new();
+        key_ring.rotate("key-1", vec![1], NOW_NS).unwrap();
+        key_ring.rotate("key-2", vec![2], NOW_NS + HOUR_NS).unwrap();
+        key_ring
+    }
+
+    #[test]
+    fn should_rotate_signing_key() {
+        let key_ring = rotated_key_ring();
+
+        let signing_key = key_ring.signing_key().unwrap();
+        assert_eq!(signing_key.key_id, "key-2");
+        assert_eq!(signing_key.activated_at_ns, NOW_NS + HOUR_NS);
+        assert_eq!(
+            key_ring.key("key-1").unwrap().retired_at_ns,
+            Some(NOW_NS + HOUR_NS)
+        );
+        assert_eq!(
+            signing_key.kid(issuer()),
+            format!("{}#key-2", did_for_principal(issuer()))
+        );
+        assert_eq!(signing_key.public_key(issuer()).seed, vec![2]);
+    }
+
+    #[test]
+    fn should_reject_invalid_or_duplicate_key_ids() {
+        let mut key_ring = rotated_key_ring();
+        for key_id in ["", "key#1", "key/1", &"k".repeat(MAX_KEY_ID_LENGTH + 1)] {
+            assert_eq!(
+                key_ring.rotate(key_id, vec![3], NOW_NS),
+                Err(IssuerKeyError::InvalidKeyId(key_id.to_string()))
+            );
+        }
+        assert_eq!(
+            key_ring.rotate("key-1", vec![3], NOW_NS),
+            Err(IssuerKeyError::DuplicateKeyId("key-1".to_string()))
+        );
+        assert_eq!(
+            IssuerKeyRing::from_keys([key_ring.keys(), key_ring.keys()].concat()),
+            Err(IssuerKeyError::DuplicateKeyId("key-1".to_string()))
+        );
+    }
+
+    #[test]
+    fn should_have_no_signing_key_when_all_keys_retired() {
+        let mut key_ring = rotated_key_ring();
+        key_ring.retire("key-2", NOW_NS + 2 * HOUR_NS).unwrap();
+
+        assert_eq!(key_ring.signing_key(), Err(IssuerKeyError::NoActiveKey));
+        assert_eq!(
+            key_ring.retire("key-3", NOW_NS),
+            Err(IssuerKeyError::UnknownKeyId("key-3".to_string()))
+        );
+    }
+
+    #[test]
+    fn should_accept_retired_key_within_grace_period() {
+        let key_ring = rotated_key_ring();
+        let grace_period = Duration::from_nanos(HOUR_NS);
+        let retired_key = key_ring.key("key-1").unwrap();
+        let active_key = key_ring.key("key-2").unwrap();
+
+        assert!(retired_key.is_accepted_at((NOW_NS + 2 * HOUR_NS) as u128, grace_period));
+        assert!(!retired_key.is_accepted_at((NOW_NS + 2 * HOUR_NS + 1) as u128, grace_period));
+        assert!(!retired_key.is_accepted_at((NOW_NS - 1) as u128, grace_period));
+        assert!(active_key.is_accepted_at(u128::MAX, grace_period));
+        assert!(!active_key.is_accepted_at(NOW_NS as u128, grace_period));
+    }
+
+    #[test]
+    fn should_prune_keys_after_grace_period() {
+        let mut key_ring = rotated_key_ring();
+
+        key_ring.prune(NOW_NS + 2 * HOUR_NS, Duration::from_nanos(HOUR_NS));
+        assert_eq!(key_ring.keys().len(), 2);
+        key_ring.prune(NOW_NS + 3 * HOUR_NS, Duration::from_nanos(HOUR_NS));
+        assert_eq!(key_ring.keys().len(), 1);
+        assert_eq!(key_ring.signing_key().unwrap().key_id, "key-2");
+    }
+
+    #[cfg(feature = "test-utils")]
+    mod verification {
+        use super::*;
+        use crate::issuer_api::CredentialSpec;
+        use crate::test_utils::{TestCanisterSigner, TestIcRootKey};
+        use crate::{CredentialParams, VC_SIGNING_INPUT_DOMAIN, build_credential_jwt};
+
+        struct TestIssuer {
+            root_key: TestIcRootKey,
+            key_ring: IssuerKeyRing,
+        }
+
+        impl TestIssuer {
+            fn new() -> Self {
+                Self {
+                    root_key: TestIcRootKey::generate(),
+                    key_ring: rotated_key_ring(),
+                }
+            }
+
+            fn credential_jws(&self, key_id: &str) -> String {
+                let key = self.key_ring.key(key_id).unwrap();
+                let credential_jwt = build_credential_jwt(CredentialParams {
+                    spec: CredentialSpec {
+                        credential_type: "VerifiedAdult".to_string(),
+                        arguments: None,
+                    },
+                    subject_id: did_for_principal(Principal::anonymous()),
+                    credential_id_url: "https://issuer.test/credentials/1".to_string(),
+                    issuer_url: "https://issuer.test".to_string(),
+                    expiration_timestamp_s: (NOW_NS / 1_000_000_000) as u32 + 24 * 3600,
+                });
+                let signing_input = key.vc_signing_input(issuer(), &credential_jwt).unwrap();
+                let sig = TestCanisterSigner::new(issuer(), &key.seed).sign(
+                    &self.root_key,
+                    VC_SIGNING_INPUT_DOMAIN,
+                    &signing_input,
+                    NOW_NS as u128,
+                );
+                key.vc_jwt_to_jws(issuer(), &credential_jwt, &sig).unwrap()
+            }
+
+            fn verify(
+                &self,
+                credential_jws: &str,
+                current_time_ns: u64,
+            ) -> Result<(), SignatureVerificationError> {
+                verify_credential_jws_with_issuer_keys(
+                    credential_jws,
+                    &issuer(),
+                    &self.key_ring,
+                    Duration::from_nanos(HOUR_NS),
+                    &self.root_key.ic_root_key(),
+                    current_time_ns as u128,
+                )
+                .map(|_| ())
+            }
+        }
+
+        #[test]
+        fn should_verify_credentials_of_accepted_keys() {
+            let issuer = TestIssuer::new();
+            let now = NOW_NS + 2 * HOUR_NS;
+
+            assert_matches!(issuer.verify(&issuer.credential_jws("key-1"), now), Ok(()));
+            assert_matches!(issuer.verify(&issuer.credential_jws("key-2"), now), Ok(()));
+        }
+
+        #[test]
+        fn should_reject_credential_of_key_after_grace_period() {
+            let issuer = TestIssuer::new();
+            let credential_jws = issuer.credential_jws("key-1");
+
+            let result = issuer.verify(&credential_jws, NOW_NS + 3 * HOUR_NS);
+
+            assert_matches!(result, Err(e) if e.to_string().contains("InactiveKey"));
+        }
+
+        #[test]
+        fn should_reject_credential_without_key_id() {
+            let issuer = TestIssuer::new();
+            let key = issuer.key_ring.signing_key().unwrap();
+            let credential_jws = TestCanisterSigner::new(super::issuer(), &key.seed)
+                .sign_credential_jwt(&issuer.root_key, "{\"exp\":9999999999}", NOW_NS as u128);
+
+            let result = issuer.verify(&credential_jws, NOW_NS + HOUR_NS);
+
+            assert_matches!(result, Err(e) if e.to_string().contains("kid is not a key of the issuer"));
+        }
+
+        #[test]
+        fn should_reject_credential_with_kid_of_other_key() {
+            let issuer = TestIssuer::new();
+            let mut key_ring = issuer.key_ring.clone();
+            // Sign with the seed of key-1, but claim to use key-2.
+            let key_1_seed = key_ring.key("key-1").unwrap().seed.clone();
+            key_ring
+                .keys
+                .iter_mut()
+                .find(|key| key.key_id == "key-2")
+                .unwrap()
+                .seed = key_1_seed;
+            let forged_issuer = TestIssuer {
+                root_key: issuer.root_key,
+                key_ring,
+            };
+            let credential_jws = forged_issuer.credential_jws("key-2");
+
+            let result = TestIssuer {
+                root_key: forged_issuer.root_key,
+                key_ring: rotated_key_ring(),
+            }
+            .verify(&credential_jws, NOW_NS + 3 * HOUR_NS);
+
+            assert_matches!(result, Err(e) if e.to_string().contains("public key does not match"));
+        }
+    }
+}
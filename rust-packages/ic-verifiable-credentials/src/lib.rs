@@ -1,13 +1,19 @@
+use crate::alternative_origins::{AlternativeOrigins, check_alias_tuple_with_alternative_origins};
+use crate::diagnostics::{VerificationCheck, observe};
+use crate::did_resolution::{DidResolver, verify_jws_signature};
+use crate::environment::RootKeyProvider;
 use crate::issuer_api::CredentialSpec;
+use crate::issuer_keys::IssuerKeyRing;
+use crate::status_list::{CertifiedStatusList, CredentialStatus, StatusListError};
+use crate::verification_error::PresentedCredential;
 use base64::Engine;
-use candid::Principal;
+use candid::{CandidType, Deserialize, Principal};
 use ic_canister_sig_creation::{CanisterSigPublicKey, extract_raw_canister_sig_pk_from_der};
 use ic_certification::Hash;
 use ic_signature_verification::verify_canister_sig;
 use identity_core::common::{Timestamp, Url};
 use identity_core::convert::FromJson;
-use identity_core::register_custom_now_utc;
-use identity_credential::credential::{Credential, CredentialBuilder, Jwt, Subject};
+use identity_credential::credential::{Credential, CredentialBuilder, Jwt, Status, Subject};
 use identity_credential::error::Error as JwtVcError;
 use identity_credential::presentation::{
     JwtPresentationOptions, Presentation, PresentationBuilder,
@@ -26,28 +32,38 @@ use sha2::{Digest, Sha256};
 use std::ops::{Add, Deref, DerefMut};
 use std::time::Duration;
 
+pub mod alternative_origins;
+mod certificate;
+pub mod challenge;
+pub mod clock;
+pub mod diagnostics;
+pub mod did_resolution;
+pub mod environment;
+pub mod holder_binding;
 pub mod issuer_api;
-
-#[cfg(not(test))]
-mod ic_time {
-    use super::*;
-    use ic_cdk::api::time;
-
-    fn ic_now() -> Timestamp {
-        Timestamp::from_unix(Duration::from_nanos(time()).as_secs() as i64).unwrap()
-    }
-    register_custom_now_utc!(ic_now);
-}
+pub mod issuer_keys;
+pub mod openid4vci;
+pub mod presentation_exchange;
+pub mod rate_limit;
+pub mod sd_jwt;
+pub mod status_list;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+pub mod threshold_signing;
+pub mod verification_error;
 
 pub const II_CREDENTIAL_URL_PREFIX: &str = "data:text/plain;charset=UTF-8,";
 pub const II_ISSUER_URL: &str = "https://identity.ic0.app/";
 pub const VC_SIGNING_INPUT_DOMAIN: &[u8; 26] = b"iccs_verifiable_credential";
 pub const DID_ICP_PREFIX: &str = "did:icp:";
 const II_MAINNET: &str = "rdmx6-jaaaa-aaaaa-aaadq-cai";
+/// The maximum size (in bytes) of presentations accepted by default, which bounds the cost of
+/// parsing presentations, e.g. within canisters, cf. `VerificationOptions::max_presentation_size`.
+pub const DEFAULT_MAX_PRESENTATION_SIZE: usize = 64 * 1024;
 
 /// A pair of identities, that denote the same user.
 /// Used in attribute sharing flow to maintain II's unlinkability of identities.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct AliasTuple {
     /// A temporary identity, used in attribute sharing flow.
     pub id_alias: Principal,
@@ -70,6 +86,18 @@ pub struct VcFlowSigners {
 pub enum CredentialVerificationError {
     InvalidJws(SignatureVerificationError),
     InvalidClaims(JwtValidationError),
+    /// A claim is missing, or does not have the value expected by the verifier.
+    UnexpectedClaim(ClaimMismatch),
+}
+
+/// A claim of a credential which does not have the expected value.
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct ClaimMismatch {
+    /// The path of the claim within the JWT claims, e.g. `iss` or `vc.type`.
+    pub claim: String,
+    pub expected: String,
+    /// The actual value of the claim, or `None` if the claim is missing.
+    pub actual: Option<String>,
 }
 
 #[derive(Debug)]
@@ -77,6 +105,16 @@ pub enum PresentationVerificationError {
     InvalidPresentationJwt(String),
     InvalidIdAliasCredential(CredentialVerificationError),
     InvalidRequestedCredential(CredentialVerificationError),
+    /// The presentation is not bound to the expected holder, audience or challenge.
+    InvalidHolderBinding(String),
+    /// The challenge contained in the presentation was not issued to the caller, has already
+    /// been used, or has expired.
+    InvalidChallenge(challenge::ChallengeError),
+    /// The presentation is valid, but was rejected by the verification policy of the caller.
+    RejectedByPolicy(String),
+    /// The status list of the issuer could not be verified, or the requested credential
+    /// has been revoked.
+    InvalidCredentialStatus(StatusListError),
     Unknown(String),
 }
 
@@ -95,12 +133,7 @@ pub fn vc_signing_input(
 /// (for domain separation), where `length(a)` is the length of byte-array `a`,
 /// and `·` denotes concatenation of bytes.
 pub fn vc_signing_input_hash(signing_input: &[u8]) -> Hash {
-    let mut hasher = Sha256::new();
-    let buf = [VC_SIGNING_INPUT_DOMAIN.len() as u8];
-    hasher.update(buf);
-    hasher.update(VC_SIGNING_INPUT_DOMAIN);
-    hasher.update(signing_input);
-    hasher.finalize().into()
+    hash_with_domain(VC_SIGNING_INPUT_DOMAIN, signing_input)
 }
 
 /// Constructs and returns a JWS (a signed JWT) from the given components.
@@ -151,7 +184,7 @@ pub fn canister_sig_pk_from_vc_signing_input(
         .map_err(|e| format!("internal: failed parsing signing_input: {:?}", e))?;
     let header = parsed_signing_input
         .protected_header()
-        .expect("internal: failed getting protected header");
+        .ok_or("internal: failed getting protected header")?;
     let canister_sig_pk_raw = get_canister_sig_pk_raw(header)
         .map_err(|e| format!("internal: failed getting canister_sig_pk_raw: {:?}", e))?;
     CanisterSigPublicKey::try_from_raw(&canister_sig_pk_raw)
@@ -183,36 +216,77 @@ pub fn get_verified_id_alias_from_jws(
     expected_vc_subject: &Principal,
     expected_derivation_origin: &str,
     signing_canister_id: &Principal,
-    root_pk_raw: &[u8],
+    root_key: &impl RootKeyProvider,
+    current_time_ns: u128,
+) -> Result<AliasTuple, CredentialVerificationError> {
+    let alias_tuple = verify_id_alias_credential_jws(
+        credential_jws,
+        signing_canister_id,
+        II_ISSUER_URL,
+        root_key,
+        current_time_ns,
+    )?;
+    check_alias_tuple(
+        &alias_tuple,
+        expected_vc_subject,
+        expected_derivation_origin,
+        signing_canister_id,
+    )?;
+    Ok(alias_tuple)
+}
+
+/// Verifies the given JWS-credential as an id_alias-VC and extracts the alias tuple,
+/// without checking who the subject of the credential is.
+/// Performs the cryptographic verification of the credential, and checks that the VC is
+/// a well-formed id_alias-VC issued by II as `expected_ii_origin` (cf. `VcFlowSigners::ii_origin`).
+/// The subject and the derivation origin of the returned alias tuple should be checked by the
+/// caller, e.g. via `check_alias_tuple()`.
+pub fn verify_id_alias_credential_jws(
+    credential_jws: &str,
+    signing_canister_id: &Principal,
+    expected_ii_origin: &str,
+    root_key: &impl RootKeyProvider,
     current_time_ns: u128,
 ) -> Result<AliasTuple, CredentialVerificationError> {
     let claims = verify_credential_jws_with_canister_id(
         credential_jws,
         signing_canister_id,
-        root_pk_raw,
+        root_key,
         current_time_ns,
     )
     .map_err(CredentialVerificationError::InvalidJws)?;
-    validate_claim("iss", II_ISSUER_URL, claims.iss())
-        .map_err(CredentialVerificationError::InvalidClaims)?;
-    let alias_tuple =
-        extract_id_alias(&claims).map_err(CredentialVerificationError::InvalidClaims)?;
+    validate_claim("iss", expected_ii_origin, claims.iss())
+        .map_err(CredentialVerificationError::UnexpectedClaim)?;
+    extract_id_alias(&claims).map_err(CredentialVerificationError::InvalidClaims)
+}
+
+/// Checks that the given alias tuple, obtained from an id_alias-VC signed by
+/// `ii_canister_id`, is for the expected subject and the expected derivation origin.
+pub fn check_alias_tuple(
+    alias_tuple: &AliasTuple,
+    expected_vc_subject: &Principal,
+    expected_derivation_origin: &str,
+    ii_canister_id: &Principal,
+) -> Result<(), CredentialVerificationError> {
     if *expected_vc_subject != alias_tuple.id_dapp {
-        return Err(CredentialVerificationError::InvalidClaims(
-            inconsistent_jwt_claims("unexpected vc subject"),
+        return Err(CredentialVerificationError::UnexpectedClaim(
+            ClaimMismatch {
+                claim: "sub".to_string(),
+                expected: did_for_principal(*expected_vc_subject),
+                actual: Some(did_for_principal(alias_tuple.id_dapp)),
+            },
         ));
     }
-
-    if !matches_expected_origin(
-        signing_canister_id,
-        expected_derivation_origin,
-        &alias_tuple,
-    ) {
-        return Err(CredentialVerificationError::InvalidClaims(
-            inconsistent_jwt_claims("unexpected derivation origin"),
+    if !matches_expected_origin(ii_canister_id, expected_derivation_origin, alias_tuple) {
+        return Err(CredentialVerificationError::UnexpectedClaim(
+            ClaimMismatch {
+                claim: DERIVATION_ORIGIN_CLAIM.to_string(),
+                expected: expected_derivation_origin.to_string(),
+                actual: Some(alias_tuple.derivation_origin.clone()),
+            },
         ));
     }
-    Ok(alias_tuple)
+    Ok(())
 }
 
 /// Verifies the specified JWS credential cryptographically and checks that the signature was
@@ -221,7 +295,7 @@ pub fn get_verified_id_alias_from_jws(
 pub fn verify_credential_jws_with_canister_id(
     credential_jws: &str,
     signing_canister_id: &Principal,
-    root_pk_raw: &[u8],
+    root_key: &impl RootKeyProvider,
     current_time_ns: u128,
 ) -> Result<JwtClaims<Value>, SignatureVerificationError> {
     ///// Decode JWS.
@@ -250,40 +324,212 @@ pub fn verify_credential_jws_with_canister_id(
         &message,
         signature,
         canister_sig_pk_der.as_slice(),
-        root_pk_raw,
+        root_key.root_pk_raw(),
     )
     .map_err(|e| invalid_signature_err(&format!("signature verification error: {}", e)))?;
 
     let claims: JwtClaims<Value> = serde_json::from_slice(jws.claims())
         .map_err(|e| invalid_signature_err(&format!("failed parsing JSON JWT claims: {}", e)))?;
     validate_expiration(claims.exp(), current_time_ns)
-        .map_err(|e| invalid_signature_err(&format!("credential expired: {}", e)))?;
+        .map_err(|e| invalid_signature_err(&format!("{}: {}", CREDENTIAL_EXPIRED, e)))?;
     Ok(claims)
 }
 
-fn extract_credentials_from_vp(vp_jwt: &str) -> Result<Vec<Jwt>, String> {
+/// Verifies the given JWS-credential of the issuer with the DID `issuer_did`, and returns its claims.
+///  * Credentials signed with canister signatures (`alg: IcCs`) are verified like
+///    `verify_credential_jws_with_canister_id()`, where `issuer_did` must be a `did:icp`-DID.
+///  * Credentials signed with `ES256`, `ES256K` or `EdDSA` are verified with the key of the
+///    verification method identified by the `kid` in the JWS header, as resolved via `resolver`.
+///    The `kid` must be a DID URL of `issuer_did`.
+pub fn verify_credential_jws(
+    credential_jws: &str,
+    issuer_did: &str,
+    resolver: &dyn DidResolver,
+    root_key: &impl RootKeyProvider,
+    current_time_ns: u128,
+) -> Result<JwtClaims<Value>, SignatureVerificationError> {
+    let decoder: Decoder = Decoder::new();
+    let jws = decoder
+        .decode_compact_serialization(credential_jws.as_ref(), None)
+        .map_err(|e| invalid_signature_err(&format!("credential JWS parsing error: {}", e)))?;
+    let jws_header = jws
+        .protected_header()
+        .ok_or(invalid_signature_err("missing JWS header"))?;
+    let alg = jws_header
+        .alg()
+        .ok_or(unsupported_alg_err("missing alg in JWS header"))?;
+    match alg {
+        JwsAlgorithm::Custom(ref name) if name == "IcCs" => {
+            let issuer_canister_id = principal_for_did(issuer_did)
+                .map_err(|e| key_decoding_err(&format!("invalid issuer: {}", e)))?;
+            verify_credential_jws_with_canister_id(
+                credential_jws,
+                &issuer_canister_id,
+                root_key,
+                current_time_ns,
+            )
+        }
+        JwsAlgorithm::ES256 | JwsAlgorithm::ES256K | JwsAlgorithm::EdDSA => {
+            let kid = jws_header
+                .kid()
+                .ok_or(key_decoding_err("missing kid in JWS header"))?;
+            if kid.split_once('#').map(|(did, _)| did) != Some(issuer_did) {
+                return Err(key_decoding_err(&format!(
+                    "kid {} is not a verification method of {}",
+                    kid, issuer_did
+                )));
+            }
+            let jwk = resolver
+                .resolve_verification_method(kid)
+                .map_err(|e| key_decoding_err(&format!("failed resolving kid {}: {:?}", kid, e)))?;
+            verify_jws_signature(&alg, &jwk, jws.signing_input(), jws.decoded_signature())
+                .map_err(|e| {
+                    invalid_signature_err(&format!("signature verification error: {}", e))
+                })?;
+
+            let claims: JwtClaims<Value> = serde_json::from_slice(jws.claims()).map_err(|e| {
+                invalid_signature_err(&format!("failed parsing JSON JWT claims: {}", e))
+            })?;
+            validate_expiration(claims.exp(), current_time_ns)
+                .map_err(|e| invalid_signature_err(&format!("{}: {}", CREDENTIAL_EXPIRED, e)))?;
+            Ok(claims)
+        }
+        alg => Err(unsupported_alg_err(&format!(
+            "unsupported alg {}",
+            alg.name()
+        ))),
+    }
+}
+
+/// The format of a presentation, which determines the expected JWS header.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum PresentationFormat {
+    /// An unsigned presentation as returned by II, with `alg: none` and `typ: JWT`.
+    Unsigned,
+    /// A presentation signed with the session key of the holder, cf. `holder_binding`.
+    /// The signature is verified separately via `holder_binding::verify_holder_binding()`.
+    HolderBound,
+}
+
+/// The holder and the credentials of a presentation.
+#[derive(Debug)]
+struct ParsedPresentation {
+    holder: Principal,
+    credentials: Vec<Jwt>,
+}
+
+/// Checks that the given presentation does not exceed `max_size` bytes.
+pub(crate) fn check_presentation_size(vp_jwt: &str, max_size: usize) -> Result<(), String> {
+    if vp_jwt.len() > max_size {
+        return Err(format!(
+            "presentation too large: {} bytes, maximum {} bytes",
+            vp_jwt.len(),
+            max_size
+        ));
+    }
+    Ok(())
+}
+
+/// Parses the given presentation strictly: checks that it has at most `max_size` bytes, its
+/// JWS header, that the holder (`iss`) is a `did:icp`-DID and that `vp.type` contains
+/// `VerifiablePresentation`, and returns the holder with the contained credentials, rejecting
+/// any malformed entries.
+/// DOES NOT verify the credentials.
+fn parse_presentation(
+    vp_jwt: &str,
+    format: PresentationFormat,
+    max_size: usize,
+) -> Result<ParsedPresentation, String> {
     use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
-    let payload = vp_jwt
-        .split('.')
-        .skip(1)
-        .next()
-        .ok_or("Failed to parse presentation JWT")?;
-    let claims: Value = serde_json::from_slice(
-        &BASE64
-            .decode(payload)
-            .map_err(|_| "Failed to decode base64")?,
-    )
-    .map_err(|_| "Failed to parse payload JSON")?;
-    let credentials = claims
-        .pointer("/vp/verifiableCredential")
-        .ok_or("Failed to extract verifiableCredential claim")?
+    check_presentation_size(vp_jwt, max_size)?;
+    let decode_json = |part: &str, name: &str| -> Result<Map<String, Value>, String> {
+        let bytes = BASE64
+            .decode(part)
+            .map_err(|e| format!("failed to decode base64 of presentation {}: {}", name, e))?;
+        serde_json::from_slice(&bytes)
+            .map_err(|e| format!("failed to parse JSON of presentation {}: {}", name, e))
+    };
+    let [header, payload, signature] = vp_jwt.split('.').collect::<Vec<_>>()[..] else {
+        return Err("malformed presentation JWT: expected three parts".to_string());
+    };
+    let header = decode_json(header, "header")?;
+    let header_claim = |name: &str| header.get(name).and_then(Value::as_str);
+    match format {
+        PresentationFormat::Unsigned => {
+            if header_claim("alg") != Some("none") {
+                return Err(format!(
+                    "unexpected alg of presentation: expected none, got {:?}",
+                    header.get("alg")
+                ));
+            }
+            if header_claim("typ") != Some("JWT") {
+                return Err(format!(
+                    "unexpected typ of presentation: expected JWT, got {:?}",
+                    header.get("typ")
+                ));
+            }
+            if !signature.is_empty() {
+                return Err("unexpected signature of unsigned presentation".to_string());
+            }
+        }
+        PresentationFormat::HolderBound => {
+            if !matches!(header_claim("alg"), Some("EdDSA" | "ES256" | "ES256K")) {
+                return Err(format!(
+                    "unexpected alg of holder-bound presentation: expected EdDSA, ES256 or ES256K, got {:?}",
+                    header.get("alg")
+                ));
+            }
+            if header
+                .get(holder_binding::DELEGATION_CHAIN_HEADER)
+                .is_none()
+            {
+                return Err("missing delegation chain of holder-bound presentation".to_string());
+            }
+        }
+    }
+    let claims = decode_json(payload, "payload")?;
+    let holder = claims
+        .get("iss")
+        .and_then(Value::as_str)
+        .ok_or("missing holder (iss) of presentation")?;
+    let holder = principal_for_did(holder)
+        .map_err(|e| format!("invalid holder (iss) of presentation: {}", e))?;
+    let vp = claims
+        .get("vp")
+        .and_then(Value::as_object)
+        .ok_or("missing vp claim of presentation")?;
+    let is_presentation = match vp.get("type") {
+        Some(Value::String(vp_type)) => vp_type == "VerifiablePresentation",
+        Some(Value::Array(vp_types)) => vp_types
+            .iter()
+            .any(|vp_type| vp_type.as_str() == Some("VerifiablePresentation")),
+        _ => false,
+    };
+    if !is_presentation {
+        return Err(format!(
+            "unexpected vp.type of presentation: expected VerifiablePresentation, got {:?}",
+            vp.get("type")
+        ));
+    }
+    let credentials = vp
+        .get("verifiableCredential")
+        .ok_or("missing verifiableCredential claim of presentation")?
         .as_array()
-        .ok_or("Invalid value for 'verifiableCredential' claim")?;
-    Ok(credentials
-        .into_iter()
-        .flat_map(|value| value.as_str())
-        .map(|jwt| Jwt::new(jwt.to_string()))
-        .collect())
+        .ok_or("invalid value for verifiableCredential claim of presentation")?
+        .iter()
+        .enumerate()
+        .map(|(index, value)| match value.as_str() {
+            Some(jws) if jws.split('.').count() == 3 => Ok(Jwt::new(jws.to_string())),
+            _ => Err(format!(
+                "malformed verifiable credential at index {}: expected a compact JWS",
+                index
+            )),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ParsedPresentation {
+        holder,
+        credentials,
+    })
 }
 
 /// Verifies the specified JWT presentation cryptographically, which should contain exactly
@@ -300,61 +546,167 @@ pub fn verify_ii_presentation_jwt_with_canister_ids(
     effective_vc_subject: Principal,
     effective_derivation_origin: String,
     vc_flow_signers: &VcFlowSigners,
-    root_pk_raw: &[u8],
+    root_key: &impl RootKeyProvider,
     current_time_ns: u128,
 ) -> Result<(AliasTuple, JwtClaims<Value>), PresentationVerificationError> {
-    let credentials = extract_credentials_from_vp(vp_jwt)
-        .map_err(PresentationVerificationError::InvalidPresentationJwt)?;
-    if credentials.len() != 2 {
-        return Err(PresentationVerificationError::InvalidPresentationJwt(
-            "expected exactly two verifiable credentials".to_string(),
-        ));
-    }
+    verify_presentation(
+        vp_jwt,
+        vc_flow_signers,
+        root_key,
+        current_time_ns,
+        |alias_tuple| {
+            check_alias_tuple(
+                alias_tuple,
+                &effective_vc_subject,
+                &effective_derivation_origin,
+                &vc_flow_signers.ii_canister_id,
+            )
+            .map_err(PresentationVerificationError::InvalidIdAliasCredential)
+        },
+    )
+}
+
+/// Verifies the specified JWT presentation cryptographically, like
+/// `verify_ii_presentation_jwt_with_canister_ids()`, but without requiring the effective
+/// subject and the derivation origin to be known up front.
+/// Instead, after all the cryptographic checks succeeded, the alias tuple extracted from
+/// the presentation is passed to the given `policy`, which decides whether the subject
+/// and the derivation origin are acceptable (cf. `check_alias_tuple()`).  A policy that
+/// accepts any alias tuple is `|_| Ok(())`.
+/// Returns the verified alias tuple, and the claims from the requested credential.
+/// DOES NOT perform semantic validation of the returned claims.
+pub fn verify_presentation<P>(
+    vp_jwt: &str,
+    vc_flow_signers: &VcFlowSigners,
+    root_key: &impl RootKeyProvider,
+    current_time_ns: u128,
+    policy: P,
+) -> Result<(AliasTuple, JwtClaims<Value>), PresentationVerificationError>
+where
+    P: FnOnce(&AliasTuple) -> Result<(), PresentationVerificationError>,
+{
+    verify_presentation_with_format(
+        vp_jwt,
+        PresentationFormat::Unsigned,
+        DEFAULT_MAX_PRESENTATION_SIZE,
+        vc_flow_signers,
+        root_key,
+        current_time_ns,
+        policy,
+    )
+    .map(|verified| (verified.alias_tuple, verified.claims))
+}
+
+// A presentation whose credentials have been verified by `verify_presentation_with_format()`.
+pub(crate) struct VerifiedPresentation {
+    pub(crate) alias_tuple: AliasTuple,
+    // The claims of the requested credential.
+    pub(crate) claims: JwtClaims<Value>,
+    pub(crate) requested_vc_jws: String,
+}
+
+/// Verifies the given presentation like `verify_presentation()`, expecting the given format and
+/// a presentation of at most `max_presentation_size` bytes.
+pub(crate) fn verify_presentation_with_format<P>(
+    vp_jwt: &str,
+    format: PresentationFormat,
+    max_presentation_size: usize,
+    vc_flow_signers: &VcFlowSigners,
+    root_key: &impl RootKeyProvider,
+    current_time_ns: u128,
+    policy: P,
+) -> Result<VerifiedPresentation, PresentationVerificationError>
+where
+    P: FnOnce(&AliasTuple) -> Result<(), PresentationVerificationError>,
+{
+    let ParsedPresentation {
+        holder,
+        credentials,
+    } = observe(None, VerificationCheck::Presentation, || {
+        let presentation = parse_presentation(vp_jwt, format, max_presentation_size)
+            .map_err(PresentationVerificationError::InvalidPresentationJwt)?;
+        if presentation.credentials.len() != 2 {
+            return Err(PresentationVerificationError::InvalidPresentationJwt(
+                "expected exactly two verifiable credentials".to_string(),
+            ));
+        }
+        Ok(presentation)
+    })?;
     let id_alias_vc_jws = credentials
         .first()
         .ok_or(PresentationVerificationError::Unknown(
             "missing id_alias vc".to_string(),
         ))?;
-    let alias_tuple = get_verified_id_alias_from_jws(
-        id_alias_vc_jws.as_str(),
-        &effective_vc_subject,
-        &effective_derivation_origin,
-        &vc_flow_signers.ii_canister_id,
-        root_pk_raw,
-        current_time_ns,
-    )
-    .map_err(PresentationVerificationError::InvalidIdAliasCredential)?;
+    let alias_tuple = observe(
+        Some(PresentedCredential::IdAlias),
+        VerificationCheck::Credential,
+        || {
+            let alias_tuple = verify_id_alias_credential_jws(
+                id_alias_vc_jws.as_str(),
+                &vc_flow_signers.ii_canister_id,
+                &vc_flow_signers.ii_origin,
+                root_key,
+                current_time_ns,
+            )
+            .map_err(PresentationVerificationError::InvalidIdAliasCredential)?;
+            if alias_tuple.id_dapp != holder {
+                return Err(PresentationVerificationError::InvalidPresentationJwt(
+                    format!(
+                        "presentation holder {} is not the subject of the id_alias credential",
+                        did_for_principal(holder)
+                    ),
+                ));
+            }
+            Ok(alias_tuple)
+        },
+    )?;
     let requested_vc_jws = credentials
         .get(1)
         .ok_or(PresentationVerificationError::Unknown(
             "missing requested vc".to_string(),
         ))?;
-    let claims = verify_credential_jws_with_canister_id(
-        requested_vc_jws.as_str(),
-        &vc_flow_signers.issuer_canister_id,
-        root_pk_raw,
-        current_time_ns,
-    )
-    .map_err(|e| {
-        PresentationVerificationError::InvalidRequestedCredential(
-            CredentialVerificationError::InvalidJws(e),
-        )
-    })?;
-    let requested_vc_subject = extract_subject(&claims).map_err(|e| {
-        PresentationVerificationError::InvalidRequestedCredential(
-            CredentialVerificationError::InvalidClaims(e),
-        )
-    })?;
-    if requested_vc_subject != alias_tuple.id_alias {
-        return Err(PresentationVerificationError::InvalidPresentationJwt(
-            format!(
-                "subject does not match id_alias: expected {}, got {}",
-                alias_tuple.id_alias, requested_vc_subject
+    let claims = observe(
+        Some(PresentedCredential::Requested),
+        VerificationCheck::Credential,
+        || {
+            let claims = verify_credential_jws_with_canister_id(
+                requested_vc_jws.as_str(),
+                &vc_flow_signers.issuer_canister_id,
+                root_key,
+                current_time_ns,
             )
-            .to_string(),
-        ));
-    }
-    Ok((alias_tuple, claims))
+            .map_err(|e| {
+                PresentationVerificationError::InvalidRequestedCredential(
+                    CredentialVerificationError::InvalidJws(e),
+                )
+            })?;
+            let requested_vc_subject = extract_subject(&claims).map_err(|e| {
+                PresentationVerificationError::InvalidRequestedCredential(
+                    CredentialVerificationError::InvalidClaims(e),
+                )
+            })?;
+            if requested_vc_subject != alias_tuple.id_alias {
+                return Err(PresentationVerificationError::InvalidPresentationJwt(
+                    format!(
+                        "subject does not match id_alias: expected {}, got {}",
+                        alias_tuple.id_alias, requested_vc_subject
+                    )
+                    .to_string(),
+                ));
+            }
+            Ok(claims)
+        },
+    )?;
+    observe(
+        Some(PresentedCredential::IdAlias),
+        VerificationCheck::Policy,
+        || policy(&alias_tuple),
+    )?;
+    Ok(VerifiedPresentation {
+        alias_tuple,
+        claims,
+        requested_vc_jws: requested_vc_jws.as_str().to_string(),
+    })
 }
 
 fn extract_vc_claims(claims: &JwtClaims<Value>) -> Result<Map<String, Value>, JwtValidationError> {
@@ -387,7 +739,7 @@ pub fn validate_ii_presentation_and_claims(
     effective_derivation_origin: String,
     vc_flow_signers: &VcFlowSigners,
     vc_spec: &CredentialSpec,
-    root_pk_raw: &[u8],
+    root_key: &impl RootKeyProvider,
     current_time_ns: u128,
 ) -> Result<(), PresentationVerificationError> {
     let (_alias_tuple, claims) = verify_ii_presentation_jwt_with_canister_ids(
@@ -395,16 +747,222 @@ pub fn validate_ii_presentation_and_claims(
         effective_vc_subject,
         effective_derivation_origin,
         vc_flow_signers,
-        root_pk_raw,
+        root_key,
+        current_time_ns,
+    )?;
+    validate_requested_vc_claims(&claims, vc_flow_signers, vc_spec)
+}
+
+/// Validates the provided presentation `vp_jwt`, both cryptographically and semantically,
+/// like `validate_ii_presentation_and_claims()`, but leaves the decision about the subject
+/// and the derivation origin to the given `policy` (cf. `verify_presentation()`).
+/// Returns the verified alias tuple.
+pub fn validate_presentation_and_claims<P>(
+    vp_jwt: &str,
+    vc_flow_signers: &VcFlowSigners,
+    vc_spec: &CredentialSpec,
+    root_key: &impl RootKeyProvider,
+    current_time_ns: u128,
+    policy: P,
+) -> Result<AliasTuple, PresentationVerificationError>
+where
+    P: FnOnce(&AliasTuple) -> Result<(), PresentationVerificationError>,
+{
+    let (alias_tuple, claims) =
+        verify_presentation(vp_jwt, vc_flow_signers, root_key, current_time_ns, policy)?;
+    validate_requested_vc_claims(&claims, vc_flow_signers, vc_spec)?;
+    Ok(alias_tuple)
+}
+
+/// Optional checks of `validate_ii_presentation_and_claims_with_options()`, in addition to those
+/// of `validate_ii_presentation_and_claims()`.  The default options add no checks.
+#[derive(Clone, Copy, Debug)]
+pub struct VerificationOptions<'a> {
+    /// The maximum size (in bytes) of the accepted presentations, `DEFAULT_MAX_PRESENTATION_SIZE`
+    /// by default.
+    pub max_presentation_size: usize,
+    /// Reject credentials which have been revoked according to the status list of the issuer.
+    pub status_list: Option<StatusListOptions<'a>>,
+    /// Reject credentials which have not been signed with an accepted key of the issuer.
+    pub issuer_keys: Option<IssuerKeyOptions<'a>>,
+    /// Accept id_alias credentials derived for any of the alternative origins, in addition to
+    /// the expected derivation origin.
+    pub alternative_origins: Option<&'a AlternativeOrigins>,
+}
+
+/// The status list of the issuer, obtained from `status_list_url`, against which credentials are
+/// checked (cf. `status_list::verify_status_list()`).  The certificate of the status list must
+/// not be older than `max_age`.  Credentials referencing another status list are rejected.
+#[derive(Clone, Copy, Debug)]
+pub struct StatusListOptions<'a> {
+    pub status_list_url: &'a str,
+    pub certified_status_list: &'a CertifiedStatusList,
+    pub max_age: Duration,
+}
+
+/// The signing keys of the issuer, of which credentials must have been signed with a key which
+/// is active or within the `grace_period` after its retirement
+/// (cf. `issuer_keys::verify_credential_jws_with_issuer_keys()`).
+#[derive(Clone, Copy, Debug)]
+pub struct IssuerKeyOptions<'a> {
+    pub issuer_keys: &'a IssuerKeyRing,
+    pub grace_period: Duration,
+}
+
+impl Default for VerificationOptions<'_> {
+    fn default() -> Self {
+        Self {
+            max_presentation_size: DEFAULT_MAX_PRESENTATION_SIZE,
+            status_list: None,
+            issuer_keys: None,
+            alternative_origins: None,
+        }
+    }
+}
+
+impl<'a> VerificationOptions<'a> {
+    pub fn with_max_presentation_size(mut self, max_presentation_size: usize) -> Self {
+        self.max_presentation_size = max_presentation_size;
+        self
+    }
+
+    pub fn with_status_list(
+        mut self,
+        status_list_url: &'a str,
+        certified_status_list: &'a CertifiedStatusList,
+        max_age: Duration,
+    ) -> Self {
+        self.status_list = Some(StatusListOptions {
+            status_list_url,
+            certified_status_list,
+            max_age,
+        });
+        self
+    }
+
+    pub fn with_issuer_keys(
+        mut self,
+        issuer_keys: &'a IssuerKeyRing,
+        grace_period: Duration,
+    ) -> Self {
+        self.issuer_keys = Some(IssuerKeyOptions {
+            issuer_keys,
+            grace_period,
+        });
+        self
+    }
+
+    pub fn with_alternative_origins(mut self, alternative_origins: &'a AlternativeOrigins) -> Self {
+        self.alternative_origins = Some(alternative_origins);
+        self
+    }
+}
+
+/// Validates the provided presentation `vp_jwt` like `validate_ii_presentation_and_claims()`,
+/// with the additional checks of the given `options`.
+#[allow(clippy::too_many_arguments)]
+pub fn validate_ii_presentation_and_claims_with_options(
+    vp_jwt: &str,
+    effective_vc_subject: Principal,
+    effective_derivation_origin: &str,
+    vc_flow_signers: &VcFlowSigners,
+    vc_spec: &CredentialSpec,
+    options: &VerificationOptions,
+    root_key: &impl RootKeyProvider,
+    current_time_ns: u128,
+) -> Result<(), PresentationVerificationError> {
+    let verified = verify_presentation_with_format(
+        vp_jwt,
+        PresentationFormat::Unsigned,
+        options.max_presentation_size,
+        vc_flow_signers,
+        root_key,
         current_time_ns,
+        |alias_tuple| {
+            match options.alternative_origins {
+                Some(alternative_origins) => check_alias_tuple_with_alternative_origins(
+                    alias_tuple,
+                    &effective_vc_subject,
+                    effective_derivation_origin,
+                    alternative_origins,
+                    &vc_flow_signers.ii_canister_id,
+                ),
+                None => check_alias_tuple(
+                    alias_tuple,
+                    &effective_vc_subject,
+                    effective_derivation_origin,
+                    &vc_flow_signers.ii_canister_id,
+                ),
+            }
+            .map_err(PresentationVerificationError::InvalidIdAliasCredential)
+        },
     )?;
-    validate_claim("iss", &vc_flow_signers.issuer_origin, claims.iss())
-        .map_err(invalid_requested_vc)?;
-    let vc_claims = extract_vc_claims(&claims).map_err(invalid_requested_vc)?;
-    validate_claims_match_spec(&vc_claims, vc_spec).map_err(invalid_requested_vc)?;
+    validate_requested_vc_claims(&verified.claims, vc_flow_signers, vc_spec)?;
+    if let Some(status_list) = &options.status_list {
+        observe(
+            Some(PresentedCredential::Requested),
+            VerificationCheck::CredentialStatus,
+            || {
+                let list = status_list::verify_status_list(
+                    status_list.certified_status_list,
+                    &vc_flow_signers.issuer_canister_id,
+                    root_key,
+                    current_time_ns,
+                    status_list.max_age,
+                )
+                .map_err(PresentationVerificationError::InvalidCredentialStatus)?;
+                let vc_claims =
+                    extract_vc_claims(&verified.claims).map_err(invalid_requested_vc)?;
+                status_list::check_credential_status(&vc_claims, status_list.status_list_url, &list)
+                    .map_err(PresentationVerificationError::InvalidCredentialStatus)
+            },
+        )?;
+    }
+    if let Some(issuer_keys) = &options.issuer_keys {
+        observe(
+            Some(PresentedCredential::Requested),
+            VerificationCheck::IssuerKey,
+            || {
+                issuer_keys
+                    .issuer_keys
+                    .accepted_key_for_jws(
+                        &verified.requested_vc_jws,
+                        vc_flow_signers.issuer_canister_id,
+                        current_time_ns,
+                        issuer_keys.grace_period,
+                    )
+                    .map_err(|e| {
+                        PresentationVerificationError::InvalidRequestedCredential(
+                            CredentialVerificationError::InvalidJws(issuer_keys::issuer_key_err(e)),
+                        )
+                    })
+            },
+        )?;
+    }
     Ok(())
 }
 
+fn validate_requested_vc_claims(
+    claims: &JwtClaims<Value>,
+    vc_flow_signers: &VcFlowSigners,
+    vc_spec: &CredentialSpec,
+) -> Result<(), PresentationVerificationError> {
+    observe(
+        Some(PresentedCredential::Requested),
+        VerificationCheck::Claims,
+        || {
+            validate_claim("iss", &vc_flow_signers.issuer_origin, claims.iss()).map_err(|e| {
+                PresentationVerificationError::InvalidRequestedCredential(
+                    CredentialVerificationError::UnexpectedClaim(e),
+                )
+            })?;
+            let vc_claims = extract_vc_claims(claims).map_err(invalid_requested_vc)?;
+            validate_claims_match_spec(&vc_claims, vc_spec)
+                .map_err(PresentationVerificationError::InvalidRequestedCredential)
+        },
+    )
+}
+
 pub struct CredentialParams {
     pub spec: CredentialSpec,
     pub subject_id: String,
@@ -415,6 +973,20 @@ pub struct CredentialParams {
 
 /// Builds a verifiable credential with the given parameters and returns the credential as a JWT-string.
 pub fn build_credential_jwt(params: CredentialParams) -> String {
+    build_credential(params, None)
+}
+
+/// Builds a verifiable credential like `build_credential_jwt()`, which additionally contains
+/// a `credentialStatus`-claim referencing the given entry of the status list of the issuer,
+/// so that the credential can be revoked (cf. `status_list`).
+pub fn build_credential_jwt_with_status(
+    params: CredentialParams,
+    status: &CredentialStatus,
+) -> String {
+    build_credential(params, Some(status))
+}
+
+fn build_credential(params: CredentialParams, status: Option<&CredentialStatus>) -> String {
     let mut subject_json = json!({"id": params.subject_id});
     subject_json.as_object_mut().unwrap().insert(
         params.spec.credential_type.clone(),
@@ -423,14 +995,18 @@ pub fn build_credential_jwt(params: CredentialParams) -> String {
     let subject = Subject::from_json_value(subject_json).unwrap();
     let expiration_date = Timestamp::from_unix(params.expiration_timestamp_s as i64)
         .expect("internal: failed computing expiration timestamp");
-    let credential: Credential = CredentialBuilder::default()
+    let mut builder = CredentialBuilder::default()
         .id(Url::parse(params.credential_id_url).unwrap())
         .issuer(Url::parse(params.issuer_url).unwrap())
         .type_(params.spec.credential_type)
         .subject(subject)
-        .expiration_date(expiration_date)
-        .build()
-        .unwrap();
+        .expiration_date(expiration_date);
+    if let Some(status) = status {
+        let status: Status =
+            Status::from_json_value(status.to_json()).expect("internal: invalid status entry");
+        builder = builder.status(status);
+    }
+    let credential: Credential = builder.build().unwrap();
     credential.serialize_jwt(None).unwrap()
 }
 
@@ -468,12 +1044,24 @@ fn credential_spec_args_to_json(spec: &CredentialSpec) -> serde_json::Value {
 ///      length(VC_SIGNING_INPUT_DOMAIN) || VC_SIGNING_INPUT_DOMAIN
 /// (for domain separation).
 fn signing_input_with_prefix(signing_input: &[u8]) -> Vec<u8> {
-    let mut result = Vec::from([VC_SIGNING_INPUT_DOMAIN.len() as u8]);
-    result.extend_from_slice(VC_SIGNING_INPUT_DOMAIN);
-    result.extend_from_slice(signing_input);
+    domain_separated(VC_SIGNING_INPUT_DOMAIN, signing_input)
+}
+
+fn domain_separated(domain: &[u8], input: &[u8]) -> Vec<u8> {
+    let mut result = Vec::from([domain.len() as u8]);
+    result.extend_from_slice(domain);
+    result.extend_from_slice(input);
     result
 }
 
+fn hash_with_domain(domain: &[u8], input: &[u8]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([domain.len() as u8]);
+    hasher.update(domain);
+    hasher.update(input);
+    hasher.finalize().into()
+}
+
 fn invalid_requested_vc(e: JwtValidationError) -> PresentationVerificationError {
     PresentationVerificationError::InvalidRequestedCredential(
         CredentialVerificationError::InvalidClaims(e),
@@ -534,20 +1122,14 @@ fn validate_claim<T: PartialEq<S> + std::fmt::Display, S: std::fmt::Display>(
     label: &str,
     expected: T,
     actual: Option<S>,
-) -> Result<(), JwtValidationError> {
-    if let Some(actual) = actual {
-        if expected == actual {
-            Ok(())
-        } else {
-            println!(
-                "inconsistent claim [{}] in VC::  expected: {}, actual: {}",
-                label, expected, actual
-            );
-            Err(inconsistent_jwt_claims("inconsistent claim in VC"))
-        }
-    } else {
-        println!("missing claim [{}] in VC", label);
-        Err(inconsistent_jwt_claims("missing claim in VC"))
+) -> Result<(), ClaimMismatch> {
+    match actual {
+        Some(actual) if expected == actual => Ok(()),
+        actual => Err(ClaimMismatch {
+            claim: label.to_string(),
+            expected: expected.to_string(),
+            actual: actual.map(|actual| actual.to_string()),
+        }),
     }
 }
 
@@ -556,9 +1138,10 @@ fn validate_expiration(
     current_time_ns: u128,
 ) -> Result<(), JwtValidationError> {
     if let Some(expiration_s) = maybe_expiration_s {
-        let expiration_ns: u128 = (expiration_s * 1_000_000_000)
-            .try_into()
-            .map_err(|_| JwtValidationError::ExpirationDate)?;
+        let expiration_ns: u128 = expiration_s
+            .checked_mul(1_000_000_000)
+            .and_then(|expiration_ns| expiration_ns.try_into().ok())
+            .ok_or(JwtValidationError::ExpirationDate)?;
         if expiration_ns > current_time_ns {
             Ok(())
         } else {
@@ -578,53 +1161,54 @@ fn validate_expiration(
 pub fn validate_claims_match_spec(
     vc_claims: &Map<String, Value>,
     spec: &CredentialSpec,
-) -> Result<(), JwtValidationError> {
+) -> Result<(), CredentialVerificationError> {
     let credential_type = &spec.credential_type;
+    let invalid_claims =
+        |message| CredentialVerificationError::InvalidClaims(inconsistent_jwt_claims(message));
 
     // Check that type-claim contains spec.credential_type.
     let vc_type_entry = vc_claims
         .get("type")
-        .ok_or(inconsistent_jwt_claims("missing type-claim"))?;
+        .ok_or(invalid_claims("missing type-claim"))?;
     let types = vc_type_entry
         .as_array()
-        .ok_or(inconsistent_jwt_claims("malformed types-claim"))?;
+        .ok_or(invalid_claims("malformed types-claim"))?;
     if !types.contains(&Value::String(credential_type.clone())) {
-        return Err(inconsistent_jwt_claims(
-            "missing credential_type in type-claim",
+        return Err(CredentialVerificationError::UnexpectedClaim(
+            ClaimMismatch {
+                claim: "vc.type".to_string(),
+                expected: credential_type.clone(),
+                actual: Some(vc_type_entry.to_string()),
+            },
         ));
     };
 
     // Check that credentialSubject-claim contains spec.credential_type entry with matching arguments.
     let credential_subject = vc_claims
         .get("credentialSubject")
-        .ok_or(inconsistent_jwt_claims("missing credentialSubject-claim"))?;
+        .ok_or(invalid_claims("missing credentialSubject-claim"))?;
     let subject = Subject::from_json_value(credential_subject.clone())
-        .map_err(|_| inconsistent_jwt_claims("malformed credentialSubject-claim"))?;
+        .map_err(|_| invalid_claims("malformed credentialSubject-claim"))?;
     let verified_claim_arguments = subject
         .properties
         .get(credential_type)
-        .ok_or(inconsistent_jwt_claims("missing credential_type claim"))?
+        .ok_or(invalid_claims("missing credential_type claim"))?
         .as_object()
-        .ok_or(inconsistent_jwt_claims(
-            "malformed credential_type arguments",
-        ))?;
+        .ok_or(invalid_claims("malformed credential_type arguments"))?;
     let spec_arguments_count = spec.arguments.as_ref().map_or(0, |args| args.len());
     if spec_arguments_count != verified_claim_arguments.len() {
-        return Err(inconsistent_jwt_claims(
-            "wrong number of credential_type arguments",
-        ));
+        return Err(invalid_claims("wrong number of credential_type arguments"));
     }
     if let Some(spec_arguments) = spec.arguments.as_ref() {
         for (key, value) in spec_arguments.iter() {
-            if let Some(v) = verified_claim_arguments.get(key) {
-                if value != v {
-                    return Err(inconsistent_jwt_claims(
-                        "wrong value in credential_type argument",
-                    ));
-                }
-            } else {
-                return Err(inconsistent_jwt_claims(
-                    "missing key in credential_type arguments",
+            let actual = verified_claim_arguments.get(key);
+            if actual.is_none_or(|actual| value != actual) {
+                return Err(CredentialVerificationError::UnexpectedClaim(
+                    ClaimMismatch {
+                        claim: format!("vc.credentialSubject.{}.{}", credential_type, key),
+                        expected: Value::from(value.clone()).to_string(),
+                        actual: actual.map(Value::to_string),
+                    },
                 ));
             }
         }
@@ -648,10 +1232,18 @@ fn canister_sig_pk_jwk(canister_sig_pk_der: &[u8]) -> Result<Jwk, String> {
 fn jws_encoder<'a>(
     credential_jwt: &'a str,
     canister_sig_pk: &CanisterSigPublicKey,
+) -> Result<CompactJwsEncoder<'a>, String> {
+    let kid = did_for_principal(canister_sig_pk.canister_id);
+    jws_encoder_with_kid(credential_jwt, canister_sig_pk, kid)
+}
+
+fn jws_encoder_with_kid<'a>(
+    credential_jwt: &'a str,
+    canister_sig_pk: &CanisterSigPublicKey,
+    kid: String,
 ) -> Result<CompactJwsEncoder<'a>, String> {
     let mut header: JwsHeader = JwsHeader::new();
     header.set_alg(JwsAlgorithm::Custom("IcCs".to_string()));
-    let kid = did_for_principal(canister_sig_pk.canister_id);
     let jwk = canister_sig_pk_jwk(&canister_sig_pk.to_der())?;
     header.set_kid(kid);
     header.deref_mut().set_jwk(jwk);
@@ -676,6 +1268,12 @@ fn invalid_signature_err(custom_message: &str) -> SignatureVerificationError {
     err.with_custom_message(custom_message.to_string())
 }
 
+// The message of the signature verification errors of expired credentials.
+pub(crate) const CREDENTIAL_EXPIRED: &str = "credential expired";
+// The path of the derivation origin within the claims of id_alias credentials.
+const DERIVATION_ORIGIN_CLAIM: &str =
+    "vc.credentialSubject.InternetIdentityIdAlias.derivationOrigin";
+
 fn inconsistent_jwt_claims(custom_message: &'static str) -> JwtValidationError {
     JwtValidationError::CredentialStructure(JwtVcError::InconsistentCredentialJwtClaims(
         custom_message,
@@ -720,15 +1318,22 @@ fn construct_verifiable_presentation_jwt(
     holder: Principal,
     vcs_jws: Vec<String>,
 ) -> Result<String, String> {
+    let presentation = build_presentation(holder, vcs_jws)?;
+    presentation_to_compact_jwt(&presentation)
+}
+
+fn build_presentation(
+    holder: Principal,
+    vcs_jws: Vec<String>,
+) -> Result<Presentation<Jwt>, String> {
     let holder_url = Url::parse(did_for_principal(holder)).map_err(|_| "Invalid holder")?;
     let mut builder = PresentationBuilder::new(holder_url, Default::default());
     for vc in vcs_jws {
         builder = builder.credential(Jwt::from(vc));
     }
-    let presentation: Presentation<Jwt> = builder
+    builder
         .build()
-        .map_err(|_| "failed building presentation")?;
-    presentation_to_compact_jwt(&presentation)
+        .map_err(|_| "failed building presentation".to_string())
 }
 
 fn presentation_to_compact_jwt(presentation: &Presentation<Jwt>) -> Result<String, String> {
@@ -748,11 +1353,65 @@ fn presentation_to_compact_jwt(presentation: &Presentation<Jwt>) -> Result<Strin
     Ok(encoder.into_jws(&[]))
 }
 
+/// Returns the origin of the given URL in the canonical form used to match derivation origins:
+///  - scheme and host are lowercase, and the default port of the scheme is omitted,
+///  - a bare trailing slash is dropped,
+///  - the local replica forms `http://127.0.0.1:<port>/?canisterId=<canister id>` and
+///    `http://localhost:<port>/?canisterId=<canister id>` become
+///    `http://<canister id>.localhost:<port>`.
+///
+/// Returns `None` for strings that are not URLs with a scheme, and for URLs with any other
+/// path, query or fragment, which thus match no origin.
+pub fn normalize_origin(origin: &str) -> Option<String> {
+    let (scheme, rest) = origin.trim().split_once("://")?;
+    let scheme = scheme.to_ascii_lowercase();
+    let (authority, path_and_query) =
+        rest.split_at(rest.find(['/', '?', '#']).unwrap_or(rest.len()));
+    let authority = authority.to_ascii_lowercase();
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()) => {
+            (host, Some(port))
+        }
+        _ => (authority.as_str(), None),
+    };
+    let port =
+        port.filter(|port| !matches!((scheme.as_str(), *port), ("https", "443") | ("http", "80")));
+    let host = match path_and_query {
+        "" | "/" => host.to_string(),
+        _ if host == "127.0.0.1" || host == "localhost" => {
+            let canister_id = canister_id_query(path_and_query)?;
+            format!("{}.localhost", canister_id.to_ascii_lowercase())
+        }
+        _ => return None,
+    };
+    Some(match port {
+        Some(port) => format!("{}://{}:{}", scheme, host, port),
+        None => format!("{}://{}", scheme, host),
+    })
+}
+
+// Returns the canister id of a path and query of the form `/?canisterId=<canister id>` or
+// `?canisterId=<canister id>`.
+fn canister_id_query(path_and_query: &str) -> Option<&str> {
+    let query = path_and_query.strip_prefix('/').unwrap_or(path_and_query);
+    query.strip_prefix("?canisterId=").filter(|canister_id| {
+        !canister_id.is_empty()
+            && canister_id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+    })
+}
+
 // Returns the allowed domains based on the expected origin's domain and the signing canister.
+// The expected origin is normalized first (cf. `normalize_origin()`), and no domains are
+// allowed if it is not an origin.
 // * If custom domain -> only use the custom domain.
 // * If canister subdomain and II mainnet -> return the old domain only.
 // * If canister subdomain and not II mainnet -> return the old and new domains.
 fn allowed_origins(origin: &str, signing_canister_id: &Principal) -> Vec<String> {
+    let Some(origin) = &normalize_origin(origin) else {
+        return vec![];
+    };
     // Define the regex for matching canister domains of the format `https://<canister-id>[.raw].icp0`
     let origin_mapping_regex =
         Regex::new(r"^https://(?<subdomain>[\w-]+(?:\.raw)?)\.icp0\.io$").expect("Invalid regex");
@@ -780,19 +1439,21 @@ fn matches_expected_origin(
     expected_derivation_origin: &str,
     alias_tuple: &AliasTuple,
 ) -> bool {
-    allowed_origins(expected_derivation_origin, signing_canister_id)
-        .contains(&alias_tuple.derivation_origin)
+    normalize_origin(&alias_tuple.derivation_origin).is_some_and(|derivation_origin| {
+        allowed_origins(expected_derivation_origin, signing_canister_id)
+            .contains(&derivation_origin)
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::environment::IcRootKey;
     use crate::issuer_api::ArgumentValue;
     use assert_matches::assert_matches;
-    use ic_canister_sig_creation::{
-        IC_ROOT_PK_DER, IC_ROOT_PK_DER_PREFIX, extract_raw_root_pk_from_der,
-    };
+    use identity_core::register_custom_now_utc;
     use std::collections::HashMap;
+    use std::time::Duration;
 
     const MINUTE_NS: u128 = 60 * 1_000_000_000;
     // Created in a local environment
@@ -839,15 +1500,13 @@ mod tests {
     }
     register_custom_now_utc!(test_time);
 
-    fn local_ic_root_pk_raw() -> Vec<u8> {
+    fn local_ic_root_key() -> IcRootKey {
         let pk_der = decode_b64(TEST_IC_ROOT_PK_B64URL).expect("failure decoding canister pk");
-        extract_raw_root_pk_from_der(pk_der.as_slice())
-            .expect("failure extracting local root pk from DER")
+        IcRootKey::from_der(pk_der.as_slice()).expect("failure parsing local root pk from DER")
     }
 
-    fn mainnet_ic_root_pk_raw() -> Vec<u8> {
-        extract_raw_root_pk_from_der(IC_ROOT_PK_DER.as_slice())
-            .expect("failure extracting mainnet root pk from DER")
+    fn mainnet_ic_root_key() -> IcRootKey {
+        IcRootKey::mainnet()
     }
 
     fn local_ii_canister_sig_pk() -> CanisterSigPublicKey {
@@ -986,7 +1645,7 @@ mod tests {
         verify_credential_jws_with_canister_id(
             ALIAS_JWS,
             &mainnet_ii_canister_sig_pk().canister_id,
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             ALIAS_CURRENT_TIME_BEFORE_EXPIRY_NS,
         )
         .expect("JWS verification failed");
@@ -997,7 +1656,7 @@ mod tests {
         let result = verify_credential_jws_with_canister_id(
             ALIAS_JWS,
             &mainnet_ii_canister_sig_pk().canister_id,
-            &&mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             ALIAS_CURRENT_TIME_AFTER_EXPIRY_NS,
         );
         assert_matches!(result, Err(e) if e.to_string().contains("credential expired"));
@@ -1008,18 +1667,31 @@ mod tests {
         let result = verify_credential_jws_with_canister_id(
             TEST_CREDENTIAL_JWS_NO_EXPIRY,
             &local_issuer_canister_sig_pk().canister_id,
-            &local_ic_root_pk_raw(),
+            &local_ic_root_key(),
             ALIAS_CURRENT_TIME_AFTER_EXPIRY_NS,
         );
         assert_matches!(result, Err(e) if e.to_string().contains("structure is not semantically correct"));
     }
 
+    #[test]
+    fn should_reject_out_of_range_expiration() {
+        for expiration_s in [i64::MAX, i64::MAX / 1_000_000_000 + 1, i64::MIN, -1] {
+            assert_matches!(
+                validate_expiration(Some(expiration_s), 0),
+                Err(JwtValidationError::ExpirationDate),
+                "{}",
+                expiration_s
+            );
+        }
+        assert_matches!(validate_expiration(Some(1), 0), Ok(()));
+    }
+
     #[test]
     fn should_fail_verify_credential_jws_without_canister_pk() {
         let result = verify_credential_jws_with_canister_id(
             ID_ALIAS_CREDENTIAL_JWS_NO_JWK,
             &mainnet_ii_canister_sig_pk().canister_id,
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             ALIAS_CURRENT_TIME_BEFORE_EXPIRY_NS,
         );
         assert_matches!(result, Err(e) if e.to_string().contains("missing JWK in JWS header"));
@@ -1031,7 +1703,7 @@ mod tests {
         let result = verify_credential_jws_with_canister_id(
             ALIAS_JWS,
             &wrong_canister_sig_pk.canister_id,
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             ALIAS_CURRENT_TIME_BEFORE_EXPIRY_NS,
         );
         assert_matches!(result, Err(e) if e.to_string().contains("canister sig canister id does not match provided canister id"));
@@ -1039,12 +1711,10 @@ mod tests {
 
     #[test]
     fn should_fail_verify_credential_jws_with_wrong_root_pk() {
-        let mut ic_root_pk = local_ic_root_pk_raw();
-        ic_root_pk[IC_ROOT_PK_DER_PREFIX.len()] += 1; // change the root pk value
         let result = verify_credential_jws_with_canister_id(
             ALIAS_JWS,
             &mainnet_ii_canister_sig_pk().canister_id,
-            &local_ic_root_pk_raw(),
+            &local_ic_root_key(),
             ALIAS_CURRENT_TIME_BEFORE_EXPIRY_NS,
         );
         let err = result.err().expect("expected error");
@@ -1058,7 +1728,7 @@ mod tests {
             &dapp_principal(),
             RP_DERIVATION_ORIGIN_OLD_DOMAIN,
             &mainnet_ii_canister_sig_pk().canister_id,
-            &&mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             ALIAS_CURRENT_TIME_BEFORE_EXPIRY_NS,
         )
         .expect("JWS verification failed");
@@ -1074,8 +1744,8 @@ mod tests {
 
     #[test]
     fn should_parse_verifiable_presentation() {
-        let id_alias_vc_jws = "a dummy id_alias_vc_jws".to_string();
-        let requested_vc_jws = "a dummy requested_vc_jws".to_string();
+        let id_alias_vc_jws = "dummy.id_alias_vc.jws".to_string();
+        let requested_vc_jws = "dummy.requested_vc.jws".to_string();
         let holder = dapp_principal();
         let vp_jwt = build_ii_verifiable_presentation_jwt(
             holder,
@@ -1083,10 +1753,151 @@ mod tests {
             requested_vc_jws.clone(),
         )
         .expect("vp-creation failed");
-        let credentials = extract_credentials_from_vp(&vp_jwt).expect("failed jwt parsing");
+        let presentation = parse_presentation(
+            &vp_jwt,
+            PresentationFormat::Unsigned,
+            DEFAULT_MAX_PRESENTATION_SIZE,
+        )
+        .expect("failed jwt parsing");
+
+        assert_eq!(presentation.holder, holder);
+        assert_eq!(
+            presentation.credentials,
+            vec![Jwt::from(id_alias_vc_jws), Jwt::from(requested_vc_jws)]
+        );
+    }
 
-        assert!(credentials.contains(&Jwt::from(id_alias_vc_jws)));
-        assert!(credentials.contains(&Jwt::from(requested_vc_jws)));
+    // Returns an unsigned JWT with the given header and payload.
+    fn unsigned_jwt(header: Value, payload: Value) -> String {
+        format!(
+            "{}.{}.",
+            encode_b64(header.to_string()),
+            encode_b64(payload.to_string())
+        )
+    }
+
+    fn vp_payload(holder: Principal, vp_type: Value, credentials: Value) -> Value {
+        json!({
+            "iss": did_for_principal(holder),
+            "vp": {
+                "@context": "https://www.w3.org/2018/credentials/v1",
+                "type": vp_type,
+                "verifiableCredential": credentials,
+            }
+        })
+    }
+
+    #[test]
+    fn should_reject_malformed_presentation() {
+        let holder = dapp_principal();
+        let header = json!({"typ": "JWT", "alg": "none"});
+        let credentials = json!(["a.b.c", "d.e.f"]);
+        let payload = vp_payload(holder, json!("VerifiablePresentation"), credentials.clone());
+        let parse = |vp_jwt: &str| {
+            parse_presentation(
+                vp_jwt,
+                PresentationFormat::Unsigned,
+                DEFAULT_MAX_PRESENTATION_SIZE,
+            )
+        };
+        assert_matches!(parse(&unsigned_jwt(header.clone(), payload.clone())), Ok(_));
+
+        let cases = [
+            (
+                unsigned_jwt(json!({"typ": "JWT", "alg": "IcCs"}), payload.clone()),
+                "unexpected alg",
+            ),
+            (
+                unsigned_jwt(json!({"alg": "none"}), payload.clone()),
+                "unexpected typ",
+            ),
+            (
+                format!("{}sig", unsigned_jwt(header.clone(), payload.clone())),
+                "unexpected signature",
+            ),
+            (
+                format!("{}.", unsigned_jwt(header.clone(), payload.clone())),
+                "expected three parts",
+            ),
+            (
+                unsigned_jwt(header.clone(), json!({"vp": payload["vp"].clone()})),
+                "missing holder",
+            ),
+            (
+                unsigned_jwt(
+                    header.clone(),
+                    json!({"iss": "did:web:example.com", "vp": payload["vp"].clone()}),
+                ),
+                "invalid holder",
+            ),
+            (
+                unsigned_jwt(
+                    header.clone(),
+                    vp_payload(holder, json!(["VerifiableCredential"]), credentials.clone()),
+                ),
+                "unexpected vp.type",
+            ),
+            (
+                unsigned_jwt(
+                    header.clone(),
+                    vp_payload(
+                        holder,
+                        json!("VerifiablePresentation"),
+                        json!(["a.b.c", 42]),
+                    ),
+                ),
+                "malformed verifiable credential at index 1",
+            ),
+            (
+                unsigned_jwt(
+                    header.clone(),
+                    vp_payload(
+                        holder,
+                        json!("VerifiablePresentation"),
+                        json!(["a.b", "d.e.f"]),
+                    ),
+                ),
+                "malformed verifiable credential at index 0",
+            ),
+            (
+                unsigned_jwt(
+                    header.clone(),
+                    vp_payload(
+                        holder,
+                        json!("VerifiablePresentation"),
+                        json!(["a.b.c", "d".repeat(DEFAULT_MAX_PRESENTATION_SIZE)]),
+                    ),
+                ),
+                "presentation too large",
+            ),
+        ];
+        for (vp_jwt, expected_error) in cases {
+            assert_matches!(parse(&vp_jwt), Err(e) if e.contains(expected_error), "{}", expected_error);
+        }
+    }
+
+    #[test]
+    fn should_fail_verify_ii_presentation_with_wrong_holder() {
+        let id_dapp = Principal::from_text(VP_RP_ID).expect("wrong principal");
+        let vp_jwt = build_ii_verifiable_presentation_jwt(
+            alias_principal(),
+            VP_ID_ALIAS_JWS.to_string(),
+            VP_VC_JWS.to_string(),
+        )
+        .expect("vp creation failed");
+        let result = verify_ii_presentation_jwt_with_canister_ids(
+            &vp_jwt,
+            id_dapp,
+            RP_DERIVATION_ORIGIN_OLD_DOMAIN.to_string(),
+            &mainnet_test_vc_flow_signers(),
+            &mainnet_ic_root_key(),
+            VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
+        );
+        assert_matches!(
+            result,
+            Err(PresentationVerificationError::InvalidPresentationJwt(e))
+                if e.contains("is not the subject of the id_alias credential")
+        );
     }
 
     #[test]
@@ -1104,7 +1915,7 @@ mod tests {
             id_dapp,
             RP_DERIVATION_ORIGIN_OLD_DOMAIN.to_string(),
             &mainnet_test_vc_flow_signers(),
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
         )
         .expect("vp verification failed");
@@ -1127,7 +1938,7 @@ mod tests {
             id_dapp,
             RP_DERIVATION_ORIGIN_NEW_DOMAIN.to_string(),
             &mainnet_test_vc_flow_signers(),
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
         )
         .expect("vp verification failed");
@@ -1149,7 +1960,7 @@ mod tests {
             id_dapp,
             RP_DERIVATION_ORIGIN_OLD_DOMAIN.to_string(),
             &mainnet_test_vc_flow_signers(),
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_AFTER_EXPIRY_NS,
         );
         assert_matches!(result, Err(e) if format!("{:?}", e).contains("credential expired"));
@@ -1163,7 +1974,7 @@ mod tests {
             vec![
                 VP_ID_ALIAS_JWS.to_string(),
                 VP_VC_JWS.to_string(),
-                "an extra vc".to_string(),
+                VP_VC_JWS.to_string(),
             ],
         )
         .expect("vp creation failed");
@@ -1172,7 +1983,7 @@ mod tests {
             id_dapp,
             RP_DERIVATION_ORIGIN_OLD_DOMAIN.to_string(),
             &mainnet_test_vc_flow_signers(),
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
         );
         assert_matches!(result, Err(e) if format!("{:?}", e).contains("expected exactly two verifiable credentials"));
@@ -1189,7 +2000,7 @@ mod tests {
             id_dapp,
             RP_DERIVATION_ORIGIN_OLD_DOMAIN.to_string(),
             &mainnet_test_vc_flow_signers(),
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
         );
         assert_matches!(result, Err(e) if format!("{:?}", e).contains("expected exactly two verifiable credentials"));
@@ -1199,7 +2010,7 @@ mod tests {
     fn should_fail_verify_ii_presentation_with_wrong_effective_subject() {
         let wrong_subject = alias_principal(); // does not match the "sub" VP_VC_JWS
         let vp_jwt = build_ii_verifiable_presentation_jwt(
-            wrong_subject,
+            Principal::from_text(VP_RP_ID).expect("wrong principal"),
             VP_ID_ALIAS_JWS.to_string(),
             VP_VC_JWS.to_string(),
         )
@@ -1209,10 +2020,15 @@ mod tests {
             wrong_subject,
             RP_DERIVATION_ORIGIN_OLD_DOMAIN.to_string(),
             &mainnet_test_vc_flow_signers(),
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
         );
-        assert_matches!(result, Err(e) if format!("{:?}", e).contains("unexpected vc subject"));
+        assert_matches!(
+            result,
+            Err(PresentationVerificationError::InvalidIdAliasCredential(
+                CredentialVerificationError::UnexpectedClaim(ClaimMismatch { claim, .. })
+            )) if claim == "sub"
+        );
     }
 
     #[test]
@@ -1231,7 +2047,7 @@ mod tests {
             id_dapp,
             RP_DERIVATION_ORIGIN_OLD_DOMAIN.to_string(),
             &mainnet_test_vc_flow_signers(),
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
         );
         assert_matches!(result, Err(e) if format!("{:?}", e).contains("subject does not match id_alias"));
@@ -1251,7 +2067,7 @@ mod tests {
             id_dapp,
             RP_DERIVATION_ORIGIN_OLD_DOMAIN.to_string(),
             &mainnet_test_vc_flow_signers(),
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
         );
         assert_matches!(result, Err(e) if format!("{:?}", e).contains("InvalidSignature"));
@@ -1274,7 +2090,7 @@ mod tests {
             id_dapp,
             RP_DERIVATION_ORIGIN_OLD_DOMAIN.to_string(),
             &mainnet_test_vc_flow_signers(),
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
         );
         assert_matches!(result, Err(e) if format!("{:?}", e).contains("InvalidSignature"));
@@ -1298,7 +2114,7 @@ mod tests {
                 ii_canister_id: local_issuer_canister_sig_pk().canister_id,
                 ..mainnet_test_vc_flow_signers()
             },
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
         );
         assert_matches!(result, Err(e) if format!("{:?}", e).contains("canister id does not match"));
@@ -1322,7 +2138,7 @@ mod tests {
                 issuer_canister_id: local_ii_canister_sig_pk().canister_id,
                 ..mainnet_test_vc_flow_signers()
             },
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
         );
         assert_matches!(result, Err(e) if format!("{:?}", e).contains("canister id does not match"));
@@ -1349,10 +2165,15 @@ mod tests {
                 issuer_canister_id: mainnet_ii_canister_sig_pk().canister_id,
                 ..mainnet_test_vc_flow_signers()
             },
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
         );
-        assert_matches!(result, Err(e) if format!("{:?}", e).contains("inconsistent claim in VC"));
+        assert_matches!(
+            result,
+            Err(PresentationVerificationError::InvalidIdAliasCredential(
+                CredentialVerificationError::UnexpectedClaim(ClaimMismatch { claim, .. })
+            )) if claim == "iss"
+        );
     }
 
     fn credential_spec_with_0_args() -> CredentialSpec {
@@ -1433,7 +2254,13 @@ mod tests {
                 Value::Array(vec![Value::String("WrongType".to_string())]),
             );
             let result = validate_claims_match_spec(&claims, &spec);
-            assert_matches!(result, Err(e) if format!("{:?}", e).contains("missing credential_type in type-claim"));
+            assert_matches!(
+                result,
+                Err(CredentialVerificationError::UnexpectedClaim(ClaimMismatch { claim, expected, actual }))
+                    if claim == "vc.type"
+                        && expected == spec.credential_type
+                        && actual.as_deref() == Some(r#"["WrongType"]"#)
+            );
         }
     }
 
@@ -1512,7 +2339,12 @@ mod tests {
                 .expect("wrong credential_type claim")
                 .insert(arg_name.clone(), Value::String("a wrong value".to_string()));
             let result = validate_claims_match_spec(&claims, &spec);
-            assert_matches!(result, Err(e) if format!("{:?}", e).contains("wrong value in credential_type argument"));
+            assert_matches!(
+                result,
+                Err(CredentialVerificationError::UnexpectedClaim(ClaimMismatch { claim, actual, .. }))
+                    if claim == format!("vc.credentialSubject.{}.{}", spec.credential_type, arg_name)
+                        && actual.as_deref() == Some(r#""a wrong value""#)
+            );
         }
     }
 
@@ -1540,7 +2372,7 @@ mod tests {
             RP_DERIVATION_ORIGIN_OLD_DOMAIN.to_string(),
             &mainnet_test_vc_flow_signers(),
             &vp_vc_spec(),
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
         )
         .expect("VP verification failed");
@@ -1566,7 +2398,7 @@ mod tests {
                 ..mainnet_test_vc_flow_signers()
             },
             &vp_vc_spec(),
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
         );
         assert_matches!(result, Err(e) if format!("{:?}", e).to_string().contains("InvalidSignature"));
@@ -1581,7 +2413,7 @@ mod tests {
                 ..mainnet_test_vc_flow_signers()
             },
             &vp_vc_spec(),
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
         );
         assert_matches!(result, Err(e) if format!("{:?}", e).to_string().contains("InvalidSignature"));
@@ -1596,10 +2428,15 @@ mod tests {
                 ..mainnet_test_vc_flow_signers()
             },
             &vp_vc_spec(),
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
         );
-        assert_matches!(result, Err(e) if format!("{:?}", e).to_string().contains("InconsistentCredentialJwtClaims"));
+        assert_matches!(
+            result,
+            Err(PresentationVerificationError::InvalidRequestedCredential(
+                CredentialVerificationError::UnexpectedClaim(ClaimMismatch { claim, expected, .. })
+            )) if claim == "iss" && expected == "https://wrong.origin.com"
+        );
     }
 
     #[test]
@@ -1618,10 +2455,17 @@ mod tests {
             RP_DERIVATION_ORIGIN_OLD_DOMAIN.to_string(),
             &mainnet_test_vc_flow_signers(),
             &vp_vc_spec(),
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
         );
-        assert_matches!(result, Err(e) if format!("{:?}", e).to_string().contains("unexpected vc subject"));
+        assert_matches!(
+            result,
+            Err(PresentationVerificationError::InvalidIdAliasCredential(
+                CredentialVerificationError::UnexpectedClaim(ClaimMismatch { claim, expected, actual })
+            )) if claim == "sub"
+                && expected == did_for_principal(id_alias)
+                && actual == Some(did_for_principal(id_dapp))
+        );
     }
 
     #[test]
@@ -1639,7 +2483,7 @@ mod tests {
             RP_DERIVATION_ORIGIN_OLD_DOMAIN.to_string(),
             &mainnet_test_vc_flow_signers(),
             &vp_vc_spec(),
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_AFTER_EXPIRY_NS,
         );
         assert_matches!(result, Err(e) if format!("{:?}", e).to_string().contains("credential expired"));
@@ -1664,10 +2508,15 @@ mod tests {
             RP_DERIVATION_ORIGIN_OLD_DOMAIN.to_string(),
             &mainnet_test_vc_flow_signers(),
             &wrong_spec,
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
         );
-        assert_matches!(result, Err(e) if format!("{:?}", e).to_string().contains("InconsistentCredentialJwtClaims"));
+        assert_matches!(
+            result,
+            Err(PresentationVerificationError::InvalidRequestedCredential(
+                CredentialVerificationError::UnexpectedClaim(ClaimMismatch { claim, expected, .. })
+            )) if claim == "vc.type" && expected == "NotSameCredential"
+        );
     }
 
     #[test]
@@ -1685,10 +2534,17 @@ mod tests {
             "not-same-derivation_origin".to_string(),
             &mainnet_test_vc_flow_signers(),
             &vp_vc_spec(),
-            &mainnet_ic_root_pk_raw(),
+            &mainnet_ic_root_key(),
             VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
         );
-        assert_matches!(result, Err(e) if format!("{:?}", e).to_string().contains("unexpected derivation origin"));
+        assert_matches!(
+            result,
+            Err(PresentationVerificationError::InvalidIdAliasCredential(
+                CredentialVerificationError::UnexpectedClaim(ClaimMismatch { claim, expected, actual })
+            )) if claim == DERIVATION_ORIGIN_CLAIM
+                && expected == "not-same-derivation_origin"
+                && actual.as_deref() == Some(RP_DERIVATION_ORIGIN_OLD_DOMAIN)
+        );
     }
 
     #[test]
@@ -1724,12 +2580,138 @@ mod tests {
             RP_DERIVATION_ORIGIN_OLD_DOMAIN.to_string(),
             &flow_signers,
             &spec,
-            &local_ic_root_pk_raw(),
+            &local_ic_root_key(),
             expiry_ns - MINUTE_NS,
         );
         assert_matches!(result, Err(e) if format!("{:?}", e).to_string().contains("missing \\\"derivationOrigin\\\" parameter in id_alias JWT vc"));
     }
 
+    #[test]
+    fn should_verify_presentation_without_known_subject() {
+        let id_alias = Principal::from_text(VP_ID_ALIAS).expect("wrong principal");
+        let id_dapp = Principal::from_text(VP_RP_ID).expect("wrong principal");
+        let vp_jwt = build_ii_verifiable_presentation_jwt(
+            id_dapp,
+            VP_ID_ALIAS_JWS.to_string(),
+            VP_VC_JWS.to_string(),
+        )
+        .expect("vp creation failed");
+        let (alias_tuple, _claims) = verify_presentation(
+            &vp_jwt,
+            &mainnet_test_vc_flow_signers(),
+            &mainnet_ic_root_key(),
+            VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
+            |_| Ok(()),
+        )
+        .expect("vp verification failed");
+        assert_eq!(
+            alias_tuple,
+            AliasTuple {
+                id_alias,
+                id_dapp,
+                derivation_origin: RP_DERIVATION_ORIGIN_OLD_DOMAIN.to_string(),
+            }
+        );
+        check_alias_tuple(
+            &alias_tuple,
+            &id_dapp,
+            RP_DERIVATION_ORIGIN_NEW_DOMAIN,
+            &mainnet_test_vc_flow_signers().ii_canister_id,
+        )
+        .expect("alias tuple check failed");
+    }
+
+    #[test]
+    fn should_fail_verify_presentation_if_rejected_by_policy() {
+        let id_dapp = Principal::from_text(VP_RP_ID).expect("wrong principal");
+        let vp_jwt = build_ii_verifiable_presentation_jwt(
+            id_dapp,
+            VP_ID_ALIAS_JWS.to_string(),
+            VP_VC_JWS.to_string(),
+        )
+        .expect("vp creation failed");
+        let result = verify_presentation(
+            &vp_jwt,
+            &mainnet_test_vc_flow_signers(),
+            &mainnet_ic_root_key(),
+            VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
+            |alias_tuple| {
+                if alias_tuple.derivation_origin == RP_DERIVATION_ORIGIN_OLD_DOMAIN {
+                    Err(PresentationVerificationError::RejectedByPolicy(
+                        "origin not audited".to_string(),
+                    ))
+                } else {
+                    Ok(())
+                }
+            },
+        );
+        assert_matches!(result, Err(PresentationVerificationError::RejectedByPolicy(e)) if e == "origin not audited");
+    }
+
+    #[test]
+    fn should_not_invoke_policy_if_presentation_invalid() {
+        let id_dapp = Principal::from_text(VP_RP_ID).expect("wrong principal");
+        let vp_jwt = build_ii_verifiable_presentation_jwt(
+            id_dapp,
+            VP_ID_ALIAS_JWS.to_string(),
+            VP_VC_JWS.to_string(),
+        )
+        .expect("vp creation failed");
+        let mut policy_invoked = false;
+        let result = verify_presentation(
+            &vp_jwt,
+            &mainnet_test_vc_flow_signers(),
+            &mainnet_ic_root_key(),
+            VP_CURRENT_TIME_AFTER_EXPIRY_NS,
+            |_| {
+                policy_invoked = true;
+                Ok(())
+            },
+        );
+        assert_matches!(result, Err(e) if format!("{:?}", e).contains("credential expired"));
+        assert!(!policy_invoked);
+    }
+
+    #[test]
+    fn should_validate_presentation_and_claims_without_known_subject() {
+        let id_dapp = Principal::from_text(VP_RP_ID).expect("wrong principal");
+        let vp_jwt = build_ii_verifiable_presentation_jwt(
+            id_dapp,
+            VP_ID_ALIAS_JWS.to_string(),
+            VP_VC_JWS.to_string(),
+        )
+        .expect("vp-creation failed");
+        let alias_tuple = validate_presentation_and_claims(
+            &vp_jwt,
+            &mainnet_test_vc_flow_signers(),
+            &vp_vc_spec(),
+            &mainnet_ic_root_key(),
+            VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
+            |_| Ok(()),
+        )
+        .expect("VP verification failed");
+        assert_eq!(alias_tuple.id_dapp, id_dapp);
+
+        let wrong_spec = CredentialSpec {
+            credential_type: "NotSameCredential".to_string(),
+            arguments: None,
+        };
+        let result = validate_presentation_and_claims(
+            &vp_jwt,
+            &mainnet_test_vc_flow_signers(),
+            &wrong_spec,
+            &mainnet_ic_root_key(),
+            VP_CURRENT_TIME_BEFORE_EXPIRY_NS,
+            |_| Ok(()),
+        );
+        assert_matches!(
+            result,
+            Err(PresentationVerificationError::InvalidRequestedCredential(
+                CredentialVerificationError::UnexpectedClaim(ClaimMismatch { claim, expected, .. })
+            )) if claim == "vc.type" && expected == "NotSameCredential"
+        );
+    }
+
     // Removes nbf-entry from the given VC-JWT.
     fn remove_nbf(vc_jwt: &str) -> String {
         let mut ret = vc_jwt.to_string();
@@ -1770,6 +2752,34 @@ mod tests {
         assert_eq!(remove_nbf(credential.as_str()), example_jwt_without_nbf);
     }
 
+    #[test]
+    fn should_build_credential_jwt_with_status() {
+        let params = CredentialParams {
+            spec: CredentialSpec {
+                credential_type: "VerifiedAdult".to_string(),
+                arguments: None,
+            },
+            subject_id: did_for_principal(Principal::anonymous()),
+            credential_id_url: "https://age_verifier.info/credentials/42".to_string(),
+            issuer_url: "https://age_verifier.info".to_string(),
+            expiration_timestamp_s: 1620329470,
+        };
+        let status = CredentialStatus {
+            status_list_url: "https://age_verifier.info/status-list".to_string(),
+            index: 42,
+        };
+
+        let credential = build_credential_jwt_with_status(params, &status);
+
+        let claims: Value = serde_json::from_str(&credential).expect("malformed claims");
+        let vc_claims = claims["vc"].as_object().expect("missing vc claims");
+        assert_eq!(vc_claims["credentialStatus"], status.to_json());
+        assert_eq!(
+            CredentialStatus::from_vc_claims(vc_claims),
+            Ok(Some(status))
+        );
+    }
+
     // Tests for `matches_expected_origin`
 
     #[test]
@@ -1904,4 +2914,192 @@ mod tests {
             &alias_tuple
         ));
     }
+
+    #[test]
+    fn should_normalize_origins() {
+        let local_id = "bkyz2-fmaaa-aaaaa-qaaaq-cai";
+        let local_origin = format!("http://{}.localhost:4943", local_id);
+        let cases = [
+            ("https://example.com", "https://example.com".to_string()),
+            ("https://example.com/", "https://example.com".to_string()),
+            ("HTTPS://Example.COM", "https://example.com".to_string()),
+            ("https://example.com:443", "https://example.com".to_string()),
+            (
+                "https://example.com:8443/",
+                "https://example.com:8443".to_string(),
+            ),
+            ("http://example.com:80", "http://example.com".to_string()),
+            (
+                "https://example.icp0.io/",
+                "https://example.icp0.io".to_string(),
+            ),
+            (&local_origin, local_origin.clone()),
+            (&format!("{}/", local_origin), local_origin.clone()),
+            (
+                &format!("HTTP://{}.LOCALHOST:4943", local_id.to_uppercase()),
+                local_origin.clone(),
+            ),
+            (
+                &format!("http://127.0.0.1:4943/?canisterId={}", local_id),
+                local_origin.clone(),
+            ),
+            (
+                &format!("http://127.0.0.1:4943?canisterId={}", local_id),
+                local_origin.clone(),
+            ),
+            (
+                &format!("http://localhost:4943/?canisterId={}", local_id),
+                local_origin.clone(),
+            ),
+            (
+                &format!("http://127.0.0.1:8080/?canisterId={}", local_id),
+                format!("http://{}.localhost:8080", local_id),
+            ),
+            ("http://127.0.0.1:4943", "http://127.0.0.1:4943".to_string()),
+        ];
+        for (origin, expected) in cases {
+            assert_eq!(normalize_origin(origin), Some(expected), "{}", origin);
+        }
+    }
+
+    #[test]
+    fn should_not_normalize_urls_with_paths() {
+        let local_id = "bkyz2-fmaaa-aaaaa-qaaaq-cai";
+        let cases = [
+            "https://example.com/some/path".to_string(),
+            "https://example.com//".to_string(),
+            "https://example.com/?".to_string(),
+            "https://example.com/#".to_string(),
+            "https://example.com?app".to_string(),
+            format!("https://example.com/?canisterId={}", local_id),
+            format!("http://localhost:4943/?id=1&canisterId={}", local_id),
+            format!("http://localhost:4943/?canisterId={}#main", local_id),
+            format!("http://127.0.0.1:4943/app?canisterId={}", local_id),
+            "http://127.0.0.1:4943/?canisterId=".to_string(),
+            "not an origin".to_string(),
+        ];
+        for origin in cases {
+            assert_eq!(normalize_origin(&origin), None, "{}", origin);
+        }
+    }
+
+    #[test]
+    fn should_match_expected_origin_in_any_form() {
+        let ii_mainnet = Principal::from_text(II_MAINNET).unwrap();
+        let local_ii = Principal::from_text(LOCAL_II_CANISTER_ID).unwrap();
+        let local_id = "bkyz2-fmaaa-aaaaa-qaaaq-cai";
+        let local_origin = format!("http://{}.localhost:4943", local_id);
+        let query_origin = format!("http://127.0.0.1:4943/?canisterId={}", local_id);
+        // (signing canister, expected derivation origin, derivation origin of the alias tuple, matches)
+        let cases = [
+            (local_ii, local_origin.as_str(), local_origin.as_str(), true),
+            (local_ii, &query_origin, &local_origin, true),
+            (local_ii, &local_origin, &query_origin, true),
+            (local_ii, &format!("{}/", local_origin), &local_origin, true),
+            (local_ii, &local_origin.to_uppercase(), &local_origin, true),
+            (
+                local_ii,
+                &local_origin,
+                "http://rrkah-fqaaa-aaaaa-aaaaq-cai.localhost:4943",
+                false,
+            ),
+            (
+                local_ii,
+                &local_origin,
+                &format!("http://{}.localhost:8080", local_id),
+                false,
+            ),
+            (local_ii, &local_origin, "http://127.0.0.1:4943", false),
+            (
+                local_ii,
+                &local_origin,
+                &format!("https://{}.localhost:4943", local_id),
+                false,
+            ),
+            (
+                local_ii,
+                "https://example.icp0.io/",
+                "https://example.icp0.io",
+                true,
+            ),
+            (
+                local_ii,
+                "https://Example.icp0.io",
+                "https://example.ic0.app",
+                true,
+            ),
+            (
+                ii_mainnet,
+                "https://example.icp0.io/",
+                "https://example.ic0.app",
+                true,
+            ),
+            (
+                ii_mainnet,
+                "https://EXAMPLE.icp0.io:443",
+                "https://example.ic0.app",
+                true,
+            ),
+            (
+                ii_mainnet,
+                "https://example.icp0.io",
+                "https://example.icp0.io/",
+                false,
+            ),
+            (
+                ii_mainnet,
+                "https://custom-domain.com",
+                "https://Custom-Domain.com/",
+                true,
+            ),
+            (
+                ii_mainnet,
+                "https://custom-domain.com",
+                "https://custom-domain.com:8443",
+                false,
+            ),
+            (
+                ii_mainnet,
+                "https://custom-domain.com",
+                "http://custom-domain.com",
+                false,
+            ),
+            (
+                ii_mainnet,
+                "https://custom-domain.com",
+                "https://custom-domain.com/app",
+                false,
+            ),
+            (
+                ii_mainnet,
+                "https://custom-domain.com/app",
+                "https://custom-domain.com",
+                false,
+            ),
+            (
+                ii_mainnet,
+                "https://custom-domain.com/app",
+                "https://custom-domain.com/app",
+                false,
+            ),
+        ];
+        for (signing_canister_id, expected_derivation_origin, derivation_origin, matches) in cases {
+            let alias_tuple = AliasTuple {
+                id_alias: Principal::from_text(VP_ID_ALIAS).expect("wrong principal"),
+                id_dapp: Principal::from_text(VP_RP_ID).expect("wrong principal"),
+                derivation_origin: derivation_origin.to_string(),
+            };
+            assert_eq!(
+                matches_expected_origin(
+                    &signing_canister_id,
+                    expected_derivation_origin,
+                    &alias_tuple
+                ),
+                matches,
+                "expected {}, got {}",
+                expected_derivation_origin,
+                derivation_origin
+            );
+        }
+    }
 }
@@ -0,0 +1,1219 @@
+//! Issuer side of OpenID for Verifiable Credential Issuance (OpenID4VCI), cf.
+//! https://openid.net/specs/openid-4-verifiable-credential-issuance-1_0.html, so that wallets
+//! outside of II can obtain credentials from issuer canisters.
+//!
+//! Only the pre-authorized code flow is supported:
+//!  1. The issuer creates a `CredentialOffer` with a pre-authorized code for one of the
+//!     credential configurations of its `IssuerManifest`.
+//!  2. The wallet exchanges the pre-authorized code at the token endpoint for an access token
+//!     and a `c_nonce` (cf. `IssuanceSessions::exchange_pre_authorized_code()`).
+//!  3. The wallet requests the credential at the credential endpoint, with a proof of possession
+//!     of its key (a JWT signed by the key of a DID of the holder, which contains the `c_nonce`).
+//!     The credential is bound to this DID, i.e. the DID is the subject of the credential.
+//!
+//! Credentials are signed with canister signatures (`alg: IcCs`), which can only be obtained
+//! in query calls, whereas the credential endpoint is served in an update call.  The issuer
+//! therefore answers credential requests with a `transaction_id`, and returns the credential
+//! at the deferred credential endpoint, just like `prepare_credential` and `get_credential`.
+use crate::challenge::ChallengeGenerator;
+use crate::did_resolution::{DidResolver, verify_jws_signature};
+use crate::issuer_api::CredentialSpec;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use identity_jose::jws::{Decoder, JwsAlgorithm};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Path of the credential issuer metadata, relative to the credential issuer identifier.
+pub const CREDENTIAL_ISSUER_METADATA_PATH: &str = "/.well-known/openid-credential-issuer";
+/// Path of the OAuth authorization server metadata, relative to the credential issuer identifier.
+pub const AUTHORIZATION_SERVER_METADATA_PATH: &str = "/.well-known/oauth-authorization-server";
+pub const TOKEN_ENDPOINT_PATH: &str = "/token";
+pub const CREDENTIAL_ENDPOINT_PATH: &str = "/credential";
+pub const DEFERRED_CREDENTIAL_ENDPOINT_PATH: &str = "/deferred_credential";
+/// Format of the issued credentials, i.e. JWT-credentials as built by `build_credential_jwt()`.
+pub const JWT_VC_JSON_FORMAT: &str = "jwt_vc_json";
+pub const PRE_AUTHORIZED_CODE_GRANT_TYPE: &str =
+    "urn:ietf:params:oauth:grant-type:pre-authorized_code";
+/// The `typ` of the JWS header of proofs of possession.
+pub const PROOF_JWT_TYPE: &str = "openid4vci-proof+jwt";
+pub const CREDENTIAL_OFFER_URI_PREFIX: &str = "openid-credential-offer://?credential_offer=";
+/// Default lifetime of the pre-authorized code of a credential offer.
+pub const DEFAULT_OFFER_TTL: Duration = Duration::from_secs(10 * 60);
+/// Default lifetime of access tokens and their `c_nonce`.
+pub const DEFAULT_ACCESS_TOKEN_TTL: Duration = Duration::from_secs(5 * 60);
+// Maximal age of a proof of possession, and maximal clock skew of the wallet.
+const MAX_PROOF_AGE: Duration = Duration::from_secs(5 * 60);
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(60);
+const JWT_PROOF_TYPE: &str = "jwt";
+const CANISTER_SIG_ALG: &str = "IcCs";
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// Errors of the endpoints, with the error codes of OAuth and OpenID4VCI.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Openid4vciError {
+    InvalidRequest(String),
+    /// The pre-authorized code is unknown, has already been used, or has expired.
+    InvalidGrant(String),
+    UnsupportedGrantType(String),
+    /// The access token is missing, unknown, has already been used, or has expired.
+    InvalidToken(String),
+    UnknownCredentialConfiguration(String),
+    InvalidProof(String),
+    /// The proof does not contain the current `c_nonce` of the access token.
+    InvalidNonce(String),
+    /// The credential of the transaction is not ready yet.
+    IssuancePending(String),
+    InvalidTransactionId(String),
+    /// The issuer failed processing a valid request.
+    ServerError(String),
+    /// The client exceeded the rate limit of the issuer, and may retry after `retry_after_s`
+    /// seconds.
+    RateLimited {
+        retry_after_s: u64,
+    },
+}
+
+impl Openid4vciError {
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            Openid4vciError::InvalidRequest(_) => "invalid_request",
+            Openid4vciError::InvalidGrant(_) => "invalid_grant",
+            Openid4vciError::UnsupportedGrantType(_) => "unsupported_grant_type",
+            Openid4vciError::InvalidToken(_) => "invalid_token",
+            Openid4vciError::UnknownCredentialConfiguration(_) => {
+                "unknown_credential_configuration"
+            }
+            Openid4vciError::InvalidProof(_) => "invalid_proof",
+            Openid4vciError::InvalidNonce(_) => "invalid_nonce",
+            Openid4vciError::IssuancePending(_) => "issuance_pending",
+            Openid4vciError::InvalidTransactionId(_) => "invalid_transaction_id",
+            Openid4vciError::ServerError(_) => "server_error",
+            Openid4vciError::RateLimited { .. } => "rate_limited",
+        }
+    }
+
+    /// The HTTP status code of the error response.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            Openid4vciError::InvalidToken(_) => 401,
+            Openid4vciError::ServerError(_) => 500,
+            Openid4vciError::RateLimited { .. } => 429,
+            _ => 400,
+        }
+    }
+
+    pub fn to_error_response(&self) -> ErrorResponse {
+        let description = match self {
+            Openid4vciError::InvalidRequest(description)
+            | Openid4vciError::InvalidGrant(description)
+            | Openid4vciError::UnsupportedGrantType(description)
+            | Openid4vciError::InvalidToken(description)
+            | Openid4vciError::UnknownCredentialConfiguration(description)
+            | Openid4vciError::InvalidProof(description)
+            | Openid4vciError::InvalidNonce(description)
+            | Openid4vciError::IssuancePending(description)
+            | Openid4vciError::InvalidTransactionId(description)
+            | Openid4vciError::ServerError(description) => description.clone(),
+            Openid4vciError::RateLimited { retry_after_s } => {
+                format!("rate limit exceeded, retry after {}s", retry_after_s)
+            }
+        };
+        ErrorResponse {
+            error: self.error_code().to_string(),
+            error_description: Some(description),
+        }
+    }
+}
+
+/// The credentials offered by an issuer, identified by the id of their credential configuration.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IssuerManifest {
+    pub display_name: String,
+    pub credential_configurations: BTreeMap<String, CredentialSpec>,
+    /// The DID methods of the holders to which credentials can be bound, e.g. `did:key`.
+    pub binding_methods: Vec<String>,
+}
+
+impl IssuerManifest {
+    /// Returns the metadata of the credential issuer with the given identifier, i.e. the URL
+    /// of the issuer relative to which the endpoints are served.
+    pub fn credential_issuer_metadata(&self, credential_issuer: &str) -> CredentialIssuerMetadata {
+        let credential_configurations_supported = self
+            .credential_configurations
+            .iter()
+            .map(|(id, spec)| {
+                let configuration = CredentialConfiguration {
+                    format: JWT_VC_JSON_FORMAT.to_string(),
+                    cryptographic_binding_methods_supported: self.binding_methods.clone(),
+                    credential_signing_alg_values_supported: vec![CANISTER_SIG_ALG.to_string()],
+                    proof_types_supported: BTreeMap::from([(
+                        JWT_PROOF_TYPE.to_string(),
+                        ProofTypeMetadata {
+                            proof_signing_alg_values_supported: [
+                                JwsAlgorithm::ES256,
+                                JwsAlgorithm::ES256K,
+                                JwsAlgorithm::EdDSA,
+                            ]
+                            .iter()
+                            .map(|alg| alg.name())
+                            .collect(),
+                        },
+                    )]),
+                    credential_definition: CredentialDefinition {
+                        types: vec![
+                            "VerifiableCredential".to_string(),
+                            spec.credential_type.clone(),
+                        ],
+                    },
+                };
+                (id.clone(), configuration)
+            })
+            .collect();
+        CredentialIssuerMetadata {
+            credential_issuer: credential_issuer.to_string(),
+            credential_endpoint: endpoint(credential_issuer, CREDENTIAL_ENDPOINT_PATH),
+            deferred_credential_endpoint: endpoint(
+                credential_issuer,
+                DEFERRED_CREDENTIAL_ENDPOINT_PATH,
+            ),
+            display: vec![Display {
+                name: self.display_name.clone(),
+            }],
+            credential_configurations_supported,
+        }
+    }
+
+    /// Returns the metadata of the authorization server, which is served by the credential
+    /// issuer itself.
+    pub fn authorization_server_metadata(
+        &self,
+        credential_issuer: &str,
+    ) -> AuthorizationServerMetadata {
+        AuthorizationServerMetadata {
+            issuer: credential_issuer.to_string(),
+            token_endpoint: endpoint(credential_issuer, TOKEN_ENDPOINT_PATH),
+            grant_types_supported: vec![PRE_AUTHORIZED_CODE_GRANT_TYPE.to_string()],
+            pre_authorized_grant_anonymous_access_supported: true,
+        }
+    }
+
+    pub fn credential_spec(
+        &self,
+        credential_configuration_id: &str,
+    ) -> Result<&CredentialSpec, Openid4vciError> {
+        self.credential_configurations
+            .get(credential_configuration_id)
+            .ok_or(Openid4vciError::UnknownCredentialConfiguration(format!(
+                "unknown credential configuration {}",
+                credential_configuration_id
+            )))
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CredentialIssuerMetadata {
+    pub credential_issuer: String,
+    pub credential_endpoint: String,
+    pub deferred_credential_endpoint: String,
+    pub display: Vec<Display>,
+    pub credential_configurations_supported: BTreeMap<String, CredentialConfiguration>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Display {
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CredentialConfiguration {
+    pub format: String,
+    pub cryptographic_binding_methods_supported: Vec<String>,
+    pub credential_signing_alg_values_supported: Vec<String>,
+    pub proof_types_supported: BTreeMap<String, ProofTypeMetadata>,
+    pub credential_definition: CredentialDefinition,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ProofTypeMetadata {
+    pub proof_signing_alg_values_supported: Vec<String>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CredentialDefinition {
+    #[serde(rename = "type")]
+    pub types: Vec<String>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AuthorizationServerMetadata {
+    pub issuer: String,
+    pub token_endpoint: String,
+    pub grant_types_supported: Vec<String>,
+    #[serde(rename = "pre-authorized_grant_anonymous_access_supported")]
+    pub pre_authorized_grant_anonymous_access_supported: bool,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CredentialOffer {
+    pub credential_issuer: String,
+    pub credential_configuration_ids: Vec<String>,
+    pub grants: CredentialOfferGrants,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CredentialOfferGrants {
+    #[serde(rename = "urn:ietf:params:oauth:grant-type:pre-authorized_code")]
+    pub pre_authorized_code: PreAuthorizedCodeGrant,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PreAuthorizedCodeGrant {
+    #[serde(rename = "pre-authorized_code")]
+    pub pre_authorized_code: String,
+}
+
+impl CredentialOffer {
+    /// Returns the offer by value as `openid-credential-offer://`-URI, e.g. to be shown as QR code.
+    pub fn to_uri(&self) -> String {
+        let offer_json = serde_json::to_string(self).expect("failed serializing offer");
+        format!(
+            "{}{}",
+            CREDENTIAL_OFFER_URI_PREFIX,
+            percent_encode(&offer_json)
+        )
+    }
+
+    pub fn from_uri(uri: &str) -> Result<Self, Openid4vciError> {
+        let offer_json = uri
+            .strip_prefix(CREDENTIAL_OFFER_URI_PREFIX)
+            .ok_or(invalid_request("not a credential offer URI"))?;
+        serde_json::from_str(&percent_decode(offer_json)?)
+            .map_err(|e| invalid_request(&format!("invalid credential offer: {}", e)))
+    }
+}
+
+/// A request to the token endpoint, sent `application/x-www-form-urlencoded`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TokenRequest {
+    pub grant_type: String,
+    pub pre_authorized_code: String,
+}
+
+impl TokenRequest {
+    pub fn from_form_urlencoded(body: &[u8]) -> Result<Self, Openid4vciError> {
+        let body =
+            std::str::from_utf8(body).map_err(|_| invalid_request("body is not valid UTF-8"))?;
+        let mut params = BTreeMap::new();
+        for pair in body.split('&').filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            params.insert(percent_decode(name)?, percent_decode(value)?);
+        }
+        let mut param = |name: &str| {
+            params
+                .remove(name)
+                .ok_or(invalid_request(&format!("missing parameter {}", name)))
+        };
+        Ok(Self {
+            grant_type: param("grant_type")?,
+            pre_authorized_code: param("pre-authorized_code")?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    /// Lifetime of the access token in seconds.
+    pub expires_in: u64,
+    /// The nonce to be contained in the proof of possession of the credential request.
+    pub c_nonce: String,
+    pub c_nonce_expires_in: u64,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CredentialRequest {
+    pub credential_configuration_id: String,
+    pub proof: Option<Proof>,
+}
+
+/// A proof of possession of the key of the holder.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Proof {
+    pub proof_type: String,
+    pub jwt: String,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CredentialResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<Vec<IssuedCredential>>,
+    /// Identifies the deferred issuance of the credential, cf. `DeferredCredentialRequest`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<String>,
+    /// Minimal time (in seconds) the wallet should wait before requesting a deferred credential.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<u64>,
+}
+
+impl CredentialResponse {
+    pub fn issued(credential_jws: String) -> Self {
+        Self {
+            credentials: Some(vec![IssuedCredential {
+                credential: credential_jws,
+            }]),
+            transaction_id: None,
+            interval: None,
+        }
+    }
+
+    pub fn deferred(transaction_id: String, interval: u64) -> Self {
+        Self {
+            credentials: None,
+            transaction_id: Some(transaction_id),
+            interval: Some(interval),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct IssuedCredential {
+    pub credential: String,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DeferredCredentialRequest {
+    pub transaction_id: String,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_description: Option<String>,
+}
+
+/// A credential request with a valid access token and proof of possession.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AuthorizedCredentialRequest {
+    pub credential_configuration_id: String,
+    /// The DID of the holder, to be used as subject of the credential.
+    pub holder_did: String,
+}
+
+/// An access token obtained for a credential offer.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IssuanceSession {
+    pub credential_configuration_id: String,
+    pub c_nonce: String,
+    /// Time (in nanoseconds since the UNIX epoch) after which the access token is no longer valid.
+    pub expires_at_ns: u128,
+}
+
+// A pre-authorized code of a credential offer.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct PendingOffer {
+    credential_configuration_id: String,
+    expires_at_ns: u128,
+}
+
+/// Storage for the pre-authorized codes of the credential offers of an issuer, and for the
+/// access tokens they were exchanged for.  Every pre-authorized code can be exchanged at most
+/// once, and every access token can be used to obtain at most one credential.
+#[derive(Clone, Debug)]
+pub struct IssuanceSessions {
+    offers: BTreeMap<String, PendingOffer>,
+    sessions: BTreeMap<String, IssuanceSession>,
+    offer_ttl: Duration,
+    access_token_ttl: Duration,
+}
+
+impl Default for IssuanceSessions {
+    fn default() -> Self {
+        Self::new(DEFAULT_OFFER_TTL, DEFAULT_ACCESS_TOKEN_TTL)
+    }
+}
+
+impl IssuanceSessions {
+    pub fn new(offer_ttl: Duration, access_token_ttl: Duration) -> Self {
+        Self {
+            offers: BTreeMap::new(),
+            sessions: BTreeMap::new(),
+            offer_ttl,
+            access_token_ttl,
+        }
+    }
+
+    /// Creates an offer of the credential with the given configuration of `manifest`, with a
+    /// new pre-authorized code.
+    pub fn create_offer(
+        &mut self,
+        manifest: &IssuerManifest,
+        credential_issuer: &str,
+        credential_configuration_id: &str,
+        generator: &mut ChallengeGenerator,
+        current_time_ns: u128,
+    ) -> Result<CredentialOffer, Openid4vciError> {
+        manifest.credential_spec(credential_configuration_id)?;
+        self.prune_expired(current_time_ns);
+        let pre_authorized_code = BASE64.encode(generator.next_bytes());
+        self.offers.insert(
+            pre_authorized_code.clone(),
+            PendingOffer {
+                credential_configuration_id: credential_configuration_id.to_string(),
+                expires_at_ns: current_time_ns.saturating_add(self.offer_ttl.as_nanos()),
+            },
+        );
+        Ok(CredentialOffer {
+            credential_issuer: credential_issuer.to_string(),
+            credential_configuration_ids: vec![credential_configuration_id.to_string()],
+            grants: CredentialOfferGrants {
+                pre_authorized_code: PreAuthorizedCodeGrant {
+                    pre_authorized_code,
+                },
+            },
+        })
+    }
+
+    /// Consumes the pre-authorized code of the given token request, and returns a new access
+    /// token together with the `c_nonce` for the proof of possession.
+    pub fn exchange_pre_authorized_code(
+        &mut self,
+        request: &TokenRequest,
+        generator: &mut ChallengeGenerator,
+        current_time_ns: u128,
+    ) -> Result<TokenResponse, Openid4vciError> {
+        if request.grant_type != PRE_AUTHORIZED_CODE_GRANT_TYPE {
+            return Err(Openid4vciError::UnsupportedGrantType(format!(
+                "unsupported grant type {}",
+                request.grant_type
+            )));
+        }
+        self.prune_expired(current_time_ns);
+        let offer = self.offers.remove(&request.pre_authorized_code).ok_or(
+            Openid4vciError::InvalidGrant("unknown or expired pre-authorized code".to_string()),
+        )?;
+        let access_token = BASE64.encode(generator.next_bytes());
+        let c_nonce = BASE64.encode(generator.next_bytes());
+        self.sessions.insert(
+            access_token.clone(),
+            IssuanceSession {
+                credential_configuration_id: offer.credential_configuration_id,
+                c_nonce: c_nonce.clone(),
+                expires_at_ns: current_time_ns.saturating_add(self.access_token_ttl.as_nanos()),
+            },
+        );
+        Ok(TokenResponse {
+            access_token,
+            token_type: BEARER_PREFIX.trim_end().to_string(),
+            expires_in: self.access_token_ttl.as_secs(),
+            c_nonce,
+            c_nonce_expires_in: self.access_token_ttl.as_secs(),
+        })
+    }
+
+    /// Checks the given credential request against the session of `access_token`, and verifies
+    /// its proof of possession (cf. `verify_proof_jwt()`).  On success, the access token is
+    /// consumed, and the authorized credential configuration and DID of the holder are returned.
+    pub fn authorize_credential_request(
+        &mut self,
+        access_token: &str,
+        request: &CredentialRequest,
+        credential_issuer: &str,
+        resolver: &dyn DidResolver,
+        current_time_ns: u128,
+    ) -> Result<AuthorizedCredentialRequest, Openid4vciError> {
+        let authorized = self.verify_credential_request(
+            access_token,
+            request,
+            credential_issuer,
+            resolver,
+            current_time_ns,
+        )?;
+        self.consume_access_token(access_token, current_time_ns)?;
+        Ok(authorized)
+    }
+
+    /// Like `authorize_credential_request()`, but without consuming the access token, so that
+    /// the issuer can perform further checks of the holder (e.g. rate limiting) before it
+    /// consumes the token via `consume_access_token()`.
+    pub fn verify_credential_request(
+        &mut self,
+        access_token: &str,
+        request: &CredentialRequest,
+        credential_issuer: &str,
+        resolver: &dyn DidResolver,
+        current_time_ns: u128,
+    ) -> Result<AuthorizedCredentialRequest, Openid4vciError> {
+        self.prune_expired(current_time_ns);
+        let session = self
+            .sessions
+            .get(access_token)
+            .ok_or(Openid4vciError::InvalidToken(
+                "unknown or expired access token".to_string(),
+            ))?;
+        if request.credential_configuration_id != session.credential_configuration_id {
+            return Err(Openid4vciError::UnknownCredentialConfiguration(format!(
+                "access token is not valid for credential configuration {}",
+                request.credential_configuration_id
+            )));
+        }
+        let proof = request
+            .proof
+            .as_ref()
+            .ok_or(invalid_proof("missing proof"))?;
+        if proof.proof_type != JWT_PROOF_TYPE {
+            return Err(invalid_proof(&format!(
+                "unsupported proof type {}",
+                proof.proof_type
+            )));
+        }
+        let holder_did = verify_proof_jwt(
+            &proof.jwt,
+            credential_issuer,
+            &session.c_nonce,
+            resolver,
+            current_time_ns,
+        )?;
+        Ok(AuthorizedCredentialRequest {
+            credential_configuration_id: session.credential_configuration_id.clone(),
+            holder_did,
+        })
+    }
+
+    /// Consumes the given access token, so that it cannot be used for further credential requests.
+    pub fn consume_access_token(
+        &mut self,
+        access_token: &str,
+        current_time_ns: u128,
+    ) -> Result<(), Openid4vciError> {
+        self.prune_expired(current_time_ns);
+        self.sessions
+            .remove(access_token)
+            .map(|_| ())
+            .ok_or(Openid4vciError::InvalidToken(
+                "unknown or expired access token".to_string(),
+            ))
+    }
+
+    /// Removes all the pre-authorized codes and access tokens that have expired at `current_time_ns`.
+    pub fn prune_expired(&mut self, current_time_ns: u128) {
+        self.offers
+            .retain(|_, offer| offer.expires_at_ns > current_time_ns);
+        self.sessions
+            .retain(|_, session| session.expires_at_ns > current_time_ns);
+    }
+}
+
+/// Verifies the given proof of possession of a credential request, and returns the DID of the
+/// holder.  The proof must
+///  * be a JWT with `typ: openid4vci-proof+jwt`, signed with `ES256`, `ES256K` or `EdDSA` by the
+///    verification method identified by the `kid` in the JWS header, as resolved via `resolver`,
+///  * contain `credential_issuer` as `aud`-claim and `c_nonce` as `nonce`-claim, and
+///  * have been issued (`iat`) at most a few minutes before `current_time_ns`.
+pub fn verify_proof_jwt(
+    proof_jwt: &str,
+    credential_issuer: &str,
+    c_nonce: &str,
+    resolver: &dyn DidResolver,
+    current_time_ns: u128,
+) -> Result<String, Openid4vciError> {
+    let decoder: Decoder = Decoder::new();
+    let jws = decoder
+        .decode_compact_serialization(proof_jwt.as_ref(), None)
+        .map_err(|e| invalid_proof(&format!("proof JWT parsing error: {}", e)))?;
+    let header = jws
+        .protected_header()
+        .ok_or(invalid_proof("missing JWS header"))?;
+    if header.typ() != Some(PROOF_JWT_TYPE) {
+        return Err(invalid_proof(&format!(
+            "expected typ {} in JWS header",
+            PROOF_JWT_TYPE
+        )));
+    }
+    let alg = header
+        .alg()
+        .ok_or(invalid_proof("missing alg in JWS header"))?;
+    let kid = header
+        .kid()
+        .ok_or(invalid_proof("missing kid in JWS header"))?;
+    let (holder_did, _) = kid
+        .split_once('#')
+        .ok_or(invalid_proof(&format!("kid {} is not a DID URL", kid)))?;
+    let jwk = resolver
+        .resolve_verification_method(kid)
+        .map_err(|e| invalid_proof(&format!("failed resolving kid {}: {:?}", kid, e)))?;
+    verify_jws_signature(&alg, &jwk, jws.signing_input(), jws.decoded_signature())
+        .map_err(|e| invalid_proof(&format!("signature verification error: {}", e)))?;
+
+    let claims: Value = serde_json::from_slice(jws.claims())
+        .map_err(|e| invalid_proof(&format!("failed parsing JSON JWT claims: {}", e)))?;
+    let audience_matches = match claims.get("aud") {
+        Some(Value::String(aud)) => aud == credential_issuer,
+        Some(Value::Array(aud)) => aud
+            .iter()
+            .any(|aud| aud.as_str() == Some(credential_issuer)),
+        _ => false,
+    };
+    if !audience_matches {
+        return Err(invalid_proof(&format!(
+            "proof is not intended for {}",
+            credential_issuer
+        )));
+    }
+    let issued_at_ns = claims
+        .get("iat")
+        .and_then(Value::as_u64)
+        .ok_or(invalid_proof("missing iat claim"))? as u128
+        * 1_000_000_000;
+    if issued_at_ns > current_time_ns.saturating_add(MAX_CLOCK_SKEW.as_nanos())
+        || issued_at_ns.saturating_add(MAX_PROOF_AGE.as_nanos()) < current_time_ns
+    {
+        return Err(invalid_proof("proof is not fresh"));
+    }
+    if claims.get("nonce").and_then(Value::as_str) != Some(c_nonce) {
+        return Err(Openid4vciError::InvalidNonce(
+            "proof does not contain the current c_nonce".to_string(),
+        ));
+    }
+    Ok(holder_did.to_string())
+}
+
+/// Returns the access token of the given `Authorization` header with scheme `Bearer`.
+pub fn bearer_token(authorization: &str) -> Result<&str, Openid4vciError> {
+    authorization
+        .strip_prefix(BEARER_PREFIX)
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .ok_or(Openid4vciError::InvalidToken(
+            "expected a bearer access token".to_string(),
+        ))
+}
+
+fn endpoint(credential_issuer: &str, path: &str) -> String {
+    format!("{}{}", credential_issuer.trim_end_matches('/'), path)
+}
+
+// Percent-encodes all characters except the unreserved ones of RFC 3986.
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// Decodes a percent-encoded value, where `+` encodes a space as in form-urlencoded values.
+fn percent_decode(value: &str) -> Result<String, Openid4vciError> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.bytes();
+    while let Some(b) = input.next() {
+        match b {
+            b'%' => {
+                let hex = [input.next(), input.next()];
+                let hex = match hex {
+                    [Some(high), Some(low)] => [high, low],
+                    _ => return Err(invalid_request("truncated percent-encoding")),
+                };
+                let decoded = std::str::from_utf8(&hex)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or(invalid_request("invalid percent-encoding"))?;
+                bytes.push(decoded);
+            }
+            b'+' => bytes.push(b' '),
+            b => bytes.push(b),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| invalid_request("percent-encoded value is not UTF-8"))
+}
+
+fn invalid_request(message: &str) -> Openid4vciError {
+    Openid4vciError::InvalidRequest(message.to_string())
+}
+
+fn invalid_proof(message: &str) -> Openid4vciError {
+    Openid4vciError::InvalidProof(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::did_resolution::{DidKeyResolver, did_key_url};
+    use crate::issuer_api::ArgumentValue;
+    use crate::threshold_signing::{ThresholdAlgorithm, ThresholdPublicKey};
+    use assert_matches::assert_matches;
+    use identity_jose::jws::{CompactJwsEncoder, JwsHeader};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    const CREDENTIAL_ISSUER: &str = "https://issuer.test";
+    const NOW_NS: u128 = 1_700_000_000_000_000_000;
+    const MINUTE_NS: u128 = 60_000_000_000;
+
+    struct Holder(ed25519_dalek::SigningKey);
+
+    impl Holder {
+        fn new(seed: u8) -> Self {
+            Self(ed25519_dalek::SigningKey::from_bytes(&[seed; 32]))
+        }
+
+        fn kid(&self) -> String {
+            let public_key = ThresholdPublicKey::new(
+                ThresholdAlgorithm::EdDsa,
+                self.0.verifying_key().to_bytes().to_vec(),
+            )
+            .expect("invalid public key");
+            did_key_url(&public_key)
+        }
+
+        fn did(&self) -> String {
+            self.kid().split_once('#').unwrap().0.to_string()
+        }
+
+        fn proof(&self, typ: &str, claims: Value) -> String {
+            use ed25519_dalek::Signer;
+            let mut header = JwsHeader::new();
+            header.set_typ(typ);
+            header.set_alg(JwsAlgorithm::EdDSA);
+            header.set_kid(self.kid());
+            let claims = claims.to_string();
+            let encoder = CompactJwsEncoder::new(claims.as_bytes(), &header).unwrap();
+            let sig = self.0.sign(encoder.signing_input()).to_bytes();
+            encoder.into_jws(&sig)
+        }
+
+        fn proof_jwt(&self, c_nonce: &str) -> String {
+            self.proof(
+                PROOF_JWT_TYPE,
+                json!({
+                    "aud": CREDENTIAL_ISSUER,
+                    "iat": (NOW_NS / 1_000_000_000) as u64,
+                    "nonce": c_nonce,
+                }),
+            )
+        }
+    }
+
+    fn manifest() -> IssuerManifest {
+        IssuerManifest {
+            display_name: "Test Issuer".to_string(),
+            credential_configurations: BTreeMap::from([
+                (
+                    "VerifiedAdult".to_string(),
+                    CredentialSpec {
+                        credential_type: "VerifiedAdult".to_string(),
+                        arguments: None,
+                    },
+                ),
+                (
+                    "VerifiedEmployee".to_string(),
+                    CredentialSpec {
+                        credential_type: "VerifiedEmployee".to_string(),
+                        arguments: Some(HashMap::from([(
+                            "employerName".to_string(),
+                            ArgumentValue::String("DFINITY Foundation".to_string()),
+                        )])),
+                    },
+                ),
+            ]),
+            binding_methods: vec!["did:key".to_string()],
+        }
+    }
+
+    fn generator() -> ChallengeGenerator {
+        ChallengeGenerator::from_seed([42; 32])
+    }
+
+    // Creates an offer and exchanges its pre-authorized code for an access token.
+    fn token_for(
+        sessions: &mut IssuanceSessions,
+        credential_configuration_id: &str,
+    ) -> TokenResponse {
+        let mut generator = generator();
+        let offer = sessions
+            .create_offer(
+                &manifest(),
+                CREDENTIAL_ISSUER,
+                credential_configuration_id,
+                &mut generator,
+                NOW_NS,
+            )
+            .expect("failed creating offer");
+        let request = TokenRequest {
+            grant_type: PRE_AUTHORIZED_CODE_GRANT_TYPE.to_string(),
+            pre_authorized_code: offer.grants.pre_authorized_code.pre_authorized_code,
+        };
+        sessions
+            .exchange_pre_authorized_code(&request, &mut generator, NOW_NS)
+            .expect("failed exchanging pre-authorized code")
+    }
+
+    fn credential_request(proof_jwt: String) -> CredentialRequest {
+        CredentialRequest {
+            credential_configuration_id: "VerifiedAdult".to_string(),
+            proof: Some(Proof {
+                proof_type: JWT_PROOF_TYPE.to_string(),
+                jwt: proof_jwt,
+            }),
+        }
+    }
+
+    #[test]
+    fn should_derive_metadata_from_manifest() {
+        let metadata = manifest().credential_issuer_metadata("https://issuer.test/");
+        let metadata_json = serde_json::to_value(&metadata).unwrap();
+        assert_eq!(
+            metadata_json["credential_endpoint"],
+            "https://issuer.test/credential"
+        );
+        assert_eq!(
+            metadata_json["credential_configurations_supported"]["VerifiedEmployee"],
+            json!({
+                "format": "jwt_vc_json",
+                "cryptographic_binding_methods_supported": ["did:key"],
+                "credential_signing_alg_values_supported": ["IcCs"],
+                "proof_types_supported": {
+                    "jwt": { "proof_signing_alg_values_supported": ["ES256", "ES256K", "EdDSA"] }
+                },
+                "credential_definition": { "type": ["VerifiableCredential", "VerifiedEmployee"] },
+            })
+        );
+        let as_metadata = manifest().authorization_server_metadata(CREDENTIAL_ISSUER);
+        assert_eq!(as_metadata.token_endpoint, "https://issuer.test/token");
+    }
+
+    #[test]
+    fn should_roundtrip_offer_uri() {
+        let offer = IssuanceSessions::default()
+            .create_offer(
+                &manifest(),
+                CREDENTIAL_ISSUER,
+                "VerifiedAdult",
+                &mut generator(),
+                NOW_NS,
+            )
+            .expect("failed creating offer");
+        let uri = offer.to_uri();
+        assert!(
+            uri.starts_with(
+                "openid-credential-offer://?credential_offer=%7B%22credential_issuer%22"
+            )
+        );
+        assert_eq!(CredentialOffer::from_uri(&uri), Ok(offer));
+    }
+
+    #[test]
+    fn should_not_offer_unknown_configuration() {
+        let result = IssuanceSessions::default().create_offer(
+            &manifest(),
+            CREDENTIAL_ISSUER,
+            "VerifiedHuman",
+            &mut generator(),
+            NOW_NS,
+        );
+        assert_matches!(
+            result,
+            Err(Openid4vciError::UnknownCredentialConfiguration(_))
+        );
+    }
+
+    #[test]
+    fn should_parse_form_urlencoded_token_request() {
+        let body = b"grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Apre-authorized_code&pre-authorized_code=a%2Bb+c";
+        assert_eq!(
+            TokenRequest::from_form_urlencoded(body),
+            Ok(TokenRequest {
+                grant_type: PRE_AUTHORIZED_CODE_GRANT_TYPE.to_string(),
+                pre_authorized_code: "a+b c".to_string(),
+            })
+        );
+        assert_matches!(
+            TokenRequest::from_form_urlencoded(b"grant_type=x"),
+            Err(Openid4vciError::InvalidRequest(_))
+        );
+        assert_matches!(
+            TokenRequest::from_form_urlencoded(b"grant_type=%4"),
+            Err(Openid4vciError::InvalidRequest(_))
+        );
+    }
+
+    #[test]
+    fn should_exchange_pre_authorized_code_only_once() {
+        let mut sessions = IssuanceSessions::default();
+        let mut generator = generator();
+        let offer = sessions
+            .create_offer(
+                &manifest(),
+                CREDENTIAL_ISSUER,
+                "VerifiedAdult",
+                &mut generator,
+                NOW_NS,
+            )
+            .unwrap();
+        let request = TokenRequest {
+            grant_type: PRE_AUTHORIZED_CODE_GRANT_TYPE.to_string(),
+            pre_authorized_code: offer.grants.pre_authorized_code.pre_authorized_code,
+        };
+        let token = sessions
+            .exchange_pre_authorized_code(&request, &mut generator, NOW_NS)
+            .expect("failed exchanging pre-authorized code");
+        assert_eq!(token.token_type, "Bearer");
+        assert_ne!(token.access_token, token.c_nonce);
+        assert_matches!(
+            sessions.exchange_pre_authorized_code(&request, &mut generator, NOW_NS),
+            Err(Openid4vciError::InvalidGrant(_))
+        );
+    }
+
+    #[test]
+    fn should_reject_expired_or_unsupported_grants() {
+        let mut sessions = IssuanceSessions::default();
+        let mut generator = generator();
+        let offer = sessions
+            .create_offer(
+                &manifest(),
+                CREDENTIAL_ISSUER,
+                "VerifiedAdult",
+                &mut generator,
+                NOW_NS,
+            )
+            .unwrap();
+        let mut request = TokenRequest {
+            grant_type: "authorization_code".to_string(),
+            pre_authorized_code: offer.grants.pre_authorized_code.pre_authorized_code,
+        };
+        assert_matches!(
+            sessions.exchange_pre_authorized_code(&request, &mut generator, NOW_NS),
+            Err(Openid4vciError::UnsupportedGrantType(_))
+        );
+        request.grant_type = PRE_AUTHORIZED_CODE_GRANT_TYPE.to_string();
+        let after_expiry_ns = NOW_NS + DEFAULT_OFFER_TTL.as_nanos();
+        assert_matches!(
+            sessions.exchange_pre_authorized_code(&request, &mut generator, after_expiry_ns),
+            Err(Openid4vciError::InvalidGrant(_))
+        );
+    }
+
+    #[test]
+    fn should_authorize_credential_request_with_valid_proof() {
+        let mut sessions = IssuanceSessions::default();
+        let token = token_for(&mut sessions, "VerifiedAdult");
+        let holder = Holder::new(1);
+        let request = credential_request(holder.proof_jwt(&token.c_nonce));
+
+        let authorized = sessions
+            .authorize_credential_request(
+                &token.access_token,
+                &request,
+                CREDENTIAL_ISSUER,
+                &DidKeyResolver,
+                NOW_NS,
+            )
+            .expect("failed authorizing credential request");
+        assert_eq!(
+            authorized,
+            AuthorizedCredentialRequest {
+                credential_configuration_id: "VerifiedAdult".to_string(),
+                holder_did: holder.did(),
+            }
+        );
+        // The access token is consumed.
+        assert_matches!(
+            sessions.authorize_credential_request(
+                &token.access_token,
+                &request,
+                CREDENTIAL_ISSUER,
+                &DidKeyResolver,
+                NOW_NS,
+            ),
+            Err(Openid4vciError::InvalidToken(_))
+        );
+    }
+
+    #[test]
+    fn should_verify_credential_request_without_consuming_access_token() {
+        let mut sessions = IssuanceSessions::default();
+        let token = token_for(&mut sessions, "VerifiedAdult");
+        let holder = Holder::new(1);
+        let request = credential_request(holder.proof_jwt(&token.c_nonce));
+
+        for _ in 0..2 {
+            let verified = sessions
+                .verify_credential_request(
+                    &token.access_token,
+                    &request,
+                    CREDENTIAL_ISSUER,
+                    &DidKeyResolver,
+                    NOW_NS,
+                )
+                .expect("failed verifying credential request");
+            assert_eq!(verified.holder_did, holder.did());
+        }
+
+        sessions
+            .consume_access_token(&token.access_token, NOW_NS)
+            .expect("failed consuming access token");
+        assert_matches!(
+            sessions.consume_access_token(&token.access_token, NOW_NS),
+            Err(Openid4vciError::InvalidToken(_))
+        );
+        assert_matches!(
+            sessions.verify_credential_request(
+                &token.access_token,
+                &request,
+                CREDENTIAL_ISSUER,
+                &DidKeyResolver,
+                NOW_NS,
+            ),
+            Err(Openid4vciError::InvalidToken(_))
+        );
+    }
+
+    #[test]
+    fn should_reject_credential_request_for_other_configuration() {
+        let mut sessions = IssuanceSessions::default();
+        let token = token_for(&mut sessions, "VerifiedEmployee");
+        let request = credential_request(Holder::new(1).proof_jwt(&token.c_nonce));
+        assert_matches!(
+            sessions.authorize_credential_request(
+                &token.access_token,
+                &request,
+                CREDENTIAL_ISSUER,
+                &DidKeyResolver,
+                NOW_NS,
+            ),
+            Err(Openid4vciError::UnknownCredentialConfiguration(_))
+        );
+    }
+
+    #[test]
+    fn should_reject_credential_request_with_invalid_proof() {
+        let mut sessions = IssuanceSessions::default();
+        let token = token_for(&mut sessions, "VerifiedAdult");
+        let holder = Holder::new(1);
+        let claims = |aud: &str, iat_ns: u128, nonce: &str| json!({ "aud": aud, "iat": (iat_ns / 1_000_000_000) as u64, "nonce": nonce });
+        let c_nonce = token.c_nonce.as_str();
+        let invalid_proofs = [
+            holder.proof("JWT", claims(CREDENTIAL_ISSUER, NOW_NS, c_nonce)),
+            holder.proof(
+                PROOF_JWT_TYPE,
+                claims("https://other.test", NOW_NS, c_nonce),
+            ),
+            holder.proof(
+                PROOF_JWT_TYPE,
+                claims(CREDENTIAL_ISSUER, NOW_NS - 10 * MINUTE_NS, c_nonce),
+            ),
+            holder.proof(
+                PROOF_JWT_TYPE,
+                claims(CREDENTIAL_ISSUER, NOW_NS + 10 * MINUTE_NS, c_nonce),
+            ),
+            // Signed by another key than the one of the kid.
+            {
+                let proof = holder.proof_jwt(c_nonce);
+                let other_proof = Holder::new(2).proof_jwt(c_nonce);
+                let (signing_input, _) = proof.rsplit_once('.').unwrap();
+                let (_, other_sig) = other_proof.rsplit_once('.').unwrap();
+                format!("{}.{}", signing_input, other_sig)
+            },
+        ];
+        for proof_jwt in invalid_proofs {
+            assert_matches!(
+                sessions.authorize_credential_request(
+                    &token.access_token,
+                    &credential_request(proof_jwt),
+                    CREDENTIAL_ISSUER,
+                    &DidKeyResolver,
+                    NOW_NS,
+                ),
+                Err(Openid4vciError::InvalidProof(_))
+            );
+        }
+        assert_matches!(
+            sessions.authorize_credential_request(
+                &token.access_token,
+                &credential_request(holder.proof_jwt("other nonce")),
+                CREDENTIAL_ISSUER,
+                &DidKeyResolver,
+                NOW_NS,
+            ),
+            Err(Openid4vciError::InvalidNonce(_))
+        );
+        // Failed requests do not consume the access token.
+        assert!(
+            sessions
+                .authorize_credential_request(
+                    &token.access_token,
+                    &credential_request(holder.proof_jwt(c_nonce)),
+                    CREDENTIAL_ISSUER,
+                    &DidKeyResolver,
+                    NOW_NS,
+                )
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn should_reject_expired_access_token() {
+        let mut sessions = IssuanceSessions::default();
+        let token = token_for(&mut sessions, "VerifiedAdult");
+        let request = credential_request(Holder::new(1).proof_jwt(&token.c_nonce));
+        assert_matches!(
+            sessions.authorize_credential_request(
+                &token.access_token,
+                &request,
+                CREDENTIAL_ISSUER,
+                &DidKeyResolver,
+                NOW_NS + DEFAULT_ACCESS_TOKEN_TTL.as_nanos(),
+            ),
+            Err(Openid4vciError::InvalidToken(_))
+        );
+    }
+
+    #[test]
+    fn should_extract_bearer_token() {
+        assert_eq!(bearer_token("Bearer abc"), Ok("abc"));
+        assert_matches!(
+            bearer_token("Basic abc"),
+            Err(Openid4vciError::InvalidToken(_))
+        );
+        assert_matches!(
+            bearer_token("Bearer "),
+            Err(Openid4vciError::InvalidToken(_))
+        );
+    }
+
+    #[test]
+    fn should_serialize_error_and_credential_responses() {
+        let error = Openid4vciError::InvalidNonce("stale".to_string());
+        assert_eq!(error.status_code(), 400);
+        assert_eq!(
+            serde_json::to_value(error.to_error_response()).unwrap(),
+            json!({ "error": "invalid_nonce", "error_description": "stale" })
+        );
+        let error = Openid4vciError::RateLimited { retry_after_s: 30 };
+        assert_eq!(error.status_code(), 429);
+        assert_eq!(
+            serde_json::to_value(error.to_error_response()).unwrap(),
+            json!({
+                "error": "rate_limited",
+                "error_description": "rate limit exceeded, retry after 30s"
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(CredentialResponse::deferred("tx".to_string(), 1)).unwrap(),
+            json!({ "transaction_id": "tx", "interval": 1 })
+        );
+        assert_eq!(
+            serde_json::to_value(CredentialResponse::issued("jws".to_string())).unwrap(),
+            json!({ "credentials": [{ "credential": "jws" }] })
+        );
+    }
+}
@@ -0,0 +1,835 @@
+//! Presentation Exchange for relying parties that use OpenID4VP.
+//!
+//! Verifiers that use [OpenID4VP](https://openid.net/specs/openid-4-verifiable-presentations-1_0.html)
+//! describe the credentials they request via a `presentation_definition` as specified by
+//! [DIF Presentation Exchange](https://identity.foundation/presentation-exchange/spec/v2.0.0/),
+//! and expect a `presentation_submission` describing where the credentials are located in the
+//! presentation.  This module maps such a definition onto the II flow:
+//!  * The definition must contain exactly one input descriptor, which is mapped onto the
+//!    `CredentialSpec` of the credential requested from II (cf. `InputDescriptor::credential_spec()`).
+//!  * The presentation returned by II is verified as `validate_ii_presentation_and_claims()`,
+//!    and the claims of the requested credential are evaluated against the fields of the input
+//!    descriptor (cf. `evaluate_ii_presentation()`).
+//!
+//! Paths of fields are evaluated against the JWT claims of the requested credential, so the
+//! claims of the VC are at `$.vc`.  Only a subset of JSONPath (`$`, `.name`, `['name']` and
+//! `[index]`) and of JSON Schema (`type`, `const`, `enum`, `pattern`, `minLength`, `maxLength`,
+//! `minimum`, `maximum`, `exclusiveMinimum`, `exclusiveMaximum` and `contains`) is supported,
+//! and definitions using anything else are rejected.
+use crate::environment::RootKeyProvider;
+use crate::issuer_api::{ArgumentValue, CredentialSpec};
+use crate::{
+    PresentationVerificationError, VcFlowSigners, validate_requested_vc_claims,
+    verify_ii_presentation_jwt_with_canister_ids,
+};
+use candid::Principal;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// The format of the presentations returned by II.
+pub const JWT_VP_FORMAT: &str = "jwt_vp";
+/// The format of the credentials contained in the presentations returned by II.
+pub const JWT_VC_FORMAT: &str = "jwt_vc";
+/// The path of the requested credential within the presentations returned by II,
+/// which contain the id_alias credential first.
+pub const REQUESTED_VC_PATH: &str = "$.vp.verifiableCredential[1]";
+const TYPE_PATHS: [&str; 2] = ["$.vc.type", "$.type"];
+const CREDENTIAL_SUBJECT_PATHS: [&str; 2] = ["$.vc.credentialSubject", "$.credentialSubject"];
+
+#[derive(Debug)]
+pub enum PresentationExchangeError {
+    /// The presentation definition is malformed.
+    InvalidDefinition(String),
+    /// The presentation definition cannot be satisfied with a credential obtained via II,
+    /// or uses unsupported JSONPath or JSON Schema features.
+    UnsupportedDefinition(String),
+    /// The presentation is not a valid presentation of the requested credential.
+    InvalidPresentation(PresentationVerificationError),
+    /// The requested credential does not satisfy the constraints of the input descriptor.
+    UnsatisfiedConstraint { descriptor_id: String, path: String },
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PresentationDefinition {
+    pub id: String,
+    pub input_descriptors: Vec<InputDescriptor>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct InputDescriptor {
+    pub id: String,
+    #[serde(default)]
+    pub constraints: Constraints,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Constraints {
+    #[serde(default)]
+    pub fields: Vec<Field>,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Field {
+    /// Alternative JSONPath expressions, of which the first one that selects a value
+    /// satisfying the `filter` is used.
+    pub path: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter>,
+    #[serde(default)]
+    pub optional: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<String>,
+}
+
+/// The `filter` of a field, i.e. a JSON Schema, which is validated (and whose patterns are
+/// compiled) once when it is created or deserialized.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(from = "Value", into = "Value")]
+pub struct Filter {
+    json: Value,
+    compiled: Result<CompiledFilter, String>,
+}
+
+impl Filter {
+    pub fn new(json: Value) -> Self {
+        let compiled = compile_filter(&json);
+        Self { json, compiled }
+    }
+
+    pub fn as_json(&self) -> &Value {
+        &self.json
+    }
+
+    /// Returns an error if the filter uses unsupported JSON Schema features.
+    pub fn check(&self) -> Result<(), String> {
+        self.compiled.as_ref().map(|_| ()).map_err(Clone::clone)
+    }
+
+    fn matches(&self, value: &Value) -> Result<bool, String> {
+        self.compiled
+            .as_ref()
+            .map(|filter| filter.matches(value))
+            .map_err(Clone::clone)
+    }
+}
+
+impl From<Value> for Filter {
+    fn from(json: Value) -> Self {
+        Self::new(json)
+    }
+}
+
+impl From<Filter> for Value {
+    fn from(filter: Filter) -> Self {
+        filter.json
+    }
+}
+
+impl PartialEq for Filter {
+    fn eq(&self, other: &Self) -> bool {
+        self.json == other.json
+    }
+}
+
+impl Eq for Filter {}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PresentationSubmission {
+    pub id: String,
+    pub definition_id: String,
+    pub descriptor_map: Vec<DescriptorMapEntry>,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct DescriptorMapEntry {
+    pub id: String,
+    pub format: String,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path_nested: Option<Box<DescriptorMapEntry>>,
+}
+
+impl PresentationDefinition {
+    /// Parses the given `presentation_definition`, and checks that all paths and filters
+    /// of its fields are supported.
+    pub fn from_json(definition_json: &str) -> Result<Self, PresentationExchangeError> {
+        let definition: Self = serde_json::from_str(definition_json).map_err(|e| {
+            PresentationExchangeError::InvalidDefinition(format!("invalid JSON: {}", e))
+        })?;
+        for descriptor in &definition.input_descriptors {
+            for field in &descriptor.constraints.fields {
+                if field.path.is_empty() {
+                    return Err(PresentationExchangeError::InvalidDefinition(format!(
+                        "field without path in input descriptor {}",
+                        descriptor.id
+                    )));
+                }
+                for path in &field.path {
+                    parse_json_path(path)
+                        .map_err(PresentationExchangeError::UnsupportedDefinition)?;
+                }
+                if let Some(filter) = &field.filter {
+                    filter
+                        .check()
+                        .map_err(PresentationExchangeError::UnsupportedDefinition)?;
+                }
+            }
+        }
+        Ok(definition)
+    }
+
+    /// Returns the only input descriptor of the definition, as II presentations contain
+    /// exactly one requested credential.
+    pub fn ii_input_descriptor(&self) -> Result<&InputDescriptor, PresentationExchangeError> {
+        match self.input_descriptors.as_slice() {
+            [descriptor] => Ok(descriptor),
+            descriptors => Err(PresentationExchangeError::UnsupportedDefinition(format!(
+                "expected exactly one input descriptor, got {}",
+                descriptors.len()
+            ))),
+        }
+    }
+}
+
+impl InputDescriptor {
+    /// Maps the input descriptor onto the spec of the credential to request from II,
+    /// following the [convention](https://github.com/dfinity/internet-identity/blob/main/docs/vc-spec.md#recommended-convention-connecting-credential-specification-with-the-returned-credentials)
+    /// that connects credential specs with the returned credentials:
+    ///  * The credential type is given by a field with path `$.vc.type` whose filter requires
+    ///    the type, i.e. `{"type": "array", "contains": {"const": "<credential type>"}}`.
+    ///  * Each argument is given by a field with path `$.vc.credentialSubject.<credential type>.<argument>`
+    ///    whose filter requires the value of the argument, i.e. `{"const": <value>}`,
+    ///    where the value must be a string or a 32-bit integer.
+    pub fn credential_spec(&self) -> Result<CredentialSpec, PresentationExchangeError> {
+        let unsupported = PresentationExchangeError::UnsupportedDefinition;
+        let credential_type = self
+            .constraints
+            .fields
+            .iter()
+            .filter(|field| {
+                field
+                    .path
+                    .iter()
+                    .any(|path| TYPE_PATHS.contains(&path.as_str()))
+            })
+            .find_map(|field| {
+                field
+                    .filter
+                    .as_ref()
+                    .and_then(|filter| filter.as_json().pointer("/contains/const"))
+                    .and_then(Value::as_str)
+            })
+            .ok_or(unsupported(format!(
+                "input descriptor {} does not require a credential type",
+                self.id
+            )))?
+            .to_string();
+
+        let mut arguments = HashMap::new();
+        let argument_prefixes: Vec<String> = CREDENTIAL_SUBJECT_PATHS
+            .iter()
+            .map(|path| format!("{}.{}.", path, credential_type))
+            .collect();
+        for field in &self.constraints.fields {
+            let Some(argument) = field.path.iter().find_map(|path| {
+                argument_prefixes
+                    .iter()
+                    .find_map(|prefix| path.strip_prefix(prefix.as_str()))
+            }) else {
+                continue;
+            };
+            let value = field
+                .filter
+                .as_ref()
+                .and_then(|filter| filter.as_json().get("const"))
+                .ok_or(unsupported(format!(
+                    "argument {} of input descriptor {} is not constant",
+                    argument, self.id
+                )))?;
+            let value = match value {
+                Value::String(s) => ArgumentValue::String(s.clone()),
+                Value::Number(n) => n
+                    .as_i64()
+                    .and_then(|n| i32::try_from(n).ok())
+                    .map(ArgumentValue::Int)
+                    .ok_or(unsupported(format!(
+                        "unsupported value of argument {}",
+                        argument
+                    )))?,
+                _ => {
+                    return Err(unsupported(format!(
+                        "unsupported value of argument {}",
+                        argument
+                    )));
+                }
+            };
+            if field.optional {
+                return Err(unsupported(format!("optional argument {}", argument)));
+            }
+            arguments.insert(argument.to_string(), value);
+        }
+        Ok(CredentialSpec {
+            credential_type,
+            arguments: (!arguments.is_empty()).then_some(arguments),
+        })
+    }
+
+    /// Evaluates the fields of the input descriptor against the given JWT claims of a
+    /// credential.  Every field that is not optional must have a path that selects a value
+    /// which satisfies the filter of the field.
+    pub fn evaluate(&self, claims: &Value) -> Result<(), PresentationExchangeError> {
+        for field in &self.constraints.fields {
+            let mut is_satisfied = false;
+            for path in &field.path {
+                let segments = parse_json_path(path)
+                    .map_err(PresentationExchangeError::UnsupportedDefinition)?;
+                let Some(value) = select(claims, &segments) else {
+                    continue;
+                };
+                is_satisfied = match &field.filter {
+                    None => true,
+                    Some(filter) => filter
+                        .matches(value)
+                        .map_err(PresentationExchangeError::UnsupportedDefinition)?,
+                };
+                if is_satisfied {
+                    break;
+                }
+            }
+            if !is_satisfied && !field.optional {
+                return Err(PresentationExchangeError::UnsatisfiedConstraint {
+                    descriptor_id: self.id.clone(),
+                    path: field.path.join(" | "),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Verifies the presentation `vp_jwt` returned by II for the given presentation definition:
+/// verifies it like `validate_ii_presentation_and_claims()` with the spec of the input
+/// descriptor of the definition (cf. `InputDescriptor::credential_spec()`), and evaluates
+/// the claims of the requested credential against the fields of the input descriptor.
+/// Returns the `presentation_submission` for the presentation, whose id is derived
+/// from the hash of the presentation.
+pub fn evaluate_ii_presentation(
+    definition: &PresentationDefinition,
+    vp_jwt: &str,
+    effective_vc_subject: Principal,
+    effective_derivation_origin: String,
+    vc_flow_signers: &VcFlowSigners,
+    root_key: &impl RootKeyProvider,
+    current_time_ns: u128,
+) -> Result<PresentationSubmission, PresentationExchangeError> {
+    let descriptor = definition.ii_input_descriptor()?;
+    let spec = descriptor.credential_spec()?;
+    let (_alias_tuple, claims) = verify_ii_presentation_jwt_with_canister_ids(
+        vp_jwt,
+        effective_vc_subject,
+        effective_derivation_origin,
+        vc_flow_signers,
+        root_key,
+        current_time_ns,
+    )
+    .map_err(PresentationExchangeError::InvalidPresentation)?;
+    validate_requested_vc_claims(&claims, vc_flow_signers, &spec)
+        .map_err(PresentationExchangeError::InvalidPresentation)?;
+    let claims = serde_json::to_value(&claims).map_err(|e| {
+        PresentationExchangeError::InvalidPresentation(
+            PresentationVerificationError::InvalidPresentationJwt(format!(
+                "failed serializing claims: {}",
+                e
+            )),
+        )
+    })?;
+    descriptor.evaluate(&claims)?;
+
+    Ok(PresentationSubmission {
+        id: hex_prefix(&Sha256::digest(vp_jwt.as_bytes())),
+        definition_id: definition.id.clone(),
+        descriptor_map: vec![DescriptorMapEntry {
+            id: descriptor.id.clone(),
+            format: JWT_VP_FORMAT.to_string(),
+            path: "$".to_string(),
+            path_nested: Some(Box::new(DescriptorMapEntry {
+                id: descriptor.id.clone(),
+                format: JWT_VC_FORMAT.to_string(),
+                path: REQUESTED_VC_PATH.to_string(),
+                path_nested: None,
+            })),
+        }],
+    })
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum PathSegment {
+    Name(String),
+    Index(usize),
+}
+
+fn parse_json_path(path: &str) -> Result<Vec<PathSegment>, String> {
+    let unsupported = || format!("unsupported JSONPath {}", path);
+    let mut rest = path.strip_prefix('$').ok_or_else(unsupported)?;
+    let mut segments = vec![];
+    while !rest.is_empty() {
+        if let Some(after_dot) = rest.strip_prefix('.') {
+            let end = after_dot.find(['.', '[']).unwrap_or(after_dot.len());
+            let name = &after_dot[..end];
+            if name.is_empty() || name == "*" {
+                return Err(unsupported());
+            }
+            segments.push(PathSegment::Name(name.to_string()));
+            rest = &after_dot[end..];
+        } else if let Some(after_bracket) = rest.strip_prefix('[') {
+            let end = after_bracket.find(']').ok_or_else(unsupported)?;
+            let selector = &after_bracket[..end];
+            let quoted = selector
+                .strip_prefix('\'')
+                .and_then(|s| s.strip_suffix('\''))
+                .or_else(|| selector.strip_prefix('"').and_then(|s| s.strip_suffix('"')));
+            let segment = match quoted {
+                Some(name) => PathSegment::Name(name.to_string()),
+                None => PathSegment::Index(selector.parse().map_err(|_| unsupported())?),
+            };
+            segments.push(segment);
+            rest = &after_bracket[end + 1..];
+        } else {
+            return Err(unsupported());
+        }
+    }
+    Ok(segments)
+}
+
+fn select<'a>(value: &'a Value, segments: &[PathSegment]) -> Option<&'a Value> {
+    segments
+        .iter()
+        .try_fold(value, |value, segment| match segment {
+            PathSegment::Name(name) => value.get(name),
+            PathSegment::Index(index) => value.get(index),
+        })
+}
+
+#[derive(Clone, Copy, Debug)]
+enum JsonType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    Array,
+    Object,
+    Null,
+}
+
+// A validated filter, cf. `compile_filter()`.
+#[derive(Clone, Debug, Default)]
+struct CompiledFilter {
+    json_type: Option<JsonType>,
+    constant: Option<Value>,
+    allowed: Option<Vec<Value>>,
+    pattern: Option<Regex>,
+    min_length: Option<u64>,
+    max_length: Option<u64>,
+    minimum: Option<f64>,
+    maximum: Option<f64>,
+    exclusive_minimum: Option<f64>,
+    exclusive_maximum: Option<f64>,
+    contains: Option<Box<CompiledFilter>>,
+}
+
+// Checks that the filter only uses supported keywords with well-formed values, and compiles
+// its pattern.
+fn compile_filter(filter: &Value) -> Result<CompiledFilter, String> {
+    let filter = filter
+        .as_object()
+        .ok_or_else(|| format!("filter {} is not an object", filter))?;
+    let mut compiled = CompiledFilter::default();
+    for (keyword, value) in filter {
+        let number = || {
+            value
+                .as_f64()
+                .ok_or_else(|| format!("{} is not a number", keyword))
+        };
+        let length = || {
+            value
+                .as_u64()
+                .ok_or_else(|| format!("{} is not a non-negative integer", keyword))
+        };
+        match keyword.as_str() {
+            "type" => {
+                let json_type = value.as_str().ok_or("type is not a string")?;
+                compiled.json_type = Some(match json_type {
+                    "string" => JsonType::String,
+                    "number" => JsonType::Number,
+                    "integer" => JsonType::Integer,
+                    "boolean" => JsonType::Boolean,
+                    "array" => JsonType::Array,
+                    "object" => JsonType::Object,
+                    "null" => JsonType::Null,
+                    _ => return Err(format!("unsupported type {}", json_type)),
+                });
+            }
+            "const" => compiled.constant = Some(value.clone()),
+            "enum" => {
+                let allowed = value.as_array().ok_or("enum is not an array")?;
+                compiled.allowed = Some(allowed.clone());
+            }
+            "pattern" => {
+                let pattern = value.as_str().ok_or("pattern is not a string")?;
+                let regex = Regex::new(pattern)
+                    .map_err(|e| format!("invalid pattern {}: {}", pattern, e))?;
+                compiled.pattern = Some(regex);
+            }
+            "minLength" => compiled.min_length = Some(length()?),
+            "maxLength" => compiled.max_length = Some(length()?),
+            "minimum" => compiled.minimum = Some(number()?),
+            "maximum" => compiled.maximum = Some(number()?),
+            "exclusiveMinimum" => compiled.exclusive_minimum = Some(number()?),
+            "exclusiveMaximum" => compiled.exclusive_maximum = Some(number()?),
+            "contains" => compiled.contains = Some(Box::new(compile_filter(value)?)),
+            _ => return Err(format!("unsupported filter keyword {}", keyword)),
+        }
+    }
+    Ok(compiled)
+}
+
+impl CompiledFilter {
+    fn matches(&self, value: &Value) -> bool {
+        if let Some(json_type) = self.json_type {
+            let has_type = match json_type {
+                JsonType::String => value.is_string(),
+                JsonType::Number => value.is_number(),
+                JsonType::Integer => value.is_i64() || value.is_u64(),
+                JsonType::Boolean => value.is_boolean(),
+                JsonType::Array => value.is_array(),
+                JsonType::Object => value.is_object(),
+                JsonType::Null => value.is_null(),
+            };
+            if !has_type {
+                return false;
+            }
+        }
+        if self
+            .constant
+            .as_ref()
+            .is_some_and(|expected| value != expected)
+        {
+            return false;
+        }
+        if self
+            .allowed
+            .as_ref()
+            .is_some_and(|allowed| !allowed.contains(value))
+        {
+            return false;
+        }
+        if let Some(s) = value.as_str() {
+            if self
+                .pattern
+                .as_ref()
+                .is_some_and(|regex| !regex.is_match(s))
+            {
+                return false;
+            }
+            let char_count = s.chars().count() as u64;
+            if self.min_length.is_some_and(|min| char_count < min)
+                || self.max_length.is_some_and(|max| char_count > max)
+            {
+                return false;
+            }
+        }
+        if let Some(n) = value.as_f64()
+            && (self.minimum.is_some_and(|min| n < min)
+                || self.maximum.is_some_and(|max| n > max)
+                || self.exclusive_minimum.is_some_and(|min| n <= min)
+                || self.exclusive_maximum.is_some_and(|max| n >= max))
+        {
+            return false;
+        }
+        if let (Some(items), Some(item_filter)) = (value.as_array(), &self.contains)
+            && !items.iter().any(|item| item_filter.matches(item))
+        {
+            return false;
+        }
+        true
+    }
+}
+
+fn hex_prefix(hash: &[u8]) -> String {
+    hash.iter()
+        .take(16)
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+    use serde_json::json;
+
+    fn definition_json(fields: Value) -> String {
+        json!({
+            "id": "age-verification",
+            "input_descriptors": [{
+                "id": "verified-adult",
+                "constraints": { "fields": fields },
+            }],
+        })
+        .to_string()
+    }
+
+    fn verified_adult_fields() -> Value {
+        json!([
+            {
+                "path": ["$.vc.type"],
+                "filter": { "type": "array", "contains": { "const": "VerifiedAdult" } },
+            },
+            {
+                "path": ["$.vc.credentialSubject.VerifiedAdult.minAge"],
+                "filter": { "const": 18 },
+            },
+            {
+                "path": ["$.iss", "$.vc.issuer"],
+                "filter": { "type": "string", "pattern": "^https://" },
+            },
+        ])
+    }
+
+    #[test]
+    fn should_map_input_descriptor_to_credential_spec() {
+        let definition =
+            PresentationDefinition::from_json(&definition_json(verified_adult_fields())).unwrap();
+
+        let spec = definition
+            .ii_input_descriptor()
+            .unwrap()
+            .credential_spec()
+            .unwrap();
+
+        assert_eq!(
+            spec,
+            CredentialSpec {
+                credential_type: "VerifiedAdult".to_string(),
+                arguments: Some(HashMap::from([(
+                    "minAge".to_string(),
+                    ArgumentValue::Int(18)
+                )])),
+            }
+        );
+    }
+
+    #[test]
+    fn should_reject_unsupported_definitions() {
+        for fields in [
+            json!([{ "path": ["$..type"] }]),
+            json!([{ "path": ["$.vc.type[*]"] }]),
+            json!([{ "path": ["$.vc.type"], "filter": { "format": "date" } }]),
+            json!([{ "path": ["$.vc.type"], "filter": { "contains": { "not": {} } } }]),
+        ] {
+            assert_matches!(
+                PresentationDefinition::from_json(&definition_json(fields)),
+                Err(PresentationExchangeError::UnsupportedDefinition(_))
+            );
+        }
+        assert_matches!(
+            PresentationDefinition::from_json(r#"{"id": "missing-descriptors"}"#),
+            Err(PresentationExchangeError::InvalidDefinition(_))
+        );
+
+        let definition = PresentationDefinition::from_json(&definition_json(json!([{
+            "path": ["$.vc.credentialSubject.VerifiedAdult.minAge"],
+            "filter": { "minimum": 18 },
+        }])))
+        .unwrap();
+        assert_matches!(
+            definition.ii_input_descriptor().unwrap().credential_spec(),
+            Err(PresentationExchangeError::UnsupportedDefinition(_))
+        );
+    }
+
+    #[test]
+    fn should_parse_json_paths() {
+        assert_eq!(parse_json_path("$"), Ok(vec![]));
+        assert_eq!(
+            parse_json_path("$.vp['verifiableCredential'][1]"),
+            Ok(vec![
+                PathSegment::Name("vp".to_string()),
+                PathSegment::Name("verifiableCredential".to_string()),
+                PathSegment::Index(1),
+            ])
+        );
+        assert_eq!(
+            parse_json_path(r#"$["credentialSubject"].name"#),
+            Ok(vec![
+                PathSegment::Name("credentialSubject".to_string()),
+                PathSegment::Name("name".to_string()),
+            ])
+        );
+        for path in ["vc.type", "$.", "$[x]", "$['a'", "$.*"] {
+            assert!(parse_json_path(path).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn should_evaluate_filters() {
+        let cases = [
+            (
+                json!("abc"),
+                json!({ "type": "string", "minLength": 3 }),
+                true,
+            ),
+            (json!("abc"), json!({ "maxLength": 2 }), false),
+            (json!("abc"), json!({ "pattern": "^a" }), true),
+            (json!("abc"), json!({ "pattern": "^b" }), false),
+            (json!(18), json!({ "type": "integer", "minimum": 18 }), true),
+            (json!(18), json!({ "exclusiveMinimum": 18 }), false),
+            (json!(18.5), json!({ "type": "integer" }), false),
+            (json!(18), json!({ "maximum": 17 }), false),
+            (json!("DE"), json!({ "enum": ["CH", "DE"] }), true),
+            (json!("FR"), json!({ "enum": ["CH", "DE"] }), false),
+            (
+                json!(["a", "b"]),
+                json!({ "contains": { "const": "b" } }),
+                true,
+            ),
+            (
+                json!(["a", "b"]),
+                json!({ "contains": { "const": "c" } }),
+                false,
+            ),
+            (json!(true), json!({ "type": "string" }), false),
+        ];
+        for (value, filter, expected) in cases {
+            assert_eq!(
+                Filter::new(filter.clone()).matches(&value),
+                Ok(expected),
+                "{} {}",
+                value,
+                filter
+            );
+        }
+    }
+
+    #[test]
+    fn should_evaluate_fields_against_claims() {
+        let definition =
+            PresentationDefinition::from_json(&definition_json(verified_adult_fields())).unwrap();
+        let descriptor = definition.ii_input_descriptor().unwrap();
+        let claims = json!({
+            "iss": "https://issuer.test/",
+            "vc": {
+                "type": ["VerifiableCredential", "VerifiedAdult"],
+                "credentialSubject": { "VerifiedAdult": { "minAge": 18 } },
+            },
+        });
+
+        assert_matches!(descriptor.evaluate(&claims), Ok(()));
+
+        let mut other_claims = claims.clone();
+        other_claims["iss"] = json!("http://issuer.test/");
+        assert_matches!(
+            descriptor.evaluate(&other_claims),
+            Err(PresentationExchangeError::UnsatisfiedConstraint { descriptor_id, path })
+                if descriptor_id == "verified-adult" && path == "$.iss | $.vc.issuer"
+        );
+    }
+
+    #[cfg(feature = "test-utils")]
+    mod presentation {
+        use super::*;
+        use crate::test_utils::TestVcFlow;
+
+        const NOW_NS: u128 = 1_700_000_000_000_000_000;
+        const RP_ORIGIN: &str = "https://rp.test";
+
+        fn id_dapp() -> Principal {
+            Principal::from_slice(&[42; 29])
+        }
+
+        #[test]
+        fn should_evaluate_ii_presentation() {
+            let flow = TestVcFlow::default();
+            let definition =
+                PresentationDefinition::from_json(&definition_json(verified_adult_fields()))
+                    .unwrap();
+            let spec = definition
+                .ii_input_descriptor()
+                .unwrap()
+                .credential_spec()
+                .unwrap();
+            let vp_jwt = flow.presentation_jwt(id_dapp(), RP_ORIGIN, &spec, NOW_NS);
+
+            let submission = evaluate_ii_presentation(
+                &definition,
+                &vp_jwt,
+                id_dapp(),
+                RP_ORIGIN.to_string(),
+                &flow.vc_flow_signers(),
+                &flow.ic_root_key(),
+                NOW_NS,
+            )
+            .expect("failed evaluating presentation");
+
+            assert_eq!(submission.definition_id, "age-verification");
+            assert_eq!(submission.id.len(), 32);
+            assert_eq!(
+                serde_json::to_value(&submission.descriptor_map).unwrap(),
+                json!([{
+                    "id": "verified-adult",
+                    "format": "jwt_vp",
+                    "path": "$",
+                    "path_nested": {
+                        "id": "verified-adult",
+                        "format": "jwt_vc",
+                        "path": "$.vp.verifiableCredential[1]",
+                    },
+                }])
+            );
+        }
+
+        #[test]
+        fn should_reject_presentation_of_other_credential() {
+            let flow = TestVcFlow::default();
+            let definition =
+                PresentationDefinition::from_json(&definition_json(verified_adult_fields()))
+                    .unwrap();
+            let other_spec = CredentialSpec {
+                credential_type: "VerifiedAdult".to_string(),
+                arguments: Some(HashMap::from([(
+                    "minAge".to_string(),
+                    ArgumentValue::Int(16),
+                )])),
+            };
+            let vp_jwt = flow.presentation_jwt(id_dapp(), RP_ORIGIN, &other_spec, NOW_NS);
+
+            let result = evaluate_ii_presentation(
+                &definition,
+                &vp_jwt,
+                id_dapp(),
+                RP_ORIGIN.to_string(),
+                &flow.vc_flow_signers(),
+                &flow.ic_root_key(),
+                NOW_NS,
+            );
+
+            assert_matches!(
+                result,
+                Err(PresentationExchangeError::InvalidPresentation(
+                    PresentationVerificationError::InvalidRequestedCredential(_)
+                ))
+            );
+        }
+    }
+}
@@ -0,0 +1,520 @@
+//! Rate limiting of credential preparation by issuers.
+//!
+//! Every call of `prepare_credential` (or of the OpenID4VCI credential endpoint) adds a
+//! canister signature to the certified `SignatureMap` of the issuer, which is kept until the
+//! signature expires.  Without a limit, any caller can inflate the signature map (and the
+//! certified data) at no cost.  A `RateLimiter` bounds the number of requests per caller and
+//! per id_alias within a fixed window, and the number of prepared credentials whose signatures
+//! have not yet expired (i.e. which are outstanding) per caller.  Requests via OpenID4VCI,
+//! which reach the issuer as anonymous HTTP requests, are limited per holder instead.
+//!
+//! The state of a `RateLimiter` is kept in `RateLimitCounters`, which are `CandidType`, so
+//! that canisters can persist them in stable memory across upgrades, cf. `counters()` and
+//! `with_counters()`.
+use crate::issuer_api::IssueCredentialError;
+use crate::openid4vci::Openid4vciError;
+use candid::{CandidType, Deserialize, Principal};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+/// Default length of the window in which requests are counted.
+pub const DEFAULT_RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+/// Default maximal number of requests per caller within a window.
+pub const DEFAULT_MAX_REQUESTS_PER_CALLER: u32 = 20;
+/// Default maximal number of requests per id_alias within a window.
+pub const DEFAULT_MAX_REQUESTS_PER_ID_ALIAS: u32 = 10;
+/// Default maximal number of requests per holder within a window.
+pub const DEFAULT_MAX_REQUESTS_PER_HOLDER: u32 = 10;
+/// Default maximal number of outstanding prepared credentials per caller.
+pub const DEFAULT_MAX_OUTSTANDING_PER_CALLER: usize = 10;
+/// Default time during which a prepared credential is outstanding, i.e. the lifetime of
+/// the signatures in a `SignatureMap`.
+pub const DEFAULT_OUTSTANDING_TTL: Duration = Duration::from_secs(60);
+/// Prefix of the messages of the errors returned for rate-limited requests.  The messages end
+/// with "retry after <seconds>s".
+pub const RATE_LIMIT_EXCEEDED: &str = "rate limit exceeded";
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RateLimitConfig {
+    /// Length of the (fixed) window in which requests are counted.
+    pub window: Duration,
+    pub max_requests_per_caller: u32,
+    pub max_requests_per_id_alias: u32,
+    pub max_requests_per_holder: u32,
+    pub max_outstanding_per_caller: usize,
+    /// Time after which a prepared credential is no longer outstanding.
+    pub outstanding_ttl: Duration,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            window: DEFAULT_RATE_LIMIT_WINDOW,
+            max_requests_per_caller: DEFAULT_MAX_REQUESTS_PER_CALLER,
+            max_requests_per_id_alias: DEFAULT_MAX_REQUESTS_PER_ID_ALIAS,
+            max_requests_per_holder: DEFAULT_MAX_REQUESTS_PER_HOLDER,
+            max_outstanding_per_caller: DEFAULT_MAX_OUTSTANDING_PER_CALLER,
+            outstanding_ttl: DEFAULT_OUTSTANDING_TTL,
+        }
+    }
+}
+
+/// The principal (or holder) whose requests are counted.
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, Ord, PartialEq, PartialOrd)]
+pub enum RateLimitKey {
+    Caller(Principal),
+    IdAlias(Principal),
+    /// The DID of the holder of a credential requested via OpenID4VCI.
+    Holder(String),
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+struct Window {
+    start_ns: u128,
+    count: u32,
+}
+
+/// The requests counted by a `RateLimiter`.
+#[derive(Clone, Debug, Default, CandidType, Deserialize, Eq, PartialEq)]
+pub struct RateLimitCounters {
+    windows: BTreeMap<RateLimitKey, Window>,
+    // Maps callers to the expiration times of their outstanding prepared credentials.
+    outstanding: BTreeMap<Principal, Vec<u128>>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RateLimitError {
+    /// The maximal number of requests within the current window has been reached.
+    TooManyRequests {
+        key: RateLimitKey,
+        retry_after: Duration,
+    },
+    /// The caller has the maximal number of outstanding prepared credentials.
+    TooManyOutstanding {
+        caller: Principal,
+        retry_after: Duration,
+    },
+}
+
+impl fmt::Display for RateLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RateLimitError::TooManyRequests { key, retry_after } => {
+                let principal = match key {
+                    RateLimitKey::Caller(p) => format!("caller {}", p),
+                    RateLimitKey::IdAlias(p) => format!("id_alias {}", p),
+                    RateLimitKey::Holder(did) => format!("holder {}", did),
+                };
+                write!(
+                    f,
+                    "{}: too many requests of {}, retry after {}s",
+                    RATE_LIMIT_EXCEEDED,
+                    principal,
+                    retry_after.as_secs_f64().ceil()
+                )
+            }
+            RateLimitError::TooManyOutstanding {
+                caller,
+                retry_after,
+            } => write!(
+                f,
+                "{}: too many outstanding credentials of caller {}, retry after {}s",
+                RATE_LIMIT_EXCEEDED,
+                caller,
+                retry_after.as_secs_f64().ceil()
+            ),
+        }
+    }
+}
+
+impl RateLimitError {
+    /// Returns the time after which the request can be retried.
+    pub fn retry_after(&self) -> Duration {
+        match self {
+            RateLimitError::TooManyRequests { retry_after, .. }
+            | RateLimitError::TooManyOutstanding { retry_after, .. } => *retry_after,
+        }
+    }
+
+    // The time after which the request can be retried, rounded up to full seconds.
+    fn retry_after_s(&self) -> u64 {
+        self.retry_after().as_secs_f64().ceil() as u64
+    }
+}
+
+// Rate-limited requests are reported as `UnauthorizedSubject`, as the variants of
+// `IssueCredentialError` are part of the Candid interface between II and the issuers.
+impl From<RateLimitError> for IssueCredentialError {
+    fn from(error: RateLimitError) -> Self {
+        IssueCredentialError::UnauthorizedSubject(error.to_string())
+    }
+}
+
+impl From<RateLimitError> for Openid4vciError {
+    fn from(error: RateLimitError) -> Self {
+        Openid4vciError::RateLimited {
+            retry_after_s: error.retry_after_s(),
+        }
+    }
+}
+
+/// Limits the requests per caller and per id_alias, and the outstanding prepared credentials
+/// per caller.  Note that the id_alias is chosen by the caller, so the limits per caller are
+/// the ones that bound the work of the issuer.
+#[derive(Clone, Debug, Default)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    counters: RateLimitCounters,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self::with_counters(config, RateLimitCounters::default())
+    }
+
+    /// Creates a rate limiter with the given counters, e.g. as restored from stable memory.
+    pub fn with_counters(config: RateLimitConfig, counters: RateLimitCounters) -> Self {
+        let config = RateLimitConfig {
+            max_requests_per_caller: config.max_requests_per_caller.max(1),
+            max_requests_per_id_alias: config.max_requests_per_id_alias.max(1),
+            max_requests_per_holder: config.max_requests_per_holder.max(1),
+            max_outstanding_per_caller: config.max_outstanding_per_caller.max(1),
+            ..config
+        };
+        Self { config, counters }
+    }
+
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.config
+    }
+
+    /// Returns the counters, e.g. to be saved to stable memory.
+    pub fn counters(&self) -> &RateLimitCounters {
+        &self.counters
+    }
+
+    /// Checks whether `caller` may prepare a credential for `id_alias`, and if so, counts the
+    /// request and the prepared credential.  Rejected requests are not counted.
+    pub fn check_and_record(
+        &mut self,
+        caller: Principal,
+        id_alias: Principal,
+        current_time_ns: u128,
+    ) -> Result<(), RateLimitError> {
+        self.prune_expired(current_time_ns);
+        let outstanding = self.counters.outstanding.get(&caller);
+        if outstanding.map_or(0, Vec::len) >= self.config.max_outstanding_per_caller {
+            let first_expiry = outstanding.into_iter().flatten().min().copied();
+            return Err(RateLimitError::TooManyOutstanding {
+                caller,
+                retry_after: duration_until(first_expiry.unwrap_or_default(), current_time_ns),
+            });
+        }
+        self.check_and_record_requests(
+            [
+                (
+                    RateLimitKey::Caller(caller),
+                    self.config.max_requests_per_caller,
+                ),
+                (
+                    RateLimitKey::IdAlias(id_alias),
+                    self.config.max_requests_per_id_alias,
+                ),
+            ],
+            current_time_ns,
+        )?;
+        self.counters
+            .outstanding
+            .entry(caller)
+            .or_default()
+            .push(current_time_ns.saturating_add(self.config.outstanding_ttl.as_nanos()));
+        Ok(())
+    }
+
+    /// Checks whether a credential may be prepared for the holder with the DID `holder_did`,
+    /// e.g. at the OpenID4VCI credential endpoint, and if so, counts the request.  Rejected
+    /// requests are not counted.
+    pub fn check_and_record_holder(
+        &mut self,
+        holder_did: &str,
+        current_time_ns: u128,
+    ) -> Result<(), RateLimitError> {
+        self.prune_expired(current_time_ns);
+        self.check_and_record_requests(
+            [(
+                RateLimitKey::Holder(holder_did.to_string()),
+                self.config.max_requests_per_holder,
+            )],
+            current_time_ns,
+        )
+    }
+
+    // Checks that none of the keys has reached its maximal number of requests within the
+    // current window, and if so, counts the request for all keys.
+    fn check_and_record_requests<const N: usize>(
+        &mut self,
+        keys: [(RateLimitKey, u32); N],
+        current_time_ns: u128,
+    ) -> Result<(), RateLimitError> {
+        for (key, max_requests) in &keys {
+            let window = self.counters.windows.get(key);
+            if let Some(window) = window.filter(|window| window.count >= *max_requests) {
+                return Err(RateLimitError::TooManyRequests {
+                    key: key.clone(),
+                    retry_after: duration_until(self.window_end(window), current_time_ns),
+                });
+            }
+        }
+        for (key, _) in keys {
+            self.counters
+                .windows
+                .entry(key)
+                .or_insert(Window {
+                    start_ns: current_time_ns,
+                    count: 0,
+                })
+                .count += 1;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of outstanding prepared credentials of `caller`.
+    pub fn outstanding(&self, caller: Principal, current_time_ns: u128) -> usize {
+        self.counters
+            .outstanding
+            .get(&caller)
+            .map_or(0, |expiries| {
+                expiries
+                    .iter()
+                    .filter(|expires_at_ns| **expires_at_ns > current_time_ns)
+                    .count()
+            })
+    }
+
+    /// Removes the windows that have ended and the credentials that are no longer outstanding.
+    pub fn prune_expired(&mut self, current_time_ns: u128) {
+        let window = self.config.window.as_nanos();
+        self.counters
+            .windows
+            .retain(|_, w| w.start_ns.saturating_add(window) > current_time_ns);
+        self.counters.outstanding.retain(|_, expiries| {
+            expiries.retain(|expires_at_ns| *expires_at_ns > current_time_ns);
+            !expiries.is_empty()
+        });
+    }
+
+    fn window_end(&self, window: &Window) -> u128 {
+        window
+            .start_ns
+            .saturating_add(self.config.window.as_nanos())
+    }
+}
+
+fn duration_until(time_ns: u128, current_time_ns: u128) -> Duration {
+    let nanos = time_ns.saturating_sub(current_time_ns);
+    Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+    use candid::{decode_one, encode_one};
+
+    const SECOND_NS: u128 = 1_000_000_000;
+    const NOW_NS: u128 = 1_700_000_000_000_000_000;
+
+    fn principal(id: u8) -> Principal {
+        Principal::from_slice(&[id; 10])
+    }
+
+    fn config() -> RateLimitConfig {
+        RateLimitConfig {
+            window: Duration::from_secs(60),
+            max_requests_per_caller: 3,
+            max_requests_per_id_alias: 2,
+            max_requests_per_holder: 2,
+            max_outstanding_per_caller: 10,
+            outstanding_ttl: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn should_limit_requests_per_caller_within_window() {
+        let mut limiter = RateLimiter::new(config());
+        let caller = principal(1);
+        for id in 0..3 {
+            assert_matches!(
+                limiter.check_and_record(caller, principal(10 + id), NOW_NS),
+                Ok(())
+            );
+        }
+
+        assert_eq!(
+            limiter.check_and_record(caller, principal(20), NOW_NS + 10 * SECOND_NS),
+            Err(RateLimitError::TooManyRequests {
+                key: RateLimitKey::Caller(caller),
+                retry_after: Duration::from_secs(50),
+            })
+        );
+        assert_matches!(
+            limiter.check_and_record(principal(2), principal(20), NOW_NS),
+            Ok(())
+        );
+        assert_matches!(
+            limiter.check_and_record(caller, principal(20), NOW_NS + 60 * SECOND_NS),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn should_limit_requests_per_id_alias() {
+        let mut limiter = RateLimiter::new(config());
+        let id_alias = principal(10);
+        assert_matches!(
+            limiter.check_and_record(principal(1), id_alias, NOW_NS),
+            Ok(())
+        );
+        assert_matches!(
+            limiter.check_and_record(principal(2), id_alias, NOW_NS),
+            Ok(())
+        );
+
+        assert_matches!(
+            limiter.check_and_record(principal(3), id_alias, NOW_NS),
+            Err(RateLimitError::TooManyRequests { key: RateLimitKey::IdAlias(key), .. })
+                if key == id_alias
+        );
+        // The rejected request is not counted for the caller.
+        assert!(
+            !limiter
+                .counters()
+                .windows
+                .contains_key(&RateLimitKey::Caller(principal(3)))
+        );
+    }
+
+    #[test]
+    fn should_limit_outstanding_credentials_per_caller() {
+        let mut limiter = RateLimiter::new(RateLimitConfig {
+            max_requests_per_caller: 100,
+            max_requests_per_id_alias: 100,
+            max_requests_per_holder: 100,
+            max_outstanding_per_caller: 2,
+            outstanding_ttl: Duration::from_secs(30),
+            ..config()
+        });
+        let caller = principal(1);
+        assert_matches!(
+            limiter.check_and_record(caller, principal(10), NOW_NS),
+            Ok(())
+        );
+        assert_matches!(
+            limiter.check_and_record(caller, principal(10), NOW_NS + 10 * SECOND_NS),
+            Ok(())
+        );
+        assert_eq!(limiter.outstanding(caller, NOW_NS + 10 * SECOND_NS), 2);
+
+        assert_eq!(
+            limiter.check_and_record(caller, principal(10), NOW_NS + 20 * SECOND_NS),
+            Err(RateLimitError::TooManyOutstanding {
+                caller,
+                retry_after: Duration::from_secs(10),
+            })
+        );
+        assert_matches!(
+            limiter.check_and_record(caller, principal(10), NOW_NS + 30 * SECOND_NS),
+            Ok(())
+        );
+        assert_eq!(limiter.outstanding(caller, NOW_NS + 30 * SECOND_NS), 2);
+    }
+
+    #[test]
+    fn should_limit_requests_per_holder() {
+        let mut limiter = RateLimiter::new(config());
+        let holder = "did:key:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK";
+        for _ in 0..2 {
+            assert_matches!(limiter.check_and_record_holder(holder, NOW_NS), Ok(()));
+        }
+
+        assert_eq!(
+            limiter.check_and_record_holder(holder, NOW_NS + 15 * SECOND_NS),
+            Err(RateLimitError::TooManyRequests {
+                key: RateLimitKey::Holder(holder.to_string()),
+                retry_after: Duration::from_secs(45),
+            })
+        );
+        assert_matches!(
+            limiter.check_and_record_holder("did:key:other", NOW_NS),
+            Ok(())
+        );
+        // Requests of holders count neither per caller nor as outstanding.
+        assert_matches!(
+            limiter.check_and_record(principal(1), principal(10), NOW_NS),
+            Ok(())
+        );
+        assert_eq!(limiter.outstanding(principal(1), NOW_NS), 1);
+        assert_matches!(
+            limiter.check_and_record_holder(holder, NOW_NS + 60 * SECOND_NS),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn should_prune_expired_counters() {
+        let mut limiter = RateLimiter::new(config());
+        limiter
+            .check_and_record(principal(1), principal(10), NOW_NS)
+            .unwrap();
+
+        limiter.prune_expired(NOW_NS + 60 * SECOND_NS);
+
+        assert_eq!(limiter.counters(), &RateLimitCounters::default());
+    }
+
+    #[test]
+    fn should_restore_counters() {
+        let mut limiter = RateLimiter::new(config());
+        let caller = principal(1);
+        for id in 0..3 {
+            limiter
+                .check_and_record(caller, principal(10 + id), NOW_NS)
+                .unwrap();
+        }
+
+        let counters: RateLimitCounters =
+            decode_one(&encode_one(limiter.counters()).unwrap()).unwrap();
+        let mut restored = RateLimiter::with_counters(config(), counters);
+
+        assert_eq!(restored.counters(), limiter.counters());
+        assert_matches!(
+            restored.check_and_record(caller, principal(20), NOW_NS),
+            Err(RateLimitError::TooManyRequests { .. })
+        );
+    }
+
+    #[test]
+    fn should_map_to_issue_credential_and_openid4vci_errors() {
+        let error = RateLimitError::TooManyOutstanding {
+            caller: Principal::anonymous(),
+            retry_after: Duration::from_millis(1500),
+        };
+
+        assert_eq!(
+            error.to_string(),
+            "rate limit exceeded: too many outstanding credentials of caller 2vxsx-fae, retry after 2s"
+        );
+        assert_eq!(
+            IssueCredentialError::from(error.clone()),
+            IssueCredentialError::UnauthorizedSubject(
+                "rate limit exceeded: too many outstanding credentials of caller 2vxsx-fae, retry after 2s"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            Openid4vciError::from(error),
+            Openid4vciError::RateLimited { retry_after_s: 2 }
+        );
+    }
+}
@@ -0,0 +1,611 @@
+//! Selective-disclosure verifiable credentials in the SD-JWT VC format
+//! (cf. https://datatracker.ietf.org/doc/draft-ietf-oauth-sd-jwt-vc/).
+//!
+//! The issuer-signed JWT of an SD-JWT VC is an ordinary credential JWT signed with a
+//! canister signature, i.e. it is produced via `vc_signing_input()` and `vc_jwt_to_jws()`.
+//! Selectively disclosable claims are not contained in the JWT directly, but only via their
+//! digests in the `_sd`-claim.  The claims themselves are transported as disclosures appended
+//! to the JWS, and the holder decides which of the disclosures to pass on to a verifier.
+use crate::environment::RootKeyProvider;
+use crate::verify_credential_jws_with_canister_id;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use candid::Principal;
+use identity_jose::jws::SignatureVerificationError;
+use serde_json::{Map, Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+
+pub const SD_JWT_SEPARATOR: char = '~';
+pub const SD_ALG_SHA_256: &str = "sha-256";
+const SD_CLAIM: &str = "_sd";
+const SD_ALG_CLAIM: &str = "_sd_alg";
+const VCT_CLAIM: &str = "vct";
+// Claims that are set by the issuer and must never be selectively disclosable.
+const RESERVED_CLAIMS: [&str; 10] = [
+    "iss",
+    "sub",
+    "exp",
+    "nbf",
+    "iat",
+    "vct",
+    "cnf",
+    "status",
+    SD_CLAIM,
+    SD_ALG_CLAIM,
+];
+
+/// A single disclosure of an SD-JWT, i.e. a salted claim `claim_name: claim_value`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Disclosure {
+    pub salt: String,
+    pub claim_name: String,
+    pub claim_value: Value,
+    /// base64url-encoding of the JSON array `[salt, claim_name, claim_value]`,
+    /// which is the input for computing the digest of this disclosure.
+    pub encoded: String,
+}
+
+impl Disclosure {
+    pub fn new(salt: String, claim_name: String, claim_value: Value) -> Self {
+        let encoded = BASE64.encode(json!([salt, claim_name, claim_value]).to_string());
+        Self {
+            salt,
+            claim_name,
+            claim_value,
+            encoded,
+        }
+    }
+
+    /// Parses a base64url-encoded disclosure.
+    pub fn parse(encoded: &str) -> Result<Self, String> {
+        let bytes = BASE64
+            .decode(encoded)
+            .map_err(|e| format!("invalid base64url encoding of disclosure: {}", e))?;
+        let value: Value = serde_json::from_slice(&bytes)
+            .map_err(|e| format!("invalid JSON in disclosure: {}", e))?;
+        let Value::Array(elements) = value else {
+            return Err("disclosure is not a JSON array".to_string());
+        };
+        let [salt, claim_name, claim_value] = <[Value; 3]>::try_from(elements)
+            .map_err(|_| "disclosure must contain exactly three elements".to_string())?;
+        let (Value::String(salt), Value::String(claim_name)) = (salt, claim_name) else {
+            return Err("salt and claim name of disclosure must be strings".to_string());
+        };
+        if RESERVED_CLAIMS.contains(&claim_name.as_str()) {
+            return Err(format!("reserved claim name in disclosure: {}", claim_name));
+        }
+        Ok(Self {
+            salt,
+            claim_name,
+            claim_value,
+            encoded: encoded.to_string(),
+        })
+    }
+
+    /// Returns the base64url-encoded SHA-256 digest of this disclosure, as referenced
+    /// from the `_sd`-claim of the issuer-signed JWT.
+    pub fn digest(&self) -> String {
+        BASE64.encode(Sha256::digest(self.encoded.as_bytes()))
+    }
+}
+
+/// An SD-JWT in its compact serialization `<issuer_jws>~<disclosure_1>~...~<disclosure_n>~`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SdJwt {
+    pub issuer_jws: String,
+    pub disclosures: Vec<Disclosure>,
+}
+
+impl SdJwt {
+    /// Parses the compact serialization of an SD-JWT.
+    /// SD-JWTs with a key binding JWT are not supported.
+    pub fn parse(sd_jwt: &str) -> Result<Self, String> {
+        let mut parts: Vec<&str> = sd_jwt.split(SD_JWT_SEPARATOR).collect();
+        if parts.len() < 2 {
+            return Err("missing separator in SD-JWT".to_string());
+        }
+        let key_binding_jwt = parts.pop().unwrap_or_default();
+        if !key_binding_jwt.is_empty() {
+            return Err("SD-JWT with key binding JWT is not supported".to_string());
+        }
+        let issuer_jws = parts.remove(0).to_string();
+        if issuer_jws.is_empty() {
+            return Err("missing issuer-signed JWT in SD-JWT".to_string());
+        }
+        let disclosures = parts
+            .into_iter()
+            .map(Disclosure::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            issuer_jws,
+            disclosures,
+        })
+    }
+
+    /// Returns the compact serialization of this SD-JWT.
+    pub fn to_compact(&self) -> String {
+        let mut result = self.issuer_jws.clone();
+        result.push(SD_JWT_SEPARATOR);
+        for disclosure in &self.disclosures {
+            result.push_str(&disclosure.encoded);
+            result.push(SD_JWT_SEPARATOR);
+        }
+        result
+    }
+
+    /// Returns a copy of this SD-JWT that contains only the disclosures of the given claims.
+    /// Used by holders to reveal only the claims requested by a verifier.
+    pub fn select(&self, claim_names: &[&str]) -> Self {
+        Self {
+            issuer_jws: self.issuer_jws.clone(),
+            disclosures: self
+                .disclosures
+                .iter()
+                .filter(|d| claim_names.contains(&d.claim_name.as_str()))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+pub struct SdJwtVcParams {
+    /// The verifiable credential type, cf. the `vct`-claim of SD-JWT VC.
+    pub vct: String,
+    pub subject_id: String,
+    pub issuer_url: String,
+    pub expiration_timestamp_s: u32,
+    /// Claims that are always disclosed, i.e. are contained in plain in the issuer-signed JWT.
+    pub claims: Map<String, Value>,
+    /// Claims that are disclosed only if chosen by the holder.
+    pub disclosable_claims: Map<String, Value>,
+    /// Secret randomness from which the salts of the disclosures are derived,
+    /// e.g. obtained via `raw_rand` of the management canister.  Must not be reused.
+    pub salt_seed: Vec<u8>,
+}
+
+/// The result of `build_sd_jwt_vc()`: the JWT to be signed by the issuer,
+/// and the disclosures to be appended to the resulting JWS.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SdJwtVcPayload {
+    pub credential_jwt: String,
+    pub disclosures: Vec<Disclosure>,
+}
+
+impl SdJwtVcPayload {
+    /// Packages the given issuer-signed JWS (i.e. `credential_jwt` signed via `vc_jwt_to_jws()`)
+    /// together with all the disclosures into an SD-JWT.
+    pub fn into_sd_jwt(self, issuer_jws: String) -> SdJwt {
+        SdJwt {
+            issuer_jws,
+            disclosures: self.disclosures,
+        }
+    }
+}
+
+/// Builds an SD-JWT VC with the given parameters.  The returned `credential_jwt` should be
+/// signed in the same way as regular credentials, i.e. via `vc_signing_input()` and `vc_jwt_to_jws()`.
+pub fn build_sd_jwt_vc(params: SdJwtVcParams) -> Result<SdJwtVcPayload, String> {
+    let mut payload = Map::new();
+    payload.insert("iss".to_string(), Value::String(params.issuer_url));
+    payload.insert("sub".to_string(), Value::String(params.subject_id));
+    payload.insert(
+        "exp".to_string(),
+        Value::from(params.expiration_timestamp_s),
+    );
+    payload.insert(VCT_CLAIM.to_string(), Value::String(params.vct));
+    for (name, value) in params.claims {
+        if RESERVED_CLAIMS.contains(&name.as_str()) {
+            return Err(format!("reserved claim name: {}", name));
+        }
+        payload.insert(name, value);
+    }
+
+    let mut disclosures = Vec::with_capacity(params.disclosable_claims.len());
+    for (name, value) in params.disclosable_claims {
+        if RESERVED_CLAIMS.contains(&name.as_str()) {
+            return Err(format!("reserved claim name: {}", name));
+        }
+        if payload.contains_key(&name) {
+            return Err(format!("claim is both disclosable and not: {}", name));
+        }
+        let salt = derive_salt(&params.salt_seed, &name);
+        disclosures.push(Disclosure::new(salt, name, value));
+    }
+    // Sort the digests, so that their order does not reveal the order of the claims.
+    let mut digests: Vec<String> = disclosures.iter().map(Disclosure::digest).collect();
+    digests.sort();
+    payload.insert(
+        SD_CLAIM.to_string(),
+        Value::Array(digests.into_iter().map(Value::String).collect()),
+    );
+    payload.insert(
+        SD_ALG_CLAIM.to_string(),
+        Value::String(SD_ALG_SHA_256.to_string()),
+    );
+
+    Ok(SdJwtVcPayload {
+        credential_jwt: Value::Object(payload).to_string(),
+        disclosures,
+    })
+}
+
+/// Derives a 128-bit salt for the disclosure of `claim_name` from the given secret seed.
+fn derive_salt(salt_seed: &[u8], claim_name: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update((salt_seed.len() as u64).to_be_bytes());
+    hasher.update(salt_seed);
+    hasher.update(claim_name.as_bytes());
+    BASE64.encode(&hasher.finalize()[..16])
+}
+
+#[derive(Debug)]
+pub enum SdJwtVerificationError {
+    InvalidSdJwt(String),
+    InvalidJws(SignatureVerificationError),
+    InvalidDisclosure(String),
+}
+
+/// Verifies the given SD-JWT VC, and returns the claims of the credential, including
+/// the disclosed claims, but without the SD-JWT specific `_sd` and `_sd_alg` claims.
+/// Specifically, it
+///  - verifies the issuer-signed JWT via `verify_credential_jws_with_canister_id()`,
+///  - checks that every disclosure is referenced by a digest in the issuer-signed JWT,
+///  - checks that no disclosure is repeated or overrides a claim of the issuer-signed JWT.
+///
+/// DOES NOT perform semantic validation of the returned claims.
+pub fn verify_sd_jwt_vc_with_canister_id(
+    sd_jwt: &str,
+    signing_canister_id: &Principal,
+    root_key: &impl RootKeyProvider,
+    current_time_ns: u128,
+) -> Result<Map<String, Value>, SdJwtVerificationError> {
+    let sd_jwt = SdJwt::parse(sd_jwt).map_err(SdJwtVerificationError::InvalidSdJwt)?;
+    verify_credential_jws_with_canister_id(
+        &sd_jwt.issuer_jws,
+        signing_canister_id,
+        root_key,
+        current_time_ns,
+    )
+    .map_err(SdJwtVerificationError::InvalidJws)?;
+    let payload = decode_jws_payload(&sd_jwt.issuer_jws)?;
+    resolve_disclosures(payload, &sd_jwt.disclosures)
+}
+
+fn decode_jws_payload(jws: &str) -> Result<Map<String, Value>, SdJwtVerificationError> {
+    let invalid_jws = |msg: &str| SdJwtVerificationError::InvalidSdJwt(msg.to_string());
+    let payload = jws
+        .split('.')
+        .nth(1)
+        .ok_or(invalid_jws("failed to parse issuer-signed JWT"))?;
+    let bytes = BASE64
+        .decode(payload)
+        .map_err(|_| invalid_jws("failed to decode base64"))?;
+    serde_json::from_slice(&bytes).map_err(|_| invalid_jws("failed to parse payload JSON"))
+}
+
+fn resolve_disclosures(
+    mut payload: Map<String, Value>,
+    disclosures: &[Disclosure],
+) -> Result<Map<String, Value>, SdJwtVerificationError> {
+    let invalid_disclosure = |msg: String| SdJwtVerificationError::InvalidDisclosure(msg);
+    match payload.remove(SD_ALG_CLAIM) {
+        // Per spec, SHA-256 is the default hash algorithm.
+        None => {}
+        Some(Value::String(alg)) if alg == SD_ALG_SHA_256 => {}
+        Some(alg) => {
+            return Err(SdJwtVerificationError::InvalidSdJwt(format!(
+                "unsupported _sd_alg: {}",
+                alg
+            )));
+        }
+    }
+    if !payload.get(VCT_CLAIM).is_some_and(Value::is_string) {
+        return Err(SdJwtVerificationError::InvalidSdJwt(
+            "missing vct claim".to_string(),
+        ));
+    }
+    let digest_values = match payload.remove(SD_CLAIM) {
+        None => vec![],
+        Some(Value::Array(digests)) => digests,
+        Some(_) => {
+            return Err(SdJwtVerificationError::InvalidSdJwt(
+                "malformed _sd claim".to_string(),
+            ));
+        }
+    };
+    let mut digests = HashSet::with_capacity(digest_values.len());
+    for digest in digest_values {
+        let Value::String(digest) = digest else {
+            return Err(SdJwtVerificationError::InvalidSdJwt(
+                "malformed _sd claim".to_string(),
+            ));
+        };
+        if !digests.insert(digest) {
+            return Err(SdJwtVerificationError::InvalidSdJwt(
+                "duplicate digest in _sd claim".to_string(),
+            ));
+        }
+    }
+
+    let mut seen_digests = HashSet::new();
+    for disclosure in disclosures {
+        let digest = disclosure.digest();
+        if !digests.contains(&digest) {
+            return Err(invalid_disclosure(format!(
+                "disclosure of claim {} is not referenced by the credential",
+                disclosure.claim_name
+            )));
+        }
+        if !seen_digests.insert(digest) {
+            return Err(invalid_disclosure(format!(
+                "repeated disclosure of claim {}",
+                disclosure.claim_name
+            )));
+        }
+        if payload.contains_key(&disclosure.claim_name) {
+            return Err(invalid_disclosure(format!(
+                "disclosure overrides claim {}",
+                disclosure.claim_name
+            )));
+        }
+        payload.insert(
+            disclosure.claim_name.clone(),
+            disclosure.claim_value.clone(),
+        );
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+
+    fn test_params() -> SdJwtVcParams {
+        let mut claims = Map::new();
+        claims.insert("country".to_string(), json!("CH"));
+        let mut disclosable_claims = Map::new();
+        disclosable_claims.insert("birthYear".to_string(), json!(1990));
+        disclosable_claims.insert("givenName".to_string(), json!("Alice"));
+        disclosable_claims.insert("familyName".to_string(), json!("Smith"));
+        SdJwtVcParams {
+            vct: "KycRecord".to_string(),
+            subject_id: "did:icp:aaaaa-aa".to_string(),
+            issuer_url: "https://kyc-issuer.vc".to_string(),
+            expiration_timestamp_s: 1_900_000_000,
+            claims,
+            disclosable_claims,
+            salt_seed: vec![42; 32],
+        }
+    }
+
+    fn payload_of(credential_jwt: &str) -> Map<String, Value> {
+        serde_json::from_str(credential_jwt).expect("failed parsing credential JWT")
+    }
+
+    #[test]
+    fn should_compute_disclosure_digest() {
+        // Example from https://datatracker.ietf.org/doc/html/draft-ietf-oauth-selective-disclosure-jwt
+        let disclosure = Disclosure::parse(
+            "WyJfMjZiYzRMVC1hYzZxMktJNmNCVzVlcyIsICJmYW1pbHlfbmFtZSIsICJNw7ZiaXVzIl0",
+        )
+        .expect("failed parsing disclosure");
+        assert_eq!(disclosure.salt, "_26bc4LT-ac6q2KI6cBW5es");
+        assert_eq!(disclosure.claim_name, "family_name");
+        assert_eq!(disclosure.claim_value, json!("Möbius"));
+        assert_eq!(
+            disclosure.digest(),
+            "X9yH0Ajrdm1Oij4tWso9UzzKJvPoDxwmuEcO3XAdRC0"
+        );
+    }
+
+    #[test]
+    fn should_fail_parsing_malformed_disclosures() {
+        for malformed in [
+            json!(["salt", "name"]),
+            json!(["salt", "name", "value", "extra"]),
+            json!([42, "name", "value"]),
+            json!({"salt": "name"}),
+            json!(["salt", "_sd", []]),
+            json!(["salt", "iss", "https://evil.com"]),
+        ] {
+            let encoded = BASE64.encode(malformed.to_string());
+            assert!(
+                Disclosure::parse(&encoded).is_err(),
+                "parsed malformed disclosure: {}",
+                malformed
+            );
+        }
+    }
+
+    #[test]
+    fn should_build_sd_jwt_vc_with_digests_of_disclosures() {
+        let sd_payload = build_sd_jwt_vc(test_params()).expect("failed building SD-JWT VC");
+        let payload = payload_of(&sd_payload.credential_jwt);
+        assert_eq!(payload["vct"], json!("KycRecord"));
+        assert_eq!(payload["country"], json!("CH"));
+        assert_eq!(payload["_sd_alg"], json!(SD_ALG_SHA_256));
+        assert!(payload.get("birthYear").is_none());
+        assert_eq!(sd_payload.disclosures.len(), 3);
+        let sd = payload["_sd"].as_array().expect("missing _sd claim");
+        for disclosure in &sd_payload.disclosures {
+            assert!(sd.contains(&Value::String(disclosure.digest())));
+        }
+    }
+
+    #[test]
+    fn should_fail_building_sd_jwt_vc_with_reserved_claims() {
+        let mut params = test_params();
+        params
+            .disclosable_claims
+            .insert("iss".to_string(), json!("https://evil.com"));
+        assert!(build_sd_jwt_vc(params).is_err());
+
+        let mut params = test_params();
+        params.claims.insert("_sd".to_string(), json!([]));
+        assert!(build_sd_jwt_vc(params).is_err());
+    }
+
+    #[test]
+    fn should_serialize_and_select_disclosures() {
+        let sd_payload = build_sd_jwt_vc(test_params()).expect("failed building SD-JWT VC");
+        let sd_jwt = sd_payload.into_sd_jwt("header.payload.signature".to_string());
+        let compact = sd_jwt.to_compact();
+        assert!(compact.ends_with(SD_JWT_SEPARATOR));
+        assert_eq!(SdJwt::parse(&compact).expect("failed parsing"), sd_jwt);
+
+        let selected = sd_jwt.select(&["birthYear"]);
+        assert_eq!(selected.disclosures.len(), 1);
+        assert_eq!(selected.disclosures[0].claim_name, "birthYear");
+        let reparsed = SdJwt::parse(&selected.to_compact()).expect("failed parsing");
+        assert_eq!(reparsed, selected);
+    }
+
+    #[test]
+    fn should_fail_parsing_sd_jwt_with_key_binding() {
+        let result = SdJwt::parse("header.payload.signature~kb.jwt.sig");
+        assert_matches!(result, Err(e) if e.contains("key binding"));
+        assert!(SdJwt::parse("header.payload.signature").is_err());
+    }
+
+    #[test]
+    fn should_resolve_selected_disclosures() {
+        let sd_payload = build_sd_jwt_vc(test_params()).expect("failed building SD-JWT VC");
+        let payload = payload_of(&sd_payload.credential_jwt);
+        let selected = sd_payload
+            .into_sd_jwt("header.payload.signature".to_string())
+            .select(&["birthYear"]);
+
+        let claims = resolve_disclosures(payload, &selected.disclosures).expect("failed resolving");
+        assert_eq!(claims["birthYear"], json!(1990));
+        assert_eq!(claims["country"], json!("CH"));
+        assert!(claims.get("givenName").is_none());
+        assert!(claims.get("_sd").is_none());
+        assert!(claims.get("_sd_alg").is_none());
+    }
+
+    #[test]
+    fn should_fail_resolving_unreferenced_disclosure() {
+        let sd_payload = build_sd_jwt_vc(test_params()).expect("failed building SD-JWT VC");
+        let payload = payload_of(&sd_payload.credential_jwt);
+        let forged = Disclosure::new(
+            "some salt".to_string(),
+            "birthYear".to_string(),
+            json!(1970),
+        );
+        let result = resolve_disclosures(payload, &[forged]);
+        assert_matches!(result, Err(SdJwtVerificationError::InvalidDisclosure(e)) if e.contains("not referenced"));
+    }
+
+    #[test]
+    fn should_fail_resolving_repeated_disclosure() {
+        let sd_payload = build_sd_jwt_vc(test_params()).expect("failed building SD-JWT VC");
+        let payload = payload_of(&sd_payload.credential_jwt);
+        let disclosure = sd_payload.disclosures[0].clone();
+        let result = resolve_disclosures(payload, &[disclosure.clone(), disclosure]);
+        assert_matches!(result, Err(SdJwtVerificationError::InvalidDisclosure(e)) if e.contains("repeated"));
+    }
+
+    #[test]
+    fn should_fail_resolving_with_unsupported_sd_alg() {
+        let sd_payload = build_sd_jwt_vc(test_params()).expect("failed building SD-JWT VC");
+        let mut payload = payload_of(&sd_payload.credential_jwt);
+        payload.insert("_sd_alg".to_string(), json!("sha-1"));
+        let result = resolve_disclosures(payload, &sd_payload.disclosures);
+        assert_matches!(result, Err(SdJwtVerificationError::InvalidSdJwt(e)) if e.contains("unsupported _sd_alg"));
+    }
+
+    #[test]
+    fn should_fail_resolving_with_duplicate_digests() {
+        let sd_payload = build_sd_jwt_vc(test_params()).expect("failed building SD-JWT VC");
+        let mut payload = payload_of(&sd_payload.credential_jwt);
+        let digest = Value::String(sd_payload.disclosures[0].digest());
+        payload.insert("_sd".to_string(), json!([digest.clone(), digest]));
+        let result = resolve_disclosures(payload, &sd_payload.disclosures[..1]);
+        assert_matches!(result, Err(SdJwtVerificationError::InvalidSdJwt(e)) if e.contains("duplicate digest"));
+    }
+
+    #[test]
+    fn should_not_derive_colliding_salts_for_long_seeds() {
+        // With a truncated length prefix, a 256-byte seed would be indistinguishable from
+        // an empty seed whose claim name starts with the same 256 bytes.
+        let long_seed = vec![b'a'; 256];
+        let shifted_claim_name = format!("{}birthYear", "a".repeat(256));
+        assert_ne!(
+            derive_salt(&long_seed, "birthYear"),
+            derive_salt(&[], &shifted_claim_name)
+        );
+    }
+
+    #[cfg(feature = "test-utils")]
+    mod signed {
+        use super::*;
+        use crate::test_utils::{TEST_ISSUER_CANISTER_ID, TestCanisterSigner, TestIcRootKey};
+
+        const NOW_NS: u128 = 1_700_000_000_000_000_000;
+
+        fn signed_sd_jwt(root_key: &TestIcRootKey) -> SdJwt {
+            let signer = TestCanisterSigner::new(
+                Principal::from_text(TEST_ISSUER_CANISTER_ID).unwrap(),
+                b"sd-jwt-issuer",
+            );
+            let sd_payload = build_sd_jwt_vc(test_params()).expect("failed building SD-JWT VC");
+            let issuer_jws =
+                signer.sign_credential_jwt(root_key, &sd_payload.credential_jwt, NOW_NS);
+            sd_payload.into_sd_jwt(issuer_jws)
+        }
+
+        #[test]
+        fn should_verify_signed_sd_jwt_vc() {
+            let root_key = TestIcRootKey::generate();
+            let sd_jwt = signed_sd_jwt(&root_key).select(&["birthYear", "givenName"]);
+
+            let claims = verify_sd_jwt_vc_with_canister_id(
+                &sd_jwt.to_compact(),
+                &Principal::from_text(TEST_ISSUER_CANISTER_ID).unwrap(),
+                &root_key.ic_root_key(),
+                NOW_NS,
+            )
+            .expect("failed verifying SD-JWT VC");
+
+            assert_eq!(claims["birthYear"], json!(1990));
+            assert_eq!(claims["givenName"], json!("Alice"));
+            assert_eq!(claims["country"], json!("CH"));
+            assert!(claims.get("familyName").is_none());
+        }
+
+        #[test]
+        fn should_reject_signed_sd_jwt_vc_with_tampered_disclosure() {
+            let root_key = TestIcRootKey::generate();
+            let mut sd_jwt = signed_sd_jwt(&root_key).select(&["birthYear"]);
+            let original = sd_jwt.disclosures[0].clone();
+            sd_jwt.disclosures[0] =
+                Disclosure::new(original.salt, original.claim_name, json!(1970));
+
+            let result = verify_sd_jwt_vc_with_canister_id(
+                &sd_jwt.to_compact(),
+                &Principal::from_text(TEST_ISSUER_CANISTER_ID).unwrap(),
+                &root_key.ic_root_key(),
+                NOW_NS,
+            );
+
+            assert_matches!(result, Err(SdJwtVerificationError::InvalidDisclosure(e)) if e.contains("not referenced"));
+        }
+
+        #[test]
+        fn should_reject_signed_sd_jwt_vc_of_other_canister() {
+            let root_key = TestIcRootKey::generate();
+            let sd_jwt = signed_sd_jwt(&root_key);
+
+            let result = verify_sd_jwt_vc_with_canister_id(
+                &sd_jwt.to_compact(),
+                &Principal::anonymous(),
+                &root_key.ic_root_key(),
+                NOW_NS,
+            );
+
+            assert_matches!(result, Err(SdJwtVerificationError::InvalidJws(_)));
+        }
+    }
+}
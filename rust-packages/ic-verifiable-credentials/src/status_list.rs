@@ -0,0 +1,647 @@
+//! Revocation of credentials via status lists, following the
+//! [Bitstring Status List](https://www.w3.org/TR/vc-bitstring-status-list/) data model.
+//!
+//! An issuer assigns each revocable credential an index in its `StatusList`, and references
+//! the list in the `credentialStatus`-claim of the credential (cf. `CredentialStatus` and
+//! `build_credential_jwt_with_status()`).  The issuer certifies the (compressed) list next to
+//! its canister signatures, cf. `certified_data()`, and serves it via a query together with a
+//! data certificate, cf. `CertifiedStatusList`.  A verifier checks the certified list with
+//! `verify_status_list()` and then rejects revoked credentials via `check_credential_status()`.
+use crate::certificate::{CertificateError, verify_certified_data};
+use crate::environment::RootKeyProvider;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use candid::{CandidType, Deserialize, Principal};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use ic_canister_sig_creation::signature_map::LABEL_SIG;
+use ic_certification::{
+    Hash, HashTree, LookupResult, fork, fork_hash, labeled, labeled_hash, leaf, pruned,
+};
+use serde::Serialize;
+use serde_bytes::ByteBuf;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::time::Duration;
+
+/// Type of the `credentialStatus`-entry of credentials referencing a status list.
+pub const BITSTRING_STATUS_LIST_ENTRY: &str = "BitstringStatusListEntry";
+/// The purpose of the status lists, which is the only purpose supported.
+pub const REVOCATION_PURPOSE: &str = "revocation";
+/// Minimal recommended size (in entries) of a status list, which provides group privacy
+/// for the holders, as the list reveals no information about individual credentials.
+pub const MIN_STATUS_LIST_SIZE: usize = 131_072;
+/// Maximal size (in bytes) of a decompressed status list accepted by `StatusList::decode()`.
+pub const MAX_STATUS_LIST_BYTES: usize = 16 * 1024 * 1024;
+/// Default maximal age of the certificate of a status list accepted by verifiers.
+pub const DEFAULT_MAX_STATUS_LIST_AGE: Duration = Duration::from_secs(5 * 60);
+/// Label of the status list in the certified data of the issuer.
+pub const LABEL_STATUS_LIST: &[u8] = b"status_list";
+// Prefix of base64url-encoded values in multibase-encoding.
+const MULTIBASE_BASE64URL_PREFIX: char = 'u';
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StatusListError {
+    /// The index is not within the status list.
+    IndexOutOfRange { index: usize, size: usize },
+    /// The status list cannot grow beyond `MAX_STATUS_LIST_BYTES`.
+    CapacityExceeded { size: usize },
+    /// The encoded status list is malformed or too large.
+    InvalidEncoding(String),
+    /// The certificate of the status list is invalid, or does not certify the status list.
+    InvalidCertificate(String),
+    /// The certificate of the status list is older than accepted by the verifier.
+    StaleStatusList { certified_at_ns: u128 },
+    /// The `credentialStatus`-claim of the credential is malformed or not supported.
+    InvalidCredentialStatus(String),
+    /// The credential references a status list other than the one it is checked against.
+    OtherStatusList { status_list_url: String },
+    /// The credential has been revoked by the issuer.
+    Revoked { index: usize },
+}
+
+/// A list of revocation flags, one bit per credential, where the bit of index 0 is the
+/// most significant bit of the first byte.
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct StatusList {
+    #[serde(with = "serde_bytes")]
+    bits: Vec<u8>,
+}
+
+impl StatusList {
+    /// Creates a list of `size` entries (rounded up to a multiple of 8), none of them revoked.
+    pub fn new(size: usize) -> Self {
+        Self {
+            bits: vec![0; size.div_ceil(8)],
+        }
+    }
+
+    /// Returns the number of entries of the list.
+    pub fn len(&self) -> usize {
+        self.bits.len() * 8
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.is_empty()
+    }
+
+    /// Appends `additional` entries (rounded up to a multiple of 8), none of them revoked.
+    /// The existing entries keep their indices.  Fails if the list would exceed
+    /// `MAX_STATUS_LIST_BYTES`, and thus be rejected by `decode()`.
+    pub fn grow(&mut self, additional: usize) -> Result<(), StatusListError> {
+        let new_len = self.bits.len().saturating_add(additional.div_ceil(8));
+        if new_len > MAX_STATUS_LIST_BYTES {
+            return Err(StatusListError::CapacityExceeded { size: self.len() });
+        }
+        self.bits.resize(new_len, 0);
+        Ok(())
+    }
+
+    pub fn is_revoked(&self, index: usize) -> Result<bool, StatusListError> {
+        let (byte, mask) = self.position(index)?;
+        Ok(self.bits[byte] & mask != 0)
+    }
+
+    /// Sets the revocation flag of the entry `index`.
+    pub fn set_revoked(&mut self, index: usize, revoked: bool) -> Result<(), StatusListError> {
+        let (byte, mask) = self.position(index)?;
+        if revoked {
+            self.bits[byte] |= mask;
+        } else {
+            self.bits[byte] &= !mask;
+        }
+        Ok(())
+    }
+
+    /// Returns the list GZIP-compressed and multibase-encoded with base64url (without padding),
+    /// as the `encodedList` of a Bitstring Status List.  The encoding is deterministic.
+    pub fn encode(&self) -> String {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder
+            .write_all(&self.bits)
+            .expect("internal: failed compressing status list");
+        let compressed = encoder
+            .finish()
+            .expect("internal: failed compressing status list");
+        format!(
+            "{}{}",
+            MULTIBASE_BASE64URL_PREFIX,
+            BASE64.encode(compressed)
+        )
+    }
+
+    /// Decodes a list encoded by `encode()`.  Lists which decompress to more than
+    /// `MAX_STATUS_LIST_BYTES` are rejected.
+    pub fn decode(encoded_list: &str) -> Result<Self, StatusListError> {
+        let compressed = encoded_list
+            .strip_prefix(MULTIBASE_BASE64URL_PREFIX)
+            .ok_or(invalid_encoding("missing multibase prefix"))?;
+        let compressed = BASE64
+            .decode(compressed)
+            .map_err(|_| invalid_encoding("malformed base64url"))?;
+        let mut bits = vec![];
+        GzDecoder::new(compressed.as_slice())
+            .take(MAX_STATUS_LIST_BYTES as u64 + 1)
+            .read_to_end(&mut bits)
+            .map_err(|_| invalid_encoding("malformed GZIP"))?;
+        if bits.len() > MAX_STATUS_LIST_BYTES {
+            return Err(invalid_encoding("status list too large"));
+        }
+        Ok(Self { bits })
+    }
+
+    fn position(&self, index: usize) -> Result<(usize, u8), StatusListError> {
+        if index >= self.len() {
+            return Err(StatusListError::IndexOutOfRange {
+                index,
+                size: self.len(),
+            });
+        }
+        Ok((index / 8, 0x80 >> (index % 8)))
+    }
+}
+
+/// The reference of a credential to its entry in a status list.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CredentialStatus {
+    /// URL identifying the status list of the issuer.
+    pub status_list_url: String,
+    /// Index of the credential in the status list.
+    pub index: usize,
+}
+
+impl CredentialStatus {
+    /// Returns the `credentialStatus`-claim referencing the entry of the credential.
+    pub fn to_json(&self) -> Value {
+        serde_json::json!({
+            "id": format!("{}#{}", self.status_list_url, self.index),
+            "type": BITSTRING_STATUS_LIST_ENTRY,
+            "statusPurpose": REVOCATION_PURPOSE,
+            "statusListIndex": self.index.to_string(),
+            "statusListCredential": self.status_list_url,
+        })
+    }
+
+    /// Parses the `credentialStatus`-claim of the given `vc`-claims.  Returns `None` if the
+    /// credential does not reference a status list, i.e. if it cannot be revoked.
+    pub fn from_vc_claims(vc_claims: &Map<String, Value>) -> Result<Option<Self>, StatusListError> {
+        let Some(status) = vc_claims.get("credentialStatus") else {
+            return Ok(None);
+        };
+        let status = status
+            .as_object()
+            .ok_or(invalid_status("malformed credentialStatus-claim"))?;
+        let get_str = |name: &'static str| {
+            status
+                .get(name)
+                .and_then(Value::as_str)
+                .ok_or_else(|| invalid_status(&format!("missing {}", name)))
+        };
+        if get_str("type")? != BITSTRING_STATUS_LIST_ENTRY {
+            return Err(invalid_status("unsupported type"));
+        }
+        if get_str("statusPurpose")? != REVOCATION_PURPOSE {
+            return Err(invalid_status("unsupported statusPurpose"));
+        }
+        let index = get_str("statusListIndex")?
+            .parse()
+            .map_err(|_| invalid_status("malformed statusListIndex"))?;
+        Ok(Some(Self {
+            status_list_url: get_str("statusListCredential")?.to_string(),
+            index,
+        }))
+    }
+}
+
+/// A status list together with the certificate and witness certifying it as part of the
+/// certified data of the issuer, as returned by the issuer to verifiers.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, Eq, PartialEq)]
+pub struct CertifiedStatusList {
+    /// The status list, as returned by `StatusList::encode()`.
+    pub encoded_list: String,
+    /// The data certificate obtained via `ic_cdk::api::data_certificate()`.
+    pub certificate: ByteBuf,
+    /// CBOR-encoded witness, whose digest is the certified data of the issuer.
+    pub witness: ByteBuf,
+}
+
+impl CertifiedStatusList {
+    /// Assembles the certified list, where `sigs_root_hash` is the root hash of the
+    /// `SignatureMap` of the issuer, cf. `certified_data()`.
+    pub fn new(encoded_list: String, certificate: Vec<u8>, sigs_root_hash: &Hash) -> Self {
+        let witness: HashTree = fork(
+            status_list_tree(&encoded_list),
+            pruned(labeled_hash(LABEL_SIG, sigs_root_hash)),
+        );
+        let mut serializer = serde_cbor::Serializer::new(Vec::new());
+        serializer
+            .self_describe()
+            .expect("internal: failed writing CBOR tag");
+        witness
+            .serialize(&mut serializer)
+            .expect("internal: failed serializing witness");
+        Self {
+            encoded_list,
+            certificate: ByteBuf::from(certificate),
+            witness: ByteBuf::from(serializer.into_inner()),
+        }
+    }
+}
+
+/// Returns the tree certifying the given encoded status list.  Issuers pass its digest as the
+/// root hash of other certified data to `SignatureMap::get_signature_as_cbor()`.
+pub fn status_list_tree(encoded_list: &str) -> HashTree {
+    labeled(
+        LABEL_STATUS_LIST,
+        leaf(Sha256::digest(encoded_list).to_vec()),
+    )
+}
+
+/// Returns the certified data of an issuer which certifies both the given status list and
+/// the canister signatures of its `SignatureMap` (with root hash `sigs_root_hash`).
+pub fn certified_data(encoded_list: &str, sigs_root_hash: &Hash) -> Hash {
+    fork_hash(
+        &status_list_tree(encoded_list).digest(),
+        &labeled_hash(LABEL_SIG, sigs_root_hash),
+    )
+}
+
+/// Verifies that the given status list is certified by the issuer `issuer_canister_id`,
+/// with a certificate valid w.r.t. `root_key` whose time differs by at most `max_age` from
+/// `current_time_ns`, and returns the decoded list.
+pub fn verify_status_list(
+    certified_list: &CertifiedStatusList,
+    issuer_canister_id: &Principal,
+    root_key: &impl RootKeyProvider,
+    current_time_ns: u128,
+    max_age: Duration,
+) -> Result<StatusList, StatusListError> {
+    let certified_data = verify_certified_data(
+        &certified_list.certificate,
+        issuer_canister_id,
+        root_key.root_pk_raw(),
+        current_time_ns,
+        max_age,
+    )
+    .map_err(|e| match e {
+        CertificateError::Invalid(message) => StatusListError::InvalidCertificate(message),
+        CertificateError::Stale { certified_at_ns } => {
+            StatusListError::StaleStatusList { certified_at_ns }
+        }
+    })?;
+    let witness: HashTree = serde_cbor::from_slice(&certified_list.witness)
+        .map_err(|e| invalid_certificate(&format!("malformed witness: {}", e)))?;
+    if witness.digest() != certified_data {
+        return Err(invalid_certificate(
+            "witness does not match the certified data",
+        ));
+    }
+    let list_hash: Hash = Sha256::digest(&certified_list.encoded_list).into();
+    match witness.lookup_path([LABEL_STATUS_LIST]) {
+        LookupResult::Found(value) if value == list_hash.as_slice() => {}
+        _ => return Err(invalid_certificate("status list is not certified")),
+    }
+    StatusList::decode(&certified_list.encoded_list)
+}
+
+/// Checks that the credential with the given `vc`-claims is not revoked according to the
+/// given (verified) status list, which was obtained from `status_list_url`.  Credentials
+/// referencing another status list are rejected, credentials without `credentialStatus`-claim
+/// are accepted.
+pub fn check_credential_status(
+    vc_claims: &Map<String, Value>,
+    status_list_url: &str,
+    status_list: &StatusList,
+) -> Result<(), StatusListError> {
+    let Some(status) = CredentialStatus::from_vc_claims(vc_claims)? else {
+        return Ok(());
+    };
+    if status.status_list_url != status_list_url {
+        return Err(StatusListError::OtherStatusList {
+            status_list_url: status.status_list_url,
+        });
+    }
+    if status_list.is_revoked(status.index)? {
+        return Err(StatusListError::Revoked {
+            index: status.index,
+        });
+    }
+    Ok(())
+}
+
+fn invalid_encoding(message: &str) -> StatusListError {
+    StatusListError::InvalidEncoding(message.to_string())
+}
+
+fn invalid_certificate(message: &str) -> StatusListError {
+    StatusListError::InvalidCertificate(message.to_string())
+}
+
+fn invalid_status(message: &str) -> StatusListError {
+    StatusListError::InvalidCredentialStatus(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+
+    const STATUS_LIST_URL: &str = "https://issuer.test/status-list";
+
+    fn vc_claims_with_status(status: Value) -> Map<String, Value> {
+        let mut claims = Map::new();
+        claims.insert("credentialStatus".to_string(), status);
+        claims
+    }
+
+    #[test]
+    fn should_set_and_clear_revocation_flags() {
+        let mut list = StatusList::new(MIN_STATUS_LIST_SIZE);
+        assert_eq!(list.len(), MIN_STATUS_LIST_SIZE);
+        list.set_revoked(0, true).unwrap();
+        list.set_revoked(42, true).unwrap();
+        assert_eq!(list.is_revoked(0), Ok(true));
+        assert_eq!(list.is_revoked(1), Ok(false));
+        assert_eq!(list.is_revoked(42), Ok(true));
+        assert_eq!(list.bits[0], 0x80);
+
+        list.set_revoked(42, false).unwrap();
+        assert_eq!(list.is_revoked(42), Ok(false));
+    }
+
+    #[test]
+    fn should_reject_index_out_of_range() {
+        let mut list = StatusList::new(10);
+        assert_eq!(list.len(), 16);
+        assert_eq!(
+            list.set_revoked(16, true),
+            Err(StatusListError::IndexOutOfRange {
+                index: 16,
+                size: 16
+            })
+        );
+        assert_matches!(
+            list.is_revoked(100),
+            Err(StatusListError::IndexOutOfRange { .. })
+        );
+    }
+
+    #[test]
+    fn should_grow_status_list() {
+        let mut list = StatusList::new(16);
+        list.set_revoked(3, true).unwrap();
+
+        list.grow(10).unwrap();
+
+        assert_eq!(list.len(), 32);
+        assert_eq!(list.is_revoked(3), Ok(true));
+        for index in 16..32 {
+            assert_eq!(list.is_revoked(index), Ok(false));
+        }
+        assert_eq!(
+            list.grow(MAX_STATUS_LIST_BYTES * 8),
+            Err(StatusListError::CapacityExceeded { size: 32 })
+        );
+        assert_eq!(list.len(), 32);
+    }
+
+    #[test]
+    fn should_encode_and_decode_status_list() {
+        let mut list = StatusList::new(MIN_STATUS_LIST_SIZE);
+        list.set_revoked(7, true).unwrap();
+        list.set_revoked(MIN_STATUS_LIST_SIZE - 1, true).unwrap();
+
+        let encoded = list.encode();
+        assert!(encoded.starts_with('u'));
+        assert_eq!(encoded, list.encode());
+        // A sparse list compresses well.
+        assert!(encoded.len() < 1_000);
+        assert_eq!(StatusList::decode(&encoded), Ok(list));
+    }
+
+    #[test]
+    fn should_reject_malformed_encoding() {
+        for encoded in ["", "H4sI", "u!!!", "uAAAA"] {
+            assert_matches!(
+                StatusList::decode(encoded),
+                Err(StatusListError::InvalidEncoding(_))
+            );
+        }
+    }
+
+    #[test]
+    fn should_reject_too_large_status_list() {
+        let encoded = StatusList::new((MAX_STATUS_LIST_BYTES + 1) * 8).encode();
+        assert_eq!(
+            StatusList::decode(&encoded),
+            Err(invalid_encoding("status list too large"))
+        );
+    }
+
+    #[test]
+    fn should_convert_credential_status_to_and_from_json() {
+        let status = CredentialStatus {
+            status_list_url: STATUS_LIST_URL.to_string(),
+            index: 42,
+        };
+        let json = status.to_json();
+        assert_eq!(json["id"], format!("{}#42", STATUS_LIST_URL));
+        assert_eq!(json["statusListIndex"], "42");
+
+        let claims = vc_claims_with_status(json);
+        assert_eq!(CredentialStatus::from_vc_claims(&claims), Ok(Some(status)));
+        assert_eq!(CredentialStatus::from_vc_claims(&Map::new()), Ok(None));
+    }
+
+    #[test]
+    fn should_reject_unsupported_credential_status() {
+        let mut json = CredentialStatus {
+            status_list_url: STATUS_LIST_URL.to_string(),
+            index: 1,
+        }
+        .to_json();
+        json["statusPurpose"] = Value::from("suspension");
+        assert_matches!(
+            CredentialStatus::from_vc_claims(&vc_claims_with_status(json)),
+            Err(StatusListError::InvalidCredentialStatus(_))
+        );
+        assert_matches!(
+            CredentialStatus::from_vc_claims(&vc_claims_with_status(Value::from(1))),
+            Err(StatusListError::InvalidCredentialStatus(_))
+        );
+    }
+
+    #[test]
+    fn should_reject_revoked_credential() {
+        let mut list = StatusList::new(16);
+        list.set_revoked(3, true).unwrap();
+        let claims_for = |index| {
+            vc_claims_with_status(
+                CredentialStatus {
+                    status_list_url: STATUS_LIST_URL.to_string(),
+                    index,
+                }
+                .to_json(),
+            )
+        };
+
+        assert_eq!(
+            check_credential_status(&claims_for(2), STATUS_LIST_URL, &list),
+            Ok(())
+        );
+        assert_eq!(
+            check_credential_status(&claims_for(3), STATUS_LIST_URL, &list),
+            Err(StatusListError::Revoked { index: 3 })
+        );
+        assert_eq!(
+            check_credential_status(&Map::new(), STATUS_LIST_URL, &list),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn should_reject_credential_of_other_status_list() {
+        let list = StatusList::new(16);
+        let claims = vc_claims_with_status(
+            CredentialStatus {
+                status_list_url: "https://other-issuer.test/status-list".to_string(),
+                index: 2,
+            }
+            .to_json(),
+        );
+
+        assert_eq!(
+            check_credential_status(&claims, STATUS_LIST_URL, &list),
+            Err(StatusListError::OtherStatusList {
+                status_list_url: "https://other-issuer.test/status-list".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn should_certify_status_list_next_to_signatures() {
+        let encoded = StatusList::new(16).encode();
+        let sigs_root_hash = [5; 32];
+        let certified = CertifiedStatusList::new(encoded.clone(), vec![], &sigs_root_hash);
+
+        let witness: HashTree = serde_cbor::from_slice(&certified.witness).unwrap();
+        assert_eq!(witness.digest(), certified_data(&encoded, &sigs_root_hash));
+        assert_matches!(
+            witness.lookup_path([LABEL_STATUS_LIST]),
+            LookupResult::Found(hash) if hash == Sha256::digest(&encoded).as_slice()
+        );
+    }
+
+    #[cfg(feature = "test-utils")]
+    mod certified {
+        use super::*;
+        use crate::test_utils::{TEST_ISSUER_CANISTER_ID, TestIcRootKey};
+
+        const NOW_NS: u128 = 1_700_000_000_000_000_000;
+
+        fn issuer() -> Principal {
+            Principal::from_text(TEST_ISSUER_CANISTER_ID).unwrap()
+        }
+
+        fn certified_list(root_key: &TestIcRootKey, list: &StatusList) -> CertifiedStatusList {
+            let encoded = list.encode();
+            let sigs_root_hash = [1; 32];
+            let certificate =
+                root_key.certify(issuer(), &certified_data(&encoded, &sigs_root_hash), NOW_NS);
+            CertifiedStatusList::new(encoded, certificate, &sigs_root_hash)
+        }
+
+        #[test]
+        fn should_verify_certified_status_list() {
+            let root_key = TestIcRootKey::generate();
+            let mut list = StatusList::new(MIN_STATUS_LIST_SIZE);
+            list.set_revoked(11, true).unwrap();
+
+            let verified = verify_status_list(
+                &certified_list(&root_key, &list),
+                &issuer(),
+                &root_key.ic_root_key(),
+                NOW_NS,
+                DEFAULT_MAX_STATUS_LIST_AGE,
+            );
+
+            assert_eq!(verified, Ok(list));
+        }
+
+        #[test]
+        fn should_reject_status_list_certified_by_other_root_key() {
+            let certified = certified_list(&TestIcRootKey::generate(), &StatusList::new(16));
+
+            let result = verify_status_list(
+                &certified,
+                &issuer(),
+                &TestIcRootKey::generate().ic_root_key(),
+                NOW_NS,
+                DEFAULT_MAX_STATUS_LIST_AGE,
+            );
+
+            assert_matches!(result, Err(StatusListError::InvalidCertificate(_)));
+        }
+
+        #[test]
+        fn should_reject_status_list_of_other_canister() {
+            let root_key = TestIcRootKey::generate();
+            let certified = certified_list(&root_key, &StatusList::new(16));
+
+            let result = verify_status_list(
+                &certified,
+                &Principal::from_slice(&[1; 10]),
+                &root_key.ic_root_key(),
+                NOW_NS,
+                DEFAULT_MAX_STATUS_LIST_AGE,
+            );
+
+            assert_matches!(result, Err(StatusListError::InvalidCertificate(_)));
+        }
+
+        #[test]
+        fn should_reject_tampered_status_list() {
+            let root_key = TestIcRootKey::generate();
+            let mut certified = certified_list(&root_key, &StatusList::new(16));
+            let mut tampered = StatusList::new(16);
+            tampered.set_revoked(0, true).unwrap();
+            certified.encoded_list = tampered.encode();
+
+            let result = verify_status_list(
+                &certified,
+                &issuer(),
+                &root_key.ic_root_key(),
+                NOW_NS,
+                DEFAULT_MAX_STATUS_LIST_AGE,
+            );
+
+            assert_eq!(
+                result,
+                Err(invalid_certificate("status list is not certified"))
+            );
+        }
+
+        #[test]
+        fn should_reject_stale_status_list() {
+            let root_key = TestIcRootKey::generate();
+            let certified = certified_list(&root_key, &StatusList::new(16));
+
+            let result = verify_status_list(
+                &certified,
+                &issuer(),
+                &root_key.ic_root_key(),
+                NOW_NS + DEFAULT_MAX_STATUS_LIST_AGE.as_nanos() + 1,
+                DEFAULT_MAX_STATUS_LIST_AGE,
+            );
+
+            assert_eq!(
+                result,
+                Err(StatusListError::StaleStatusList {
+                    certified_at_ns: NOW_NS
+                })
+            );
+        }
+    }
+}
@@ -0,0 +1,757 @@
+//! Utilities for testing issuers and relying parties without a replica (feature `test-utils`).
+//!
+//! Provides a throwaway IC root key (`TestIcRootKey`), which certifies the certified data
+//! of arbitrary canisters, and canister signers (`TestCanisterSigner`), which create canister
+//! signatures verifiable against that root key.  On top of those, `TestVcFlow` mints
+//! id_alias credentials, requested credentials and presentations, as created by II and an
+//! issuer during a VC flow, that are valid at any chosen time.  `TestThresholdSigner`
+//! mocks the threshold signing APIs of the management canister (cf. `threshold_signing`).
+use crate::did_resolution::did_key_url;
+use crate::environment::{IcRootKey, VcEnvironment};
+use crate::holder_binding::{
+    Delegation, DelegationChain, ED25519_DER_PREFIX, HolderBindingOptions, SECP256K1_DER_PREFIX,
+    SignedDelegation, build_ii_holder_bound_presentation_jwt, vp_jwt_to_jws, vp_signing_input,
+};
+use crate::issuer_api::{ArgumentValue, CredentialSpec};
+use crate::openid4vci::PROOF_JWT_TYPE;
+use crate::threshold_signing::{SigningBackend, ThresholdAlgorithm, ThresholdPublicKey};
+use crate::{
+    CredentialParams, II_CREDENTIAL_URL_PREFIX, II_ISSUER_URL, VC_SIGNING_INPUT_DOMAIN,
+    VcFlowSigners, build_credential_jwt, build_ii_verifiable_presentation_jwt, did_for_principal,
+    hash_with_domain, vc_jwt_to_jws, vc_signing_input,
+};
+use candid::Principal;
+use ic_canister_sig_creation::signature_map::LABEL_SIG;
+use ic_canister_sig_creation::{
+    CanisterSigPublicKey, DELEGATION_SIG_DOMAIN, IC_ROOT_PK_DER_PREFIX, delegation_signature_msg,
+};
+use ic_certification::{Certificate, Hash, HashTree, fork, label, leaf};
+use ic_verify_bls_signature::PrivateKey;
+use identity_jose::jws::{CompactJwsEncoder, JwsHeader};
+use rand_chacha::ChaCha20Rng;
+use rand_chacha::rand_core::SeedableRng;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// The canister id of II used by `TestVcFlow`.  Not the canister id of II on mainnet, for which
+/// only derivation origins on the legacy `ic0.app` domain are accepted.
+pub const TEST_II_CANISTER_ID: &str = "be2us-64aaa-aaaaa-qaabq-cai";
+/// The canister id of the issuer used by `TestVcFlow`.
+pub const TEST_ISSUER_CANISTER_ID: &str = "bkyz2-fmaaa-aaaaa-qaaaq-cai";
+/// The origin of the issuer used by `TestVcFlow`.
+pub const TEST_ISSUER_ORIGIN: &str = "https://issuer.test/";
+/// The validity of the credentials minted by `TestVcFlow`.
+pub const TEST_CREDENTIAL_VALIDITY: Duration = Duration::from_secs(15 * 60);
+const IC_STATE_ROOT_DOMAIN_SEPARATOR: &[u8; 14] = b"\x0Dic-state-root";
+
+/// A throwaway IC root key, which certifies the certified data of arbitrary canisters.
+pub struct TestIcRootKey {
+    secret_key: PrivateKey,
+}
+
+impl TestIcRootKey {
+    /// Generates a new random root key.
+    pub fn generate() -> Self {
+        Self::from_seed(rand::random())
+    }
+
+    /// Derives a root key deterministically from the given seed.
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Self {
+            secret_key: PrivateKey::random(&mut ChaCha20Rng::from_seed(seed)),
+        }
+    }
+
+    /// Returns the root public key, as expected by the verification functions.
+    pub fn ic_root_key(&self) -> IcRootKey {
+        IcRootKey::from_raw(&self.public_key_raw()).expect("invalid root key")
+    }
+
+    /// Returns the raw root public key.
+    pub fn public_key_raw(&self) -> Vec<u8> {
+        self.secret_key.public_key().serialize().to_vec()
+    }
+
+    /// Returns the DER-encoded root public key, e.g. for the config of an issuer.
+    pub fn public_key_der(&self) -> Vec<u8> {
+        [IC_ROOT_PK_DER_PREFIX.as_slice(), &self.public_key_raw()].concat()
+    }
+
+    /// Returns a CBOR-encoded certificate, signed by this root key, which certifies
+    /// `certified_data` as the certified data of `canister_id` at `time_ns`.
+    pub fn certify(&self, canister_id: Principal, certified_data: &Hash, time_ns: u128) -> Vec<u8> {
+        let tree: HashTree = fork(
+            label(
+                "canister",
+                label(
+                    canister_id.as_slice(),
+                    label("certified_data", leaf(certified_data.to_vec())),
+                ),
+            ),
+            label("time", leaf(leb128(time_ns))),
+        );
+        let message = [
+            IC_STATE_ROOT_DOMAIN_SEPARATOR.as_slice(),
+            tree.digest().as_slice(),
+        ]
+        .concat();
+        let certificate = Certificate {
+            tree,
+            signature: self.secret_key.sign(&message).serialize().to_vec(),
+            delegation: None,
+        };
+        to_self_describing_cbor(&certificate)
+    }
+}
+
+/// Returns the witness for a canister signature on `message` (in the given `domain`) with
+/// the given `seed`, as returned by a `SignatureMap` that contains only this signature.
+/// The certified data of the signing canister has to be `witness.digest()`.
+pub fn signature_witness(seed: &[u8], domain: &[u8], message: &[u8]) -> HashTree {
+    let seed_hash: Hash = Sha256::digest(seed).into();
+    let message_hash = hash_with_domain(domain, message);
+    label(
+        LABEL_SIG,
+        label(
+            seed_hash.as_slice(),
+            label(message_hash.as_slice(), leaf(vec![])),
+        ),
+    )
+}
+
+/// A canister which creates canister signatures with the given seed.
+#[derive(Clone, Debug)]
+pub struct TestCanisterSigner {
+    pub canister_id: Principal,
+    pub seed: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct CanisterSig {
+    #[serde(with = "serde_bytes")]
+    certificate: Vec<u8>,
+    tree: HashTree,
+}
+
+impl TestCanisterSigner {
+    pub fn new(canister_id: Principal, seed: &[u8]) -> Self {
+        Self {
+            canister_id,
+            seed: seed.to_vec(),
+        }
+    }
+
+    pub fn public_key(&self) -> CanisterSigPublicKey {
+        CanisterSigPublicKey::new(self.canister_id, self.seed.clone())
+    }
+
+    /// Returns the self-authenticating principal of the public key of this signer.
+    pub fn principal(&self) -> Principal {
+        Principal::self_authenticating(self.public_key().to_der())
+    }
+
+    /// Returns a CBOR-encoded canister signature on `message` in the given `domain`,
+    /// certified by `root_key` at `time_ns`.
+    pub fn sign(
+        &self,
+        root_key: &TestIcRootKey,
+        domain: &[u8],
+        message: &[u8],
+        time_ns: u128,
+    ) -> Vec<u8> {
+        let tree = signature_witness(&self.seed, domain, message);
+        let certificate = root_key.certify(self.canister_id, &tree.digest(), time_ns);
+        to_self_describing_cbor(&CanisterSig { certificate, tree })
+    }
+
+    /// Signs the given credential, and returns it as a JWS.
+    pub fn sign_credential_jwt(
+        &self,
+        root_key: &TestIcRootKey,
+        credential_jwt: &str,
+        time_ns: u128,
+    ) -> String {
+        let public_key = self.public_key();
+        let signing_input =
+            vc_signing_input(credential_jwt, &public_key).expect("failed computing signing input");
+        let sig = self.sign(root_key, VC_SIGNING_INPUT_DOMAIN, &signing_input, time_ns);
+        vc_jwt_to_jws(credential_jwt, &public_key, &sig).expect("failed creating JWS")
+    }
+}
+
+/// A local mock of the threshold ECDSA and Schnorr APIs of the management canister,
+/// which signs with a secret key derived from the given seed.
+pub struct TestThresholdSigner {
+    secret_key: TestThresholdSecretKey,
+    public_key: ThresholdPublicKey,
+}
+
+enum TestThresholdSecretKey {
+    Es256k(k256::ecdsa::SigningKey),
+    EdDsa(ed25519_dalek::SigningKey),
+}
+
+impl TestThresholdSigner {
+    pub fn new(algorithm: ThresholdAlgorithm, seed: [u8; 32]) -> Self {
+        let (secret_key, public_key) = match algorithm {
+            ThresholdAlgorithm::Es256k => {
+                let secret_key = k256::ecdsa::SigningKey::from_slice(&seed)
+                    .expect("invalid secp256k1 secret key");
+                let public_key = secret_key.verifying_key().to_sec1_bytes().to_vec();
+                (TestThresholdSecretKey::Es256k(secret_key), public_key)
+            }
+            ThresholdAlgorithm::EdDsa => {
+                let secret_key = ed25519_dalek::SigningKey::from_bytes(&seed);
+                let public_key = secret_key.verifying_key().to_bytes().to_vec();
+                (TestThresholdSecretKey::EdDsa(secret_key), public_key)
+            }
+        };
+        Self {
+            secret_key,
+            public_key: ThresholdPublicKey::new(algorithm, public_key).expect("invalid public key"),
+        }
+    }
+
+    /// Signs `message` like `sign_with_ecdsa` (on the SHA-256 hash of the message)
+    /// or `sign_with_schnorr`, respectively.
+    pub fn sign_message(&self, message: &[u8]) -> Vec<u8> {
+        use ed25519_dalek::Signer as _;
+        match &self.secret_key {
+            TestThresholdSecretKey::Es256k(secret_key) => {
+                let sig: k256::ecdsa::Signature = secret_key.sign(message);
+                sig.to_bytes().to_vec()
+            }
+            TestThresholdSecretKey::EdDsa(secret_key) => {
+                secret_key.sign(message).to_bytes().to_vec()
+            }
+        }
+    }
+
+    /// Returns the DER-encoded public key of the signer, e.g. to be used as session key in a
+    /// delegation chain (cf. `holder_binding`).
+    pub fn public_key_der(&self) -> Vec<u8> {
+        match &self.secret_key {
+            TestThresholdSecretKey::Es256k(secret_key) => {
+                let point = secret_key.verifying_key().to_encoded_point(false);
+                [SECP256K1_DER_PREFIX.as_slice(), point.as_bytes()].concat()
+            }
+            TestThresholdSecretKey::EdDsa(secret_key) => [
+                ED25519_DER_PREFIX.as_slice(),
+                secret_key.verifying_key().as_bytes(),
+            ]
+            .concat(),
+        }
+    }
+
+    /// Returns the DID URL of the `did:key`-DID of the signer, cf. `did_key_url()`.
+    pub fn did_key_url(&self) -> String {
+        did_key_url(&self.public_key)
+    }
+
+    /// Returns a proof of possession of the key of the signer for an OpenID4VCI credential
+    /// request (cf. `openid4vci`), with the `did:key`-DID of the signer as `kid`.
+    pub fn openid4vci_proof_jwt(
+        &self,
+        credential_issuer: &str,
+        c_nonce: &str,
+        issued_at_s: u64,
+    ) -> String {
+        let claims = serde_json::json!({
+            "aud": credential_issuer,
+            "iat": issued_at_s,
+            "nonce": c_nonce,
+        })
+        .to_string();
+        let mut header = JwsHeader::new();
+        header.set_typ(PROOF_JWT_TYPE);
+        header.set_alg(self.public_key.algorithm.jws_algorithm());
+        header.set_kid(self.did_key_url());
+        let encoder = CompactJwsEncoder::new(claims.as_bytes(), &header)
+            .expect("failed creating JWS encoder");
+        let sig = self.sign_message(encoder.signing_input());
+        encoder.into_jws(&sig)
+    }
+}
+
+impl SigningBackend for TestThresholdSigner {
+    fn public_key(&self) -> &ThresholdPublicKey {
+        &self.public_key
+    }
+
+    async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, String> {
+        Ok(self.sign_message(message))
+    }
+}
+
+/// The parties of a VC flow, which mint the credentials and presentations of the flow.
+pub struct TestVcFlow {
+    pub root_key: TestIcRootKey,
+    pub ii: TestCanisterSigner,
+    pub ii_origin: String,
+    pub issuer: TestCanisterSigner,
+    pub issuer_origin: String,
+}
+
+impl Default for TestVcFlow {
+    fn default() -> Self {
+        Self::new(TestIcRootKey::generate())
+    }
+}
+
+impl TestVcFlow {
+    /// Creates a VC flow with II and an issuer with default canister ids and origins,
+    /// whose signatures are certified by `root_key`.
+    pub fn new(root_key: TestIcRootKey) -> Self {
+        Self {
+            root_key,
+            ii: TestCanisterSigner::new(
+                Principal::from_text(TEST_II_CANISTER_ID).expect("invalid II canister id"),
+                b"ii",
+            ),
+            ii_origin: II_ISSUER_URL.to_string(),
+            issuer: TestCanisterSigner::new(
+                Principal::from_text(TEST_ISSUER_CANISTER_ID).expect("invalid issuer canister id"),
+                b"issuer",
+            ),
+            issuer_origin: TEST_ISSUER_ORIGIN.to_string(),
+        }
+    }
+
+    pub fn vc_flow_signers(&self) -> VcFlowSigners {
+        VcFlowSigners {
+            ii_canister_id: self.ii.canister_id,
+            ii_origin: self.ii_origin.clone(),
+            issuer_canister_id: self.issuer.canister_id,
+            issuer_origin: self.issuer_origin.clone(),
+        }
+    }
+
+    pub fn ic_root_key(&self) -> IcRootKey {
+        self.root_key.ic_root_key()
+    }
+
+    /// Returns the environment of the flow, i.e. a local environment with II and the root key
+    /// of the flow.
+    pub fn environment(&self) -> VcEnvironment {
+        VcEnvironment::local(self.ii.canister_id, &self.ii_origin, self.ic_root_key())
+    }
+
+    /// Returns the id_alias that II would use for the user `id_dapp` at `derivation_origin`.
+    pub fn id_alias(&self, id_dapp: Principal, derivation_origin: &str) -> Principal {
+        let seed = [
+            id_dapp.as_slice(),
+            b"#".as_slice(),
+            derivation_origin.as_bytes(),
+        ]
+        .concat();
+        TestCanisterSigner::new(self.ii.canister_id, &Sha256::digest(seed)).principal()
+    }
+
+    /// Returns an id_alias credential signed by II at `time_ns`, which links `id_dapp`
+    /// to `id_alias` for `derivation_origin`.
+    pub fn id_alias_vc_jws(
+        &self,
+        id_dapp: Principal,
+        id_alias: Principal,
+        derivation_origin: &str,
+        time_ns: u128,
+    ) -> String {
+        let spec = CredentialSpec {
+            credential_type: "InternetIdentityIdAlias".to_string(),
+            arguments: Some(HashMap::from([
+                (
+                    "hasIdAlias".to_string(),
+                    ArgumentValue::String(id_alias.to_text()),
+                ),
+                (
+                    "derivationOrigin".to_string(),
+                    ArgumentValue::String(derivation_origin.to_string()),
+                ),
+            ])),
+        };
+        let credential_jwt = credential_jwt(
+            spec,
+            id_dapp,
+            &self.ii_origin,
+            &format!("alias:{}", id_alias),
+            time_ns,
+        );
+        self.ii
+            .sign_credential_jwt(&self.root_key, &credential_jwt, time_ns)
+    }
+
+    /// Returns a credential for `id_alias` matching `spec`, signed by the issuer at `time_ns`.
+    pub fn requested_vc_jws(
+        &self,
+        id_alias: Principal,
+        spec: &CredentialSpec,
+        time_ns: u128,
+    ) -> String {
+        let credential_jwt = credential_jwt(
+            spec.clone(),
+            id_alias,
+            &self.issuer_origin,
+            &format!("issuer:{}", self.issuer_origin),
+            time_ns,
+        );
+        self.issuer
+            .sign_credential_jwt(&self.root_key, &credential_jwt, time_ns)
+    }
+
+    /// Returns a presentation as returned by II to the relying party at `derivation_origin`
+    /// for the user `id_dapp`, containing an id_alias credential and a credential matching
+    /// `spec`, both created at `time_ns`.
+    pub fn presentation_jwt(
+        &self,
+        id_dapp: Principal,
+        derivation_origin: &str,
+        spec: &CredentialSpec,
+        time_ns: u128,
+    ) -> String {
+        let id_alias = self.id_alias(id_dapp, derivation_origin);
+        build_ii_verifiable_presentation_jwt(
+            id_dapp,
+            self.id_alias_vc_jws(id_dapp, id_alias, derivation_origin, time_ns),
+            self.requested_vc_jws(id_alias, spec, time_ns),
+        )
+        .expect("failed building presentation")
+    }
+
+    /// Returns a delegation of the key of the user of II with the given seed to `session_key_der`
+    /// until `expiration_ns`, as obtained by the frontend of a relying party from II at `time_ns`.
+    /// The holder of the delegation chain is the `id_dapp` of the user.
+    pub fn delegation_chain(
+        &self,
+        user_seed: &[u8],
+        session_key_der: &[u8],
+        expiration_ns: u64,
+        time_ns: u128,
+    ) -> DelegationChain {
+        let user = TestCanisterSigner::new(self.ii.canister_id, user_seed);
+        let message = delegation_signature_msg(session_key_der, expiration_ns, None);
+        DelegationChain {
+            public_key: user.public_key().to_der(),
+            delegations: vec![SignedDelegation {
+                delegation: Delegation {
+                    pubkey: session_key_der.to_vec(),
+                    expiration: expiration_ns,
+                    targets: None,
+                },
+                signature: user.sign(&self.root_key, DELEGATION_SIG_DOMAIN, &message, time_ns),
+            }],
+        }
+    }
+
+    /// Returns a holder-bound presentation of the holder of `delegation_chain`, signed with
+    /// `session_key`, containing an id_alias credential for `derivation_origin` and a credential
+    /// matching `spec`.
+    pub fn holder_bound_presentation_jws(
+        &self,
+        delegation_chain: &DelegationChain,
+        session_key: &TestThresholdSigner,
+        derivation_origin: &str,
+        spec: &CredentialSpec,
+        options: &HolderBindingOptions,
+        time_ns: u128,
+    ) -> String {
+        let id_dapp = delegation_chain.holder();
+        let id_alias = self.id_alias(id_dapp, derivation_origin);
+        let vp_jwt = build_ii_holder_bound_presentation_jwt(
+            id_dapp,
+            self.id_alias_vc_jws(id_dapp, id_alias, derivation_origin, time_ns),
+            self.requested_vc_jws(id_alias, spec, time_ns),
+            options,
+        )
+        .expect("failed building presentation");
+        let signing_input =
+            vp_signing_input(&vp_jwt, delegation_chain).expect("failed computing signing input");
+        vp_jwt_to_jws(
+            &vp_jwt,
+            delegation_chain,
+            &session_key.sign_message(&signing_input),
+        )
+        .expect("failed signing presentation")
+    }
+}
+
+// Builds a credential issued at `time_ns` and valid for `TEST_CREDENTIAL_VALIDITY`.
+fn credential_jwt(
+    spec: CredentialSpec,
+    subject: Principal,
+    issuer_url: &str,
+    credential_id: &str,
+    time_ns: u128,
+) -> String {
+    let time_s = (time_ns / 1_000_000_000) as u32;
+    let credential_jwt = build_credential_jwt(CredentialParams {
+        spec,
+        subject_id: did_for_principal(subject),
+        credential_id_url: format!(
+            "{}{},timestamp_ns:{}",
+            II_CREDENTIAL_URL_PREFIX, credential_id, time_ns
+        ),
+        issuer_url: issuer_url.to_string(),
+        expiration_timestamp_s: time_s + TEST_CREDENTIAL_VALIDITY.as_secs() as u32,
+    });
+    // The issuance time is taken from the clock, so set it to the given time.
+    let mut claims: Map<String, Value> =
+        serde_json::from_str(&credential_jwt).expect("failed parsing credential");
+    claims.insert("nbf".to_string(), Value::from(time_s));
+    serde_json::to_string(&claims).expect("failed serializing credential")
+}
+
+fn to_self_describing_cbor<T: Serialize>(value: &T) -> Vec<u8> {
+    let mut serializer = serde_cbor::Serializer::new(Vec::new());
+    serializer.self_describe().expect("failed writing CBOR tag");
+    value
+        .serialize(&mut serializer)
+        .expect("failed serializing to CBOR");
+    serializer.into_inner()
+}
+
+fn leb128(mut value: u128) -> Vec<u8> {
+    let mut bytes = vec![];
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        AliasTuple, VerificationOptions, validate_ii_presentation_and_claims,
+        validate_ii_presentation_and_claims_with_options, verify_credential_jws_with_canister_id,
+    };
+    use assert_matches::assert_matches;
+    use ic_certification::LookupResult;
+    use ic_signature_verification::verify_canister_sig;
+
+    const TIME_NS: u128 = 1_700_000_000 * 1_000_000_000;
+    const RP_ORIGIN: &str = "https://l7rua-raaaa-aaaap-ahh6a-cai.icp0.io";
+
+    fn test_flow() -> TestVcFlow {
+        TestVcFlow::new(TestIcRootKey::from_seed([42; 32]))
+    }
+
+    fn verified_adult_spec() -> CredentialSpec {
+        CredentialSpec {
+            credential_type: "VerifiedAdult".to_string(),
+            arguments: Some(HashMap::from([(
+                "minAge".to_string(),
+                ArgumentValue::Int(18),
+            )])),
+        }
+    }
+
+    #[test]
+    fn should_encode_leb128() {
+        assert_eq!(leb128(0), vec![0]);
+        assert_eq!(leb128(127), vec![0x7f]);
+        assert_eq!(leb128(624485), vec![0xe5, 0x8e, 0x26]);
+    }
+
+    #[test]
+    fn should_create_verifiable_canister_signature() {
+        let root_key = TestIcRootKey::from_seed([1; 32]);
+        let signer = TestCanisterSigner::new(Principal::from_slice(&[1, 2, 3]), b"seed");
+        let sig = signer.sign(&root_key, b"domain", b"message", TIME_NS);
+        let message = [&[6u8][..], b"domain", b"message"].concat();
+        verify_canister_sig(
+            &message,
+            &sig,
+            &signer.public_key().to_der(),
+            &root_key.public_key_raw(),
+        )
+        .expect("failed verifying canister signature");
+        assert_matches!(
+            verify_canister_sig(
+                b"other message",
+                &sig,
+                &signer.public_key().to_der(),
+                &root_key.public_key_raw(),
+            ),
+            Err(_)
+        );
+    }
+
+    #[test]
+    fn should_create_witness_for_signature() {
+        let witness = signature_witness(b"seed", b"domain", b"message");
+        let seed_hash = Sha256::digest(b"seed");
+        let message_hash = hash_with_domain(b"domain", b"message");
+        assert_matches!(
+            witness.lookup_path([
+                LABEL_SIG,
+                seed_hash.as_slice(),
+                message_hash.as_slice()
+            ]),
+            LookupResult::Found(value) if value.is_empty()
+        );
+    }
+
+    #[test]
+    fn should_mint_credential_valid_at_given_time() {
+        let flow = test_flow();
+        let id_alias = Principal::from_slice(&[7; 29]);
+        let vc_jws = flow.requested_vc_jws(id_alias, &verified_adult_spec(), TIME_NS);
+        let claims = verify_credential_jws_with_canister_id(
+            &vc_jws,
+            &flow.issuer.canister_id,
+            &flow.ic_root_key(),
+            TIME_NS,
+        )
+        .expect("failed verifying credential");
+        assert_eq!(claims.nbf(), Some((TIME_NS / 1_000_000_000) as i64));
+        let expired = verify_credential_jws_with_canister_id(
+            &vc_jws,
+            &flow.issuer.canister_id,
+            &flow.ic_root_key(),
+            TIME_NS + TEST_CREDENTIAL_VALIDITY.as_nanos(),
+        );
+        assert_matches!(expired, Err(e) if e.to_string().contains("credential expired"));
+    }
+
+    #[test]
+    fn should_mint_presentation_that_passes_validation() {
+        let flow = test_flow();
+        let id_dapp = Principal::from_slice(&[8; 29]);
+        let vp_jwt = flow.presentation_jwt(id_dapp, RP_ORIGIN, &verified_adult_spec(), TIME_NS);
+        validate_ii_presentation_and_claims(
+            &vp_jwt,
+            id_dapp,
+            RP_ORIGIN.to_string(),
+            &flow.vc_flow_signers(),
+            &verified_adult_spec(),
+            &flow.ic_root_key(),
+            TIME_NS + 1,
+        )
+        .expect("failed validating presentation");
+        let (alias_tuple, _) = crate::verify_presentation(
+            &vp_jwt,
+            &flow.vc_flow_signers(),
+            &flow.ic_root_key(),
+            TIME_NS + 1,
+            |_| Ok(()),
+        )
+        .expect("failed verifying presentation");
+        assert_eq!(
+            alias_tuple,
+            AliasTuple {
+                id_alias: flow.id_alias(id_dapp, RP_ORIGIN),
+                id_dapp,
+                derivation_origin: RP_ORIGIN.to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn should_validate_presentation_in_environment_of_flow() {
+        let flow = test_flow();
+        let env = flow.environment();
+        let id_dapp = Principal::from_slice(&[8; 29]);
+        let vp_jwt = flow.presentation_jwt(id_dapp, RP_ORIGIN, &verified_adult_spec(), TIME_NS);
+        validate_ii_presentation_and_claims(
+            &vp_jwt,
+            id_dapp,
+            RP_ORIGIN.to_string(),
+            &env.vc_flow_signers(flow.issuer.canister_id, TEST_ISSUER_ORIGIN),
+            &verified_adult_spec(),
+            &env,
+            TIME_NS + 1,
+        )
+        .expect("failed validating presentation");
+        let result = validate_ii_presentation_and_claims(
+            &vp_jwt,
+            id_dapp,
+            RP_ORIGIN.to_string(),
+            &env.vc_flow_signers(flow.issuer.canister_id, TEST_ISSUER_ORIGIN),
+            &verified_adult_spec(),
+            &VcEnvironment::mainnet(),
+            TIME_NS + 1,
+        );
+        assert_matches!(result, Err(_));
+    }
+
+    #[test]
+    fn should_validate_presentation_with_ii_origin_of_flow() {
+        let mut flow = test_flow();
+        flow.ii_origin = format!("http://{}.localhost:4943/", flow.ii.canister_id);
+        let id_dapp = Principal::from_slice(&[8; 29]);
+        let vp_jwt = flow.presentation_jwt(id_dapp, RP_ORIGIN, &verified_adult_spec(), TIME_NS);
+        let validate = |vc_flow_signers: &VcFlowSigners| {
+            validate_ii_presentation_and_claims(
+                &vp_jwt,
+                id_dapp,
+                RP_ORIGIN.to_string(),
+                vc_flow_signers,
+                &verified_adult_spec(),
+                &flow.environment(),
+                TIME_NS + 1,
+            )
+        };
+
+        validate(&flow.vc_flow_signers()).expect("failed validating presentation");
+        assert_matches!(
+            validate(&VcFlowSigners {
+                ii_origin: II_ISSUER_URL.to_string(),
+                ..flow.vc_flow_signers()
+            }),
+            Err(crate::PresentationVerificationError::InvalidIdAliasCredential(
+                crate::CredentialVerificationError::UnexpectedClaim(mismatch)
+            )) if mismatch.claim == "iss"
+        );
+    }
+
+    #[test]
+    fn should_validate_presentation_within_custom_size_limit() {
+        let flow = test_flow();
+        let id_dapp = Principal::from_slice(&[8; 29]);
+        let vp_jwt = flow.presentation_jwt(id_dapp, RP_ORIGIN, &verified_adult_spec(), TIME_NS);
+        let validate = |max_presentation_size: usize| {
+            validate_ii_presentation_and_claims_with_options(
+                &vp_jwt,
+                id_dapp,
+                RP_ORIGIN,
+                &flow.vc_flow_signers(),
+                &verified_adult_spec(),
+                &VerificationOptions::default().with_max_presentation_size(max_presentation_size),
+                &flow.environment(),
+                TIME_NS + 1,
+            )
+        };
+
+        validate(vp_jwt.len()).expect("failed validating presentation");
+        assert_matches!(
+            validate(vp_jwt.len() - 1),
+            Err(crate::PresentationVerificationError::InvalidPresentationJwt(e))
+                if e.starts_with("presentation too large")
+        );
+    }
+
+    #[test]
+    fn should_fail_validating_presentation_with_other_root_key() {
+        let flow = test_flow();
+        let id_dapp = Principal::from_slice(&[8; 29]);
+        let vp_jwt = flow.presentation_jwt(id_dapp, RP_ORIGIN, &verified_adult_spec(), TIME_NS);
+        let result = validate_ii_presentation_and_claims(
+            &vp_jwt,
+            id_dapp,
+            RP_ORIGIN.to_string(),
+            &flow.vc_flow_signers(),
+            &verified_adult_spec(),
+            &TestIcRootKey::from_seed([43; 32]).ic_root_key(),
+            TIME_NS + 1,
+        );
+        assert_matches!(result, Err(_));
+    }
+}
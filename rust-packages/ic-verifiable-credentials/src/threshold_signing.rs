@@ -0,0 +1,596 @@
+//! Issuing credentials with threshold signatures, for verifiers outside of the IC.
+//!
+//! Credentials signed with canister signatures (`alg: IcCs`) can only be verified with
+//! IC-specific code (cf. `verify_credential_jws_with_canister_id()`).  Alternatively, issuers
+//! can sign credentials with a `SigningBackend`, which creates standard `ES256K` (ECDSA over
+//! secp256k1) or `EdDSA` (Ed25519) signatures, e.g. via the threshold ECDSA and Schnorr APIs
+//! of the management canister (`ThresholdEcdsaSigner`, `ThresholdSchnorrSigner`).
+//!
+//! The JWS header of such credentials contains the public key as JWK, and a `kid` of the
+//! form `did:icp:<issuer canister id>#<JWK thumbprint>`, where the thumbprint is computed
+//! as specified in [RFC 7638](https://datatracker.ietf.org/doc/html/rfc7638).
+use crate::{
+    CREDENTIAL_EXPIRED, did_for_principal, invalid_signature_err, key_decoding_err,
+    unsupported_alg_err, validate_expiration,
+};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use candid::Principal;
+use ic_cdk::management_canister::{
+    EcdsaCurve, EcdsaKeyId, EcdsaPublicKeyArgs, SchnorrAlgorithm, SchnorrKeyId,
+    SchnorrPublicKeyArgs, SignWithEcdsaArgs, SignWithSchnorrArgs,
+};
+use identity_jose::jwk::{Jwk, JwkParams, JwkParamsEc, JwkParamsOkp, JwkType};
+use identity_jose::jws::{
+    CompactJwsEncoder, Decoder, JwsAlgorithm, JwsHeader, SignatureVerificationError,
+};
+use identity_jose::jwt::JwtClaims;
+use identity_jose::jwu::{decode_b64, encode_b64};
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+
+const SECP256K1_CURVE: &str = "secp256k1";
+const ED25519_CURVE: &str = "Ed25519";
+
+/// The signature algorithms supported for credentials signed via a `SigningBackend`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ThresholdAlgorithm {
+    /// ECDSA over secp256k1 with SHA-256, as created by `sign_with_ecdsa`.
+    Es256k,
+    /// Ed25519, as created by `sign_with_schnorr`.
+    EdDsa,
+}
+
+impl ThresholdAlgorithm {
+    /// Returns the name of the algorithm, as used in the `alg` of JWS headers and JWKs.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ThresholdAlgorithm::Es256k => "ES256K",
+            ThresholdAlgorithm::EdDsa => "EdDSA",
+        }
+    }
+
+    pub fn jws_algorithm(&self) -> JwsAlgorithm {
+        match self {
+            ThresholdAlgorithm::Es256k => JwsAlgorithm::ES256K,
+            ThresholdAlgorithm::EdDsa => JwsAlgorithm::EdDSA,
+        }
+    }
+}
+
+/// A public key of a `SigningBackend`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ThresholdPublicKey {
+    pub algorithm: ThresholdAlgorithm,
+    /// The SEC1-encoded (compressed) public key for `Es256k`, and the raw 32 bytes for `EdDsa`,
+    /// as returned by `ecdsa_public_key` and `schnorr_public_key` respectively.
+    pub public_key: Vec<u8>,
+}
+
+impl ThresholdPublicKey {
+    pub fn new(algorithm: ThresholdAlgorithm, public_key: Vec<u8>) -> Result<Self, String> {
+        let public_key = match algorithm {
+            ThresholdAlgorithm::Es256k => secp256k1_public_key(&public_key)?
+                .to_encoded_point(true)
+                .as_bytes()
+                .to_vec(),
+            ThresholdAlgorithm::EdDsa => ed25519_public_key(&public_key)?.to_bytes().to_vec(),
+        };
+        Ok(Self {
+            algorithm,
+            public_key,
+        })
+    }
+
+    /// Returns the public key as JWK, i.e. as `EC`-JWK for `Es256k` and as `OKP`-JWK
+    /// for `EdDsa` (cf. [RFC 8037](https://datatracker.ietf.org/doc/html/rfc8037)).
+    pub fn to_jwk(&self) -> Result<Jwk, String> {
+        let (kty, params) = match self.algorithm {
+            ThresholdAlgorithm::Es256k => {
+                let (x, y) = self.ec_coordinates()?;
+                let mut params = JwkParamsEc::new();
+                params.crv = SECP256K1_CURVE.to_string();
+                params.x = x;
+                params.y = y;
+                (JwkType::Ec, JwkParams::Ec(params))
+            }
+            ThresholdAlgorithm::EdDsa => {
+                let mut params = JwkParamsOkp::new();
+                params.crv = ED25519_CURVE.to_string();
+                params.x = encode_b64(&self.public_key);
+                (JwkType::Okp, JwkParams::Okp(params))
+            }
+        };
+        let mut jwk = Jwk::new(kty);
+        jwk.set_alg(self.algorithm.name());
+        jwk.set_params(params)
+            .map_err(|e| format!("internal: failed creating JWK: {:?}", e))?;
+        Ok(jwk)
+    }
+
+    /// Parses a public key from the given JWK, as returned by `to_jwk()`.
+    pub fn from_jwk(jwk: &Jwk) -> Result<Self, String> {
+        match jwk.kty() {
+            JwkType::Ec => {
+                let params = jwk
+                    .try_ec_params()
+                    .map_err(|_| "missing JWK EC params".to_string())?;
+                if params.crv != SECP256K1_CURVE {
+                    return Err(format!("unsupported curve {}", params.crv));
+                }
+                let mut uncompressed = vec![0x04];
+                uncompressed.extend(decode_jwk_coordinate(&params.x)?);
+                uncompressed.extend(decode_jwk_coordinate(&params.y)?);
+                Self::new(ThresholdAlgorithm::Es256k, uncompressed)
+            }
+            JwkType::Okp => {
+                let params = jwk
+                    .try_okp_params()
+                    .map_err(|_| "missing JWK OKP params".to_string())?;
+                if params.crv != ED25519_CURVE {
+                    return Err(format!("unsupported curve {}", params.crv));
+                }
+                Self::new(ThresholdAlgorithm::EdDsa, decode_jwk_coordinate(&params.x)?)
+            }
+            kty => Err(format!("unsupported JWK type {:?}", kty)),
+        }
+    }
+
+    /// Returns the base64url-encoded SHA-256 thumbprint of the JWK of this key,
+    /// as specified in [RFC 7638](https://datatracker.ietf.org/doc/html/rfc7638).
+    pub fn thumbprint(&self) -> Result<String, String> {
+        // The required members of the JWK, in lexicographic order and without whitespace.
+        let canonical_jwk = match self.algorithm {
+            ThresholdAlgorithm::Es256k => {
+                let (x, y) = self.ec_coordinates()?;
+                format!(
+                    r#"{{"crv":"{}","kty":"EC","x":"{}","y":"{}"}}"#,
+                    SECP256K1_CURVE, x, y
+                )
+            }
+            ThresholdAlgorithm::EdDsa => format!(
+                r#"{{"crv":"{}","kty":"OKP","x":"{}"}}"#,
+                ED25519_CURVE,
+                encode_b64(&self.public_key)
+            ),
+        };
+        Ok(BASE64.encode(Sha256::digest(canonical_jwk.as_bytes())))
+    }
+
+    /// Returns the `kid` of credentials signed by `issuer_canister_id` with this key.
+    pub fn kid(&self, issuer_canister_id: Principal) -> Result<String, String> {
+        Ok(format!(
+            "{}#{}",
+            did_for_principal(issuer_canister_id),
+            self.thumbprint()?
+        ))
+    }
+
+    /// Verifies the given signature on `message`, where `Es256k`-signatures are expected
+    /// as concatenation of `r` and `s` (64 bytes), as required for JWS.
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), String> {
+        match self.algorithm {
+            ThresholdAlgorithm::Es256k => {
+                use k256::ecdsa::signature::Verifier;
+                let verifying_key =
+                    k256::ecdsa::VerifyingKey::from(secp256k1_public_key(&self.public_key)?);
+                let signature = k256::ecdsa::Signature::from_slice(signature)
+                    .map_err(|_| "malformed ES256K signature".to_string())?;
+                // Signatures with a high `s` are valid ECDSA signatures, but rejected by `k256`.
+                let signature = signature.normalize_s().unwrap_or(signature);
+                verifying_key
+                    .verify(message, &signature)
+                    .map_err(|_| "invalid ES256K signature".to_string())
+            }
+            ThresholdAlgorithm::EdDsa => {
+                let verifying_key = ed25519_public_key(&self.public_key)?;
+                let signature = ed25519_dalek::Signature::from_slice(signature)
+                    .map_err(|_| "malformed EdDSA signature".to_string())?;
+                verifying_key
+                    .verify_strict(message, &signature)
+                    .map_err(|_| "invalid EdDSA signature".to_string())
+            }
+        }
+    }
+
+    fn ec_coordinates(&self) -> Result<(String, String), String> {
+        let point = secp256k1_public_key(&self.public_key)?.to_encoded_point(false);
+        match (point.x(), point.y()) {
+            (Some(x), Some(y)) => Ok((encode_b64(x), encode_b64(y))),
+            _ => Err("internal: failed decompressing public key".to_string()),
+        }
+    }
+}
+
+/// A signer of credentials with standard JWS signatures.
+pub trait SigningBackend {
+    fn public_key(&self) -> &ThresholdPublicKey;
+
+    /// Signs the given message, i.e. the signing input of a JWS, with the algorithm
+    /// of `public_key()`.
+    fn sign(&self, message: &[u8]) -> impl Future<Output = Result<Vec<u8>, String>>;
+}
+
+/// A `SigningBackend` using the threshold ECDSA API of the management canister (`ES256K`).
+/// Can only be used within a canister.
+#[derive(Clone, Debug)]
+pub struct ThresholdEcdsaSigner {
+    key_id: EcdsaKeyId,
+    derivation_path: Vec<Vec<u8>>,
+    public_key: ThresholdPublicKey,
+}
+
+impl ThresholdEcdsaSigner {
+    /// Creates a signer with the secp256k1 key `key_name` (e.g. `key_1` on mainnet, or
+    /// `dfx_test_key` locally), and fetches the public key of the calling canister
+    /// for the given derivation path.
+    pub async fn new(key_name: &str, derivation_path: Vec<Vec<u8>>) -> Result<Self, String> {
+        let key_id = EcdsaKeyId {
+            curve: EcdsaCurve::Secp256k1,
+            name: key_name.to_string(),
+        };
+        let result = ic_cdk::management_canister::ecdsa_public_key(&EcdsaPublicKeyArgs {
+            canister_id: None,
+            derivation_path: derivation_path.clone(),
+            key_id: key_id.clone(),
+        })
+        .await
+        .map_err(|e| format!("failed obtaining ECDSA public key: {:?}", e))?;
+        Ok(Self {
+            key_id,
+            derivation_path,
+            public_key: ThresholdPublicKey::new(ThresholdAlgorithm::Es256k, result.public_key)?,
+        })
+    }
+}
+
+impl SigningBackend for ThresholdEcdsaSigner {
+    fn public_key(&self) -> &ThresholdPublicKey {
+        &self.public_key
+    }
+
+    async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, String> {
+        let result = ic_cdk::management_canister::sign_with_ecdsa(&SignWithEcdsaArgs {
+            message_hash: Sha256::digest(message).to_vec(),
+            derivation_path: self.derivation_path.clone(),
+            key_id: self.key_id.clone(),
+        })
+        .await
+        .map_err(|e| format!("failed signing with ECDSA: {:?}", e))?;
+        Ok(result.signature)
+    }
+}
+
+/// A `SigningBackend` using the threshold Schnorr API of the management canister with
+/// Ed25519 keys (`EdDSA`).  Can only be used within a canister.
+#[derive(Clone, Debug)]
+pub struct ThresholdSchnorrSigner {
+    key_id: SchnorrKeyId,
+    derivation_path: Vec<Vec<u8>>,
+    public_key: ThresholdPublicKey,
+}
+
+impl ThresholdSchnorrSigner {
+    /// Creates a signer with the Ed25519 key `key_name` (e.g. `key_1` on mainnet, or
+    /// `dfx_test_key` locally), and fetches the public key of the calling canister
+    /// for the given derivation path.
+    pub async fn new(key_name: &str, derivation_path: Vec<Vec<u8>>) -> Result<Self, String> {
+        let key_id = SchnorrKeyId {
+            algorithm: SchnorrAlgorithm::Ed25519,
+            name: key_name.to_string(),
+        };
+        let result = ic_cdk::management_canister::schnorr_public_key(&SchnorrPublicKeyArgs {
+            canister_id: None,
+            derivation_path: derivation_path.clone(),
+            key_id: key_id.clone(),
+        })
+        .await
+        .map_err(|e| format!("failed obtaining Schnorr public key: {:?}", e))?;
+        Ok(Self {
+            key_id,
+            derivation_path,
+            public_key: ThresholdPublicKey::new(ThresholdAlgorithm::EdDsa, result.public_key)?,
+        })
+    }
+}
+
+impl SigningBackend for ThresholdSchnorrSigner {
+    fn public_key(&self) -> &ThresholdPublicKey {
+        &self.public_key
+    }
+
+    async fn sign(&self, message: &[u8]) -> Result<Vec<u8>, String> {
+        let result = ic_cdk::management_canister::sign_with_schnorr(&SignWithSchnorrArgs {
+            message: message.to_vec(),
+            derivation_path: self.derivation_path.clone(),
+            key_id: self.key_id.clone(),
+            aux: None,
+        })
+        .await
+        .map_err(|e| format!("failed signing with Schnorr: {:?}", e))?;
+        Ok(result.signature)
+    }
+}
+
+/// Returns the bytes to be signed with `public_key` for the given JWT-credential,
+/// i.e. the signing input of the JWS.
+pub fn threshold_signing_input(
+    credential_jwt: &str,
+    issuer_canister_id: Principal,
+    public_key: &ThresholdPublicKey,
+) -> Result<Vec<u8>, String> {
+    let encoder = threshold_jws_encoder(credential_jwt, issuer_canister_id, public_key)?;
+    Ok(encoder.signing_input().to_vec())
+}
+
+/// Constructs and returns a JWS from the given JWT-credential and the signature `sig`
+/// created over the bytes returned by `threshold_signing_input()`.
+/// Note: the validity of the signature is not checked.
+pub fn threshold_jwt_to_jws(
+    credential_jwt: &str,
+    issuer_canister_id: Principal,
+    public_key: &ThresholdPublicKey,
+    sig: &[u8],
+) -> Result<String, String> {
+    let encoder = threshold_jws_encoder(credential_jwt, issuer_canister_id, public_key)?;
+    Ok(encoder.into_jws(sig))
+}
+
+/// Signs the given JWT-credential with `signer`, and returns it as a JWS.
+pub async fn sign_credential_jwt<S: SigningBackend>(
+    signer: &S,
+    issuer_canister_id: Principal,
+    credential_jwt: &str,
+) -> Result<String, String> {
+    let public_key = signer.public_key();
+    let signing_input = threshold_signing_input(credential_jwt, issuer_canister_id, public_key)?;
+    let sig = signer.sign(&signing_input).await?;
+    threshold_jwt_to_jws(credential_jwt, issuer_canister_id, public_key, &sig)
+}
+
+/// Verifies the given JWS-credential, which must be signed by `issuer_canister_id` with
+/// the key `public_key` (cf. `sign_credential_jwt()`), and returns its claims.
+/// Checks that the JWK and the `kid` in the JWS header match `public_key`.
+pub fn verify_threshold_credential_jws(
+    credential_jws: &str,
+    issuer_canister_id: &Principal,
+    public_key: &ThresholdPublicKey,
+    current_time_ns: u128,
+) -> Result<JwtClaims<Value>, SignatureVerificationError> {
+    let decoder = Decoder::new();
+    let jws = decoder
+        .decode_compact_serialization(credential_jws.as_bytes(), None)
+        .map_err(|e| invalid_signature_err(&format!("credential JWS parsing error: {}", e)))?;
+    let header = jws
+        .protected_header()
+        .ok_or(invalid_signature_err("missing JWS header"))?;
+    if header.alg() != Some(public_key.algorithm.jws_algorithm()) {
+        return Err(unsupported_alg_err(&format!(
+            "expected {}",
+            public_key.algorithm.name()
+        )));
+    }
+    let jwk = header
+        .deref()
+        .jwk()
+        .ok_or(key_decoding_err("missing JWK in JWS header"))?;
+    let header_pk = ThresholdPublicKey::from_jwk(jwk)
+        .map_err(|e| key_decoding_err(&format!("invalid JWK in JWS header: {}", e)))?;
+    if &header_pk != public_key {
+        return Err(invalid_signature_err(
+            "public key in JWS header does not match the public key of the issuer",
+        ));
+    }
+    let expected_kid = public_key
+        .kid(*issuer_canister_id)
+        .map_err(|e| key_decoding_err(&e))?;
+    if header.kid() != Some(expected_kid.as_str()) {
+        return Err(key_decoding_err(&format!(
+            "expected kid {}, got {:?}",
+            expected_kid,
+            header.kid()
+        )));
+    }
+
+    public_key
+        .verify(jws.signing_input(), jws.decoded_signature())
+        .map_err(|e| invalid_signature_err(&format!("signature verification error: {}", e)))?;
+
+    let claims: JwtClaims<Value> = serde_json::from_slice(jws.claims())
+        .map_err(|e| invalid_signature_err(&format!("failed parsing JSON JWT claims: {}", e)))?;
+    validate_expiration(claims.exp(), current_time_ns)
+        .map_err(|e| invalid_signature_err(&format!("{}: {}", CREDENTIAL_EXPIRED, e)))?;
+    Ok(claims)
+}
+
+fn threshold_jws_encoder<'a>(
+    credential_jwt: &'a str,
+    issuer_canister_id: Principal,
+    public_key: &ThresholdPublicKey,
+) -> Result<CompactJwsEncoder<'a>, String> {
+    let mut header: JwsHeader = JwsHeader::new();
+    header.set_alg(public_key.algorithm.jws_algorithm());
+    header.set_kid(public_key.kid(issuer_canister_id)?);
+    header.deref_mut().set_jwk(public_key.to_jwk()?);
+
+    let encoder: CompactJwsEncoder = CompactJwsEncoder::new(credential_jwt.as_ref(), &header)
+        .map_err(|e| format!("internal: failed creating JWS encoder: {:?}", e))?;
+    Ok(encoder)
+}
+
+fn secp256k1_public_key(public_key: &[u8]) -> Result<k256::PublicKey, String> {
+    k256::PublicKey::from_sec1_bytes(public_key)
+        .map_err(|_| "invalid secp256k1 public key".to_string())
+}
+
+fn ed25519_public_key(public_key: &[u8]) -> Result<ed25519_dalek::VerifyingKey, String> {
+    let public_key: &[u8; 32] = public_key
+        .try_into()
+        .map_err(|_| "invalid length of Ed25519 public key".to_string())?;
+    ed25519_dalek::VerifyingKey::from_bytes(public_key)
+        .map_err(|_| "invalid Ed25519 public key".to_string())
+}
+
+fn decode_jwk_coordinate(value: &str) -> Result<Vec<u8>, String> {
+    decode_b64(value.as_bytes()).map_err(|_| "invalid base64url encoding".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ED25519_PUBLIC_KEY: [u8; 32] = [
+        0xd7, 0x5a, 0x98, 0x01, 0x82, 0xb1, 0x0a, 0xb7, 0xd5, 0x4b, 0xfe, 0xd3, 0xc9, 0x64, 0x07,
+        0x3a, 0x0e, 0xe1, 0x72, 0xf3, 0xda, 0xa6, 0x23, 0x25, 0xaf, 0x02, 0x1a, 0x68, 0xf7, 0x07,
+        0x51, 0x1a,
+    ];
+
+    #[test]
+    fn should_compute_rfc_8037_thumbprint() {
+        // Example from https://datatracker.ietf.org/doc/html/rfc8037#appendix-A.3
+        let public_key =
+            ThresholdPublicKey::new(ThresholdAlgorithm::EdDsa, ED25519_PUBLIC_KEY.to_vec())
+                .expect("invalid public key");
+
+        assert_eq!(
+            public_key.thumbprint().unwrap(),
+            "kPrK_qmxVWaYVA9wwBF6Iuo3vVzz7TxHCTwXBygrS4k"
+        );
+        assert_eq!(
+            public_key.kid(Principal::anonymous()).unwrap(),
+            "did:icp:2vxsx-fae#kPrK_qmxVWaYVA9wwBF6Iuo3vVzz7TxHCTwXBygrS4k"
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_public_keys() {
+        assert!(ThresholdPublicKey::new(ThresholdAlgorithm::EdDsa, vec![1; 31]).is_err());
+        assert!(ThresholdPublicKey::new(ThresholdAlgorithm::Es256k, vec![5; 33]).is_err());
+        assert!(ThresholdPublicKey::new(ThresholdAlgorithm::Es256k, vec![]).is_err());
+    }
+
+    #[cfg(feature = "test-utils")]
+    mod signing {
+        use super::*;
+        use crate::test_utils::TestThresholdSigner;
+        use assert_matches::assert_matches;
+
+        const CREDENTIAL_JWT: &str = r#"{"iss":"https://issuer.test","exp":1700000000}"#;
+        const BEFORE_EXPIRATION_NS: u128 = 1_600_000_000_000_000_000;
+
+        fn issuer() -> Principal {
+            Principal::from_slice(&[1; 10])
+        }
+
+        fn signers() -> [TestThresholdSigner; 2] {
+            [
+                TestThresholdSigner::new(ThresholdAlgorithm::Es256k, [7; 32]),
+                TestThresholdSigner::new(ThresholdAlgorithm::EdDsa, [7; 32]),
+            ]
+        }
+
+        fn sign(signer: &TestThresholdSigner, credential_jwt: &str) -> String {
+            futures::executor::block_on(sign_credential_jwt(signer, issuer(), credential_jwt))
+                .expect("failed signing credential")
+        }
+
+        #[test]
+        fn should_roundtrip_jwk() {
+            for signer in signers() {
+                let public_key = signer.public_key();
+                let jwk = public_key.to_jwk().unwrap();
+
+                assert_eq!(jwk.alg(), Some(public_key.algorithm.name()));
+                assert_eq!(&ThresholdPublicKey::from_jwk(&jwk).unwrap(), public_key);
+            }
+        }
+
+        #[test]
+        fn should_verify_signed_credential() {
+            for signer in signers() {
+                let credential_jws = sign(&signer, CREDENTIAL_JWT);
+
+                let claims = verify_threshold_credential_jws(
+                    &credential_jws,
+                    &issuer(),
+                    signer.public_key(),
+                    BEFORE_EXPIRATION_NS,
+                )
+                .expect("failed verifying credential");
+
+                assert_eq!(claims.iss(), Some("https://issuer.test"));
+            }
+        }
+
+        #[test]
+        fn should_fail_verifying_credential_of_other_key() {
+            let other_signer = TestThresholdSigner::new(ThresholdAlgorithm::Es256k, [8; 32]);
+            for signer in signers() {
+                let credential_jws = sign(&signer, CREDENTIAL_JWT);
+
+                let result = verify_threshold_credential_jws(
+                    &credential_jws,
+                    &issuer(),
+                    other_signer.public_key(),
+                    BEFORE_EXPIRATION_NS,
+                );
+
+                assert_matches!(result, Err(_));
+            }
+        }
+
+        #[test]
+        fn should_fail_verifying_credential_of_other_issuer() {
+            for signer in signers() {
+                let credential_jws = sign(&signer, CREDENTIAL_JWT);
+
+                let result = verify_threshold_credential_jws(
+                    &credential_jws,
+                    &Principal::anonymous(),
+                    signer.public_key(),
+                    BEFORE_EXPIRATION_NS,
+                );
+
+                assert_matches!(result, Err(e) if e.to_string().contains("expected kid"));
+            }
+        }
+
+        #[test]
+        fn should_fail_verifying_tampered_credential() {
+            for signer in signers() {
+                let credential_jws = sign(&signer, CREDENTIAL_JWT);
+                let other_jws = sign(&signer, r#"{"iss":"https://other.test","exp":1700000000}"#);
+                let mut parts: Vec<&str> = credential_jws.split('.').collect();
+                parts[2] = other_jws.split('.').nth(2).unwrap();
+
+                let result = verify_threshold_credential_jws(
+                    &parts.join("."),
+                    &issuer(),
+                    signer.public_key(),
+                    BEFORE_EXPIRATION_NS,
+                );
+
+                assert_matches!(result, Err(e) if e.to_string().contains("signature verification error"));
+            }
+        }
+
+        #[test]
+        fn should_fail_verifying_expired_credential() {
+            for signer in signers() {
+                let credential_jws = sign(&signer, CREDENTIAL_JWT);
+
+                let result = verify_threshold_credential_jws(
+                    &credential_jws,
+                    &issuer(),
+                    signer.public_key(),
+                    1_700_000_000_000_000_000,
+                );
+
+                assert_matches!(result, Err(e) if e.to_string().contains("credential expired"));
+            }
+        }
+    }
+}
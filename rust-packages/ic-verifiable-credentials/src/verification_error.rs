@@ -0,0 +1,268 @@
+//! Candid-encodable verification errors, which canisters can return to their callers.
+//!
+//! `PresentationVerificationError` and `CredentialVerificationError` wrap the error types of
+//! the underlying libraries, which are neither stable nor `CandidType`.  `VerificationError`
+//! describes the same errors with a stable, machine-readable `VerificationErrorCode`, and,
+//! for claims that do not have the expected value, with the claim and the expected and
+//! actual values.
+use crate::status_list::StatusListError;
+use crate::{
+    CREDENTIAL_EXPIRED, ClaimMismatch, CredentialVerificationError, PresentationVerificationError,
+};
+use candid::{CandidType, Deserialize};
+use identity_credential::validator::JwtValidationError;
+
+#[derive(Clone, Copy, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub enum VerificationErrorCode {
+    /// The presentation is malformed, or does not contain the expected credentials.
+    InvalidPresentation,
+    /// The signature of a credential is invalid, or not by the expected signer.
+    InvalidSignature,
+    /// A credential has expired.
+    Expired,
+    /// The claims of a credential are malformed.
+    InvalidClaims,
+    /// A claim expected by the verifier is missing.
+    MissingClaim,
+    /// A claim does not have the value expected by the verifier.
+    UnexpectedClaim,
+    /// The presentation is not bound to the expected holder, audience or challenge.
+    InvalidHolderBinding,
+    /// The challenge of the presentation is unknown, already used, or expired.
+    InvalidChallenge,
+    /// The presentation was rejected by the verification policy of the verifier.
+    RejectedByPolicy,
+    /// The status list of the issuer could not be verified.
+    InvalidCredentialStatus,
+    /// The credential has been revoked by the issuer.
+    Revoked,
+    Unknown,
+}
+
+/// The credential of a presentation an error refers to.
+#[derive(Clone, Copy, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub enum PresentedCredential {
+    /// The id_alias credential issued by II.
+    IdAlias,
+    /// The credential requested from the issuer.
+    Requested,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct VerificationError {
+    pub code: VerificationErrorCode,
+    pub credential: Option<PresentedCredential>,
+    /// The claim that is missing or does not have the expected value, e.g. `iss`.
+    pub claim: Option<String>,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+    /// A description of the error for humans, which is not stable.
+    pub message: String,
+}
+
+impl VerificationError {
+    fn new(code: VerificationErrorCode, message: String) -> Self {
+        Self {
+            code,
+            credential: None,
+            claim: None,
+            expected: None,
+            actual: None,
+            message,
+        }
+    }
+
+    fn for_credential(credential: PresentedCredential, error: CredentialVerificationError) -> Self {
+        Self {
+            credential: Some(credential),
+            ..Self::from(error)
+        }
+    }
+}
+
+impl From<CredentialVerificationError> for VerificationError {
+    fn from(error: CredentialVerificationError) -> Self {
+        match error {
+            CredentialVerificationError::InvalidJws(e) => {
+                let message = e.to_string();
+                let code = if message.contains(CREDENTIAL_EXPIRED) {
+                    VerificationErrorCode::Expired
+                } else {
+                    VerificationErrorCode::InvalidSignature
+                };
+                Self::new(code, message)
+            }
+            CredentialVerificationError::InvalidClaims(JwtValidationError::ExpirationDate) => {
+                Self::new(
+                    VerificationErrorCode::Expired,
+                    JwtValidationError::ExpirationDate.to_string(),
+                )
+            }
+            CredentialVerificationError::InvalidClaims(e) => {
+                Self::new(VerificationErrorCode::InvalidClaims, e.to_string())
+            }
+            CredentialVerificationError::UnexpectedClaim(ClaimMismatch {
+                claim,
+                expected,
+                actual,
+            }) => {
+                let (code, message) = match &actual {
+                    Some(actual) => (
+                        VerificationErrorCode::UnexpectedClaim,
+                        format!(
+                            "unexpected claim {}: expected {}, actual {}",
+                            claim, expected, actual
+                        ),
+                    ),
+                    None => (
+                        VerificationErrorCode::MissingClaim,
+                        format!("missing claim {}: expected {}", claim, expected),
+                    ),
+                };
+                Self {
+                    claim: Some(claim),
+                    expected: Some(expected),
+                    actual,
+                    ..Self::new(code, message)
+                }
+            }
+        }
+    }
+}
+
+impl From<PresentationVerificationError> for VerificationError {
+    fn from(error: PresentationVerificationError) -> Self {
+        match error {
+            PresentationVerificationError::InvalidIdAliasCredential(e) => {
+                Self::for_credential(PresentedCredential::IdAlias, e)
+            }
+            PresentationVerificationError::InvalidRequestedCredential(e) => {
+                Self::for_credential(PresentedCredential::Requested, e)
+            }
+            PresentationVerificationError::InvalidPresentationJwt(message) => {
+                Self::new(VerificationErrorCode::InvalidPresentation, message)
+            }
+            PresentationVerificationError::InvalidHolderBinding(message) => {
+                Self::new(VerificationErrorCode::InvalidHolderBinding, message)
+            }
+            PresentationVerificationError::InvalidChallenge(e) => {
+                Self::new(VerificationErrorCode::InvalidChallenge, format!("{:?}", e))
+            }
+            PresentationVerificationError::RejectedByPolicy(message) => {
+                Self::new(VerificationErrorCode::RejectedByPolicy, message)
+            }
+            PresentationVerificationError::InvalidCredentialStatus(e) => {
+                let code = match e {
+                    StatusListError::Revoked { .. } => VerificationErrorCode::Revoked,
+                    _ => VerificationErrorCode::InvalidCredentialStatus,
+                };
+                Self {
+                    credential: Some(PresentedCredential::Requested),
+                    ..Self::new(code, format!("{:?}", e))
+                }
+            }
+            PresentationVerificationError::Unknown(message) => {
+                Self::new(VerificationErrorCode::Unknown, message)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::ChallengeError;
+    use candid::{decode_one, encode_one};
+
+    fn unexpected_issuer() -> PresentationVerificationError {
+        PresentationVerificationError::InvalidRequestedCredential(
+            CredentialVerificationError::UnexpectedClaim(ClaimMismatch {
+                claim: "iss".to_string(),
+                expected: "https://issuer.test".to_string(),
+                actual: Some("https://other-issuer.test".to_string()),
+            }),
+        )
+    }
+
+    #[test]
+    fn should_describe_unexpected_claim() {
+        let error = VerificationError::from(unexpected_issuer());
+
+        assert_eq!(
+            error,
+            VerificationError {
+                code: VerificationErrorCode::UnexpectedClaim,
+                credential: Some(PresentedCredential::Requested),
+                claim: Some("iss".to_string()),
+                expected: Some("https://issuer.test".to_string()),
+                actual: Some("https://other-issuer.test".to_string()),
+                message: "unexpected claim iss: expected https://issuer.test, actual https://other-issuer.test".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn should_describe_missing_claim() {
+        let error =
+            VerificationError::from(PresentationVerificationError::InvalidIdAliasCredential(
+                CredentialVerificationError::UnexpectedClaim(ClaimMismatch {
+                    claim: "iss".to_string(),
+                    expected: "https://identity.ic0.app/".to_string(),
+                    actual: None,
+                }),
+            ));
+
+        assert_eq!(error.code, VerificationErrorCode::MissingClaim);
+        assert_eq!(error.credential, Some(PresentedCredential::IdAlias));
+        assert_eq!(error.claim.as_deref(), Some("iss"));
+        assert_eq!(error.actual, None);
+    }
+
+    #[test]
+    fn should_map_error_codes() {
+        let cases = [
+            (
+                PresentationVerificationError::InvalidPresentationJwt("malformed".to_string()),
+                VerificationErrorCode::InvalidPresentation,
+            ),
+            (
+                PresentationVerificationError::InvalidChallenge(ChallengeError::ExpiredChallenge),
+                VerificationErrorCode::InvalidChallenge,
+            ),
+            (
+                PresentationVerificationError::RejectedByPolicy("not audited".to_string()),
+                VerificationErrorCode::RejectedByPolicy,
+            ),
+            (
+                PresentationVerificationError::InvalidCredentialStatus(StatusListError::Revoked {
+                    index: 7,
+                }),
+                VerificationErrorCode::Revoked,
+            ),
+            (
+                PresentationVerificationError::InvalidCredentialStatus(
+                    StatusListError::StaleStatusList { certified_at_ns: 0 },
+                ),
+                VerificationErrorCode::InvalidCredentialStatus,
+            ),
+            (
+                PresentationVerificationError::InvalidRequestedCredential(
+                    CredentialVerificationError::InvalidClaims(JwtValidationError::ExpirationDate),
+                ),
+                VerificationErrorCode::Expired,
+            ),
+        ];
+        for (error, code) in cases {
+            assert_eq!(VerificationError::from(error).code, code);
+        }
+    }
+
+    #[test]
+    fn should_encode_as_candid() {
+        let error = VerificationError::from(unexpected_issuer());
+
+        let decoded: VerificationError = decode_one(&encode_one(&error).unwrap()).unwrap();
+
+        assert_eq!(decoded, error);
+    }
+}
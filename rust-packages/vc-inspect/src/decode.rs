@@ -0,0 +1,150 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use candid::Principal;
+use ic_canister_sig_creation::CanisterSigPublicKey;
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+
+/// A decoded (but not verified) credential or presentation.
+pub struct DecodedJwt {
+    pub header: Value,
+    pub claims: Value,
+    /// The canister signature public key from the `jwk` in the header, if any.
+    pub canister_sig_pk: Option<CanisterSigPublicKey>,
+    /// The credentials contained in a presentation, decoded recursively.
+    pub credentials: Vec<DecodedJwt>,
+}
+
+/// Decodes the given credential (JWS) or presentation (JWT, possibly unsigned).
+pub fn decode(input: &str) -> Result<DecodedJwt, String> {
+    let mut parts = input.trim().split('.');
+    let (Some(header), Some(claims)) = (parts.next(), parts.next()) else {
+        return Err("expected a JWT or JWS in compact serialization".to_string());
+    };
+    let header = decode_json_part(header).map_err(|e| format!("invalid header: {}", e))?;
+    let claims = decode_json_part(claims).map_err(|e| format!("invalid claims: {}", e))?;
+    let canister_sig_pk = match header.pointer("/jwk/k") {
+        Some(k) => Some(canister_sig_pk_from_jwk_key(k)?),
+        None => None,
+    };
+    let credentials = credentials_of(&claims)
+        .into_iter()
+        .enumerate()
+        .map(|(i, credential)| {
+            decode(credential).map_err(|e| format!("invalid credential #{}: {}", i + 1, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(DecodedJwt {
+        header,
+        claims,
+        canister_sig_pk,
+        credentials,
+    })
+}
+
+/// Returns the credentials contained in the claims of a presentation.
+pub fn credentials_of(claims: &Value) -> Vec<&str> {
+    claims
+        .pointer("/vp/verifiableCredential")
+        .and_then(Value::as_array)
+        .map(|credentials| credentials.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default()
+}
+
+fn decode_json_part(part: &str) -> Result<Value, String> {
+    let bytes = BASE64
+        .decode(part)
+        .map_err(|e| format!("base64 decoding failed: {}", e))?;
+    serde_json::from_slice(&bytes).map_err(|e| format!("JSON parsing failed: {}", e))
+}
+
+fn canister_sig_pk_from_jwk_key(k: &Value) -> Result<CanisterSigPublicKey, String> {
+    let pk_der = k
+        .as_str()
+        .ok_or("malformed jwk key")
+        .and_then(|k| BASE64.decode(k).map_err(|_| "malformed jwk key"))?;
+    CanisterSigPublicKey::try_from(pk_der.as_slice())
+        .map_err(|e| format!("invalid canister sig public key: {}", e))
+}
+
+impl Display for DecodedJwt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+impl DecodedJwt {
+    fn fmt_indented(&self, f: &mut Formatter<'_>, indent: usize) -> std::fmt::Result {
+        let pad = " ".repeat(indent);
+        let pretty = |value: &Value| {
+            serde_json::to_string_pretty(value)
+                .unwrap_or_default()
+                .replace('\n', &format!("\n{}", pad))
+        };
+        writeln!(f, "{}header: {}", pad, pretty(&self.header))?;
+        writeln!(f, "{}claims: {}", pad, pretty(&self.claims))?;
+        if let Some(pk) = &self.canister_sig_pk {
+            writeln!(f, "{}canister signature public key:", pad)?;
+            writeln!(f, "{}  canister id: {}", pad, pk.canister_id)?;
+            writeln!(f, "{}  seed: {}", pad, hex::encode(&pk.seed))?;
+            writeln!(
+                f,
+                "{}  principal: {}",
+                pad,
+                Principal::self_authenticating(pk.to_der())
+            )?;
+        }
+        for (i, credential) in self.credentials.iter().enumerate() {
+            writeln!(f, "{}credential #{}:", pad, i + 1)?;
+            credential.fmt_indented(f, indent + 2)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{ALIAS_JWS, II_SIGNING_CANISTER_ID};
+    use ic_verifiable_credentials::build_ii_verifiable_presentation_jwt;
+    use serde_json::json;
+
+    #[test]
+    fn should_decode_credential() {
+        let decoded = decode(ALIAS_JWS).expect("failed decoding credential");
+        assert_eq!(decoded.header["alg"], json!("IcCs"));
+        assert_eq!(decoded.claims["iss"], json!("https://identity.ic0.app/"));
+        assert_eq!(
+            decoded
+                .canister_sig_pk
+                .expect("missing canister sig pk")
+                .canister_id
+                .to_text(),
+            II_SIGNING_CANISTER_ID
+        );
+        assert!(decoded.credentials.is_empty());
+    }
+
+    #[test]
+    fn should_decode_presentation() {
+        let holder =
+            Principal::from_text("7eboi-tyuys-aqm4c-w2l7i-vgucm-xvawx-lemzx-6kq2g-f53u7-yvfh2-nae")
+                .unwrap();
+        let vp_jwt = build_ii_verifiable_presentation_jwt(
+            holder,
+            ALIAS_JWS.to_string(),
+            ALIAS_JWS.to_string(),
+        )
+        .expect("failed building presentation");
+        let decoded = decode(&vp_jwt).expect("failed decoding presentation");
+        assert!(decoded.canister_sig_pk.is_none());
+        assert_eq!(decoded.credentials.len(), 2);
+        assert!(decoded.to_string().contains("credential #2:"));
+    }
+
+    #[test]
+    fn should_fail_decoding_malformed_input() {
+        assert!(decode("not a jwt").is_err());
+        assert!(decode("bm90IGpzb24.e30.").is_err());
+    }
+}
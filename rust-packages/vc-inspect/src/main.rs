@@ -0,0 +1,273 @@
+//! Off-chain decoding and verification of verifiable credentials and presentations
+//! of the Internet Computer, e.g. for debugging failed attribute sharing flows.
+use candid::Principal;
+use clap::{Args, Parser, Subcommand};
+use ic_verifiable_credentials::clock::{FixedClock, set_clock};
+use ic_verifiable_credentials::environment::IcRootKey;
+use ic_verifiable_credentials::issuer_api::{ArgumentValue, CredentialSpec};
+use ic_verifiable_credentials::{II_ISSUER_URL, VcFlowSigners};
+use std::collections::HashMap;
+use std::io::Read;
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod decode;
+mod verify;
+
+#[derive(Parser)]
+#[command(
+    name = "vc-inspect",
+    version,
+    about = "Decodes and verifies verifiable credentials and presentations of the Internet Computer"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Decodes a credential (JWS) or a presentation (JWT), and prints the header, the claims,
+    /// the canister signature public key, and the contained credentials.
+    Decode {
+        /// The credential or presentation, or `-` to read it from stdin.
+        input: String,
+    },
+    /// Verifies the signature and expiry of a credential.
+    VerifyVc {
+        /// The credential, or `-` to read it from stdin.
+        input: String,
+        /// The canister that signed the credential.
+        #[arg(long, value_parser = parse_principal)]
+        signer: Principal,
+        #[command(flatten)]
+        env: VerificationEnv,
+    },
+    /// Verifies a presentation as returned by II, and checks that it matches the credential spec.
+    VerifyVp {
+        /// The presentation, or `-` to read it from stdin.
+        input: String,
+        /// The principal of the user at the relying party.
+        #[arg(long, value_parser = parse_principal)]
+        subject: Principal,
+        /// The derivation origin of the relying party.
+        #[arg(long)]
+        derivation_origin: String,
+        /// The canister that signed the id_alias credential.
+        #[arg(long, value_parser = parse_principal)]
+        ii_canister_id: Principal,
+        #[arg(long, default_value = II_ISSUER_URL)]
+        ii_origin: String,
+        /// The canister that signed the requested credential.
+        #[arg(long, value_parser = parse_principal)]
+        issuer_canister_id: Principal,
+        #[arg(long)]
+        issuer_origin: String,
+        /// The type of the requested credential.
+        #[arg(long)]
+        credential_type: String,
+        /// An argument of the requested credential; values that are integers are passed as `Int`.
+        #[arg(long = "argument", value_name = "NAME=VALUE", value_parser = parse_argument)]
+        arguments: Vec<(String, ArgumentValue)>,
+        #[command(flatten)]
+        env: VerificationEnv,
+    },
+}
+
+#[derive(Args)]
+struct VerificationEnv {
+    /// The DER-encoded IC root public key (hex or base64url). Defaults to the mainnet root key.
+    #[arg(long)]
+    root_key: Option<String>,
+    /// The time at which to verify, in seconds since the UNIX epoch. Defaults to now.
+    #[arg(long)]
+    time: Option<u64>,
+}
+
+impl VerificationEnv {
+    fn root_key(&self) -> Result<IcRootKey, String> {
+        match &self.root_key {
+            Some(root_key) => IcRootKey::from_der(&decode_bytes(root_key)?),
+            None => Ok(IcRootKey::mainnet()),
+        }
+    }
+
+    fn current_time_ns(&self) -> u128 {
+        match self.time {
+            Some(time_s) => time_s as u128 * 1_000_000_000,
+            None => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("system time before UNIX epoch")
+                .as_nanos(),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+// Returns whether all the checks passed.
+fn run(cli: Cli) -> Result<bool, String> {
+    match cli.command {
+        Command::Decode { input } => {
+            print!("{}", decode::decode(&read_input(&input)?)?);
+            Ok(true)
+        }
+        Command::VerifyVc { input, signer, env } => {
+            let current_time_ns = env.current_time_ns();
+            set_clock(FixedClock(current_time_ns));
+            let report = verify::verify_vc(
+                &read_input(&input)?,
+                &signer,
+                &env.root_key()?,
+                current_time_ns,
+            );
+            print!("{}", report);
+            Ok(report.is_success())
+        }
+        Command::VerifyVp {
+            input,
+            subject,
+            derivation_origin,
+            ii_canister_id,
+            ii_origin,
+            issuer_canister_id,
+            issuer_origin,
+            credential_type,
+            arguments,
+            env,
+        } => {
+            let current_time_ns = env.current_time_ns();
+            set_clock(FixedClock(current_time_ns));
+            let params = verify::PresentationParams {
+                effective_vc_subject: subject,
+                effective_derivation_origin: derivation_origin,
+                vc_flow_signers: VcFlowSigners {
+                    ii_canister_id,
+                    ii_origin,
+                    issuer_canister_id,
+                    issuer_origin,
+                },
+                vc_spec: CredentialSpec {
+                    credential_type,
+                    arguments: (!arguments.is_empty())
+                        .then(|| arguments.into_iter().collect::<HashMap<_, _>>()),
+                },
+            };
+            let report = verify::verify_vp(
+                &read_input(&input)?,
+                &params,
+                &env.root_key()?,
+                current_time_ns,
+            );
+            print!("{}", report);
+            Ok(report.is_success())
+        }
+    }
+}
+
+fn read_input(input: &str) -> Result<String, String> {
+    if input != "-" {
+        return Ok(input.trim().to_string());
+    }
+    let mut buffer = String::new();
+    std::io::stdin()
+        .read_to_string(&mut buffer)
+        .map_err(|e| format!("failed reading stdin: {}", e))?;
+    Ok(buffer.trim().to_string())
+}
+
+fn parse_principal(text: &str) -> Result<Principal, String> {
+    Principal::from_text(text).map_err(|e| format!("invalid principal: {}", e))
+}
+
+fn parse_argument(text: &str) -> Result<(String, ArgumentValue), String> {
+    let (name, value) = text
+        .split_once('=')
+        .ok_or("expected an argument of the form NAME=VALUE")?;
+    let value = match value.parse::<i32>() {
+        Ok(i) => ArgumentValue::Int(i),
+        Err(_) => ArgumentValue::String(value.to_string()),
+    };
+    Ok((name.to_string(), value))
+}
+
+// Decodes the given hex- or base64url-encoded bytes.
+fn decode_bytes(encoded: &str) -> Result<Vec<u8>, String> {
+    use base64::Engine;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+    if let Ok(bytes) = hex::decode(encoded) {
+        return Ok(bytes);
+    }
+    BASE64
+        .decode(encoded.trim_end_matches('='))
+        .map_err(|_| "expected hex or base64url encoding".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(crate) const II_SIGNING_CANISTER_ID: &str = "fgte5-ciaaa-aaaad-aaatq-cai";
+    pub(crate) const ALIAS_JWS_EXPIRY_S: u64 = 1729758359;
+    pub(crate) const ALIAS_JWS: &str = "eyJqd2siOnsia3R5Ijoib2N0IiwiYWxnIjoiSWNDcyIsImsiOiJNRHd3REFZS0t3WUJCQUdEdUVNQkFnTXNBQW9BQUFBQUFHQUFKd0VCMWROcEZhMjNYSHVienc2YlA3MUVTb20yTFFwcUhmWjVhazdOWGRqeFdVQSJ9LCJraWQiOiJkaWQ6aWNwOmZndGU1LWNpYWFhLWFhYWFkLWFhYXRxLWNhaSIsImFsZyI6IkljQ3MifQ.eyJleHAiOjE3Mjk3NTgzNTksImlzcyI6Imh0dHBzOi8vaWRlbnRpdHkuaWMwLmFwcC8iLCJuYmYiOjE3Mjk3NTc0NTksImp0aSI6ImRhdGE6dGV4dC9wbGFpbjtjaGFyc2V0PVVURi04LHRpbWVzdGFtcF9uczoxNzI5NzU3NDU5MDQyODQ0NzkyLGFsaWFzX2hhc2g6ZDQ4OTIwM2EwOGExY2Q0N2YxY2QyOWVkZDEwODdhNTIxOWJhMDc2NzM4ZmUwYWU1YWUzOWNjZDlmMTFhNzUzMyIsInN1YiI6ImRpZDppY3A6N2Vib2ktdHl1eXMtYXFtNGMtdzJsN2ktdmd1Y20teHZhd3gtbGVtengtNmtxMmctZjUzdTcteXZmaDItbmFlIiwidmMiOnsiQGNvbnRleHQiOiJodHRwczovL3d3dy53My5vcmcvMjAxOC9jcmVkZW50aWFscy92MSIsInR5cGUiOlsiVmVyaWZpYWJsZUNyZWRlbnRpYWwiLCJJbnRlcm5ldElkZW50aXR5SWRBbGlhcyJdLCJjcmVkZW50aWFsU3ViamVjdCI6eyJJbnRlcm5ldElkZW50aXR5SWRBbGlhcyI6eyJoYXNJZEFsaWFzIjoiemo3ZmgtajNjZ3YtZW9qZHItaDU1aGEtNG82N3ItMzJ1dHUtMjJpdjItbzdoaHktM3lvb3QteGRjcmYtNXFlIiwiZGVyaXZhdGlvbk9yaWdpbiI6Imh0dHBzOi8vbDdydWEtcmFhYWEtYWFhYXAtYWhoNmEtY2FpLmljMC5hcHAifX19fQ.2dn3omtjZXJ0aWZpY2F0ZVkFbdnZ96NkdHJlZYMBgwGDAYIEWCCmWvo44iQiiGOonrtzz-Vc-cC15sj4dWw5iM14OXZei4MCSGNhbmlzdGVygwGDAYMBgwGDAYMBggRYIAIQTI4tvDRgL5ohfUDFZTkN99yxeUcFSZEPNIUMKZIVgwGCBFggQEL7KETbIG4XJKJI7vOT9csdIigPKY2Uj8GOCkCFM0ODAYIEWCCNPbxbGsgH608xO5FxLblP30pQBoIHcZ8cujd3GyrI74MCSgAAAAAAYAAnAQGDAYMBgwJOY2VydGlmaWVkX2RhdGGCA1ggK94e042ibWRcCDatgNq7FbfQvmk6k_gEnuLRHVktoaWCBFggbM1rsxpUdh1KVunP2MujhNW4-0cYToyhPLcOBPIgms6CBFggJxQXULs1dQvHYawdYMxqLUmGQD8B1du-ha8XhgEfBPSCBFggPeeB3ggR9ahGkWbFlPlDPZZvaG9PQGWtk5XjC_rBU-KCBFggyyqUBXAErjNvtSujkRfPkKqt7-At3-kgW8wTyPYVCgKCBFggvB-bTFT2brj8JTgekGQa5Z74fFkBhjVRYqUstIdSQsuCBFggb8yhZG3SMgMK91m93LVVhLWbI-8p3BVCgObtABGjCHeCBFgg9_rtUWU57ZbqOhjuhW_K5PePAOkqbSsD8qC8yNYyjP6CBFggJAf5RCyKZ6eeRpJCfSKCI65TMIwLHHJK8yOXVBNXs_KDAYIEWCDSNFYEA4p6QwQAd18Nc6Jp25bkaCmmZeMHfTuH_iQdnoMCRHRpbWWCA0n4gNPu6KfVgBhpc2lnbmF0dXJlWDCS57wG5PXgM1EPsxX76Z9YUWSD98WNfz65NnZBZoXGmyBFazFCFS9l7dT2602nOy5qZGVsZWdhdGlvbqJpc3VibmV0X2lkWB0sVbNH7PJobIN4HWxZ0bQ-e0y6jetsGzdhB_LNAmtjZXJ0aWZpY2F0ZVkClNnZ96JkdHJlZYMBggRYIMn7ubxmVJ-rn7nj6UC7uIZSpjd9DZIZQkBUqkfLmjUggwGDAYIEWCBYlr0e6vPqPOEgzNI1ULovxaF5f0mqIr0kZk0gGA3mj4MCRnN1Ym5ldIMBgwGDAYMBggRYIIc5-77dPe2qj-9BhwNnwJBb3jdrY9034rF2-wi1ggUvgwGCBFggg_I0Z_VeOyicnsbKOf6cAu9lvR5u1cEYZsKqvna-qnaDAYMCWB0sVbNH7PJobIN4HWxZ0bQ-e0y6jetsGzdhB_LNAoMBgwJPY2FuaXN0ZXJfcmFuZ2VzggNYMtnZ94KCSgAAAAAAYAAAAQFKAAAAAABgAK4BAYJKAAAAAABgALABAUoAAAAAAG___wEBgwJKcHVibGljX2tleYIDWIUwgYIwHQYNKwYBBAGC3HwFAwECAQYMKwYBBAGC3HwFAwIBA2EAkAdRIHeOshpTCgK8x2Pn9KGSkzUGlmr3tUwQpNKyTeaoayAONEC65iZ79MSI2aEdBHLDjBtiIRmPmOTmiCujilpOOqWvzombf4Je2VrfoSYpaIBzVW8nR1JyE-jXPkDOggRYIDbzzSV9kPs45CWX8ZOl4DHb1YW2KSeTuwTbR5SAPOBuggRYIIj-oNtp84-c8_uoj4oEDzytya53cvoaQGpupGT6hYueggRYIGlh7xN8Ku4LBGcILvbTwSwD6TATtgKky2IUJw5ISGPxggRYIH4XYSU8iq7e4e1D2Iu1vaYwwPH4nnQKa88RkZ8eDR8LgwJEdGltZYIDScO7z9Hp8ZuAGGlzaWduYXR1cmVYMJSfsuzf0IsuGWBkZnOkzxXFhJHg30mRgqLNMxQLgcKrygFQuYg2iR4BecwAja35ZmR0cmVlgwGCBFggY_VDRlzjs-wNRdGdOHAK2Qfbt6qCiMuZ0VxLk2vfbziDAkNzaWeDAYIEWCAMbgXnP8FSPBVYUcL1bvbtHEYgLUFzvvhw-QR-UW6Uv4MCWCDz48dIHnkz9PyXWqYC1lBlRQSCFT7a2tJpWgMWbvMOoIMBgwJYID1jLiMLV5LdNLLULW3ax_err0pz6iJJUhe8uBMAiqzdggNAggRYIOIQ-FDH9cCtWre7kxNlvAKzOyNp6vymkVPgsJzxl6ve";
+
+    #[test]
+    fn should_parse_arguments() {
+        assert_eq!(
+            parse_argument("minAge=18"),
+            Ok(("minAge".to_string(), ArgumentValue::Int(18)))
+        );
+        assert_eq!(
+            parse_argument("employerName=DFINITY Foundation"),
+            Ok((
+                "employerName".to_string(),
+                ArgumentValue::String("DFINITY Foundation".to_string())
+            ))
+        );
+        assert!(parse_argument("minAge").is_err());
+    }
+
+    #[test]
+    fn should_decode_root_key_in_hex_and_base64url() {
+        use base64::Engine;
+        use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+        let env_with_root_key = |root_key: String| VerificationEnv {
+            root_key: Some(root_key),
+            time: None,
+        };
+        let expected = VerificationEnv {
+            root_key: None,
+            time: None,
+        }
+        .root_key()
+        .expect("invalid mainnet root key");
+        assert_eq!(
+            env_with_root_key(hex::encode(IcRootKey::mainnet().to_der())).root_key(),
+            Ok(expected.clone())
+        );
+        assert_eq!(
+            env_with_root_key(BASE64.encode(IcRootKey::mainnet().to_der())).root_key(),
+            Ok(expected)
+        );
+        assert!(
+            env_with_root_key("not a key".to_string())
+                .root_key()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn should_verify_cli_definition() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+}
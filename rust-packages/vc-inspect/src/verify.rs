@@ -0,0 +1,367 @@
+use crate::decode::credentials_of;
+use candid::Principal;
+use ic_verifiable_credentials::environment::IcRootKey;
+use ic_verifiable_credentials::issuer_api::CredentialSpec;
+use ic_verifiable_credentials::verification_error::VerificationError;
+use ic_verifiable_credentials::{
+    CredentialVerificationError, VcFlowSigners, check_alias_tuple, did_for_principal,
+    validate_claims_match_spec, validate_ii_presentation_and_claims,
+    verify_credential_jws_with_canister_id, verify_id_alias_credential_jws,
+};
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+
+pub enum Outcome {
+    Passed,
+    Failed(String),
+    Skipped(String),
+}
+
+pub struct Check {
+    pub name: String,
+    pub outcome: Outcome,
+}
+
+/// The outcomes of the individual checks of a verification.
+#[derive(Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    fn record<T, E: Display>(&mut self, name: &str, result: Result<T, E>) -> Option<T> {
+        let (outcome, value) = match result {
+            Ok(value) => (Outcome::Passed, Some(value)),
+            Err(e) => (Outcome::Failed(e.to_string()), None),
+        };
+        self.checks.push(Check {
+            name: name.to_string(),
+            outcome,
+        });
+        value
+    }
+
+    fn skip(&mut self, name: &str, reason: &str) {
+        self.checks.push(Check {
+            name: name.to_string(),
+            outcome: Outcome::Skipped(reason.to_string()),
+        });
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| matches!(check.outcome, Outcome::Passed))
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for check in &self.checks {
+            match &check.outcome {
+                Outcome::Passed => writeln!(f, "[ok]      {}", check.name)?,
+                Outcome::Failed(e) => writeln!(f, "[FAILED]  {}: {}", check.name, e)?,
+                Outcome::Skipped(reason) => writeln!(f, "[skipped] {}: {}", check.name, reason)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Verifies the signature and the expiry of the given credential.
+pub fn verify_vc(
+    credential_jws: &str,
+    signing_canister_id: &Principal,
+    root_key: &IcRootKey,
+    current_time_ns: u128,
+) -> Report {
+    let mut report = Report::default();
+    report.record(
+        &format!("credential signature by {} and expiry", signing_canister_id),
+        verify_credential_jws_with_canister_id(
+            credential_jws,
+            signing_canister_id,
+            root_key,
+            current_time_ns,
+        ),
+    );
+    report
+}
+
+/// Parameters of the verification of a presentation.
+pub struct PresentationParams {
+    pub effective_vc_subject: Principal,
+    pub effective_derivation_origin: String,
+    pub vc_flow_signers: VcFlowSigners,
+    pub vc_spec: CredentialSpec,
+}
+
+/// Verifies the given presentation step by step, continuing after failed checks where possible,
+/// and finally runs the complete validation via `validate_ii_presentation_and_claims()`.
+pub fn verify_vp(
+    vp_jwt: &str,
+    params: &PresentationParams,
+    root_key: &IcRootKey,
+    current_time_ns: u128,
+) -> Report {
+    let mut report = Report::default();
+    let signers = &params.vc_flow_signers;
+    let credentials = report
+        .record("presentation structure", presentation_credentials(vp_jwt))
+        .unwrap_or_default();
+
+    match credentials.first() {
+        Some(id_alias_jws) => {
+            let alias_tuple = report.record(
+                &format!(
+                    "id_alias credential signature by {} and expiry",
+                    signers.ii_canister_id
+                ),
+                verify_id_alias_credential_jws(
+                    id_alias_jws,
+                    &signers.ii_canister_id,
+                    &signers.ii_origin,
+                    root_key,
+                    current_time_ns,
+                )
+                .map_err(debug_string),
+            );
+            let name = "id_alias credential subject and derivation origin";
+            match &alias_tuple {
+                Some(alias_tuple) => {
+                    report.record(
+                        name,
+                        check_alias_tuple(
+                            alias_tuple,
+                            &params.effective_vc_subject,
+                            &params.effective_derivation_origin,
+                            &signers.ii_canister_id,
+                        )
+                        .map_err(claim_error),
+                    );
+                }
+                None => report.skip(name, "id_alias credential is invalid"),
+            }
+        }
+        None => report.skip("id_alias credential", "missing in presentation"),
+    }
+
+    match credentials.get(1) {
+        Some(requested_vc_jws) => {
+            let claims = report.record(
+                &format!(
+                    "requested credential signature by {} and expiry",
+                    signers.issuer_canister_id
+                ),
+                verify_credential_jws_with_canister_id(
+                    requested_vc_jws,
+                    &signers.issuer_canister_id,
+                    root_key,
+                    current_time_ns,
+                )
+                .map_err(|e| e.to_string())
+                .and_then(|claims| serde_json::to_value(claims).map_err(|e| e.to_string())),
+            );
+            let checks = [
+                "requested credential issuer",
+                "requested credential matches spec",
+            ];
+            match claims {
+                Some(claims) => {
+                    report.record(
+                        checks[0],
+                        expect_claim(&claims, "iss", &signers.issuer_origin),
+                    );
+                    report.record(
+                        checks[1],
+                        claims
+                            .get("vc")
+                            .and_then(Value::as_object)
+                            .ok_or("missing vc claim".to_string())
+                            .and_then(|vc| {
+                                validate_claims_match_spec(vc, &params.vc_spec).map_err(claim_error)
+                            }),
+                    );
+                }
+                None => checks
+                    .iter()
+                    .for_each(|name| report.skip(name, "requested credential is invalid")),
+            }
+        }
+        None => report.skip("requested credential", "missing in presentation"),
+    }
+
+    report.record(
+        &format!(
+            "complete validation (subject {})",
+            did_for_principal(params.effective_vc_subject)
+        ),
+        validate_ii_presentation_and_claims(
+            vp_jwt,
+            params.effective_vc_subject,
+            params.effective_derivation_origin.clone(),
+            signers,
+            &params.vc_spec,
+            root_key,
+            current_time_ns,
+        )
+        .map_err(debug_string),
+    );
+    report
+}
+
+fn presentation_credentials(vp_jwt: &str) -> Result<Vec<String>, String> {
+    let decoded = crate::decode::decode(vp_jwt)?;
+    let credentials: Vec<String> = credentials_of(&decoded.claims)
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    if credentials.len() != 2 {
+        return Err(format!(
+            "expected exactly 2 credentials, got {}",
+            credentials.len()
+        ));
+    }
+    Ok(credentials)
+}
+
+fn expect_claim(claims: &Value, name: &str, expected: &str) -> Result<(), String> {
+    match claims.get(name).and_then(Value::as_str) {
+        Some(actual) if actual == expected => Ok(()),
+        Some(actual) => Err(format!("expected {}, got {}", expected, actual)),
+        None => Err(format!("missing {} claim", name)),
+    }
+}
+
+// Describes the claim which does not match the expected value.
+fn claim_error(e: CredentialVerificationError) -> String {
+    VerificationError::from(e).message
+}
+
+fn debug_string<E: std::fmt::Debug>(e: E) -> String {
+    format!("{:?}", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{ALIAS_JWS, ALIAS_JWS_EXPIRY_S, II_SIGNING_CANISTER_ID};
+    use ic_verifiable_credentials::II_ISSUER_URL;
+    use ic_verifiable_credentials::test_utils::TestVcFlow;
+
+    const NOW_NS: u128 = 1_700_000_000_000_000_000;
+    const RP_ORIGIN: &str = "https://relying-party.test";
+
+    #[test]
+    fn should_report_valid_credential() {
+        let report = verify_vc(
+            ALIAS_JWS,
+            &Principal::from_text(II_SIGNING_CANISTER_ID).unwrap(),
+            &IcRootKey::mainnet(),
+            (ALIAS_JWS_EXPIRY_S - 60) as u128 * 1_000_000_000,
+        );
+        assert!(report.is_success(), "{}", report);
+    }
+
+    #[test]
+    fn should_report_expired_credential() {
+        let report = verify_vc(
+            ALIAS_JWS,
+            &Principal::from_text(II_SIGNING_CANISTER_ID).unwrap(),
+            &IcRootKey::mainnet(),
+            (ALIAS_JWS_EXPIRY_S + 60) as u128 * 1_000_000_000,
+        );
+        assert!(!report.is_success());
+        assert!(report.to_string().contains("credential expired"));
+    }
+
+    #[test]
+    fn should_report_each_failed_check_of_presentation() {
+        let ii_canister_id = Principal::from_text(II_SIGNING_CANISTER_ID).unwrap();
+        let params = PresentationParams {
+            effective_vc_subject: Principal::anonymous(),
+            effective_derivation_origin: "https://some.origin".to_string(),
+            vc_flow_signers: VcFlowSigners {
+                ii_canister_id,
+                ii_origin: II_ISSUER_URL.to_string(),
+                issuer_canister_id: ii_canister_id,
+                issuer_origin: "https://issuer.example".to_string(),
+            },
+            vc_spec: CredentialSpec {
+                credential_type: "VerifiedAdult".to_string(),
+                arguments: None,
+            },
+        };
+        let report = verify_vp(
+            "not a presentation",
+            &params,
+            &IcRootKey::mainnet(),
+            (ALIAS_JWS_EXPIRY_S - 60) as u128 * 1_000_000_000,
+        );
+        assert_eq!(
+            outcomes(&report),
+            vec!["failed", "skipped", "skipped", "failed"]
+        );
+    }
+
+    #[test]
+    fn should_report_valid_presentation() {
+        let flow = TestVcFlow::default();
+        let id_dapp = Principal::from_slice(&[7; 29]);
+        let params = presentation_params(&flow, id_dapp);
+        let vp_jwt = flow.presentation_jwt(id_dapp, RP_ORIGIN, &params.vc_spec, NOW_NS);
+
+        let report = verify_vp(&vp_jwt, &params, &flow.ic_root_key(), NOW_NS);
+
+        assert!(report.is_success(), "{}", report);
+        assert_eq!(outcomes(&report), vec!["passed"; 7]);
+    }
+
+    #[test]
+    fn should_report_presentation_for_other_subject() {
+        let flow = TestVcFlow::default();
+        let params = presentation_params(&flow, Principal::from_slice(&[8; 29]));
+        let vp_jwt = flow.presentation_jwt(
+            Principal::from_slice(&[7; 29]),
+            RP_ORIGIN,
+            &params.vc_spec,
+            NOW_NS,
+        );
+
+        let report = verify_vp(&vp_jwt, &params, &flow.ic_root_key(), NOW_NS);
+
+        assert_eq!(
+            outcomes(&report),
+            vec![
+                "passed", "passed", "failed", "passed", "passed", "passed", "failed"
+            ]
+        );
+    }
+
+    fn presentation_params(
+        flow: &TestVcFlow,
+        effective_vc_subject: Principal,
+    ) -> PresentationParams {
+        PresentationParams {
+            effective_vc_subject,
+            effective_derivation_origin: RP_ORIGIN.to_string(),
+            vc_flow_signers: flow.vc_flow_signers(),
+            vc_spec: CredentialSpec {
+                credential_type: "VerifiedAdult".to_string(),
+                arguments: None,
+            },
+        }
+    }
+
+    fn outcomes(report: &Report) -> Vec<&'static str> {
+        report
+            .checks
+            .iter()
+            .map(|check| match check.outcome {
+                Outcome::Passed => "passed",
+                Outcome::Failed(_) => "failed",
+                Outcome::Skipped(_) => "skipped",
+            })
+            .collect()
+    }
+}
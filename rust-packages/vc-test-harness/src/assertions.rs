@@ -0,0 +1,108 @@
+//! Assertion helpers for the errors returned by issuers.
+use ic_verifiable_credentials::issuer_api::IssueCredentialError;
+use ic_verifiable_credentials::rate_limit::RATE_LIMIT_EXCEEDED;
+use std::fmt::Debug;
+
+macro_rules! issue_credential_error_assertion {
+    ($name:ident, $variant:ident) => {
+        #[doc = concat!("Asserts that `result` is `IssueCredentialError::", stringify!($variant), "`")]
+        #[doc = "with a message containing `expected_message`."]
+        #[track_caller]
+        pub fn $name<T: Debug>(result: Result<T, IssueCredentialError>, expected_message: &str) {
+            match result {
+                Err(IssueCredentialError::$variant(message)) => assert!(
+                    message.contains(expected_message),
+                    "expected message containing {:?}, got {:?}",
+                    expected_message,
+                    message
+                ),
+                other => panic!(
+                    "expected IssueCredentialError::{}, got {:?}",
+                    stringify!($variant),
+                    other
+                ),
+            }
+        }
+    };
+}
+
+issue_credential_error_assertion!(assert_unknown_subject, UnknownSubject);
+issue_credential_error_assertion!(assert_unauthorized_subject, UnauthorizedSubject);
+issue_credential_error_assertion!(assert_invalid_id_alias, InvalidIdAlias);
+issue_credential_error_assertion!(assert_signature_not_found, SignatureNotFound);
+issue_credential_error_assertion!(assert_internal_error, Internal);
+issue_credential_error_assertion!(
+    assert_unsupported_credential_spec,
+    UnsupportedCredentialSpec
+);
+
+/// Asserts that `result` is the `IssueCredentialError::UnauthorizedSubject` of a rate-limited
+/// request (cf. `RATE_LIMIT_EXCEEDED`), and returns the seconds after which to retry.
+#[track_caller]
+pub fn assert_rate_limited<T: Debug>(result: Result<T, IssueCredentialError>) -> u64 {
+    let retry_after_s = match &result {
+        Err(IssueCredentialError::UnauthorizedSubject(message))
+            if message.starts_with(RATE_LIMIT_EXCEEDED) =>
+        {
+            message
+                .rsplit_once("retry after ")
+                .and_then(|(_, retry_after)| retry_after.strip_suffix('s'))
+                .and_then(|retry_after| retry_after.parse().ok())
+        }
+        _ => None,
+    };
+    retry_after_s.unwrap_or_else(|| panic!("expected rate-limited request, got {:?}", result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_accept_expected_error() {
+        let result: Result<(), _> = Err(IssueCredentialError::UnknownSubject(
+            "unknown principal 2vxsx-fae".to_string(),
+        ));
+        assert_unknown_subject(result, "2vxsx-fae");
+    }
+
+    #[test]
+    #[should_panic(expected = "expected IssueCredentialError::InvalidIdAlias")]
+    fn should_reject_other_error() {
+        let result: Result<(), _> = Err(IssueCredentialError::Internal("oops".to_string()));
+        assert_invalid_id_alias(result, "oops");
+    }
+
+    #[test]
+    #[should_panic(expected = "expected message containing")]
+    fn should_reject_other_message() {
+        let result: Result<(), _> = Err(IssueCredentialError::SignatureNotFound(
+            "signature expired".to_string(),
+        ));
+        assert_signature_not_found(result, "not prepared");
+    }
+
+    #[test]
+    fn should_return_retry_after_of_rate_limited_error() {
+        let result: Result<(), _> = Err(IssueCredentialError::UnauthorizedSubject(
+            "rate limit exceeded: too many requests of caller 2vxsx-fae, retry after 42s"
+                .to_string(),
+        ));
+        assert_eq!(assert_rate_limited(result), 42);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected rate-limited request")]
+    fn should_reject_other_error_than_rate_limited() {
+        let result: Result<(), _> = Err(IssueCredentialError::UnauthorizedSubject(
+            "unknown principal 2vxsx-fae".to_string(),
+        ));
+        assert_rate_limited(result);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected IssueCredentialError::Internal, got Ok(42)")]
+    fn should_reject_success() {
+        assert_internal_error(Ok::<_, IssueCredentialError>(42), "");
+    }
+}
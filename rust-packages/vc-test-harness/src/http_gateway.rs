@@ -0,0 +1,117 @@
+//! Types and a client for the HTTP interface of canisters (`http_request` and
+//! `http_request_update`), which forwards requests like the HTTP gateway of the IC.
+use candid::{CandidType, Deserialize, Principal, decode_one, encode_one};
+use pocket_ic::{PocketIc, WasmResult};
+use serde::de::DeserializeOwned;
+use serde_bytes::ByteBuf;
+
+pub type HeaderField = (String, String);
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<HeaderField>,
+    pub body: ByteBuf,
+    pub certificate_version: Option<u16>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct HttpUpdateRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<HeaderField>,
+    pub body: ByteBuf,
+}
+
+/// The response of `http_request`, or of `http_request_update` (which has no `upgrade`).
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub headers: Vec<HeaderField>,
+    pub body: ByteBuf,
+    pub upgrade: Option<bool>,
+}
+
+impl HttpRequest {
+    pub fn get(url: &str) -> Self {
+        Self::new("GET", url, vec![])
+    }
+
+    pub fn post(url: &str, body: impl Into<Vec<u8>>) -> Self {
+        Self::new("POST", url, body.into())
+    }
+
+    fn new(method: &str, url: &str, body: Vec<u8>) -> Self {
+        Self {
+            method: method.to_string(),
+            url: url.to_string(),
+            headers: vec![],
+            body: ByteBuf::from(body),
+            certificate_version: Some(2),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+impl HttpResponse {
+    /// Returns the value of the header with the given (case-insensitive) name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Parses the body of the response as JSON.
+    pub fn json<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(&self.body).unwrap_or_else(|e| {
+            panic!(
+                "invalid JSON body {}: {}",
+                String::from_utf8_lossy(&self.body),
+                e
+            )
+        })
+    }
+}
+
+/// Sends the request to the canister like the HTTP gateway, i.e. via the query `http_request`,
+/// and via the update `http_request_update` if the canister asks to upgrade the request.
+/// The request is made by the anonymous principal.
+pub fn http_request(pic: &PocketIc, canister_id: Principal, request: HttpRequest) -> HttpResponse {
+    let arg = encode_one(&request).unwrap();
+    let reply = pic
+        .query_call(canister_id, Principal::anonymous(), "http_request", arg)
+        .expect("Error calling http_request");
+    let response: HttpResponse = decode_reply("http_request", reply);
+    if response.upgrade != Some(true) {
+        return response;
+    }
+    let update_request = HttpUpdateRequest {
+        method: request.method,
+        url: request.url,
+        headers: request.headers,
+        body: request.body,
+    };
+    let arg = encode_one(update_request).unwrap();
+    let reply = pic
+        .update_call(
+            canister_id,
+            Principal::anonymous(),
+            "http_request_update",
+            arg,
+        )
+        .expect("Error calling http_request_update");
+    decode_reply("http_request_update", reply)
+}
+
+fn decode_reply(method: &str, reply: WasmResult) -> HttpResponse {
+    match reply {
+        WasmResult::Reply(reply) => decode_one(&reply).unwrap(),
+        WasmResult::Reject(message) => panic!("Call of {} was rejected: {}", method, message),
+    }
+}
@@ -0,0 +1,114 @@
+//! Types and typed wrappers for the calls of the dummy identity provider
+//! (cf. `dummy-identity-provider/dummy_identity_provider.did`).
+use crate::{CanisterCall, call_canister};
+use candid::{CandidType, Deserialize, Principal};
+use pocket_ic::PocketIc;
+use serde_bytes::ByteBuf;
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct PrepareIdAliasRequest {
+    pub relying_party: String,
+    pub issuer: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct PreparedIdAlias {
+    pub rp_id_alias_jwt: String,
+    pub issuer_id_alias_jwt: String,
+    pub canister_sig_pk_der: ByteBuf,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub enum PrepareIdAliasError {
+    Internal(String),
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct GetIdAliasRequest {
+    pub rp_id_alias_jwt: String,
+    pub issuer_id_alias_jwt: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct SignedIdAlias {
+    pub id_alias: Principal,
+    pub id_dapp: Principal,
+    pub credential_jws: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct IdAliasCredentials {
+    pub rp_id_alias_credential: SignedIdAlias,
+    pub issuer_id_alias_credential: SignedIdAlias,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub enum GetIdAliasError {
+    Unauthorized(Principal),
+    NoSuchCredentials(String),
+    Internal(String),
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct GetPresentationRequest {
+    pub rp_id_alias_jws: String,
+    pub requested_vc_jws: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub struct PresentationData {
+    pub vp_jwt: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub enum GetPresentationError {
+    Internal(String),
+}
+
+pub fn prepare_id_alias(
+    pic: &PocketIc,
+    canister_id: Principal,
+    request: PrepareIdAliasRequest,
+    sender: Option<Principal>,
+) -> Result<PreparedIdAlias, PrepareIdAliasError> {
+    call_canister(
+        pic,
+        "prepare_id_alias",
+        CanisterCall::Update,
+        canister_id,
+        request,
+        sender,
+    )
+}
+
+pub fn get_id_alias(
+    pic: &PocketIc,
+    canister_id: Principal,
+    request: GetIdAliasRequest,
+    sender: Option<Principal>,
+) -> Result<IdAliasCredentials, GetIdAliasError> {
+    call_canister(
+        pic,
+        "get_id_alias",
+        CanisterCall::Query,
+        canister_id,
+        request,
+        sender,
+    )
+}
+
+pub fn get_presentation(
+    pic: &PocketIc,
+    canister_id: Principal,
+    request: GetPresentationRequest,
+    sender: Option<Principal>,
+) -> Result<PresentationData, GetPresentationError> {
+    call_canister(
+        pic,
+        "get_presentation",
+        CanisterCall::Query,
+        canister_id,
+        request,
+        sender,
+    )
+}
@@ -0,0 +1,73 @@
+//! Typed wrappers for the calls of the issuer API.
+use crate::{CanisterCall, call_canister};
+use candid::Principal;
+use ic_verifiable_credentials::issuer_api::{
+    DerivationOriginData, DerivationOriginError, DerivationOriginRequest, GetCredentialRequest,
+    Icrc21ConsentInfo, Icrc21Error, Icrc21VcConsentMessageRequest, IssueCredentialError,
+    IssuedCredentialData, PrepareCredentialRequest, PreparedCredentialData,
+};
+use pocket_ic::PocketIc;
+
+pub fn vc_consent_message(
+    pic: &PocketIc,
+    canister_id: Principal,
+    request: Icrc21VcConsentMessageRequest,
+    sender: Option<Principal>,
+) -> Result<Icrc21ConsentInfo, Icrc21Error> {
+    call_canister(
+        pic,
+        "vc_consent_message",
+        CanisterCall::Update,
+        canister_id,
+        request,
+        sender,
+    )
+}
+
+pub fn derivation_origin(
+    pic: &PocketIc,
+    canister_id: Principal,
+    request: DerivationOriginRequest,
+    sender: Option<Principal>,
+) -> Result<DerivationOriginData, DerivationOriginError> {
+    call_canister(
+        pic,
+        "derivation_origin",
+        CanisterCall::Update,
+        canister_id,
+        request,
+        sender,
+    )
+}
+
+pub fn prepare_credential(
+    pic: &PocketIc,
+    canister_id: Principal,
+    request: PrepareCredentialRequest,
+    sender: Option<Principal>,
+) -> Result<PreparedCredentialData, IssueCredentialError> {
+    call_canister(
+        pic,
+        "prepare_credential",
+        CanisterCall::Update,
+        canister_id,
+        request,
+        sender,
+    )
+}
+
+pub fn get_credential(
+    pic: &PocketIc,
+    canister_id: Principal,
+    request: GetCredentialRequest,
+    sender: Option<Principal>,
+) -> Result<IssuedCredentialData, IssueCredentialError> {
+    call_canister(
+        pic,
+        "get_credential",
+        CanisterCall::Query,
+        canister_id,
+        request,
+        sender,
+    )
+}
@@ -0,0 +1,338 @@
+//! PocketIC harness to test issuers and relying parties in the VC flow.
+//!
+//! `VcTestEnv` installs an issuer and (optionally) the dummy identity provider, which plays
+//! the role of Internet Identity, and drives the VC flow as done by Internet Identity:
+//! consent message, derivation origin, id_alias, `prepare_credential` and `get_credential`.
+//! The resulting presentation can then be validated like a relying party would do.
+use candid::{CandidType, Deserialize, Principal, decode_one, encode_one};
+use ic_verifiable_credentials::environment::{IcRootKey, VcEnvironment};
+use ic_verifiable_credentials::issuer_api::{
+    CredentialSpec, DerivationOriginError, DerivationOriginRequest, GetCredentialRequest,
+    Icrc21ConsentInfo, Icrc21ConsentPreferences, Icrc21Error, Icrc21VcConsentMessageRequest,
+    IssueCredentialError, PrepareCredentialRequest, SignedIdAlias,
+};
+use ic_verifiable_credentials::{
+    II_ISSUER_URL, PresentationVerificationError, VcFlowSigners,
+    validate_ii_presentation_and_claims,
+};
+use idp_api::{
+    GetIdAliasError, GetIdAliasRequest, GetPresentationError, GetPresentationRequest,
+    IdAliasCredentials, PrepareIdAliasError, PrepareIdAliasRequest,
+};
+use pocket_ic::{PocketIc, PocketIcBuilder, WasmResult};
+use serde::de::DeserializeOwned;
+use std::time::UNIX_EPOCH;
+
+pub mod assertions;
+pub mod http_gateway;
+pub mod idp_api;
+pub mod issuer_api;
+
+#[derive(Clone, Debug, CandidType, Deserialize, Eq, PartialEq)]
+pub enum VariantResponse<T, E> {
+    Ok(T),
+    Err(E),
+}
+
+pub enum CanisterCall {
+    Query,
+    Update,
+}
+
+/// Calls `method` of the given canister, and decodes the response as a `Result`.
+/// If no `sender` is given, the call is made by the anonymous principal.
+pub fn call_canister<Req, Succ, Err>(
+    pic: &PocketIc,
+    method: &str,
+    call_type: CanisterCall,
+    canister_id: Principal,
+    request: Req,
+    sender: Option<Principal>,
+) -> Result<Succ, Err>
+where
+    Req: CandidType,
+    Succ: CandidType + DeserializeOwned,
+    Err: CandidType + DeserializeOwned,
+{
+    let sender = sender.unwrap_or(Principal::anonymous());
+    let arg = encode_one(request).unwrap();
+    let reply = match call_type {
+        CanisterCall::Query => pic.query_call(canister_id, sender, method, arg),
+        CanisterCall::Update => pic.update_call(canister_id, sender, method, arg),
+    }
+    .expect("Error calling canister");
+    let reply = match reply {
+        WasmResult::Reply(reply) => reply,
+        WasmResult::Reject(message) => panic!("Call of {} was rejected: {}", method, message),
+    };
+    match decode_one(&reply).unwrap() {
+        VariantResponse::Ok(success) => Ok(success),
+        VariantResponse::Err(err) => Err(err),
+    }
+}
+
+/// Parameters of the VC flow.
+#[derive(Clone, Debug)]
+pub struct VcFlowRequest {
+    /// The (derivation) origin of the relying party.
+    pub relying_party: String,
+    /// The frontend hostname of the issuer, passed to `derivation_origin`.
+    pub issuer_frontend_hostname: String,
+    pub credential_spec: CredentialSpec,
+}
+
+/// The artifacts of a successful VC flow.
+#[derive(Clone, Debug)]
+pub struct VcFlowResult {
+    pub consent_info: Icrc21ConsentInfo,
+    /// The derivation origin of the issuer, as returned by `derivation_origin`.
+    pub issuer_derivation_origin: String,
+    pub id_alias_credentials: IdAliasCredentials,
+    /// The credential issued by the issuer.
+    pub vc_jws: String,
+    /// The presentation for the relying party.
+    pub vp_jwt: String,
+}
+
+/// The step of the VC flow that failed, with the returned error.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum VcFlowError {
+    ConsentMessage(Icrc21Error),
+    DerivationOrigin(DerivationOriginError),
+    PrepareIdAlias(PrepareIdAliasError),
+    GetIdAlias(GetIdAliasError),
+    PrepareCredential(IssueCredentialError),
+    GetCredential(IssueCredentialError),
+    GetPresentation(GetPresentationError),
+}
+
+impl VcFlowError {
+    /// Returns the error returned by the issuer in `prepare_credential` or `get_credential`, if any.
+    pub fn issue_credential_error(&self) -> Option<&IssueCredentialError> {
+        match self {
+            VcFlowError::PrepareCredential(e) | VcFlowError::GetCredential(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A PocketIC instance with an issuer and optionally the dummy identity provider.
+pub struct VcTestEnv {
+    pub pic: PocketIc,
+    issuer_id: Option<Principal>,
+    idp_id: Option<Principal>,
+}
+
+impl Default for VcTestEnv {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VcTestEnv {
+    /// Creates a PocketIC instance with an NNS subnet (which provides the root key)
+    /// and an application subnet, on which the canisters are installed.
+    pub fn new() -> Self {
+        let pic = PocketIcBuilder::new()
+            .with_nns_subnet()
+            .with_application_subnet()
+            .build();
+        Self {
+            pic,
+            issuer_id: None,
+            idp_id: None,
+        }
+    }
+
+    /// Installs the given wasm with the given (candid-encoded) init argument
+    /// on the application subnet.
+    pub fn install_canister(&self, wasm: &[u8], arg: Vec<u8>) -> Principal {
+        let app_subnet = self.pic.topology().get_app_subnets()[0];
+        let canister_id = self.pic.create_canister_on_subnet(None, None, app_subnet);
+        self.pic.add_cycles(canister_id, 2_000_000_000_000);
+        self.pic
+            .install_canister(canister_id, wasm.to_vec(), arg, None);
+        canister_id
+    }
+
+    /// Installs the issuer under test.  The init argument typically contains the root key
+    /// (cf. `root_key_der()`) and the canister id of the identity provider, so the identity
+    /// provider should be installed first.
+    pub fn install_issuer(&mut self, wasm: &[u8], arg: Vec<u8>) -> Principal {
+        let canister_id = self.install_canister(wasm, arg);
+        self.issuer_id = Some(canister_id);
+        canister_id
+    }
+
+    /// Installs the dummy identity provider, which signs the id_alias credentials.
+    pub fn install_identity_provider(&mut self, wasm: &[u8]) -> Principal {
+        let canister_id = self.install_canister(wasm, vec![]);
+        self.idp_id = Some(canister_id);
+        canister_id
+    }
+
+    pub fn issuer_id(&self) -> Principal {
+        self.issuer_id.expect("no issuer installed")
+    }
+
+    pub fn idp_id(&self) -> Principal {
+        self.idp_id.expect("no identity provider installed")
+    }
+
+    pub fn root_key_der(&self) -> Vec<u8> {
+        self.pic.root_key().expect("missing root key")
+    }
+
+    pub fn ic_root_key(&self) -> IcRootKey {
+        IcRootKey::from_der(&self.root_key_der()).expect("invalid root key")
+    }
+
+    /// Returns the environment of the VC flow, with the dummy identity provider as II, which
+    /// issues id_alias credentials as `II_ISSUER_URL` like II.
+    pub fn environment(&self) -> VcEnvironment {
+        VcEnvironment::local(self.idp_id(), II_ISSUER_URL, self.ic_root_key())
+    }
+
+    /// Returns the current time of the PocketIC instance in nanoseconds since the UNIX epoch.
+    pub fn time_ns(&self) -> u128 {
+        self.pic
+            .get_time()
+            .duration_since(UNIX_EPOCH)
+            .expect("invalid time")
+            .as_nanos()
+    }
+
+    /// Returns the signers of the credentials obtained via `run_vc_flow()`, where the id_alias
+    /// credential is signed by the dummy identity provider.
+    pub fn vc_flow_signers(&self, issuer_origin: &str) -> VcFlowSigners {
+        self.environment()
+            .vc_flow_signers(self.issuer_id(), issuer_origin)
+    }
+
+    /// Runs the VC flow for `user` against the issuer, with the dummy identity provider, and
+    /// returns the presentation for the relying party together with the intermediate artifacts.
+    pub fn run_vc_flow(
+        &self,
+        user: Principal,
+        request: &VcFlowRequest,
+    ) -> Result<VcFlowResult, VcFlowError> {
+        let sender = Some(user);
+        let consent_info = issuer_api::vc_consent_message(
+            &self.pic,
+            self.issuer_id(),
+            Icrc21VcConsentMessageRequest {
+                preferences: Icrc21ConsentPreferences {
+                    language: "en".to_string(),
+                },
+                credential_spec: request.credential_spec.clone(),
+            },
+            sender,
+        )
+        .map_err(VcFlowError::ConsentMessage)?;
+        let issuer_derivation_origin = issuer_api::derivation_origin(
+            &self.pic,
+            self.issuer_id(),
+            DerivationOriginRequest {
+                frontend_hostname: request.issuer_frontend_hostname.clone(),
+            },
+            sender,
+        )
+        .map_err(VcFlowError::DerivationOrigin)?
+        .origin;
+
+        let prepared_id_alias = idp_api::prepare_id_alias(
+            &self.pic,
+            self.idp_id(),
+            PrepareIdAliasRequest {
+                relying_party: request.relying_party.clone(),
+                issuer: issuer_derivation_origin.clone(),
+            },
+            sender,
+        )
+        .map_err(VcFlowError::PrepareIdAlias)?;
+        let id_alias_credentials = idp_api::get_id_alias(
+            &self.pic,
+            self.idp_id(),
+            GetIdAliasRequest {
+                rp_id_alias_jwt: prepared_id_alias.rp_id_alias_jwt,
+                issuer_id_alias_jwt: prepared_id_alias.issuer_id_alias_jwt,
+            },
+            sender,
+        )
+        .map_err(VcFlowError::GetIdAlias)?;
+
+        let signed_id_alias = SignedIdAlias {
+            credential_jws: id_alias_credentials
+                .issuer_id_alias_credential
+                .credential_jws
+                .clone(),
+        };
+        let prepared_credential = issuer_api::prepare_credential(
+            &self.pic,
+            self.issuer_id(),
+            PrepareCredentialRequest {
+                signed_id_alias: signed_id_alias.clone(),
+                credential_spec: request.credential_spec.clone(),
+            },
+            sender,
+        )
+        .map_err(VcFlowError::PrepareCredential)?;
+        let vc_jws = issuer_api::get_credential(
+            &self.pic,
+            self.issuer_id(),
+            GetCredentialRequest {
+                signed_id_alias,
+                credential_spec: request.credential_spec.clone(),
+                prepared_context: prepared_credential.prepared_context,
+            },
+            sender,
+        )
+        .map_err(VcFlowError::GetCredential)?
+        .vc_jws;
+
+        let vp_jwt = idp_api::get_presentation(
+            &self.pic,
+            self.idp_id(),
+            GetPresentationRequest {
+                rp_id_alias_jws: id_alias_credentials
+                    .rp_id_alias_credential
+                    .credential_jws
+                    .clone(),
+                requested_vc_jws: vc_jws.clone(),
+            },
+            sender,
+        )
+        .map_err(VcFlowError::GetPresentation)?
+        .vp_jwt;
+
+        Ok(VcFlowResult {
+            consent_info,
+            issuer_derivation_origin,
+            id_alias_credentials,
+            vc_jws,
+            vp_jwt,
+        })
+    }
+
+    /// Validates the given presentation like a relying party with the given derivation
+    /// origin would do (cf. `validate_ii_presentation_and_claims`), at the current time
+    /// of the PocketIC instance.
+    pub fn validate_presentation(
+        &self,
+        vp_jwt: &str,
+        user: Principal,
+        relying_party: &str,
+        issuer_origin: &str,
+        credential_spec: &CredentialSpec,
+    ) -> Result<(), PresentationVerificationError> {
+        validate_ii_presentation_and_claims(
+            vp_jwt,
+            user,
+            relying_party.to_string(),
+            &self.vc_flow_signers(issuer_origin),
+            credential_spec,
+            &self.environment(),
+            self.time_ns(),
+        )
+    }
+}